@@ -0,0 +1,141 @@
+//! Parsing throughput for each way of reading a file.
+//!
+//! Run with `cargo bench --bench parse --features testing`. Set `HOSTFILE_BENCH_ENTRIES` to
+//! change the size of the generated blocklist, which has a million entries by default.
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hostfile::{
+    get_hostfile_path, parse_bytes_borrowed, parse_file_with, parse_files_parallel,
+    HostfileFixture, ReadMode,
+};
+
+const SMALL: &str = "\
+# Static table lookup for hostnames.
+# See hosts(5) for details.
+127.0.0.1\tlocalhost
+127.0.1.1\tworkstation.example.test workstation
+
+# The following lines are desirable for IPv6 capable hosts
+::1     ip6-localhost ip6-loopback
+fe00::0 ip6-localnet
+ff00::0 ip6-mcastprefix
+ff02::1 ip6-allnodes
+ff02::2 ip6-allrouters
+";
+
+/// How many files the blocklist is split across for the parallel benchmark
+const PARALLEL_FILES: usize = 8;
+
+fn blocklist_entries() -> usize {
+    std::env::var("HOSTFILE_BENCH_ENTRIES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1_000_000)
+}
+
+/// A blocklist in the style of the ones published for ad blocking, with the entries in `range`
+/// split into commented groups
+fn blocklist(range: std::ops::Range<usize>) -> HostfileFixture {
+    let mut fixture = HostfileFixture::new()
+        .comment("Generated blocklist")
+        .entry("127.0.0.1", ["localhost"])
+        .entry("::1", ["localhost"]);
+    for idx in range {
+        if idx % 1000 == 0 {
+            fixture = fixture.line("").comment(&format!("group {}", idx / 1000));
+        }
+        fixture = fixture.entry(
+            "0.0.0.0",
+            [format!("ads{idx}.tracker{}.example.test", idx % 97)],
+        );
+    }
+    fixture
+}
+
+fn bench_fixture(c: &mut Criterion, name: &str, fixture: &HostfileFixture) {
+    let file = fixture.build_tempfile().unwrap();
+    bench_file(c, name, file.path(), fixture.build().as_bytes());
+}
+
+fn bench_file(c: &mut Criterion, name: &str, path: &Path, contents: &[u8]) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(contents.len() as u64));
+    if contents.len() > 1 << 20 {
+        group.sample_size(10);
+    }
+    for (label, mode) in [
+        ("buffered", ReadMode::Buffered),
+        ("whole_file", ReadMode::WholeFile),
+    ] {
+        group.bench_with_input(BenchmarkId::new("parse_file", label), path, |b, path| {
+            b.iter(|| parse_file_with(path, mode).unwrap())
+        });
+    }
+    group.bench_with_input(
+        BenchmarkId::new("parse_bytes_borrowed", "in_memory"),
+        contents,
+        |b, contents| b.iter(|| parse_bytes_borrowed(contents).unwrap()),
+    );
+    group.finish();
+}
+
+fn small(c: &mut Criterion) {
+    bench_fixture(c, "small", &HostfileFixture::new().line(SMALL.trim_end()));
+}
+
+fn system(c: &mut Criterion) {
+    let path = get_hostfile_path().unwrap();
+    match std::fs::read(&path) {
+        Ok(contents) => bench_file(c, "system", &path, &contents),
+        Err(err) => eprintln!("Skipping the system hosts file ({:?}): {err}", path),
+    }
+}
+
+fn large(c: &mut Criterion) {
+    bench_fixture(c, "blocklist", &blocklist(0..blocklist_entries()));
+}
+
+/// The blocklist split into several files, parsed one after another and with
+/// [`parse_files_parallel`]
+fn parallel(c: &mut Criterion) {
+    let entries = blocklist_entries();
+    let per_file = entries.div_ceil(PARALLEL_FILES);
+    let fixtures: Vec<_> = (0..PARALLEL_FILES)
+        .map(|idx| blocklist(idx * per_file..entries.min((idx + 1) * per_file)))
+        .collect();
+    let files: Vec<_> = fixtures
+        .iter()
+        .map(|fixture| fixture.build_tempfile().unwrap())
+        .collect();
+    let paths: Vec<_> = files.iter().map(|file| file.path()).collect();
+    let bytes: usize = fixtures.iter().map(|fixture| fixture.build().len()).sum();
+
+    let mut group = c.benchmark_group("parallel");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    if bytes > 1 << 20 {
+        group.sample_size(10);
+    }
+    group.bench_with_input(
+        BenchmarkId::new("parse_file", "sequential"),
+        &paths,
+        |b, paths| {
+            b.iter(|| {
+                paths
+                    .iter()
+                    .flat_map(|path| parse_file_with(path, ReadMode::WholeFile).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("parse_files_parallel", PARALLEL_FILES),
+        &paths,
+        |b, paths| b.iter(|| parse_files_parallel(paths).unwrap()),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, small, system, large, parallel);
+criterion_main!(benches);
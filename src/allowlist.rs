@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::iter::FromIterator;
+use std::path::Path;
+
+use crate::{hostname_key, read_file, HostEntry, HostfileError};
+
+/// Names that must never be blocked or overridden when merging hosts files.
+///
+/// Each pattern is either a name, such as `example.com`, that matches only that name, or a
+/// wildcard, such as `*.example.com`, that matches every subdomain of `example.com` at any
+/// depth but not `example.com` itself. List both to allow a domain and all its subdomains.
+/// Names are compared like [`crate::hostname_eq`] does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Allowlist {
+    names: HashSet<String>,
+    /// Domains whose subdomains are allowed, each with a leading `.`
+    suffixes: HashSet<String>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read patterns from `contents`, one per line. Blank lines and `#` comments are skipped.
+    pub fn parse(contents: &str) -> Self {
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    }
+
+    /// Read patterns from the file at `path`, see [`Allowlist::parse`]
+    pub fn read(path: &Path) -> Result<Self, HostfileError> {
+        Ok(Self::parse(&read_file(path)?))
+    }
+
+    /// Allow the names matching `pattern`
+    pub fn insert(&mut self, pattern: &str) {
+        match pattern.strip_prefix("*.") {
+            Some(domain) => {
+                self.suffixes.insert(format!(".{}", hostname_key(domain)));
+            }
+            None => {
+                self.names.insert(hostname_key(pattern).into_owned());
+            }
+        }
+    }
+
+    /// Whether `name` matches any pattern
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let name = hostname_key(name);
+        if self.names.contains(name.as_ref()) {
+            return true;
+        }
+        // Check every parent domain of `name` from the longest down
+        name.match_indices('.')
+            .any(|(idx, _)| idx > 0 && self.suffixes.contains(&name[idx..]))
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Allowlist {
+    fn from_iter<I: IntoIterator<Item = S>>(patterns: I) -> Self {
+        let mut allowlist = Allowlist::new();
+        for pattern in patterns {
+            allowlist.insert(pattern.as_ref());
+        }
+        allowlist
+    }
+}
+
+/// Remove the names that `allowlist` allows from `entries`, dropping entries left without
+/// names. Returns what was exempted, one entry per entry that lost names.
+///
+/// See [`crate::HostsFile::apply_allowlist`] to comment out entries in a file instead.
+pub fn apply_allowlist(entries: &mut Vec<HostEntry>, allowlist: &Allowlist) -> Vec<HostEntry> {
+    let mut exempted = Vec::new();
+    entries.retain_mut(|entry| {
+        let (allowed, kept): (Vec<String>, Vec<String>) = entry
+            .names
+            .drain(..)
+            .partition(|name| allowlist.is_allowed(name));
+        entry.names = kept;
+        if !allowed.is_empty() {
+            exempted.push(HostEntry {
+                ip: entry.ip,
+                names: allowed,
+            });
+        }
+        !entry.names.is_empty()
+    });
+    exempted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlisted_names_are_exempted() {
+        let allowlist = Allowlist::parse("# keep these\nexample.com\n*.cdn.example.net  # all\n");
+        assert!(allowlist.is_allowed("Example.COM"));
+        assert!(!allowlist.is_allowed("www.example.com"));
+        assert!(allowlist.is_allowed("a.b.cdn.example.net"));
+        assert!(!allowlist.is_allowed("cdn.example.net"));
+        assert!(!allowlist.is_allowed("xcdn.example.net"));
+
+        let mut entries: Vec<HostEntry> = vec![
+            "0.0.0.0 ads.example.org example.com".parse().unwrap(),
+            "0.0.0.0 img.cdn.example.net".parse().unwrap(),
+            "0.0.0.0 tracker.example.org".parse().unwrap(),
+        ];
+        let exempted = apply_allowlist(&mut entries, &allowlist);
+        assert_eq!(
+            entries,
+            vec![
+                "0.0.0.0 ads.example.org".parse().unwrap(),
+                "0.0.0.0 tracker.example.org".parse().unwrap(),
+            ]
+        );
+        assert_eq!(
+            exempted,
+            vec![
+                "0.0.0.0 example.com".parse().unwrap(),
+                "0.0.0.0 img.cdn.example.net".parse().unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use hostfile::{Severity, WatchEvent};
+
+/// When to color output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color output to terminals, unless `NO_COLOR` is set
+    Auto,
+    Always,
+    Never,
+}
+
+/// What a piece of output is, which decides its color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Header,
+    Address,
+    Name,
+    Alias,
+    Section,
+    Added,
+    Removed,
+    Changed,
+    Severity(Severity),
+}
+
+impl Style {
+    /// The SGR parameters of this style
+    fn sgr(self) -> &'static str {
+        match self {
+            Style::Header => "1",
+            Style::Address => "36",
+            Style::Name => "1",
+            Style::Alias => "32",
+            Style::Section => "35",
+            Style::Added => "32",
+            Style::Removed => "31",
+            Style::Changed => "33",
+            Style::Severity(Severity::Error) => "1;31",
+            Style::Severity(Severity::Warning) => "1;33",
+            Style::Severity(Severity::Note) => "1;36",
+        }
+    }
+}
+
+/// Colors text for standard output with ANSI escape codes, if enabled
+#[derive(Debug, Clone, Copy)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub fn new(choice: ColorChoice) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org
+            ColorChoice::Auto => {
+                io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
+        };
+        Painter { enabled }
+    }
+
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if self.enabled && !text.is_empty() {
+            format!("\x1b[{}m{text}\x1b[0m", style.sgr())
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Print `rows` as a table with aligned columns and a header
+    pub fn print_table(&self, header: &[&str], styles: &[Style], rows: &[Vec<String>]) {
+        let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+        for row in rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let line = |cells: Vec<(&str, Style)>| {
+            let last = cells.len().saturating_sub(1);
+            let cells: Vec<String> = cells
+                .into_iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(idx, ((cell, style), width))| {
+                    // Padding the last column would only add trailing whitespace
+                    let padding = if idx == last {
+                        0
+                    } else {
+                        width - cell.chars().count()
+                    };
+                    format!("{}{}", self.paint(style, cell), " ".repeat(padding))
+                })
+                .collect();
+            println!("{}", cells.join("  "));
+        };
+        line(header.iter().map(|title| (*title, Style::Header)).collect());
+        for row in rows {
+            line(
+                row.iter()
+                    .map(String::as_str)
+                    .zip(styles.iter().copied())
+                    .collect(),
+            );
+        }
+    }
+
+    /// `event` as a line of a diff
+    pub fn event(&self, event: &WatchEvent) -> String {
+        let addrs = |addrs: &[std::net::IpAddr]| {
+            addrs
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match event {
+            WatchEvent::EntryAdded { name, addrs: ips } => {
+                self.paint(Style::Added, &format!("+ {name} {}", addrs(ips)))
+            }
+            WatchEvent::EntryRemoved { name, addrs: ips } => {
+                self.paint(Style::Removed, &format!("- {name} {}", addrs(ips)))
+            }
+            WatchEvent::IpChanged { name, old, new } => self.paint(
+                Style::Changed,
+                &format!("~ {name} {} -> {}", addrs(old), addrs(new)),
+            ),
+            // Events added later are shown without details
+            _ => format!("? {}", event.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_events() {
+        let event = WatchEvent::IpChanged {
+            name: String::from("web"),
+            old: vec!["10.0.0.1".parse().unwrap()],
+            new: vec!["10.0.0.2".parse().unwrap(), "fd00::2".parse().unwrap()],
+        };
+        let plain = Painter::new(ColorChoice::Never);
+        assert_eq!(plain.event(&event), "~ web 10.0.0.1 -> 10.0.0.2, fd00::2");
+        let colored = Painter::new(ColorChoice::Always);
+        assert_eq!(
+            colored.event(&event),
+            "\x1b[33m~ web 10.0.0.1 -> 10.0.0.2, fd00::2\x1b[0m"
+        );
+    }
+}
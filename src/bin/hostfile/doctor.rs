@@ -0,0 +1,440 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use hostfile::{
+    hostfile_location, lint_str_with, lookup, self_entry, HostsFile, LintOptions, LookupOptions,
+    Severity,
+};
+
+use crate::color::{Painter, Style};
+
+/// A line of the report
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    /// Something worth knowing that is neither right nor wrong
+    Info(String),
+    /// A check that passed
+    Ok(String),
+    Problem(Severity, String),
+}
+
+/// Print a report on the hosts file at `path`, or the system one, and on how this machine
+/// resolves names. Fails if anything is an error.
+pub fn run(path: Option<&Path>, painter: &Painter) -> Result<(), String> {
+    let mut sections: Vec<(&str, Vec<Item>)> = Vec::new();
+
+    let mut file_items = Vec::new();
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let location = hostfile_location().map_err(|err| err.to_string())?;
+            file_items.push(Item::Info(location.to_string()));
+            location.path
+        }
+    };
+    let bytes = std::fs::read(&path);
+    match &bytes {
+        Ok(bytes) => {
+            file_items.extend(permission_checks(&path));
+            file_items.extend(encoding_checks(bytes));
+        }
+        Err(err) => file_items.push(Item::Problem(
+            Severity::Error,
+            format!("Could not read {}: {err}", path.display()),
+        )),
+    }
+    sections.push(("Hosts file", file_items));
+
+    if let Ok(bytes) = &bytes {
+        let contents = String::from_utf8_lossy(bytes);
+        sections.push(("Entries", content_checks(&contents)));
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        let read = |path: &str| std::fs::read_to_string(path).ok();
+        sections.push((
+            "Name resolution",
+            resolution_checks(
+                read("/etc/nsswitch.conf").as_deref(),
+                read("/etc/host.conf").as_deref(),
+                read("/etc/resolv.conf").as_deref(),
+            ),
+        ));
+    }
+    #[cfg(target_os = "macos")]
+    sections.push((
+        "Name resolution",
+        vec![Item::Info(String::from(
+            "The hosts file is read by mDNSResponder before DNS is asked",
+        ))],
+    ));
+    #[cfg(windows)]
+    sections.push((
+        "Name resolution",
+        vec![Item::Info(String::from(
+            "The DNS Client service reads the hosts file before asking DNS",
+        ))],
+    ));
+
+    let mut errors = 0;
+    for (idx, (title, items)) in sections.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        println!("{}", painter.paint(Style::Header, title));
+        for item in items {
+            match item {
+                Item::Info(message) => println!("  {message}"),
+                Item::Ok(message) => println!("  {}: {message}", painter.paint(Style::Added, "ok")),
+                Item::Problem(severity, message) => {
+                    errors += usize::from(*severity == Severity::Error);
+                    println!(
+                        "  {}: {message}",
+                        painter.paint(Style::Severity(*severity), &severity.to_string())
+                    );
+                }
+            }
+        }
+    }
+    match errors {
+        0 => Ok(()),
+        1 => Err(String::from("Found 1 error")),
+        errors => Err(format!("Found {errors} errors")),
+    }
+}
+
+/// Whether every program can read the file and only root can change it
+#[cfg(unix)]
+fn permission_checks(path: &Path) -> Vec<Item> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            return vec![Item::Problem(
+                Severity::Error,
+                format!("Could not read the permissions: {err}"),
+            )]
+        }
+    };
+    let mode = metadata.mode() & 0o777;
+    let mut items = Vec::new();
+    if mode & 0o004 == 0 {
+        items.push(Item::Problem(
+            Severity::Error,
+            format!(
+                "The mode is {mode:o}, so programs not run by its owner or group can't read it"
+            ),
+        ));
+    } else if mode & 0o022 != 0 {
+        items.push(Item::Problem(
+            Severity::Warning,
+            format!("The mode is {mode:o}, so users other than its owner can redirect names"),
+        ));
+    } else {
+        items.push(Item::Ok(format!(
+            "Everyone can read it, the mode is {mode:o}"
+        )));
+    }
+    if metadata.uid() != 0 {
+        items.push(Item::Problem(
+            Severity::Note,
+            format!("It is owned by uid {} rather than root", metadata.uid()),
+        ));
+    }
+    items
+}
+
+#[cfg(not(unix))]
+fn permission_checks(path: &Path) -> Vec<Item> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.permissions().readonly() => vec![Item::Info(String::from(
+            "It is read-only, so editors have to clear the attribute to change it",
+        ))],
+        Ok(_) => Vec::new(),
+        Err(err) => vec![Item::Problem(
+            Severity::Error,
+            format!("Could not read the attributes: {err}"),
+        )],
+    }
+}
+
+/// Problems with how the file is encoded that resolvers trip over
+fn encoding_checks(bytes: &[u8]) -> Vec<Item> {
+    let mut items = Vec::new();
+    if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+        items.push(Item::Problem(
+            Severity::Error,
+            String::from("It is encoded as UTF-16, which resolvers can't read"),
+        ));
+        return items;
+    }
+    if bytes.starts_with("\u{feff}".as_bytes()) {
+        items.push(Item::Problem(
+            Severity::Warning,
+            String::from(
+                "It starts with a UTF-8 byte order mark, which resolvers read as part of the \
+                 first line",
+            ),
+        ));
+    }
+    if bytes.contains(&0) {
+        items.push(Item::Problem(
+            Severity::Error,
+            String::from("It contains NUL bytes, which end the file early for some resolvers"),
+        ));
+    }
+    let invalid: Vec<String> = bytes
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| std::str::from_utf8(line).is_err())
+        .map(|(idx, _)| (idx + 1).to_string())
+        .collect();
+    if !invalid.is_empty() {
+        items.push(Item::Problem(
+            Severity::Warning,
+            format!("Not valid UTF-8 on line {}", invalid.join(", ")),
+        ));
+    }
+    let crlf = bytes.windows(2).filter(|pair| pair == b"\r\n").count();
+    let lf = bytes.iter().filter(|&&b| b == b'\n').count();
+    if crlf > 0 && crlf < lf {
+        items.push(Item::Problem(
+            Severity::Note,
+            String::from("It mixes \\r\\n and \\n line endings"),
+        ));
+    }
+    if items.is_empty() {
+        items.push(Item::Ok(String::from("It is valid UTF-8")));
+    }
+    items
+}
+
+/// The lint findings for `contents` and whether this machine's hostname has an entry
+fn content_checks(contents: &str) -> Vec<Item> {
+    let file = HostsFile::parse(contents);
+    let entries: Vec<_> = file.entries().collect();
+    let mut items = vec![Item::Info(format!(
+        "Entries: {}, distinct addresses: {}",
+        entries.len(),
+        entries
+            .iter()
+            .map(|entry| entry.ip)
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+    ))];
+
+    let findings = lint_str_with(contents, &LintOptions::new().check_localhost(true));
+    for finding in &findings {
+        items.push(Item::Problem(
+            finding.severity,
+            format!(
+                "Line {}: {} [{}]",
+                finding.line,
+                finding.message,
+                finding.rule.id()
+            ),
+        ));
+    }
+    if findings.is_empty() {
+        items.push(Item::Ok(String::from(
+            "No problems found, and localhost resolves to 127.0.0.1 and ::1",
+        )));
+    }
+
+    // Only the names are needed, so any address will do
+    if let Ok(machine) = self_entry(Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))) {
+        for name in &machine.names {
+            let addrs = lookup(&entries, name, LookupOptions::default());
+            if addrs.is_empty() {
+                items.push(Item::Problem(
+                    Severity::Warning,
+                    format!(
+                        "This machine's name {name} has no entry, so resolving it relies on DNS \
+                         and programs such as sudo may stall"
+                    ),
+                ));
+            } else {
+                let addrs: Vec<String> = addrs.iter().map(IpAddr::to_string).collect();
+                items.push(Item::Ok(format!(
+                    "This machine's name {name} resolves to {}",
+                    addrs.join(", ")
+                )));
+            }
+        }
+    }
+    items
+}
+
+/// How the C library looks up names according to `nsswitch.conf`, `host.conf` and
+/// `resolv.conf`, and whether that leaves the hosts file in charge
+#[cfg_attr(any(not(unix), target_os = "macos"), allow(dead_code))]
+fn resolution_checks(
+    nsswitch: Option<&str>,
+    host_conf: Option<&str>,
+    resolv_conf: Option<&str>,
+) -> Vec<Item> {
+    let mut items = Vec::new();
+    let sources = nsswitch.and_then(|conf| {
+        conf.lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .find_map(|line| line.trim().strip_prefix("hosts:"))
+            .map(|sources| sources.split_whitespace().collect::<Vec<_>>())
+    });
+    match sources {
+        Some(sources) => {
+            items.push(Item::Info(format!(
+                "Names are looked up with: {}",
+                sources.join(" ")
+            )));
+            // Actions such as `[NOTFOUND=return]` aren't sources
+            let services: Vec<&str> = sources
+                .iter()
+                .copied()
+                .filter(|source| !source.starts_with('['))
+                .collect();
+            match services.iter().position(|&service| service == "files") {
+                None if services.contains(&"resolve") => items.push(Item::Problem(
+                    Severity::Note,
+                    String::from(
+                        "systemd-resolved answers lookups and reads the hosts file itself",
+                    ),
+                )),
+                None => items.push(Item::Problem(
+                    Severity::Error,
+                    String::from(
+                        "nsswitch.conf doesn't list `files` for hosts, so the hosts file is \
+                         not used",
+                    ),
+                )),
+                Some(at) => {
+                    // These only answer for container names and systemd-resolved reads the
+                    // hosts file itself
+                    let before: Vec<&str> = services[..at]
+                        .iter()
+                        .copied()
+                        .filter(|&service| service != "mymachines" && service != "resolve")
+                        .collect();
+                    if before.is_empty() {
+                        items.push(Item::Ok(String::from(
+                            "The hosts file is consulted before DNS",
+                        )));
+                    } else {
+                        items.push(Item::Problem(
+                            Severity::Warning,
+                            format!(
+                                "{} {} consulted before the hosts file and can override it",
+                                before.join(", "),
+                                if before.len() == 1 { "is" } else { "are" }
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        None => items.push(Item::Problem(
+            Severity::Note,
+            String::from(
+                "nsswitch.conf has no hosts line, so glibc asks DNS before the hosts file, while \
+                 musl always reads the hosts file first",
+            ),
+        )),
+    }
+
+    if let Some(conf) = host_conf {
+        let multi = conf.lines().any(|line| {
+            let mut words = line.split_whitespace();
+            words.next() == Some("multi") && words.next() == Some("on")
+        });
+        if !multi {
+            items.push(Item::Problem(
+                Severity::Note,
+                String::from(
+                    "host.conf doesn't turn on `multi`, so a name with several entries only \
+                     resolves to the first address",
+                ),
+            ));
+        }
+    }
+
+    if let Some(conf) = resolv_conf {
+        let mut nameservers = Vec::new();
+        let mut search = Vec::new();
+        for line in conf.lines() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => nameservers.extend(words.next()),
+                // The last one wins
+                Some("search") | Some("domain") => search = words.collect(),
+                _ => {}
+            }
+        }
+        if nameservers.is_empty() {
+            items.push(Item::Info(String::from(
+                "resolv.conf lists no DNS servers, so the local machine is asked",
+            )));
+        } else {
+            items.push(Item::Info(format!(
+                "DNS servers: {}",
+                nameservers.join(", ")
+            )));
+        }
+        if nameservers.contains(&"127.0.0.53") {
+            items.push(Item::Info(String::from(
+                "DNS goes through systemd-resolved, which caches answers",
+            )));
+        }
+        if !search.is_empty() {
+            items.push(Item::Info(format!(
+                "Names without enough dots are also tried in: {}",
+                search.join(", ")
+            )));
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_order() {
+        let items = resolution_checks(
+            Some("passwd: files\n# hosts: dns\nhosts: dns [NOTFOUND=return] files mdns4\n"),
+            Some("multi on\n"),
+            Some("nameserver 10.0.0.53\nsearch corp.example.org\n"),
+        );
+        assert_eq!(
+            items,
+            vec![
+                Item::Info(String::from(
+                    "Names are looked up with: dns [NOTFOUND=return] files mdns4"
+                )),
+                Item::Problem(
+                    Severity::Warning,
+                    String::from("dns is consulted before the hosts file and can override it")
+                ),
+                Item::Info(String::from("DNS servers: 10.0.0.53")),
+                Item::Info(String::from(
+                    "Names without enough dots are also tried in: corp.example.org"
+                )),
+            ]
+        );
+
+        let items = resolution_checks(Some("hosts: mymachines resolve files dns\n"), None, None);
+        assert_eq!(
+            items[1],
+            Item::Ok(String::from("The hosts file is consulted before DNS"))
+        );
+        let items = resolution_checks(Some("hosts: dns\n"), Some("order hosts,bind\n"), None);
+        assert!(matches!(items[1], Item::Problem(Severity::Error, _)));
+        assert!(matches!(items[2], Item::Problem(Severity::Note, _)));
+
+        assert_eq!(
+            encoding_checks(b"\xef\xbb\xbf127.0.0.1 localhost\r\n::1 localhost\n\xff\n").len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use hostfile::{parse_str_strict, BackupPolicy, HostsFile, WriteOptions};
+
+/// The editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// Edit the hosts file at `path` in the user's editor, like `visudo` does for sudoers.
+///
+/// The file is copied to a temporary file for editing, and the result is only installed if
+/// it passes the strict parser. Invalid edits are reported and the user can edit them again
+/// or discard them. The file is replaced atomically and backed up first.
+pub fn run(path: &Path) -> Result<(), String> {
+    let original = HostsFile::read(path)
+        .map_err(|err| err.to_string())?
+        .to_string();
+    let temp = create_temp(path, &original)?;
+    let result = edit_loop(path, &original, &temp);
+    // Keep the edits around if they couldn't be installed
+    if result.is_ok() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn edit_loop(path: &Path, original: &str, temp: &Path) -> Result<(), String> {
+    let (program, args) = editor_command(
+        std::env::var("VISUAL")
+            .ok()
+            .or_else(|| std::env::var("EDITOR").ok()),
+    );
+    loop {
+        let status = Command::new(&program)
+            .args(&args)
+            .arg(temp)
+            .status()
+            .map_err(|err| format!("Could not run editor `{program}`: {err}"))?;
+        if !status.success() {
+            return Err(format!(
+                "Editor `{program}` failed ({status}), edits are kept in {}",
+                temp.display()
+            ));
+        }
+
+        let edited = fs::read_to_string(temp)
+            .map_err(|err| format!("Could not read ({:?}): {err}", temp))?;
+        if edited == original {
+            eprintln!("hostfile: {} unchanged", path.display());
+            return Ok(());
+        }
+        match parse_str_strict(&edited) {
+            Ok(_) => return install(path, original, &edited, temp),
+            Err(err) => eprint!("{}", err.render(&edited)),
+        }
+        if !prompt_edit_again()? {
+            eprintln!("hostfile: discarded changes to {}", path.display());
+            return Ok(());
+        }
+    }
+}
+
+/// Replace `path` with `edited`, unless it changed since it was read as `original`
+fn install(path: &Path, original: &str, edited: &str, temp: &Path) -> Result<(), String> {
+    if HostsFile::read(path)
+        .map_err(|err| err.to_string())?
+        .to_string()
+        != original
+    {
+        return Err(format!(
+            "{} changed while it was being edited, edits are kept in {}",
+            path.display(),
+            temp.display()
+        ));
+    }
+    let options = WriteOptions::new().backup(BackupPolicy::new());
+    for warning in HostsFile::parse(edited)
+        .write(path, &options)
+        .map_err(|err| err.to_string())?
+    {
+        eprintln!("hostfile: warning: {warning}");
+    }
+    Ok(())
+}
+
+/// Ask whether to edit invalid contents again. Returns `false` to discard them.
+fn prompt_edit_again() -> Result<bool, String> {
+    let stdin = io::stdin();
+    loop {
+        eprint!("What now? (e)dit again, e(x)it without saving: ");
+        io::stderr().flush().ok();
+        let mut answer = String::new();
+        let read = stdin
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|err| format!("Could not read standard input: {err}"))?;
+        match answer.trim() {
+            _ if read == 0 => return Ok(false),
+            "e" | "E" => return Ok(true),
+            "x" | "X" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// The program and arguments to run for `editor`, the value of `VISUAL` or `EDITOR`, which
+/// may include arguments such as `code --wait`
+fn editor_command(editor: Option<String>) -> (String, Vec<String>) {
+    let editor = editor.filter(|editor| !editor.trim().is_empty());
+    let mut words = editor
+        .as_deref()
+        .unwrap_or(DEFAULT_EDITOR)
+        .split_whitespace()
+        .map(String::from);
+    let program = words.next().unwrap_or_default();
+    (program, words.collect())
+}
+
+/// A new temporary file holding `contents`, named after `path` so editors can tell what it is
+fn create_temp(path: &Path, contents: &str) -> Result<PathBuf, String> {
+    let file_name = path.file_name().map_or_else(
+        || String::from("hosts"),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let dir = std::env::temp_dir();
+    for attempt in 0u32.. {
+        let temp = dir.join(format!(
+            "hostfile-edit-{}-{attempt}-{file_name}",
+            std::process::id()
+        ));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(format!("Could not create ({:?}): {err}", temp)),
+        };
+        file.write_all(contents.as_bytes())
+            .map_err(|err| format!("Could not write ({:?}): {err}", temp))?;
+        return Ok(temp);
+    }
+    unreachable!("ran out of temporary file names")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_from_environment() {
+        assert_eq!(
+            editor_command(Some(String::from("code --wait"))),
+            (String::from("code"), vec![String::from("--wait")])
+        );
+        assert_eq!(
+            editor_command(Some(String::from("  "))),
+            (String::from(DEFAULT_EDITOR), vec![])
+        );
+        assert_eq!(editor_command(None), (String::from(DEFAULT_EDITOR), vec![]));
+    }
+}
@@ -0,0 +1,241 @@
+use std::ops::Range;
+
+use clap::ValueEnum;
+use hostfile::{parse_lossless, TokenKind};
+use regex::{Regex, RegexBuilder};
+
+use crate::color::{Painter, Style};
+
+/// How the pattern of `grep` is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// Anywhere in the text
+    Substring,
+    /// The whole text, with `*` matching any run of characters and `?` any one character
+    Glob,
+    /// Anywhere in the text, as a regular expression
+    Regex,
+}
+
+/// A part of a line that `grep` searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Name,
+    Ip,
+    /// Comments, including commented out entries
+    Comment,
+}
+
+impl Field {
+    fn as_str(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Ip => "ip",
+            Field::Comment => "comment",
+        }
+    }
+}
+
+/// A pattern to search for, ignoring case like hostnames do
+pub struct Matcher {
+    mode: Mode,
+    pattern: String,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, mode: Mode) -> Result<Self, String> {
+        let regex = match mode {
+            Mode::Regex => Some(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| format!("Invalid regex: {err}"))?,
+            ),
+            _ => None,
+        };
+        Ok(Matcher {
+            mode,
+            pattern: pattern.to_lowercase(),
+            regex,
+        })
+    }
+
+    /// The part of `text` that matches, if any
+    fn find(&self, text: &str) -> Option<Range<usize>> {
+        match (self.mode, &self.regex) {
+            (Mode::Regex, Some(regex)) => regex.find(text).map(|found| found.range()),
+            (Mode::Glob, _) => {
+                glob_matches(&self.pattern, &text.to_lowercase()).then_some(0..text.len())
+            }
+            _ => {
+                // Lowercasing can change lengths outside of ASCII, so highlight the whole text then
+                let text_lower = text.to_lowercase();
+                if text_lower.len() == text.len() {
+                    let start = text_lower.find(&self.pattern)?;
+                    Some(start..start + self.pattern.len())
+                } else {
+                    text_lower.contains(&self.pattern).then_some(0..text.len())
+                }
+            }
+        }
+    }
+}
+
+/// Whether all of `text` matches the glob `pattern`
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, from)) => {
+                    p = star + 1;
+                    t = from + 1;
+                    backtrack = Some((star, from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// A line with parts that match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    /// 1-based line number
+    pub line: usize,
+    /// The line without its newline
+    pub text: String,
+    /// What matched, as byte ranges of `text`
+    pub matches: Vec<(Field, Range<usize>)>,
+}
+
+/// The lines of `source` where `fields` match `matcher`
+pub fn grep(source: &str, matcher: &Matcher, fields: &[Field]) -> Vec<LineMatch> {
+    let tree = parse_lossless(source);
+    let mut found = Vec::new();
+    for (idx, line) in tree.lines().iter().enumerate() {
+        let start = line.range.start;
+        let mut matches = Vec::new();
+        for token in &line.tokens {
+            let field = match token.kind {
+                TokenKind::Name => Field::Name,
+                TokenKind::Ip => Field::Ip,
+                TokenKind::Comment => Field::Comment,
+                _ => continue,
+            };
+            if !fields.contains(&field) {
+                continue;
+            }
+            if let Some(range) = matcher.find(tree.text(token)) {
+                let offset = token.range.start - start;
+                matches.push((field, offset + range.start..offset + range.end));
+            }
+        }
+        if !matches.is_empty() {
+            let text = &source[line.range.clone()];
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            found.push(LineMatch {
+                line: idx + 1,
+                text: text.strip_suffix('\r').unwrap_or(text).to_string(),
+                matches,
+            });
+        }
+    }
+    found
+}
+
+/// Print `found` like `grep -n` does, highlighting the matches
+pub fn print(found: &[LineMatch], painter: &Painter) {
+    for line in found {
+        let mut out = String::new();
+        let mut at = 0;
+        for (_, range) in &line.matches {
+            out.push_str(&line.text[at..range.start]);
+            out.push_str(&painter.paint(Style::Changed, &line.text[range.clone()]));
+            at = range.end;
+        }
+        out.push_str(&line.text[at..]);
+        println!(
+            "{}:{out}",
+            painter.paint(Style::Section, &line.line.to_string())
+        );
+    }
+}
+
+/// `found` as a JSON array with an object per line
+pub fn to_json(found: &[LineMatch]) -> String {
+    let lines: Vec<serde_json::Value> = found
+        .iter()
+        .map(|line| {
+            let matches: Vec<serde_json::Value> = line
+                .matches
+                .iter()
+                .map(|(field, range)| {
+                    serde_json::json!({
+                        "field": field.as_str(),
+                        "text": &line.text[range.clone()],
+                        "start": range.start,
+                        "end": range.end,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "line": line.line,
+                "text": line.text,
+                "matches": matches,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&lines).expect("JSON values always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grep_modes() {
+        let source = "127.0.0.1 localhost\n\
+                      10.0.0.1 web.corp WWW # the web server\r\n\
+                      # 10.0.0.2 web-old\n\
+                      10.0.0.3 db\n";
+        let names = [Field::Name, Field::Ip];
+        let lines = |pattern, mode, fields: &[Field]| -> Vec<usize> {
+            let matcher = Matcher::new(pattern, mode).unwrap();
+            grep(source, &matcher, fields)
+                .iter()
+                .map(|line| line.line)
+                .collect()
+        };
+        assert_eq!(lines("web", Mode::Substring, &names), [2]);
+        assert_eq!(lines("www", Mode::Substring, &names), [2]);
+        assert_eq!(lines("web", Mode::Substring, &[Field::Comment]), [2, 3]);
+        assert_eq!(lines("*.corp", Mode::Glob, &names), [2]);
+        assert_eq!(lines("w?b", Mode::Glob, &names), Vec::<usize>::new());
+        assert_eq!(lines("10.0.0.[13]$", Mode::Regex, &names), [2, 4]);
+
+        let matcher = Matcher::new("WEB", Mode::Substring).unwrap();
+        let found = grep(source, &matcher, &names);
+        assert_eq!(found[0].text, "10.0.0.1 web.corp WWW # the web server");
+        assert_eq!(found[0].matches, vec![(Field::Name, 9..12)]);
+        assert!(to_json(&found).contains("\"field\": \"name\""));
+
+        assert!(glob_matches("a*b*c", "aXbYbc"));
+        assert!(!glob_matches("a*b", "ab c"));
+        assert!(Matcher::new("(", Mode::Regex).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::process::Command;
+
+use hostfile::WatchEvent;
+
+use crate::color::{ColorChoice, Painter};
+
+/// Run `command` with the shell for a set of changes to the hosts file at `path`, waiting for
+/// it to finish.
+///
+/// The changes are described to the command in environment variables, see [`change_env`].
+pub fn run(command: &str, path: &Path, events: &[WatchEvent]) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(command)
+        .envs(change_env(path, events))
+        .status()
+        .map_err(|err| format!("Could not run `{command}`: {err}"))?;
+    if !status.success() {
+        return Err(format!("`{command}` failed ({status})"));
+    }
+    Ok(())
+}
+
+/// The environment variables describing `events`:
+///
+/// - `HOSTFILE_WATCH_PATH`: the hosts file
+/// - `HOSTFILE_ADDED`, `HOSTFILE_REMOVED` and `HOSTFILE_CHANGED`: the names that were added,
+///   removed or given other addresses, separated by spaces
+/// - `HOSTFILE_EVENTS`: the changes as `watch` prints them, one per line
+pub fn change_env(path: &Path, events: &[WatchEvent]) -> Vec<(&'static str, String)> {
+    let names = |pick: fn(&WatchEvent) -> bool| {
+        events
+            .iter()
+            .filter(|event| pick(event))
+            .map(WatchEvent::name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let painter = Painter::new(ColorChoice::Never);
+    vec![
+        ("HOSTFILE_WATCH_PATH", path.display().to_string()),
+        (
+            "HOSTFILE_ADDED",
+            names(|event| matches!(event, WatchEvent::EntryAdded { .. })),
+        ),
+        (
+            "HOSTFILE_REMOVED",
+            names(|event| matches!(event, WatchEvent::EntryRemoved { .. })),
+        ),
+        (
+            "HOSTFILE_CHANGED",
+            names(|event| matches!(event, WatchEvent::IpChanged { .. })),
+        ),
+        (
+            "HOSTFILE_EVENTS",
+            events
+                .iter()
+                .map(|event| painter.event(event))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hostfile::diff_entries;
+
+    #[test]
+    fn describe_changes() {
+        let old = vec![
+            "10.0.0.1 web".parse().unwrap(),
+            "10.0.0.2 db".parse().unwrap(),
+        ];
+        let new = vec![
+            "10.0.0.3 web".parse().unwrap(),
+            "10.0.0.4 cache".parse().unwrap(),
+        ];
+        let env = change_env(Path::new("/etc/hosts"), &diff_entries(&old, &new));
+        assert_eq!(
+            env,
+            vec![
+                ("HOSTFILE_WATCH_PATH", String::from("/etc/hosts")),
+                ("HOSTFILE_ADDED", String::from("cache")),
+                ("HOSTFILE_REMOVED", String::from("db")),
+                ("HOSTFILE_CHANGED", String::from("web")),
+                (
+                    "HOSTFILE_EVENTS",
+                    String::from("- db 10.0.0.2\n~ web 10.0.0.1 -> 10.0.0.3\n+ cache 10.0.0.4")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use hostfile::{
+    diff_entries, export_entries, lint_fix, lint_str_with, no_proxy_list, parse_lossless,
+    proxy_bypass_list, Allowlist, BackupPolicy, DesiredState, ExportFormat, HostEntry, HostsFile,
+    LintOptions, Severity, TokenKind, Watcher, WriteOptions,
+};
+
+use color::{ColorChoice, Painter, Style};
+
+mod color;
+mod doctor;
+mod edit;
+mod grep;
+mod hook;
+#[cfg(feature = "tui")]
+mod tui;
+
+/// Inspect and edit hosts files
+#[derive(Parser)]
+#[command(name = "hostfile", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// When to color output. `auto` colors terminals unless `NO_COLOR` is set.
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the entries of a hosts file as a table
+    List {
+        /// The hosts file, `-` for standard input, the system one by default
+        path: Option<PathBuf>,
+    },
+    /// Print the entries of a hosts file in another format
+    Export {
+        /// The hosts file, `-` for standard input, the system one by default
+        path: Option<PathBuf>,
+        #[arg(short, long, value_parser = export_format)]
+        format: ExportFormat,
+        /// Only export the entries of this section, either a header section or a managed one
+        #[arg(long)]
+        section: Option<String>,
+        /// Only export names matching this pattern, a name or a wildcard like `*.example.com`.
+        /// Can be given more than once.
+        #[arg(long = "match", value_name = "PATTERN")]
+        patterns: Vec<String>,
+        /// A domain to add to `no-proxy` and `proxy-bypass` lists along with its subdomains.
+        /// Can be given more than once.
+        #[arg(long = "suffix", value_name = "DOMAIN")]
+        suffixes: Vec<String>,
+    },
+    /// Print the lines of a hosts file whose names or addresses match a pattern, failing if
+    /// none do
+    Grep {
+        pattern: String,
+        /// The hosts file, `-` for standard input, the system one by default
+        path: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = grep::Mode::Substring)]
+        mode: grep::Mode,
+        /// What to search. Can be given more than once. Names and addresses by default.
+        #[arg(long = "in", value_enum, value_name = "FIELD")]
+        fields: Vec<grep::Field>,
+        /// Print the matches as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Normalize the spacing of a hosts file, keeping comments and invalid lines
+    Fmt {
+        /// The hosts file, `-` for standard input, the system one by default
+        path: Option<PathBuf>,
+        /// Where to write the result, `-` for standard output. Files are formatted in place
+        /// and standard input is written to standard output by default.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Report problems in a hosts file, failing if any are errors
+    Check {
+        /// The hosts file, `-` for standard input, the system one by default
+        path: Option<PathBuf>,
+        /// Fix what can be fixed mechanically, writing standard input to standard output
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Change a hosts file to match the desired state declared in a TOML file, printing what
+    /// changed
+    Apply {
+        /// The TOML file declaring the entries, removals and managed sections
+        config: PathBuf,
+        /// The hosts file to change, the system one by default
+        #[arg(long)]
+        hosts: Option<PathBuf>,
+        /// Print what would change without writing the hosts file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Explain how this machine resolves names and what is wrong with its hosts file
+    Doctor {
+        /// The hosts file, the system one by default
+        path: Option<PathBuf>,
+    },
+    /// Print the names whose addresses differ between two hosts files
+    Diff {
+        /// The old hosts file, `-` for standard input
+        old: PathBuf,
+        /// The new hosts file, `-` for standard input
+        new: PathBuf,
+    },
+    /// Print changes to a hosts file as they happen, until interrupted
+    Watch {
+        /// The hosts file, the system one by default
+        path: Option<PathBuf>,
+        /// Seconds between checks of the file
+        #[arg(short, long, default_value_t = 1.0)]
+        interval: f64,
+        /// A shell command to run after each set of changes. `HOSTFILE_ADDED`,
+        /// `HOSTFILE_REMOVED` and `HOSTFILE_CHANGED` hold the names that changed, and
+        /// `HOSTFILE_EVENTS` the changes as printed.
+        #[arg(short = 'x', long, value_name = "COMMAND")]
+        exec: Option<String>,
+    },
+    /// Edit a hosts file in `$VISUAL` or `$EDITOR`, installing it only if it is valid
+    Edit {
+        /// The hosts file, the system one by default
+        path: Option<PathBuf>,
+    },
+    /// Edit a hosts file interactively
+    #[cfg(feature = "tui")]
+    Tui {
+        /// The hosts file, the system one by default
+        path: Option<PathBuf>,
+    },
+}
+
+fn export_format(format: &str) -> Result<ExportFormat, String> {
+    format.parse().map_err(|err| {
+        let formats: Vec<&str> = ExportFormat::ALL.iter().map(ExportFormat::as_str).collect();
+        format!("{err}, expected one of {}", formats.join(", "))
+    })
+}
+
+/// The entries of `contents` to export: those in `section` if it is given, with only the
+/// names matching `patterns` if there are any
+fn export_selection(
+    contents: &str,
+    section: Option<&str>,
+    patterns: &[String],
+) -> Result<Vec<HostEntry>, String> {
+    let file = HostsFile::parse(contents);
+    let mut entries = match section {
+        Some(name) => file
+            .section(name)
+            .map(|section| section.entries())
+            .or_else(|| file.managed_section(name))
+            .ok_or_else(|| format!("There is no section named {name}"))?,
+        None => file.entries().collect(),
+    };
+    if !patterns.is_empty() {
+        let allowlist: Allowlist = patterns.iter().collect();
+        entries = entries
+            .into_iter()
+            .filter_map(|mut entry| {
+                entry.names.retain(|name| allowlist.is_allowed(name));
+                (!entry.names.is_empty()).then_some(entry)
+            })
+            .collect();
+    }
+    Ok(entries)
+}
+
+/// `path`, or the system hosts file if it isn't given
+fn hosts_path(path: Option<PathBuf>) -> Result<PathBuf, String> {
+    match path {
+        Some(path) => Ok(path),
+        None => hostfile::get_hostfile_path().map_err(|err| err.to_string()),
+    }
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// The name of `path` in messages
+fn display_name(path: &Path) -> String {
+    if is_stdio(path) {
+        String::from("<stdin>")
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// Read the hosts file at `path`, or standard input if it is `-`
+fn read_input(path: &Path) -> Result<String, String> {
+    if !is_stdio(path) {
+        return HostsFile::read(path)
+            .map(|file| file.to_string())
+            .map_err(|err| err.to_string());
+    }
+    let mut contents = String::new();
+    io::stdin()
+        .read_to_string(&mut contents)
+        .map_err(|err| format!("Could not read standard input: {err}"))?;
+    Ok(contents)
+}
+
+/// Write `contents` to the file at `path`, or standard output if it is `-`
+fn write_output(path: &Path, contents: &str) -> Result<(), String> {
+    if is_stdio(path) {
+        return io::stdout()
+            .write_all(contents.as_bytes())
+            .map_err(|err| format!("Could not write to standard output: {err}"));
+    }
+    let warnings = HostsFile::parse(contents)
+        .write(path, &hostfile::WriteOptions::new())
+        .map_err(|err| err.to_string())?;
+    for warning in warnings {
+        eprintln!("hostfile: warning: {warning}");
+    }
+    Ok(())
+}
+
+/// `source` with one tab after each address, single spaces between names and before
+/// trailing comments, and no trailing whitespace. Lines that don't parse are kept as is.
+fn format(source: &str) -> String {
+    let tree = parse_lossless(source);
+    let mut out = String::with_capacity(source.len());
+    for line in tree.lines() {
+        let newline = line
+            .tokens
+            .iter()
+            .find(|token| token.kind == TokenKind::Newline)
+            .map_or("", |token| tree.text(token));
+        if line.error().is_some() {
+            out.push_str(&source[line.range.clone()]);
+            continue;
+        }
+
+        let mut words = Vec::new();
+        if let Some(ip) = line.ip() {
+            words.push(tree.text(ip).to_string());
+            let names: Vec<&str> = line.names().map(|name| tree.text(name)).collect();
+            words.push(names.join(" "));
+        }
+        if let Some(comment) = line.comment() {
+            words.push(tree.text(comment).trim_end().to_string());
+        }
+        match words.as_slice() {
+            [ip, names, rest @ ..] => {
+                out.push_str(&format!("{ip}\t{names}"));
+                for comment in rest {
+                    out.push_str(&format!(" {comment}"));
+                }
+            }
+            [comment] => out.push_str(comment),
+            _ => {}
+        }
+        out.push_str(newline);
+    }
+    out
+}
+
+/// The rows of the `list` table for the enabled entries of `contents`: the address, the
+/// canonical name, the aliases and the section the entry is in
+fn list_rows(contents: &str) -> Vec<Vec<String>> {
+    let file = HostsFile::parse(contents);
+    let sections = file.sections();
+    (0..file.line_count())
+        .filter_map(|idx| {
+            let (entry, enabled) = file.line_entry(idx)?;
+            if !enabled {
+                return None;
+            }
+            let section = sections
+                .iter()
+                .find(|section| section.lines().contains(&idx))
+                .map_or("", |section| section.name());
+            let (name, aliases) = entry.names.split_first()?;
+            Some(vec![
+                entry.ip.to_string(),
+                name.clone(),
+                aliases.join(" "),
+                section.to_string(),
+            ])
+        })
+        .collect()
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let painter = Painter::new(cli.color);
+    match cli.command {
+        Command::List { path } => {
+            let contents = read_input(&hosts_path(path)?)?;
+            // Fail on invalid lines rather than leaving them out of the table
+            hostfile::parse_bytes_borrowed(contents.as_bytes()).map_err(|err| err.to_string())?;
+            painter.print_table(
+                &["ADDRESS", "NAME", "ALIASES", "SECTION"],
+                &[Style::Address, Style::Name, Style::Alias, Style::Section],
+                &list_rows(&contents),
+            );
+            Ok(())
+        }
+        Command::Export {
+            path,
+            format,
+            section,
+            patterns,
+            suffixes,
+        } => {
+            let contents = read_input(&hosts_path(path)?)?;
+            let entries = export_selection(&contents, section.as_deref(), &patterns)?;
+            let suffixes: Vec<&str> = suffixes.iter().map(String::as_str).collect();
+            match format {
+                ExportFormat::NoProxy => println!("{}", no_proxy_list(&entries, &suffixes)),
+                ExportFormat::ProxyBypass => {
+                    println!("{}", proxy_bypass_list(&entries, &suffixes))
+                }
+                _ if !suffixes.is_empty() => {
+                    return Err(String::from(
+                        "--suffix only applies to no-proxy and proxy-bypass",
+                    ))
+                }
+                _ => print!("{}", export_entries(&entries, format)),
+            }
+            Ok(())
+        }
+        Command::Grep {
+            pattern,
+            path,
+            mode,
+            mut fields,
+            json,
+        } => {
+            if fields.is_empty() {
+                fields = vec![grep::Field::Name, grep::Field::Ip];
+            }
+            let matcher = grep::Matcher::new(&pattern, mode)?;
+            let found = grep::grep(&read_input(&hosts_path(path)?)?, &matcher, &fields);
+            if json {
+                println!("{}", grep::to_json(&found));
+            } else {
+                grep::print(&found, &painter);
+            }
+            if found.is_empty() {
+                return Err(String::from("no matches"));
+            }
+            Ok(())
+        }
+        Command::Fmt { path, output } => {
+            let path = hosts_path(path)?;
+            let formatted = format(&read_input(&path)?);
+            write_output(&output.unwrap_or(path), &formatted)
+        }
+        Command::Check { path, fix } => {
+            // Snippets of hosts files don't need a localhost entry
+            let options = LintOptions::new().check_localhost(path.is_none());
+            let path = hosts_path(path)?;
+            let name = display_name(&path);
+            let mut contents = read_input(&path)?;
+            // Keep standard output for the fixed file
+            let report = |line: String| {
+                if fix && is_stdio(&path) {
+                    eprintln!("{line}");
+                } else {
+                    println!("{line}");
+                }
+            };
+            if fix {
+                let (fixed, findings) = lint_fix(&contents);
+                for finding in &findings {
+                    report(format!(
+                        "{name}:{}:{}: {}: {} [{}]",
+                        finding.line,
+                        finding.column(),
+                        painter.paint(Style::Added, "fixed"),
+                        finding.message,
+                        finding.rule.id()
+                    ));
+                }
+                if is_stdio(&path) || !findings.is_empty() {
+                    write_output(&path, &fixed)?;
+                }
+                contents = fixed;
+            }
+
+            let findings = lint_str_with(&contents, &options);
+            for finding in &findings {
+                report(format!(
+                    "{name}:{}:{}: {}: {} [{}]",
+                    finding.line,
+                    finding.column(),
+                    painter.paint(
+                        Style::Severity(finding.severity),
+                        &finding.severity.to_string()
+                    ),
+                    finding.message,
+                    finding.rule.id()
+                ));
+            }
+            match findings
+                .iter()
+                .filter(|finding| finding.severity == Severity::Error)
+                .count()
+            {
+                0 => Ok(()),
+                1 => Err(format!("{name} has 1 error")),
+                errors => Err(format!("{name} has {errors} errors")),
+            }
+        }
+        Command::Apply {
+            config,
+            hosts,
+            dry_run,
+        } => {
+            let state = DesiredState::read(&config).map_err(|err| err.to_string())?;
+            let path = hosts_path(hosts)?;
+            if is_stdio(&path) {
+                return Err(String::from(
+                    "apply can only change files, not standard input",
+                ));
+            }
+            let mut file = HostsFile::read(&path).map_err(|err| err.to_string())?;
+            let original = file.clone();
+            for event in file.apply_state(&state).map_err(|err| err.to_string())? {
+                println!("{}", painter.event(&event));
+            }
+            if !dry_run && file != original {
+                let options = WriteOptions::new().backup(BackupPolicy::new());
+                for warning in file.write(&path, &options).map_err(|err| err.to_string())? {
+                    eprintln!("hostfile: warning: {warning}");
+                }
+            }
+            Ok(())
+        }
+        Command::Doctor { path } => {
+            if path.as_deref().is_some_and(is_stdio) {
+                return Err(String::from(
+                    "doctor can only check files, not standard input",
+                ));
+            }
+            doctor::run(path.as_deref(), &painter)
+        }
+        Command::Diff { old, new } => {
+            if is_stdio(&old) && is_stdio(&new) {
+                return Err(String::from(
+                    "Only one side of a diff can be standard input",
+                ));
+            }
+            let old = HostsFile::parse(&read_input(&old)?)
+                .entries()
+                .collect::<Vec<_>>();
+            let new = HostsFile::parse(&read_input(&new)?)
+                .entries()
+                .collect::<Vec<_>>();
+            for event in diff_entries(&old, &new) {
+                println!("{}", painter.event(&event));
+            }
+            Ok(())
+        }
+        Command::Watch {
+            path,
+            interval,
+            exec,
+        } => {
+            let path = hosts_path(path)?;
+            if is_stdio(&path) {
+                return Err(String::from(
+                    "watch can only watch files, not standard input",
+                ));
+            }
+            let interval = Duration::try_from_secs_f64(interval)
+                .map_err(|err| format!("Invalid interval {interval}: {err}"))?;
+            let mut watcher = Watcher::new(&path).map_err(|err| err.to_string())?;
+            loop {
+                std::thread::sleep(interval);
+                let events = watcher.poll().map_err(|err| err.to_string())?;
+                for event in &events {
+                    println!("{}", painter.event(event));
+                }
+                if let (Some(command), false) = (&exec, events.is_empty()) {
+                    // A failing command shouldn't stop the watch
+                    if let Err(err) = hook::run(command, &path, &events) {
+                        eprintln!("hostfile: {err}");
+                    }
+                }
+            }
+        }
+        Command::Edit { path } => {
+            let path = hosts_path(path)?;
+            if is_stdio(&path) {
+                return Err(String::from("edit can only edit files, not standard input"));
+            }
+            edit::run(&path)
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui { path } => {
+            let path = hosts_path(path)?;
+            if is_stdio(&path) {
+                return Err(String::from("tui can only edit files, not standard input"));
+            }
+            tui::run(&path)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("hostfile: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_lines() {
+        let source = "  127.0.0.1   localhost\tloopback   #  local  \r\n\
+                      \t# comment \n\
+                      \n\
+                      10.0.0.1\tweb\n\
+                      not an entry  \n\
+                      ::1 ip6-localhost";
+        assert_eq!(
+            format(source),
+            "127.0.0.1\tlocalhost loopback #  local\r\n\
+             # comment\n\
+             \n\
+             10.0.0.1\tweb\n\
+             not an entry  \n\
+             ::1\tip6-localhost"
+        );
+    }
+
+    #[test]
+    fn export_filters() {
+        let source = "127.0.0.1 localhost\n\
+                      ## work ##\n\
+                      10.0.0.1 web.corp.example wiki\n\
+                      10.0.0.2 mail\n";
+        let patterns = [String::from("*.example"), String::from("localhost")];
+        assert_eq!(
+            export_selection(source, None, &patterns),
+            Ok(vec![
+                "127.0.0.1 localhost".parse().unwrap(),
+                "10.0.0.1 web.corp.example".parse().unwrap(),
+            ])
+        );
+        assert_eq!(
+            export_selection(source, Some("work"), &[]).unwrap().len(),
+            2
+        );
+        assert!(export_selection(source, Some("home"), &[]).is_err());
+    }
+
+    #[test]
+    fn list_rows_with_sections() {
+        let source = "127.0.0.1 localhost\n\
+                      ## work ##\n\
+                      10.0.0.1 web web.corp www\n\
+                      # 10.0.0.2 old\n";
+        let rows = list_rows(source);
+        assert_eq!(
+            rows,
+            vec![
+                vec!["127.0.0.1", "localhost", "", ""],
+                vec!["10.0.0.1", "web", "web.corp www", "work"],
+            ]
+        );
+    }
+}
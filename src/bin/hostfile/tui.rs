@@ -0,0 +1,389 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use hostfile::{HostEntry, HostsFile, LineId, WriteOptions};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+const HELP: &str =
+    "/ search  space toggle  a add  d delete  u undo  r redo  v diff  s save  q quit";
+
+/// Diffs with more lines than this are shown as a plain removal and addition, since they
+/// take quadratic time
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Search,
+    Add,
+    Diff,
+}
+
+struct App {
+    path: PathBuf,
+    file: HostsFile,
+    /// The contents of the file as last read or saved
+    saved: String,
+    mode: Mode,
+    query: String,
+    /// Text of the add form
+    input: String,
+    /// Lines with an entry that match the query
+    rows: Vec<LineId>,
+    list: ListState,
+    diff_scroll: u16,
+    status: String,
+    /// Whether `q` was pressed once with unsaved changes
+    confirm_quit: bool,
+}
+
+/// Edit the hosts file at `path` until the user quits
+pub fn run(path: &Path) -> Result<(), String> {
+    let file = HostsFile::read(path).map_err(|err| err.to_string())?;
+    let mut app = App {
+        path: path.to_path_buf(),
+        saved: file.to_string(),
+        file,
+        mode: Mode::Browse,
+        query: String::new(),
+        input: String::new(),
+        rows: Vec::new(),
+        list: ListState::default(),
+        diff_scroll: 0,
+        status: String::from(HELP),
+        confirm_quit: false,
+    };
+    app.refresh_rows();
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result.map_err(|err| format!("Terminal error: {err}"))
+}
+
+impl App {
+    fn modified(&self) -> bool {
+        self.file.to_string() != self.saved
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// The entries shown in the list, in file order
+    fn refresh_rows(&mut self) {
+        let query = self.query.to_ascii_lowercase();
+        self.rows = (0..self.file.line_count())
+            .filter(|&index| match self.file.line_entry(index) {
+                Some((entry, _)) => query.is_empty() || matches_query(&entry, &query),
+                None => false,
+            })
+            .filter_map(|index| self.file.line_id(index))
+            .collect();
+        let selected = match self.list.selected() {
+            Some(_) if self.rows.is_empty() => None,
+            Some(selected) => Some(selected.min(self.rows.len() - 1)),
+            None if self.rows.is_empty() => None,
+            None => Some(0),
+        };
+        self.list.select(selected);
+    }
+
+    fn selected(&self) -> Option<LineId> {
+        self.list
+            .selected()
+            .and_then(|idx| self.rows.get(idx).copied())
+    }
+
+    /// Handle a key press, returning whether to keep running
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let quit_pressed = self.mode == Mode::Browse && matches!(key.code, KeyCode::Char('q'));
+        match self.mode {
+            Mode::Browse => {
+                if !self.browse_key(key.code) {
+                    return false;
+                }
+            }
+            Mode::Search => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Char(c) => self.query.push(c),
+                _ => {}
+            },
+            Mode::Add => match key.code {
+                KeyCode::Enter => self.add_entry(),
+                KeyCode::Esc => {
+                    self.input.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            },
+            Mode::Diff => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('v') => self.mode = Mode::Browse,
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.diff_scroll = self.diff_scroll.saturating_add(1)
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.diff_scroll = self.diff_scroll.saturating_sub(1)
+                }
+                _ => {}
+            },
+        }
+        if !quit_pressed {
+            self.confirm_quit = false;
+        }
+        self.refresh_rows();
+        true
+    }
+
+    fn browse_key(&mut self, code: KeyCode) -> bool {
+        self.status = String::from(HELP);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if !self.modified() || self.confirm_quit {
+                    return false;
+                }
+                self.confirm_quit = true;
+                self.status = String::from("Unsaved changes, press q again to quit");
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Char('a') => self.mode = Mode::Add,
+            KeyCode::Char('v') => {
+                self.diff_scroll = 0;
+                self.mode = Mode::Diff;
+            }
+            KeyCode::Char(' ') => {
+                if let Some(id) = self.selected() {
+                    let enabled = self
+                        .file
+                        .line_index(id)
+                        .and_then(|index| self.file.line_entry(index))
+                        .is_some_and(|(_, enabled)| enabled);
+                    let result = if enabled {
+                        self.file.disable_entry(id)
+                    } else {
+                        self.file.enable_entry(id)
+                    };
+                    self.report(result);
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(id) = self.selected() {
+                    let result = self.file.remove_line(id);
+                    self.report(result);
+                }
+            }
+            KeyCode::Char('u') if !self.file.undo() => {
+                self.status = String::from("Nothing to undo");
+            }
+            KeyCode::Char('r') if !self.file.redo() => {
+                self.status = String::from("Nothing to redo");
+            }
+            KeyCode::Char('s') => self.save(),
+            _ => {}
+        }
+        true
+    }
+
+    fn report<T, E: ToString>(&mut self, result: Result<T, E>) {
+        if let Err(err) = result {
+            self.status = err.to_string();
+        }
+    }
+
+    fn add_entry(&mut self) {
+        match self.input.parse::<HostEntry>() {
+            Ok(entry) => {
+                let result = self.file.add_entry(&entry);
+                self.report(result);
+                self.input.clear();
+                self.mode = Mode::Browse;
+                self.list.select(Some(self.file.line_count()));
+            }
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    fn save(&mut self) {
+        match self.file.write(&self.path, &WriteOptions::new()) {
+            Ok(warnings) => {
+                self.saved = self.file.to_string();
+                self.status = match warnings.first() {
+                    Some(warning) => format!("Saved, but {warning}"),
+                    None => format!("Saved {}", self.path.display()),
+                };
+            }
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let title = format!(
+            " {}{} ",
+            self.path.display(),
+            if self.modified() { " [modified]" } else { "" }
+        );
+
+        if self.mode == Mode::Diff {
+            let lines: Vec<Line> = diff_lines(&self.saved, &self.file.to_string())
+                .into_iter()
+                .map(|(tag, text)| {
+                    let style = match tag {
+                        '+' => Style::new().fg(Color::Green),
+                        '-' => Style::new().fg(Color::Red),
+                        _ => Style::new().add_modifier(Modifier::DIM),
+                    };
+                    Line::styled(format!("{tag} {text}"), style)
+                })
+                .collect();
+            let diff = Paragraph::new(lines)
+                .block(Block::bordered().title(format!("{title}- unsaved changes ")))
+                .scroll((self.diff_scroll, 0));
+            frame.render_widget(diff, main);
+            frame.render_widget(Paragraph::new("j/k scroll  esc back"), footer);
+            return;
+        }
+
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .filter_map(|&id| self.file.line_entry(self.file.line_index(id)?))
+            .map(|(entry, enabled)| {
+                let (mark, style) = if enabled {
+                    ("[x]", Style::new())
+                } else {
+                    ("[ ]", Style::new().add_modifier(Modifier::DIM))
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{mark} ")),
+                    Span::styled(format!("{:<40}", entry.ip.to_string()), style),
+                    Span::styled(entry.names.join(" "), style),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, main, &mut self.list);
+
+        let footer_text = match self.mode {
+            Mode::Search => format!("/{}", self.query),
+            Mode::Add => format!("add (ip names...): {}", self.input),
+            _ => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+}
+
+/// Whether the address or any name of `entry` contains `query`, which is lowercase
+fn matches_query(entry: &HostEntry, query: &str) -> bool {
+    entry.ip.to_string().contains(query)
+        || entry
+            .names
+            .iter()
+            .any(|name| name.to_ascii_lowercase().contains(query))
+}
+
+/// A line diff from `old` to `new`, tagging each line with `' '`, `'-'` or `'+'`
+fn diff_lines(old: &str, new: &str) -> Vec<(char, String)> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut diff: Vec<(char, String)> = old[..prefix]
+        .iter()
+        .map(|line| (' ', line.to_string()))
+        .collect();
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        diff.extend(a.iter().map(|line| ('-', line.to_string())));
+        diff.extend(b.iter().map(|line| ('+', line.to_string())));
+    } else {
+        // Longest common subsequence of the changed middle, from the end
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                diff.push((' ', a[i].to_string()));
+                i += 1;
+                j += 1;
+            } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                diff.push(('+', b[j].to_string()));
+                j += 1;
+            } else {
+                diff.push(('-', a[i].to_string()));
+                i += 1;
+            }
+        }
+    }
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| (' ', line.to_string())),
+    );
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_of_edits() {
+        let old = "# hosts\n127.0.0.1 localhost\n10.0.0.1 web\n10.0.0.2 db\n";
+        let new = "# hosts\n127.0.0.1 localhost\n# 10.0.0.1 web\n10.0.0.2 db\n10.0.0.3 api\n";
+        assert_eq!(
+            diff_lines(old, new),
+            vec![
+                (' ', String::from("# hosts")),
+                (' ', String::from("127.0.0.1 localhost")),
+                ('+', String::from("# 10.0.0.1 web")),
+                ('-', String::from("10.0.0.1 web")),
+                (' ', String::from("10.0.0.2 db")),
+                ('+', String::from("10.0.0.3 api")),
+            ]
+        );
+    }
+}
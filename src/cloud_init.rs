@@ -0,0 +1,110 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How cloud-init regenerates `/etc/hosts`, from its `manage_etc_hosts` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManageEtcHosts {
+    /// `true` or `template`: the file is rendered from `/etc/cloud/templates/hosts.*.tmpl`
+    Template,
+    /// `localhost`: an entry for the local hostname is added or updated
+    Localhost,
+}
+
+impl fmt::Display for ManageEtcHosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ManageEtcHosts::Template => "template",
+            ManageEtcHosts::Localhost => "localhost",
+        })
+    }
+}
+
+/// Check whether cloud-init is configured to regenerate `/etc/hosts` on boot.
+///
+/// This reads `manage_etc_hosts` from cloud-init's system config and the user data of the
+/// current instance, later settings overriding earlier ones as they do in cloud-init. It
+/// returns `None` if cloud-init isn't installed or leaves the file alone.
+pub fn cloud_init_manages_etc_hosts() -> Option<ManageEtcHosts> {
+    manage_etc_hosts(Path::new("/"))
+}
+
+/// [`cloud_init_manages_etc_hosts`] for the system rooted at `root`
+pub(crate) fn manage_etc_hosts(root: &Path) -> Option<ManageEtcHosts> {
+    let mut configs = vec![root.join("etc/cloud/cloud.cfg")];
+    if let Ok(entries) = fs::read_dir(root.join("etc/cloud/cloud.cfg.d")) {
+        let mut drop_ins: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "cfg"))
+            .collect();
+        drop_ins.sort();
+        configs.extend(drop_ins);
+    }
+    configs.push(root.join("var/lib/cloud/instance/cloud-config.txt"));
+
+    let mut setting = None;
+    for config in configs {
+        if let Ok(contents) = fs::read_to_string(config) {
+            if let Some(value) = find_setting(&contents) {
+                setting = value;
+            }
+        }
+    }
+    setting
+}
+
+/// The `manage_etc_hosts` setting in a cloud-config file, if it is set.
+///
+/// Only top level `key: value` lines are considered, which is how the setting is written in
+/// practice, so this avoids parsing YAML.
+fn find_setting(contents: &str) -> Option<Option<ManageEtcHosts>> {
+    contents.lines().rev().find_map(|line| {
+        let value = line.strip_prefix("manage_etc_hosts:")?;
+        let value = value.split('#').next().unwrap_or_default().trim();
+        let value = value.trim_matches(|c| c == '"' || c == '\'');
+        Some(match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "template" => Some(ManageEtcHosts::Template),
+            "localhost" => Some(ManageEtcHosts::Localhost),
+            _ => None,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use super::*;
+
+    #[test]
+    fn later_configs_override() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let root = temp_dir.as_path();
+        assert_eq!(manage_etc_hosts(root), None);
+
+        fs::create_dir_all(root.join("etc/cloud/cloud.cfg.d")).unwrap();
+        fs::write(
+            root.join("etc/cloud/cloud.cfg"),
+            "users:\n  - default\nmanage_etc_hosts: true # comment\n",
+        )
+        .unwrap();
+        assert_eq!(manage_etc_hosts(root), Some(ManageEtcHosts::Template));
+
+        fs::write(
+            root.join("etc/cloud/cloud.cfg.d/99-local.cfg"),
+            "manage_etc_hosts: 'localhost'\n",
+        )
+        .unwrap();
+        assert_eq!(manage_etc_hosts(root), Some(ManageEtcHosts::Localhost));
+
+        fs::create_dir_all(root.join("var/lib/cloud/instance")).unwrap();
+        fs::write(
+            root.join("var/lib/cloud/instance/cloud-config.txt"),
+            "#cloud-config\nmanage_etc_hosts: false\n",
+        )
+        .unwrap();
+        assert_eq!(manage_etc_hosts(root), None);
+    }
+}
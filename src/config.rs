@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, HashSet};
+#[cfg(feature = "config")]
+use std::path::Path;
+
+#[cfg(feature = "config")]
+use serde::Deserialize;
+
+#[cfg(feature = "config")]
+use crate::{get_hostfile_path, read_file, BackupPolicy, HostsFile, WatchEvent, WriteOptions};
+use crate::{hostname_key, HostEntry, HostfileError};
+
+/// What a hosts file should contain, see [`crate::HostsFile::apply_state`].
+///
+/// With the `config` feature, it can be declared in a TOML file like this one:
+///
+/// ```toml
+/// # Entries that must be in the file. Each name resolves to exactly the addresses listed
+/// # for it here.
+/// entries = ["10.0.0.1 web www", "fd00::1 web"]
+///
+/// # Names that must not be in the file
+/// remove = ["old.example.org"]
+///
+/// # Managed sections and their entries, see `HostsFile::set_managed_section`
+/// [sections]
+/// lab = ["10.0.1.1 switch", "10.0.1.2 printer"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DesiredState {
+    pub entries: Vec<HostEntry>,
+    pub remove: Vec<String>,
+    pub sections: BTreeMap<String, Vec<HostEntry>>,
+}
+
+/// The TOML form of a [`DesiredState`]
+#[cfg(feature = "config")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    entries: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    #[serde(default)]
+    sections: BTreeMap<String, Vec<String>>,
+}
+
+impl DesiredState {
+    /// Parse the TOML form of a desired state, see [`DesiredState`]
+    #[cfg(feature = "config")]
+    pub fn parse_toml(contents: &str) -> Result<Self, HostfileError> {
+        let config: Config =
+            toml::from_str(contents).map_err(|err| format!("Invalid config: {err}"))?;
+        let parse_entries = |lines: Vec<String>| -> Result<Vec<HostEntry>, HostfileError> {
+            lines
+                .iter()
+                .map(|line| {
+                    line.parse().map_err(|err| {
+                        HostfileError::from(format!("Invalid entry '{line}' in config: {err}"))
+                    })
+                })
+                .collect()
+        };
+        let state = DesiredState {
+            entries: parse_entries(config.entries)?,
+            remove: config.remove,
+            sections: config
+                .sections
+                .into_iter()
+                .map(|(name, lines)| Ok((name, parse_entries(lines)?)))
+                .collect::<Result<_, HostfileError>>()?,
+        };
+        state.check()?;
+        Ok(state)
+    }
+
+    /// Read the TOML file at `path`, see [`DesiredState::parse_toml`]
+    #[cfg(feature = "config")]
+    pub fn read(path: &Path) -> Result<Self, HostfileError> {
+        Self::parse_toml(&read_file(path)?)
+    }
+
+    /// Check that no name is declared in more than one place, which could never be applied
+    /// without undoing itself
+    pub(crate) fn check(&self) -> Result<(), HostfileError> {
+        let mut places: Vec<(String, String)> = Vec::new();
+        let mut add = |names: &mut dyn Iterator<Item = &String>, place: String| {
+            let names: HashSet<String> =
+                names.map(|name| hostname_key(name).into_owned()).collect();
+            for name in names {
+                if let Some((_, other)) = places.iter().find(|(seen, _)| *seen == name) {
+                    return Err(format!("{name} is in both {other} and {place}"));
+                }
+                places.push((name, place.clone()));
+            }
+            Ok(())
+        };
+        add(
+            &mut self.entries.iter().flat_map(|entry| &entry.names),
+            String::from("entries"),
+        )?;
+        add(&mut self.remove.iter(), String::from("remove"))?;
+        for (section, entries) in &self.sections {
+            add(
+                &mut entries.iter().flat_map(|entry| &entry.names),
+                format!("section {section}"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Reconcile the system hosts file with the desired state in the TOML file at `path`, see
+/// [`HostsFile::apply_state`]. Returns how its entries changed.
+///
+/// The file is only written if it changed, keeping a backup of the old one.
+#[cfg(feature = "config")]
+pub fn apply_config(path: &Path) -> Result<Vec<WatchEvent>, HostfileError> {
+    let state = DesiredState::read(path)?;
+    let hosts_path = get_hostfile_path()?;
+    let mut file = HostsFile::read(&hosts_path)?;
+    let original = file.clone();
+    let events = file.apply_state(&state)?;
+    if file != original {
+        file.write(
+            &hosts_path,
+            &WriteOptions::new().backup(BackupPolicy::new()),
+        )?;
+    }
+    Ok(events)
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_is_idempotent() {
+        let state = DesiredState::parse_toml(
+            r#"
+            entries = ["10.0.0.1 web www", "fd00::1 web", "10.0.0.5 nas"]
+            remove = ["old"]
+
+            [sections]
+            lab = ["10.0.1.1 switch"]
+            "#,
+        )
+        .unwrap();
+        let mut file = HostsFile::parse(
+            "# my hosts\n\
+             127.0.0.1 localhost\n\
+             10.0.0.9 web old # moved\n\
+             10.0.0.5 nas\n",
+        );
+
+        let events = file.apply_state(&state).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "# my hosts\n\
+             127.0.0.1 localhost\n\
+             10.0.0.5 nas\n\
+             # BEGIN hostfile section lab\n\
+             10.0.1.1\tswitch\n\
+             # END hostfile section lab\n\
+             10.0.0.1\tweb www\n\
+             fd00::1\tweb\n"
+        );
+        assert_eq!(events.len(), 4);
+        assert!(file.undo());
+        assert!(!file.can_undo());
+        assert!(file.redo());
+
+        let again = file.clone();
+        assert_eq!(file.apply_state(&state), Ok(Vec::new()));
+        assert_eq!(file, again);
+
+        let err = DesiredState::parse_toml("entries = [\"10.0.0.1 web\"]\nremove = [\"WEB\"]\n");
+        assert_eq!(
+            err,
+            Err(HostfileError::from("web is in both entries and remove"))
+        );
+        let conflicting = DesiredState {
+            entries: vec!["10.0.0.1 a".parse().unwrap()],
+            sections: [(String::from("s"), vec!["10.0.0.2 A".parse().unwrap()])].into(),
+            ..DesiredState::default()
+        };
+        assert_eq!(
+            file.apply_state(&conflicting),
+            Err(HostfileError::from("a is in both entries and section s"))
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::net::IpAddr;
+use std::ops::{Deref, DerefMut};
+
+use crate::{hostname_key, HostEntry, HostfileError, IpEquivalence};
+
+impl TryFrom<&str> for HostEntry {
+    type Error = HostfileError;
+
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        line.parse()
+    }
+}
+
+impl From<(IpAddr, Vec<String>)> for HostEntry {
+    fn from((ip, names): (IpAddr, Vec<String>)) -> Self {
+        HostEntry { ip, names }
+    }
+}
+
+impl From<(IpAddr, String)> for HostEntry {
+    fn from((ip, name): (IpAddr, String)) -> Self {
+        HostEntry {
+            ip,
+            names: vec![name],
+        }
+    }
+}
+
+impl From<HostEntry> for (IpAddr, Vec<String>) {
+    fn from(entry: HostEntry) -> Self {
+        (entry.ip, entry.names)
+    }
+}
+
+/// One `(ip, name)` pair per name of the entry
+impl From<HostEntry> for Vec<(IpAddr, String)> {
+    fn from(entry: HostEntry) -> Self {
+        let ip = entry.ip;
+        entry.names.into_iter().map(|name| (ip, name)).collect()
+    }
+}
+
+/// Entries built from `(ip, name)` pairs, with one entry per distinct IP.
+///
+/// Entries are ordered by the first occurrence of their IP, and their names keep the order of
+/// the pairs. Repeated pairs are only added once, comparing names with
+/// [`crate::hostname_eq`]. This is the inverse of [`crate::flatten`].
+///
+/// `Vec<HostEntry>` can't implement `FromIterator<(IpAddr, String)>` because of Rust's orphan
+/// rules, so collect into this instead. It derefs to the `Vec` of entries:
+///
+/// ```
+/// use std::net::{IpAddr, Ipv4Addr};
+/// use hostfile::HostEntries;
+///
+/// let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+/// let entries: HostEntries = vec![(localhost, "localhost"), (localhost, "loopback")]
+///     .into_iter()
+///     .collect();
+/// assert_eq!(entries.len(), 1);
+/// assert_eq!(entries[0].names, vec!["localhost", "loopback"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostEntries(pub Vec<HostEntry>);
+
+impl<S: Into<String>> FromIterator<(IpAddr, S)> for HostEntries {
+    fn from_iter<I: IntoIterator<Item = (IpAddr, S)>>(pairs: I) -> Self {
+        HostEntries(group_by_ip_with(pairs, IpEquivalence::Exact))
+    }
+}
+
+impl Deref for HostEntries {
+    type Target = Vec<HostEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for HostEntries {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<HostEntries> for Vec<HostEntry> {
+    fn from(entries: HostEntries) -> Self {
+        entries.0
+    }
+}
+
+impl IntoIterator for HostEntries {
+    type Item = HostEntry;
+    type IntoIter = std::vec::IntoIter<HostEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Group `(ip, name)` pairs into entries like collecting them into [`HostEntries`], treating
+/// addresses that are equivalent according to `ip_equivalence` as the same IP. Each entry
+/// keeps the form of its IP that occurs first.
+pub fn group_by_ip_with<I, S>(pairs: I, ip_equivalence: IpEquivalence) -> Vec<HostEntry>
+where
+    I: IntoIterator<Item = (IpAddr, S)>,
+    S: Into<String>,
+{
+    let mut entries: Vec<HostEntry> = Vec::new();
+    let mut entry_idx: HashMap<IpAddr, usize> = HashMap::new();
+    let mut seen = HashSet::new();
+    for (ip, name) in pairs {
+        let name = name.into();
+        let key = ip_equivalence.canonical(ip);
+        if !seen.insert((key, hostname_key(&name).into_owned())) {
+            continue;
+        }
+        match entry_idx.get(&key) {
+            Some(&idx) => entries[idx].names.push(name),
+            None => {
+                entry_idx.insert(key, entries.len());
+                entries.push(HostEntry::from((ip, name)));
+            }
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::flatten;
+
+    #[test]
+    fn tuple_conversions() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let entry = HostEntry::try_from("10.0.0.1 web www").unwrap();
+        assert_eq!(
+            entry,
+            HostEntry::from((ip, vec![String::from("web"), String::from("www")]))
+        );
+        assert_eq!(
+            Vec::<(IpAddr, String)>::from(entry.clone()),
+            vec![(ip, String::from("web")), (ip, String::from("www"))]
+        );
+        assert_eq!(
+            <(IpAddr, Vec<String>)>::from(entry),
+            (ip, vec![String::from("web"), String::from("www")])
+        );
+        assert!(HostEntry::try_from("web").is_err());
+    }
+
+    #[test]
+    fn group_round_trips_flatten() {
+        let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let entries: HostEntries = vec![
+            (v4, "localhost"),
+            (v6, "localhost"),
+            (v4, "loopback"),
+            (v4, "localhost"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            *entries,
+            vec![
+                HostEntry::from((
+                    v4,
+                    vec![String::from("localhost"), String::from("loopback")]
+                )),
+                HostEntry::from((v6, String::from("localhost"))),
+            ]
+        );
+        let pairs = flatten(&entries).map(|(ip, name, _)| (ip, name));
+        assert_eq!(pairs.collect::<HostEntries>(), entries);
+    }
+
+    #[test]
+    fn group_ipv4_mapped() {
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        let mapped: IpAddr = "::ffff:192.0.2.1".parse().unwrap();
+        let pairs = vec![(mapped, "web"), (v4, "web"), (v4, "www")];
+        assert_eq!(pairs.iter().copied().collect::<HostEntries>().len(), 2);
+        assert_eq!(
+            group_by_ip_with(pairs, IpEquivalence::Ipv4Mapped),
+            vec![HostEntry::from((
+                mapped,
+                vec![String::from("web"), String::from("www")]
+            ))]
+        );
+    }
+}
@@ -0,0 +1,567 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::{accept_backoff, is_transient, AddressOrder, SharedHosts};
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+const RCODE_REFUSED: u8 = 5;
+
+/// Largest UDP response without EDNS, larger responses are truncated
+const MAX_UDP_LEN: usize = 512;
+/// Largest TCP response, whose length has to fit the 2 byte prefix
+const MAX_TCP_LEN: usize = u16::MAX as usize;
+const HEADER_LEN: usize = 12;
+/// How long a TCP client may take to send each part of a query
+const TCP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A callback for the errors a [`DnsServer`] keeps serving after, see [`DnsServer::on_error`]
+type ErrorHandler = dyn Fn(&io::Error) + Send + Sync;
+
+/// A minimal authoritative DNS server answering from a hosts file.
+///
+/// `A`, `AAAA` and `PTR` queries for names in the file are answered with its entries. Queries
+/// for other names get `NXDOMAIN`, and queries outside of the `IN` class get `REFUSED`. The
+/// file is read from a [`SharedHosts`], so it can be reloaded while the server runs.
+#[derive(Clone)]
+pub struct DnsServer {
+    hosts: Arc<SharedHosts>,
+    ttl: u32,
+    max_connections: usize,
+    on_error: Option<Arc<ErrorHandler>>,
+    /// TCP connections being answered by [`DnsServer::serve_tcp`]
+    connections: Arc<AtomicUsize>,
+}
+
+impl fmt::Debug for DnsServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DnsServer")
+            .field("hosts", &self.hosts)
+            .field("ttl", &self.ttl)
+            .field("max_connections", &self.max_connections)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The question of a query
+struct Question {
+    /// The name without a trailing dot
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    /// End of the question in the query
+    end: usize,
+}
+
+impl DnsServer {
+    pub fn new(hosts: Arc<SharedHosts>) -> Self {
+        DnsServer {
+            hosts,
+            ttl: 60,
+            max_connections: 64,
+            on_error: None,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// TTL of the records in answers, 60 seconds by default
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How many TCP connections [`DnsServer::serve_tcp`] answers at once, 64 by default.
+    /// Connections beyond that are closed without an answer.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Call `f` with the errors of single queries and connections, which the server otherwise
+    /// ignores to keep serving, e.g. to log them
+    pub fn on_error(mut self, f: impl Fn(&io::Error) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Arc::new(f));
+        self
+    }
+
+    /// Serve queries over UDP and TCP on `addr`, blocking until serving either fails. Both
+    /// are served on the same port, also when `addr` asks for any free one with port 0.
+    pub fn serve(self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let (socket, listener) = bind(addr)?;
+        let (done, result) = mpsc::channel();
+        let server = self.clone();
+        let tcp_done = done.clone();
+        thread::spawn(move || tcp_done.send(server.serve_tcp(listener)));
+        thread::spawn(move || done.send(self.serve_udp(socket)));
+        result
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::other("DNS server thread panicked")))
+    }
+
+    /// Answer queries received on `socket` until receiving from it fails for a reason other
+    /// than a single bad packet
+    pub fn serve_udp(&self, socket: UdpSocket) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(err) if is_transient(&err) => {
+                    self.report(&err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if let Some(response) = self.answer(&buf[..len]) {
+                let response = truncate(response, MAX_UDP_LEN);
+                if let Err(err) = socket.send_to(&response, peer) {
+                    self.report(&err);
+                }
+            }
+        }
+    }
+
+    /// Answer queries from connections to `listener`, each on its own thread, until accepting
+    /// connections fails for a reason other than a single bad connection
+    pub fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) if is_transient(&err) => {
+                    self.report(&err);
+                    accept_backoff(&err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if self.connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+                self.connections.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            let server = self.clone();
+            thread::spawn(move || {
+                if let Err(err) = server.handle_tcp(stream) {
+                    server.report(&err);
+                }
+                server.connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+
+    fn report(&self, err: &io::Error) {
+        if let Some(on_error) = &self.on_error {
+            on_error(err);
+        }
+    }
+
+    /// Answer length prefixed queries on `stream` until it is closed or idle for too long
+    fn handle_tcp(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TCP_TIMEOUT))?;
+        stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+        loop {
+            let mut len = [0; 2];
+            if stream.read_exact(&mut len).is_err() {
+                return Ok(());
+            }
+            let mut query = vec![0; usize::from(u16::from_be_bytes(len))];
+            stream.read_exact(&mut query)?;
+            if let Some(response) = self.answer(&query) {
+                let response = truncate(response, MAX_TCP_LEN);
+                stream.write_all(&(response.len() as u16).to_be_bytes())?;
+                stream.write_all(&response)?;
+            }
+        }
+    }
+
+    /// The response to the DNS message `query`, or `None` if it is too malformed to answer
+    pub fn answer(&self, query: &[u8]) -> Option<Vec<u8>> {
+        if query.len() < HEADER_LEN || query[2] & 0x80 != 0 {
+            // Too short for a header, or a response rather than a query
+            return None;
+        }
+        let opcode = (query[2] >> 3) & 0xf;
+        let qdcount = u16::from_be_bytes([query[4], query[5]]);
+        let question = if qdcount == 1 {
+            parse_question(query)
+        } else {
+            None
+        };
+        if opcode != 0 {
+            return Some(error_response(query, question.as_ref(), RCODE_NOTIMP));
+        }
+        let question = match question {
+            Some(question) => question,
+            None => return Some(error_response(query, None, RCODE_FORMERR)),
+        };
+        if question.qclass != CLASS_IN {
+            return Some(error_response(query, Some(&question), RCODE_REFUSED));
+        }
+
+        let (rcode, answers) = self.records(&question);
+        let mut response = Vec::with_capacity(512);
+        response.extend_from_slice(&query[..2]);
+        // QR and AA, keeping RD from the query
+        response.push(0x84 | (query[2] & 0x01));
+        response.push(rcode);
+        response.extend_from_slice(&1u16.to_be_bytes());
+        response.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        response.extend_from_slice(&query[HEADER_LEN..question.end]);
+        for (rtype, rdata) in answers {
+            // A pointer to the name in the question
+            response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+            response.extend_from_slice(&rtype.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&self.ttl.to_be_bytes());
+            response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            response.extend_from_slice(&rdata);
+        }
+        Some(response)
+    }
+
+    /// The response code and `(type, data)` of the answers to `question`
+    fn records(&self, question: &Question) -> (u8, Vec<(u16, Vec<u8>)>) {
+        let snapshot = self.hosts.load();
+
+        if let Some(ip) = parse_reverse_name(&question.name) {
+            let names: Vec<&str> = snapshot
+                .entries()
+                .iter()
+                .filter(|entry| entry.ip == ip)
+                .flat_map(|entry| entry.names.iter().map(String::as_str))
+                .collect();
+            if names.is_empty() {
+                return (RCODE_NXDOMAIN, Vec::new());
+            }
+            let mut answers: Vec<(u16, Vec<u8>)> = Vec::new();
+            if question.qtype == TYPE_PTR {
+                // Names that can't be encoded are left out rather than answering for another
+                for rdata in names.into_iter().filter_map(encode_name) {
+                    if !answers.iter().any(|(_, other)| other == &rdata) {
+                        answers.push((TYPE_PTR, rdata));
+                    }
+                }
+            }
+            return (0, answers);
+        }
+
+        let addrs = snapshot.lookup(&question.name, AddressOrder::AsListed);
+        if addrs.is_empty() {
+            return (RCODE_NXDOMAIN, Vec::new());
+        }
+        let answers = addrs
+            .into_iter()
+            .filter_map(|ip| match (ip, question.qtype) {
+                (IpAddr::V4(ip), TYPE_A) => Some((TYPE_A, ip.octets().to_vec())),
+                (IpAddr::V6(ip), TYPE_AAAA) => Some((TYPE_AAAA, ip.octets().to_vec())),
+                _ => None,
+            })
+            .collect();
+        (0, answers)
+    }
+}
+
+/// Bind UDP and TCP to the same address, using the port UDP got if `addr` has port 0
+fn bind(addr: impl ToSocketAddrs) -> io::Result<(UdpSocket, TcpListener)> {
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+    let socket = UdpSocket::bind(&addrs[..])?;
+    let listener = TcpListener::bind(socket.local_addr()?)?;
+    Ok((socket, listener))
+}
+
+/// A response to `query` with `rcode` and no records. It repeats `question`, so clients can
+/// match it to their query, and is just the header without one.
+fn error_response(query: &[u8], question: Option<&Question>, rcode: u8) -> Vec<u8> {
+    let end = question.map_or(HEADER_LEN, |question| question.end);
+    let mut response = query[..end].to_vec();
+    response[2] = 0x80 | (query[2] & 0x79);
+    response[3] = rcode;
+    response[4..HEADER_LEN].fill(0);
+    if question.is_some() {
+        response[5] = 1;
+    }
+    response
+}
+
+/// Drop the records of responses longer than `max_len`, keeping the header and question, and
+/// set the truncation bit. Over UDP, this makes the client retry over TCP.
+fn truncate(mut response: Vec<u8>, max_len: usize) -> Vec<u8> {
+    if response.len() > max_len {
+        match parse_question(&response) {
+            Some(question) => response.truncate(question.end),
+            None => {
+                response.truncate(HEADER_LEN);
+                response[4..6].fill(0);
+            }
+        }
+        response[2] |= 0x02;
+        response[6..HEADER_LEN].fill(0);
+    }
+    response
+}
+
+fn parse_question(query: &[u8]) -> Option<Question> {
+    let mut labels = Vec::new();
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = usize::from(*query.get(pos)?);
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers and extended labels aren't valid in questions
+        if len > 63 {
+            return None;
+        }
+        let label = query.get(pos..pos + len)?;
+        labels.push(std::str::from_utf8(label).ok()?);
+        pos += len;
+    }
+    let fields = query.get(pos..pos + 4)?;
+    Some(Question {
+        name: labels.join("."),
+        qtype: u16::from_be_bytes([fields[0], fields[1]]),
+        qclass: u16::from_be_bytes([fields[2], fields[3]]),
+        end: pos + 4,
+    })
+}
+
+/// Encode `name` as DNS labels, or `None` if it has an empty label or is longer than DNS
+/// allows
+fn encode_name(name: &str) -> Option<Vec<u8>> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    (encoded.len() <= 255).then_some(encoded)
+}
+
+/// The address of a reverse lookup name, such as `1.0.0.10.in-addr.arpa`
+fn parse_reverse_name(name: &str) -> Option<IpAddr> {
+    let lower = name.to_ascii_lowercase();
+    if let Some(rest) = lower.strip_suffix(".in-addr.arpa") {
+        let mut octets = [0u8; 4];
+        let parts: Vec<&str> = rest.split('.').collect();
+        if parts.len() != 4 {
+            return None;
+        }
+        for (octet, part) in octets.iter_mut().rev().zip(parts) {
+            *octet = part.parse().ok()?;
+        }
+        Some(IpAddr::V4(Ipv4Addr::from(octets)))
+    } else if let Some(rest) = lower.strip_suffix(".ip6.arpa") {
+        let nibbles: Vec<&str> = rest.split('.').collect();
+        if nibbles.len() != 32 {
+            return None;
+        }
+        let mut addr = 0u128;
+        for nibble in nibbles.iter().rev() {
+            if nibble.len() != 1 {
+                return None;
+            }
+            addr = addr << 4 | u128::from_str_radix(nibble, 16).ok()?;
+        }
+        Some(IpAddr::V6(Ipv6Addr::from(addr)))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HostsFile;
+
+    fn server() -> DnsServer {
+        let file = HostsFile::parse("10.0.0.1 web www\n2001:db8::1 web\n10.0.0.2 db\n");
+        DnsServer::new(Arc::new(SharedHosts::new(file)))
+    }
+
+    fn query(name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend(encode_name(name).unwrap());
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&qclass.to_be_bytes());
+        query
+    }
+
+    /// The response code and the data of the answers in `response`
+    fn parse_response(response: &[u8], query_len: usize) -> (u8, Vec<Vec<u8>>) {
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        assert_eq!(response[2] & 0x80, 0x80);
+        let ancount = u16::from_be_bytes([response[6], response[7]]);
+        let mut pos = query_len;
+        let mut answers = Vec::new();
+        for _ in 0..ancount {
+            // Name pointer, type, class, TTL and data length
+            pos += 12;
+            let len = usize::from(u16::from_be_bytes([response[pos - 2], response[pos - 1]]));
+            answers.push(response[pos..pos + len].to_vec());
+            pos += len;
+        }
+        assert_eq!(pos, response.len());
+        (response[3] & 0xf, answers)
+    }
+
+    fn ask(name: &str, qtype: u16) -> (u8, Vec<Vec<u8>>) {
+        let query = query(name, qtype, CLASS_IN);
+        parse_response(&server().answer(&query).unwrap(), query.len())
+    }
+
+    #[test]
+    fn answers_from_entries() {
+        assert_eq!(ask("WEB", TYPE_A), (0, vec![vec![10, 0, 0, 1]]));
+        let v6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        assert_eq!(ask("web.", TYPE_AAAA), (0, vec![v6]));
+        assert_eq!(ask("db", TYPE_AAAA), (0, Vec::new()));
+        assert_eq!(ask("missing", TYPE_A), (RCODE_NXDOMAIN, Vec::new()));
+        assert_eq!(
+            ask("1.0.0.10.in-addr.arpa", TYPE_PTR),
+            (
+                0,
+                vec![encode_name("web").unwrap(), encode_name("www").unwrap()]
+            )
+        );
+        assert_eq!(
+            ask("9.0.0.10.in-addr.arpa", TYPE_PTR),
+            (RCODE_NXDOMAIN, Vec::new())
+        );
+
+        let query = query("web", TYPE_A, 3);
+        let response = server().answer(&query).unwrap();
+        assert_eq!(
+            parse_response(&response, query.len()),
+            (RCODE_REFUSED, Vec::new())
+        );
+        assert_eq!(response[HEADER_LEN..], query[HEADER_LEN..]);
+        assert_eq!(server().answer(&query[..5]), None);
+    }
+
+    #[test]
+    fn error_responses_repeat_the_question() {
+        // An inverse query, with opcode 1
+        let mut inverse = query("web", TYPE_A, CLASS_IN);
+        inverse[2] |= 0x08;
+        let response = server().answer(&inverse).unwrap();
+        assert_eq!(
+            parse_response(&response, inverse.len()),
+            (RCODE_NOTIMP, Vec::new())
+        );
+        assert_eq!(response[2] & 0x78, 0x08);
+        assert_eq!(response[4..6], [0, 1]);
+        assert_eq!(response[HEADER_LEN..], inverse[HEADER_LEN..]);
+
+        // A question cut short is a format error, which doesn't repeat it
+        let query = query("web", TYPE_A, CLASS_IN);
+        let response = server().answer(&query[..query.len() - 1]).unwrap();
+        assert_eq!(response[3], RCODE_FORMERR);
+        assert_eq!(response.len(), HEADER_LEN);
+        assert_eq!(response[4..HEADER_LEN], [0; 8]);
+    }
+
+    #[test]
+    fn reverse_names() {
+        assert_eq!(
+            parse_reverse_name("4.3.2.1.in-addr.arpa"),
+            Some("1.2.3.4".parse().unwrap())
+        );
+        let v6 = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa";
+        assert_eq!(parse_reverse_name(v6), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(parse_reverse_name("3.2.1.in-addr.arpa"), None);
+        assert_eq!(parse_reverse_name("example.com"), None);
+    }
+
+    #[test]
+    fn serve_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || server().serve_udp(socket));
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let query = query("www", TYPE_A, CLASS_IN);
+        client.send_to(&query, addr).unwrap();
+        let mut buf = [0; 512];
+        let len = client.recv(&mut buf).unwrap();
+        assert_eq!(
+            parse_response(&buf[..len], query.len()),
+            (0, vec![vec![10, 0, 0, 1]])
+        );
+    }
+
+    #[test]
+    fn serve_over_tcp_on_the_udp_port() {
+        let (socket, listener) = bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        thread::spawn(move || server().serve_tcp(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let query = query("www", TYPE_A, CLASS_IN);
+        stream
+            .write_all(&(query.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&query).unwrap();
+        let mut len = [0; 2];
+        stream.read_exact(&mut len).unwrap();
+        let mut response = vec![0; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut response).unwrap();
+        assert_eq!(
+            parse_response(&response, query.len()),
+            (0, vec![vec![10, 0, 0, 1]])
+        );
+    }
+
+    #[test]
+    fn oversized_names_and_responses() {
+        let long = "a".repeat(64);
+        assert_eq!(encode_name(&long), None);
+        assert_eq!(encode_name("web..example"), None);
+        assert_eq!(encode_name(&"a.".repeat(128)), None);
+        assert_eq!(encode_name("web."), Some(b"\x03web\x00".to_vec()));
+
+        let file = HostsFile::parse(&format!("10.0.0.1 {long}.example web\n"));
+        let server = DnsServer::new(Arc::new(SharedHosts::new(file)));
+        let query = query("1.0.0.10.in-addr.arpa", TYPE_PTR, CLASS_IN);
+        let response = server.answer(&query).unwrap();
+        assert_eq!(
+            parse_response(&response, query.len()),
+            (0, vec![encode_name("web").unwrap()])
+        );
+
+        let mut response = response;
+        response.resize(MAX_TCP_LEN + 1, 0);
+        let response = truncate(response, MAX_TCP_LEN);
+        assert_eq!(parse_response(&response, query.len()), (0, Vec::new()));
+        assert_eq!(response[2] & 0x02, 0x02);
+        assert_eq!(response[4..HEADER_LEN], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response[HEADER_LEN..], query[HEADER_LEN..]);
+    }
+
+    #[test]
+    fn tcp_connections_are_limited() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server().max_connections(0).serve_tcp(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(TCP_TIMEOUT)).unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,152 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::{HostEntry, HostfileError, MacAddr};
+
+/// Convert the leases in a dnsmasq lease file (usually `/var/lib/misc/dnsmasq.leases`)
+/// into hosts entries, one per lease whose client sent a hostname.
+///
+/// DHCPv4 lease lines are `expiry mac ip hostname client-id`, and DHCPv6 ones, which follow a
+/// `duid` line, have an IAID in place of the MAC. Clients without a hostname are recorded
+/// as `*` and are left out.
+pub fn parse_dnsmasq_leases(input: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let mut entries = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] | ["duid", ..] => continue,
+            [_, _, ip, hostname, ..] => {
+                let ip: IpAddr = ip.parse().map_err(|err| {
+                    format!(
+                        "Couldn't parse a valid IP address: {err} at line {}",
+                        idx + 1
+                    )
+                })?;
+                if *hostname != "*" {
+                    entries.push(HostEntry {
+                        ip,
+                        names: vec![hostname.to_string()],
+                    });
+                }
+            }
+            _ => return Err(format!("Malformed lease at line {}: '{line}'", idx + 1).into()),
+        }
+    }
+    Ok(entries)
+}
+
+/// Read a dnsmasq lease file, see [`parse_dnsmasq_leases`]
+pub fn parse_dnsmasq_leases_file(path: &Path) -> Result<Vec<HostEntry>, HostfileError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    parse_dnsmasq_leases(&contents)
+}
+
+/// Convert the static leases of a dnsmasq `dhcp-hostsfile` into hosts entries.
+///
+/// Each line has the comma separated fields of a `dhcp-host` option, such as
+/// `00:20:e0:3b:13:af,192.168.0.60,wap,infinite`. Only lines that give both an address and a
+/// hostname produce an entry.
+pub fn parse_dhcp_hostsfile(input: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let mut entries = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut ip = None;
+        let mut hostname = None;
+        for field in line.split(',').map(str::trim) {
+            if let Some(addr) = field.strip_prefix('[').and_then(|f| f.strip_suffix(']')) {
+                // IPv6 addresses are bracketed so they can't be mistaken for MAC addresses
+                let addr = addr.parse().map_err(|err| {
+                    format!(
+                        "Couldn't parse a valid IP address: {err} at line {}",
+                        idx + 1
+                    )
+                })?;
+                ip = Some(addr);
+            } else if let Ok(addr) = field.parse() {
+                ip = Some(addr);
+            } else if !is_dhcp_host_option(field) && hostname.is_none() {
+                hostname = Some(field);
+            }
+        }
+        if let (Some(ip), Some(hostname)) = (ip, hostname) {
+            entries.push(HostEntry {
+                ip,
+                names: vec![hostname.to_string()],
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Read a dnsmasq `dhcp-hostsfile`, see [`parse_dhcp_hostsfile`]
+pub fn parse_dhcp_hostsfile_file(path: &Path) -> Result<Vec<HostEntry>, HostfileError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    parse_dhcp_hostsfile(&contents)
+}
+
+/// Whether `field` of a `dhcp-host` line is something other than the hostname: a hardware
+/// address, client ID, tag, lease time or `ignore`
+fn is_dhcp_host_option(field: &str) -> bool {
+    let lease_time = field
+        .strip_suffix(|c| matches!(c, 'm' | 'h' | 'd' | 'w'))
+        .unwrap_or(field);
+    field.is_empty()
+        || field.parse::<MacAddr>().is_ok()
+        || ["id:", "set:", "tag:", "net:"]
+            .iter()
+            .any(|prefix| field.starts_with(prefix))
+        || matches!(field, "*" | "ignore" | "infinite")
+        || (!lease_time.is_empty() && lease_time.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leases() {
+        let entries = parse_dnsmasq_leases(
+            "1700000000 00:20:e0:3b:13:af 192.168.0.60 wap 01:00:20:e0:3b:13:af\n\
+             1700000000 00:20:e0:3b:13:b0 192.168.0.61 * *\n\
+             duid 00:01:00:01:2a:2b:2c:2d:00:20:e0:3b:13:af\n\
+             1700000000 1234 fd00::60 laptop 00:01:00:01:2a\n",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                "192.168.0.60 wap".parse().unwrap(),
+                "fd00::60 laptop".parse().unwrap(),
+            ]
+        );
+        assert!(parse_dnsmasq_leases("1700000000 00:20:e0:3b:13:af\n").is_err());
+    }
+
+    #[test]
+    fn hostsfile() {
+        let entries = parse_dhcp_hostsfile(
+            "# static leases\n\
+             00:20:e0:3b:13:af,192.168.0.60,wap,infinite\n\
+             id:01:02:03,set:red,printer,192.168.0.70,12h\n\
+             00:20:e0:3b:13:b0,[fd00::61],nas\n\
+             00:20:e0:3b:13:b1,ignore\n\
+             laptop\n",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                "192.168.0.60 wap".parse().unwrap(),
+                "192.168.0.70 printer".parse().unwrap(),
+                "fd00::61 nas".parse().unwrap(),
+            ]
+        );
+        assert!(parse_dhcp_hostsfile("00:20:e0:3b:13:af,[fd00::xyz],nas\n").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{HostEntry, HostfileError, HostsFile};
+
+/// Name of the managed section [`refresh_docker_section`] keeps the containers in
+pub const DOCKER_SECTION: &str = "docker";
+
+/// The parts of `docker inspect` output that name containers
+#[derive(Deserialize)]
+struct Container {
+    #[serde(rename = "Name", default)]
+    name: String,
+    #[serde(rename = "State")]
+    state: Option<State>,
+    #[serde(rename = "NetworkSettings")]
+    network_settings: Option<NetworkSettings>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    #[serde(rename = "Running", default)]
+    running: bool,
+}
+
+#[derive(Deserialize)]
+struct NetworkSettings {
+    #[serde(rename = "Networks", default)]
+    networks: BTreeMap<String, Network>,
+}
+
+#[derive(Deserialize)]
+struct Network {
+    #[serde(rename = "IPAddress", default)]
+    ip_address: String,
+    #[serde(rename = "GlobalIPv6Address", default)]
+    ipv6_address: String,
+    #[serde(rename = "Aliases", default)]
+    aliases: Option<Vec<String>>,
+}
+
+/// Convert the output of `docker inspect` for some containers into hosts entries.
+///
+/// Every running container gets an entry for its address on each of its networks, naming
+/// it by the container name and its aliases on that network.
+pub fn parse_docker_inspect(json: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let containers: Vec<Container> = serde_json::from_str(json)
+        .map_err(|err| format!("Could not parse docker inspect output: {err}"))?;
+
+    let mut entries = Vec::new();
+    for container in containers {
+        if !container.state.is_some_and(|state| state.running) {
+            continue;
+        }
+        let networks = match container.network_settings {
+            Some(settings) => settings.networks,
+            None => continue,
+        };
+        let name = container.name.trim_start_matches('/');
+        for network in networks.into_values() {
+            let mut names = vec![name.to_string()];
+            for alias in network.aliases.unwrap_or_default() {
+                if !names.contains(&alias) {
+                    names.push(alias);
+                }
+            }
+            names.retain(|name| !name.is_empty());
+            if names.is_empty() {
+                continue;
+            }
+            for ip in [&network.ip_address, &network.ipv6_address] {
+                if ip.is_empty() {
+                    continue;
+                }
+                let ip: IpAddr = ip.parse().map_err(|err| {
+                    format!("Container {name} has an invalid address {ip}: {err}")
+                })?;
+                entries.push(HostEntry {
+                    ip,
+                    names: names.clone(),
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Ask the Docker daemon for the running containers with the `docker` CLI and convert them
+/// with [`parse_docker_inspect`]
+pub fn import_docker() -> Result<Vec<HostEntry>, HostfileError> {
+    let ids = run_docker(&["ps", "--quiet", "--no-trunc"])?;
+    let ids: Vec<&str> = ids.split_whitespace().collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut args = vec!["inspect"];
+    args.extend(ids);
+    parse_docker_inspect(&run_docker(&args)?)
+}
+
+/// Replace the `docker` managed section of `file` with the running containers
+pub fn refresh_docker_section(file: &mut HostsFile) -> Result<(), HostfileError> {
+    let entries = import_docker()?;
+    file.set_managed_section(DOCKER_SECTION, &entries)
+}
+
+fn run_docker(args: &[&str]) -> Result<String, HostfileError> {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .map_err(|err| format!("Could not run docker: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "docker {} failed ({}): {}",
+            args[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_containers() {
+        let json = r#"[
+            {
+                "Id": "4f66ad9a0b2e",
+                "Name": "/web",
+                "State": {"Status": "running", "Running": true},
+                "NetworkSettings": {
+                    "Networks": {
+                        "bridge": {
+                            "IPAddress": "172.17.0.2",
+                            "GlobalIPv6Address": "",
+                            "Aliases": null
+                        },
+                        "app": {
+                            "IPAddress": "172.18.0.3",
+                            "GlobalIPv6Address": "fd00:18::3",
+                            "Aliases": ["web", "frontend"]
+                        }
+                    }
+                }
+            },
+            {
+                "Name": "/stopped",
+                "State": {"Status": "exited", "Running": false},
+                "NetworkSettings": {
+                    "Networks": {"bridge": {"IPAddress": "", "Aliases": null}}
+                }
+            }
+        ]"#;
+        let entries = parse_docker_inspect(json).unwrap();
+        let expected: Vec<HostEntry> = vec![
+            "172.18.0.3 web frontend".parse().unwrap(),
+            "fd00:18::3 web frontend".parse().unwrap(),
+            "172.17.0.2 web".parse().unwrap(),
+        ];
+        assert_eq!(entries, expected);
+        assert!(parse_docker_inspect("{}").is_err());
+    }
+}
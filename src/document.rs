@@ -0,0 +1,2586 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+use std::path::Path;
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::hostctl::{is_profile_end, is_profile_name, profile_entry, profile_header};
+use crate::machine::machine_names;
+use crate::protect::find_protected_regions;
+use crate::section::header_name;
+use crate::syntax::{comment_range, inline_comment, lex_line};
+use crate::write::replace_file;
+use crate::{
+    diff_entries, hostname_eq, hostname_key, parse_line_bytes, read_file, self_entry,
+    strip_newline, Allowlist, DesiredState, Extensions, Generator, HostEntry, HostctlProfile,
+    HostfileError, ProtectedRegion, Section, SectionPattern, Token, TokenKind, WatchEvent,
+    WriteOptions, WriteWarning,
+};
+
+/// A hosts file that can be edited without disturbing the parts that aren't changed.
+///
+/// The file is kept as its original lines of text. Edits only replace the bytes they affect,
+/// so comments, blank lines, spacing, line endings and even lines that don't parse are kept
+/// exactly as they were. Writing back a file that wasn't edited reproduces it byte for byte.
+///
+/// Parts of the file managed by other tools, such as Docker Desktop or WSL, are protected:
+/// edits that would change them fail with a [`ProtectedRegion`] error unless forced with
+/// [`HostsFile::set_force`].
+///
+/// Edits can be reverted with [`HostsFile::undo`] and reapplied with [`HostsFile::redo`].
+#[derive(Debug, Clone)]
+pub struct HostsFile {
+    /// Lines of the file, each including its newline if it has one
+    lines: Vec<TextLine>,
+    /// ID of the next line added to the file
+    next_id: u64,
+    /// Whether edits may change protected regions and critical entries
+    force: bool,
+    /// Whether edits may remove or retarget critical entries
+    safe_mode: bool,
+    /// The names of this machine that safe mode guards, looked up the first time an edit is
+    /// checked unless they were set
+    protected_names: Option<Vec<String>>,
+    /// Forms of the comments that start sections
+    section_patterns: Vec<SectionPattern>,
+    /// The edits that can be undone, oldest first, each as the splice that reverts it
+    undo: VecDeque<Splice>,
+    /// The undone edits that can be redone, most recent last
+    redo: Vec<Splice>,
+    /// The most edits that can be undone
+    history_limit: usize,
+    /// Data tools attached to the file
+    extensions: Extensions,
+    /// Data tools attached to lines, which is kept when lines are removed in case they come
+    /// back with an undo
+    line_extensions: HashMap<LineId, Extensions>,
+}
+
+/// Whether the hostname of the machine resolves to `127.0.1.1`, see
+/// [`HostsFile::debian_hostname`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostnameStatus {
+    /// The hostname resolves to `127.0.1.1`
+    Present,
+    /// The hostname has no entry
+    Missing,
+    /// The hostname resolves to these addresses, the first of which isn't `127.0.1.1`
+    Elsewhere(Vec<IpAddr>),
+}
+
+/// `localhost` and the aliases stock hosts files give its loopback addresses
+const LOCALHOST_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "ip6-localhost",
+    "ip6-loopback",
+];
+
+/// The address Debian and Ubuntu give the machine's hostname if it has no permanent address
+pub(crate) const DEBIAN_HOSTNAME_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 1, 1));
+
+/// Number of edits that can be undone unless changed with [`HostsFile::set_history_limit`]
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A handle to a line of a [`HostsFile`] that stays the same as other lines are added,
+/// removed or moved, and as the line itself is edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineId(u64);
+
+/// What a line of a [`HostsFile`] holds, see [`HostsFile::lines`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Line<'a> {
+    /// An entry, with the comment after its names without the `#` and surrounding whitespace
+    Entry {
+        entry: HostEntry,
+        comment: Option<&'a str>,
+    },
+    /// A line that is only a comment, as the text after the `#` and any spaces. Entries that
+    /// were disabled with [`HostsFile::disable_entry`] are comments too.
+    Comment(&'a str),
+    /// An empty line, or one with only whitespace
+    Blank,
+    /// A line that can't be parsed, as written
+    Invalid(&'a str),
+}
+
+/// A line of a [`HostsFile`] and its handle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TextLine {
+    pub(crate) id: LineId,
+    /// The text of the line, including its newline if it has one
+    pub(crate) text: String,
+}
+
+/// A change to the lines of a [`HostsFile`] recorded for undo and redo: `len` lines at
+/// `start` replaced `lines`
+#[derive(Debug, Clone)]
+struct Splice {
+    start: usize,
+    len: usize,
+    lines: Vec<TextLine>,
+}
+
+impl Splice {
+    /// The splice that turns `after` back into `before`, if they differ
+    fn between(mut before: Vec<TextLine>, after: &[TextLine]) -> Option<Splice> {
+        let (prefix, suffix) = unchanged_ends(&before, after);
+        if prefix == before.len() && prefix == after.len() {
+            return None;
+        }
+        Some(Splice {
+            start: prefix,
+            len: after.len() - prefix - suffix,
+            lines: before.drain(prefix..before.len() - suffix).collect(),
+        })
+    }
+
+    /// Apply the splice to `lines`, returning the one that reverts it
+    fn apply(self, lines: &mut Vec<TextLine>) -> Splice {
+        let len = self.lines.len();
+        let removed = lines
+            .splice(self.start..self.start + self.len, self.lines)
+            .collect();
+        Splice {
+            start: self.start,
+            len,
+            lines: removed,
+        }
+    }
+}
+
+/// The number of lines at the start and at the end of `before` and `after` that are the same,
+/// not counting any line twice
+fn unchanged_ends(before: &[TextLine], after: &[TextLine]) -> (usize, usize) {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix, suffix)
+}
+
+impl AsRef<str> for TextLine {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Default for HostsFile {
+    fn default() -> Self {
+        HostsFile {
+            lines: Vec::new(),
+            next_id: 0,
+            force: false,
+            safe_mode: true,
+            protected_names: None,
+            section_patterns: SectionPattern::defaults(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            extensions: Extensions::new(),
+            line_extensions: HashMap::new(),
+        }
+    }
+}
+
+/// Files are equal if they have the same contents and settings, whatever their line IDs and
+/// history
+impl PartialEq for HostsFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.lines.len() == other.lines.len()
+            && self
+                .lines
+                .iter()
+                .zip(&other.lines)
+                .all(|(a, b)| a.text == b.text)
+            && self.force == other.force
+            && self.safe_mode == other.safe_mode
+            && self.section_patterns == other.section_patterns
+    }
+}
+
+impl Eq for HostsFile {}
+
+/// A file with a line for each entry, formatted like [`HostEntry`]'s `Display`.
+///
+/// # Panics
+///
+/// Panics if an entry wouldn't read back as the same entry, see [`HostsFile::try_extend`].
+impl FromIterator<HostEntry> for HostsFile {
+    fn from_iter<I: IntoIterator<Item = HostEntry>>(iter: I) -> Self {
+        let mut file = Self::new();
+        file.extend(iter);
+        file.clear_history();
+        file
+    }
+}
+
+/// Appends a line for each entry as a single edit, like [`HostsFile::add_entry`] but without
+/// checking whether the file is generated by another tool.
+///
+/// # Panics
+///
+/// Panics if an entry wouldn't read back as the same entry, e.g. because a name contains a
+/// newline. Use [`HostsFile::try_extend`] for entries that aren't known to be valid.
+impl Extend<HostEntry> for HostsFile {
+    fn extend<I: IntoIterator<Item = HostEntry>>(&mut self, iter: I) {
+        if let Err(err) = self.try_extend(iter) {
+            panic!("can't extend the hosts file: {}", err);
+        }
+    }
+}
+
+impl<'a> Extend<&'a HostEntry> for HostsFile {
+    fn extend<I: IntoIterator<Item = &'a HostEntry>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned());
+    }
+}
+
+impl HostsFile {
+    /// An empty hosts file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a hosts file from `contents`. This never fails, invalid lines are kept as is.
+    pub fn parse(contents: &str) -> Self {
+        let mut file = Self::default();
+        for line in contents.split_inclusive('\n') {
+            let line = file.new_line(line.to_string());
+            file.lines.push(line);
+        }
+        file
+    }
+
+    /// Read the hosts file at `path`
+    pub fn read(path: &Path) -> Result<Self, HostfileError> {
+        Ok(Self::parse(&read_file(path)?))
+    }
+
+    /// The valid entries of the file in order. Lines that can't be parsed are skipped.
+    pub fn entries(&self) -> impl Iterator<Item = HostEntry> + '_ {
+        self.lines.iter().filter_map(|line| {
+            match parse_line_bytes(strip_newline(line.text.as_bytes())) {
+                Ok(Some(entry)) => Some(entry.into_owned()),
+                _ => None,
+            }
+        })
+    }
+
+    /// The regions of the file managed by other tools, in order
+    pub fn protected_regions(&self) -> Vec<ProtectedRegion> {
+        find_protected_regions(&self.lines)
+    }
+
+    /// Allow edits to change protected regions, which the tools managing them may overwrite,
+    /// and critical entries despite [`HostsFile::set_safe_mode`]
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Refuse edits that remove or retarget the critical entries of the file unless forced,
+    /// which is on by default. Refused edits fail with a [`ProtectedRegion`] of
+    /// [`Generator::SafeMode`] for the line of the entry, leaving the file as it was.
+    ///
+    /// The critical entries are the loopback addresses of `localhost` and its usual aliases,
+    /// and the addresses of this machine's own names. An edit may add addresses for them but
+    /// not remove any or change which one they resolve to first.
+    /// [`HostsFile::ensure_localhost`], [`HostsFile::ensure_debian_hostname`] and
+    /// [`HostsFile::add_self`] are meant to change them and aren't refused.
+    pub fn set_safe_mode(&mut self, safe_mode: bool) {
+        self.safe_mode = safe_mode;
+    }
+
+    /// Set the names of this machine that [`HostsFile::set_safe_mode`] guards. By default they
+    /// are looked up from the system once, the first time an edit is checked.
+    pub fn set_protected_names(&mut self, names: Vec<String>) {
+        self.protected_names = Some(names);
+    }
+
+    /// Data attached to the file by tools using it, which is never written to the file
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Data attached to the line `id` by tools using the file, which follows the line as it
+    /// is edited or moved. `None` if nothing was attached or the line is not in the file.
+    pub fn line_extensions(&self, id: LineId) -> Option<&Extensions> {
+        self.line_index(id)?;
+        self.line_extensions.get(&id)
+    }
+
+    /// Data attached to the line `id`, see [`HostsFile::line_extensions`]. `None` if the line
+    /// is not in the file.
+    pub fn line_extensions_mut(&mut self, id: LineId) -> Option<&mut Extensions> {
+        self.line_index(id)?;
+        Some(self.line_extensions.entry(id).or_default())
+    }
+
+    /// Run the edit `f` like [`HostsFile::edit_unchecked`], undoing it if it fails or
+    /// [`HostsFile::set_safe_mode`] refuses it
+    fn edit<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ProtectedRegion>,
+    ) -> Result<T, ProtectedRegion> {
+        self.record(|file, before| {
+            let result = f(file).and_then(|value| {
+                file.check_safe_mode(before)?;
+                Ok(value)
+            });
+            if result.is_err() {
+                file.lines = before.to_vec();
+            }
+            result
+        })
+    }
+
+    /// Check that no critical entry of `before` was removed or retargeted, see
+    /// [`HostsFile::set_safe_mode`]
+    fn check_safe_mode(&mut self, before: &[TextLine]) -> Result<(), ProtectedRegion> {
+        if self.force || !self.safe_mode {
+            return Ok(());
+        }
+        // Only the lines between the parts at the start and end that are unchanged were edited
+        let (prefix, suffix) = unchanged_ends(before, &self.lines);
+        let removed = &before[prefix..before.len() - suffix];
+        let added = &self.lines[prefix..self.lines.len() - suffix];
+        if removed.is_empty() && added.is_empty() {
+            return Ok(());
+        }
+
+        let own_names: &[String] = self
+            .protected_names
+            .get_or_insert_with(|| machine_names().unwrap_or_default());
+        let is_localhost = |name: &str| LOCALHOST_NAMES.iter().any(|n| hostname_eq(n, name));
+        let is_own = |name: &str| own_names.iter().any(|n| hostname_eq(n, name));
+        let is_critical =
+            |name: &str, ip: IpAddr| (ip.is_loopback() && is_localhost(name)) || is_own(name);
+        // The addresses of each name of `lines` that `keep` accepts, in order, along with the
+        // index in `before` of the line they're on, which starts at `offset`
+        let mappings = |lines: &[TextLine], offset: usize, keep: &dyn Fn(&str) -> bool| {
+            let mut mappings: HashMap<String, Vec<(IpAddr, usize)>> = HashMap::new();
+            for (index, line) in lines.iter().enumerate() {
+                if let Ok(Some(entry)) = parse_line_bytes(strip_newline(line.text.as_bytes())) {
+                    for name in entry.names.iter().filter(|name| keep(name)) {
+                        mappings
+                            .entry(hostname_key(name).into_owned())
+                            .or_default()
+                            .push((entry.ip, offset + index));
+                    }
+                }
+            }
+            mappings
+        };
+        let may_be_critical = |name: &str| is_localhost(name) || is_own(name);
+        let old = mappings(removed, prefix, &may_be_critical);
+        let new = mappings(added, prefix, &may_be_critical);
+        if old.is_empty() && new.is_empty() {
+            return Ok(());
+        }
+
+        // The unchanged lines only matter for the names the edit touched
+        let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+        names.sort_unstable();
+        names.dedup();
+        let touched = |name: &str| {
+            let key = hostname_key(name);
+            names.iter().any(|other| key == other.as_str())
+        };
+        let head = mappings(&before[..prefix], 0, &touched);
+        let tail = mappings(
+            &before[before.len() - suffix..],
+            before.len() - suffix,
+            &touched,
+        );
+
+        let mut refused: Option<usize> = None;
+        for &name in &names {
+            let get = |mappings| addresses(mappings, name);
+            let (head, old, new, tail) = (get(&head), get(&old), get(&new), get(&tail));
+            let kept = |ip: &IpAddr| {
+                [head, new, tail]
+                    .iter()
+                    .any(|addrs| addrs.iter().any(|(other, _)| other == ip))
+            };
+            let lost = old
+                .iter()
+                .find(|(ip, _)| is_critical(name, *ip) && !kept(ip));
+            let first_before = head.first().or(old.first()).or(tail.first());
+            let first_after = head.first().or(new.first()).or(tail.first());
+            let moved = first_before.filter(|(ip, _)| {
+                is_critical(name, *ip) && first_after.map(|(other, _)| other) != Some(ip)
+            });
+            if let Some(&(_, index)) = lost.or(moved) {
+                refused = Some(refused.map_or(index, |other| other.min(index)));
+            }
+        }
+        match refused {
+            Some(index) => Err(ProtectedRegion {
+                generator: Generator::SafeMode,
+                lines: index..index + 1,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the edit `f`, recording the file as it was before for [`HostsFile::undo`] if the
+    /// edit changed it
+    fn edit_unchecked<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.record(|file, _| f(file))
+    }
+
+    /// Run `f` with the lines as they were before it, recording the change it made for
+    /// [`HostsFile::undo`]
+    fn record<T>(&mut self, f: impl FnOnce(&mut Self, &[TextLine]) -> T) -> T {
+        let before = self.lines.clone();
+        let result = f(self, &before);
+        if let Some(splice) = Splice::between(before, &self.lines) {
+            self.redo.clear();
+            self.push_undo(splice);
+        }
+        result
+    }
+
+    fn push_undo(&mut self, splice: Splice) {
+        if self.history_limit == 0 {
+            return;
+        }
+        while self.undo.len() >= self.history_limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(splice);
+    }
+
+    /// Revert the most recent edit that hasn't been undone. Returns whether there was one.
+    ///
+    /// Line handles are restored along with the lines.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some(splice) => {
+                let redo = splice.apply(&mut self.lines);
+                self.redo.push(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapply the most recently undone edit. Returns whether there was one.
+    ///
+    /// Making a new edit after undoing discards the edits that could have been redone.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(splice) => {
+                let undo = splice.apply(&mut self.lines);
+                self.push_undo(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Keep at most `limit` edits for [`HostsFile::undo`], forgetting the oldest ones first.
+    /// The default is 100.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.undo.len() > limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Forget all edits that could be undone or redone
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// The newline used by the file: `\r\n` if any line uses it, otherwise `\n`
+    fn newline(&self) -> &'static str {
+        if self.lines.iter().any(|line| line.text.ends_with("\r\n")) {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// Append `entry` as a new line at the end of the file.
+    ///
+    /// This fails if the whole file is generated by another tool, or if the entry wouldn't
+    /// read back as the same entry, see [`HostfileError::InvalidEntry`].
+    pub fn add_entry(&mut self, entry: &HostEntry) -> Result<(), HostfileError> {
+        check_writable(entry)?;
+        self.edit(|file| {
+            file.check_append()?;
+            file.push_line(&entry.to_string());
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Append a line for each of `entries` as a single edit, like [`Extend`] does.
+    ///
+    /// Nothing is added if any entry wouldn't read back as the same entry, see
+    /// [`HostfileError::InvalidEntry`].
+    pub fn try_extend<I: IntoIterator<Item = HostEntry>>(
+        &mut self,
+        entries: I,
+    ) -> Result<(), HostfileError> {
+        let entries: Vec<HostEntry> = entries.into_iter().collect();
+        for entry in &entries {
+            check_writable(entry)?;
+        }
+        self.edit_unchecked(|file| {
+            for entry in &entries {
+                file.push_line(&entry.to_string());
+            }
+        });
+        Ok(())
+    }
+
+    /// Check that lines may be appended to the file
+    fn check_append(&self) -> Result<(), ProtectedRegion> {
+        if !self.force {
+            let regions = self.protected_regions();
+            if let Some(region) = regions
+                .into_iter()
+                .find(|region| region.generator.owns_file())
+            {
+                return Err(region);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append `line`, which doesn't include a newline, to the end of the file
+    fn push_line(&mut self, line: &str) {
+        self.terminate_last_line();
+        let newline = self.newline();
+        let line = self.new_line(format!("{line}{newline}"));
+        self.lines.push(line);
+    }
+
+    /// A line with a new ID
+    fn new_line(&mut self, text: String) -> TextLine {
+        let id = LineId(self.next_id);
+        self.next_id += 1;
+        TextLine { id, text }
+    }
+
+    /// Add a newline to the last line if it is missing one, so lines can be appended
+    fn terminate_last_line(&mut self) {
+        let newline = self.newline();
+        if let Some(last) = self.lines.last_mut() {
+            if !last.text.ends_with('\n') {
+                last.text.push_str(newline);
+            }
+        }
+    }
+
+    /// The lines of the managed section `name`, including its markers. A section without an
+    /// end marker runs to the end of the file.
+    fn managed_section_range(&self, name: &str) -> Option<Range<usize>> {
+        let (begin, end) = section_markers(name);
+        let start = self
+            .lines
+            .iter()
+            .position(|line| line.text.trim_end() == begin)?;
+        let len = self.lines[start..]
+            .iter()
+            .position(|line| line.text.trim_end() == end)
+            .map_or(self.lines.len() - start, |idx| idx + 1);
+        Some(start..start + len)
+    }
+
+    /// The entries of the section `name` managed with [`HostsFile::set_managed_section`]
+    pub fn managed_section(&self, name: &str) -> Option<Vec<HostEntry>> {
+        let range = self.managed_section_range(name)?;
+        let text: String = self.lines[range]
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        let section = HostsFile::parse(&text);
+        Some(section.entries().collect())
+    }
+
+    /// Replace the entries of the section `name`, which is added to the end of the file if it
+    /// doesn't exist yet.
+    ///
+    /// Managed sections are delimited by `# BEGIN hostfile section <name>` and
+    /// `# END hostfile section <name>` comments, and are how tools keep their own entries
+    /// apart from the rest of the file. Nothing outside of the section is changed.
+    ///
+    /// Section names may only contain ASCII letters, digits, `-`, `_` and `.`, so that they
+    /// can't break the markers. Entries without names, or with names that wouldn't read back
+    /// as the same entry, are refused, see [`HostfileError::InvalidEntry`].
+    pub fn set_managed_section(
+        &mut self,
+        name: &str,
+        entries: &[HostEntry],
+    ) -> Result<(), HostfileError> {
+        if !is_section_name(name) {
+            return Err(HostfileError::InvalidSectionName(name.to_string()));
+        }
+        for entry in entries {
+            check_writable(entry)?;
+        }
+        self.edit(|file| {
+            let (begin, end) = section_markers(name);
+            let newline = file.newline();
+            let mut section = vec![file.new_line(format!("{begin}{newline}"))];
+            for entry in entries {
+                let line = file.new_line(format!("{entry}{newline}"));
+                section.push(line);
+            }
+            section.push(file.new_line(format!("{end}{newline}")));
+
+            match file.managed_section_range(name) {
+                Some(range) => {
+                    file.check_lines(range.clone())?;
+                    file.lines.splice(range, section);
+                }
+                None => {
+                    file.check_append()?;
+                    file.terminate_last_line();
+                    file.lines.extend(section);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Remove the managed section `name` and its entries. Returns whether it existed.
+    pub fn remove_managed_section(&mut self, name: &str) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| match file.managed_section_range(name) {
+            Some(range) => {
+                file.check_lines(range.clone())?;
+                file.lines.drain(range);
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    /// The lines of the hostctl profile `name`, including its markers. A profile without an
+    /// end marker runs to the end of the file.
+    fn hostctl_profile_range(&self, name: &str) -> Option<Range<usize>> {
+        let start = self.lines.iter().position(|line| {
+            profile_header(&line.text).is_some_and(|(header, _)| header == name)
+        })?;
+        let len = self.lines[start..]
+            .iter()
+            .position(|line| is_profile_end(&line.text))
+            .map_or(self.lines.len() - start, |idx| idx + 1);
+        Some(start..start + len)
+    }
+
+    /// The hostctl profiles of the file in order, see [`HostctlProfile`]
+    pub fn hostctl_profiles(&self) -> Vec<HostctlProfile> {
+        let mut profiles = Vec::new();
+        let mut index = 0;
+        while index < self.lines.len() {
+            let (name, enabled) = match profile_header(&self.lines[index].text) {
+                Some(header) => header,
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+            let mut profile = HostctlProfile {
+                name: name.to_string(),
+                enabled,
+                entries: Vec::new(),
+            };
+            index += 1;
+            while index < self.lines.len() {
+                let line = strip_newline(self.lines[index].text.as_bytes());
+                let line = String::from_utf8_lossy(line);
+                index += 1;
+                if is_profile_end(&line) {
+                    break;
+                }
+                profile.entries.extend(profile_entry(&line, enabled));
+            }
+            profiles.push(profile);
+        }
+        profiles
+    }
+
+    /// Replace the hostctl profile with the name of `profile`, which is added to the end of
+    /// the file if it doesn't exist yet. Turning a profile on or off is replacing it with
+    /// [`HostctlProfile::enabled`] changed.
+    ///
+    /// Profile names can't be empty or contain whitespace or `#`, so that they can't break the
+    /// header, see [`HostfileError::InvalidSectionName`]. Entries that wouldn't read back as
+    /// the same entry are refused, see [`HostfileError::InvalidEntry`].
+    pub fn set_hostctl_profile(&mut self, profile: &HostctlProfile) -> Result<(), HostfileError> {
+        if !is_profile_name(&profile.name) {
+            return Err(HostfileError::InvalidSectionName(profile.name.clone()));
+        }
+        for entry in &profile.entries {
+            check_writable(entry)?;
+        }
+        self.edit(|file| {
+            let newline = file.newline();
+            let block: Vec<TextLine> = profile
+                .block()
+                .into_iter()
+                .map(|line| file.new_line(format!("{line}{newline}")))
+                .collect();
+            match file.hostctl_profile_range(&profile.name) {
+                Some(range) => {
+                    file.check_lines(range.clone())?;
+                    file.lines.splice(range, block);
+                }
+                None => {
+                    file.check_append()?;
+                    file.terminate_last_line();
+                    file.lines.extend(block);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Remove the hostctl profile `name` and its entries. Returns whether it existed.
+    pub fn remove_hostctl_profile(&mut self, name: &str) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| match file.hostctl_profile_range(name) {
+            Some(range) => {
+                file.check_lines(range.clone())?;
+                file.lines.drain(range);
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    /// Recognize section headers of the forms in `patterns` rather than
+    /// [`SectionPattern::defaults`]
+    pub fn set_section_patterns(&mut self, patterns: Vec<SectionPattern>) {
+        self.section_patterns = patterns;
+    }
+
+    /// The sections of the file introduced by header comments such as `## vpn ##`, in order.
+    ///
+    /// Each section runs up to the next header, or the start of a managed section. Lines
+    /// before the first header aren't part of any section.
+    pub fn sections(&self) -> Vec<Section<'_>> {
+        let mut sections: Vec<Section<'_>> = Vec::new();
+        for (idx, line) in self.lines.iter().enumerate() {
+            let name = header_name(&self.section_patterns, &line.text);
+            if name.is_some() || line.text.starts_with(MANAGED_BEGIN) {
+                if let Some(last) = sections.last_mut() {
+                    if last.range.end == self.lines.len() {
+                        last.range.end = idx;
+                    }
+                }
+            }
+            if let Some(name) = name {
+                sections.push(Section {
+                    name,
+                    range: idx..self.lines.len(),
+                    lines: &[],
+                });
+            }
+        }
+        for section in &mut sections {
+            section.lines = &self.lines[section.range.clone()];
+        }
+        sections
+    }
+
+    /// The first section called `name`, compared case-insensitively
+    pub fn section(&self, name: &str) -> Option<Section<'_>> {
+        self.sections()
+            .into_iter()
+            .find(|section| section.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Replace the entries of the section `name` with `entries`. Returns whether the section
+    /// exists.
+    ///
+    /// The new entries take the place of the first existing entry, or follow the header if
+    /// the section has none. Comments, blank lines and invalid lines in the section are kept.
+    /// Entries that wouldn't read back as the same entry are refused, see
+    /// [`HostfileError::InvalidEntry`].
+    pub fn replace_section(
+        &mut self,
+        name: &str,
+        entries: &[HostEntry],
+    ) -> Result<bool, HostfileError> {
+        for entry in entries {
+            check_writable(entry)?;
+        }
+        let replaced = self.edit(|file| {
+            let range = match file.section(name) {
+                Some(section) => section.range,
+                None => return Ok(false),
+            };
+            file.check_lines(range.clone())?;
+            if range.end == file.lines.len() {
+                file.terminate_last_line();
+            }
+
+            let newline = file.newline();
+            let mut new_entries = Vec::new();
+            for entry in entries {
+                let line = file.new_line(format!("{entry}{newline}"));
+                new_entries.push(line);
+            }
+            let mut body = Vec::new();
+            for line in file.lines.drain(range.start + 1..range.end) {
+                match parse_line_bytes(strip_newline(line.text.as_bytes())) {
+                    Ok(Some(_)) => body.append(&mut new_entries),
+                    _ => body.push(line),
+                }
+            }
+            // The section had no entries to replace
+            body.splice(0..0, new_entries);
+            file.lines.splice(range.start + 1..range.start + 1, body);
+            Ok(true)
+        })?;
+        Ok(replaced)
+    }
+
+    /// Remove the section `name`, including its header. Returns whether it existed.
+    pub fn remove_section(&mut self, name: &str) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| match file.section(name) {
+            Some(section) => {
+                let range = section.range;
+                file.check_lines(range.clone())?;
+                file.lines.drain(range);
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    /// Check that the lines at `range` may be changed
+    fn check_lines(&self, range: Range<usize>) -> Result<(), ProtectedRegion> {
+        if !self.force {
+            let regions = self.protected_regions();
+            if let Some(region) = regions
+                .into_iter()
+                .find(|region| region.lines.start < range.end && range.start < region.lines.end)
+            {
+                return Err(region);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove `name` from every entry, comparing names with [`hostname_eq`].
+    ///
+    /// Only the name and the whitespace separating it from its neighbours are removed from a
+    /// line; lines left without any names are removed entirely. Returns the number of names
+    /// removed. Nothing is removed if any of the names are in a protected region.
+    pub fn remove_name(&mut self, name: &str) -> Result<usize, ProtectedRegion> {
+        self.edit(|file| {
+            file.check_name_removal(name)?;
+            Ok(file.remove_name_unchecked(name))
+        })
+    }
+
+    /// Check that `name` may be removed from every line it is on
+    fn check_name_removal(&self, name: &str) -> Result<(), ProtectedRegion> {
+        if !self.force {
+            for region in self.protected_regions() {
+                let lines = &self.lines[region.lines.clone()];
+                if lines
+                    .iter()
+                    .any(|line| name_removal_range(&line.text, name).is_some())
+                {
+                    return Err(region);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// [`HostsFile::remove_name`] without checking protected regions or recording the edit
+    fn remove_name_unchecked(&mut self, name: &str) -> usize {
+        let mut removed = 0;
+        self.lines.retain_mut(|TextLine { text: line, .. }| {
+            let mut edited = false;
+            while let Some(range) = name_removal_range(line, name) {
+                line.replace_range(range, "");
+                removed += 1;
+                edited = true;
+            }
+            if !edited {
+                return true;
+            }
+
+            // Drop lines that we removed the last name from
+            let mut tokens = Vec::new();
+            lex_line(line, 0..line.len(), &mut tokens);
+            let is_ip_only = tokens.iter().any(|token| token.kind == TokenKind::Ip)
+                && !tokens.iter().any(|token| token.kind == TokenKind::Name);
+            !is_ip_only
+        });
+        removed
+    }
+
+    /// Map the names of this machine to `ip`, or to its primary address if `ip` is `None`.
+    /// Returns the entry for the machine, see [`crate::self_entry`].
+    ///
+    /// The names are removed from other entries and the entry is appended to the file,
+    /// unless the names already resolve to just that address. This fails if the names or
+    /// address of the machine can't be found, or any of the lines to change are in a
+    /// protected region.
+    pub fn add_self(&mut self, ip: Option<IpAddr>) -> Result<HostEntry, HostfileError> {
+        let entry = self_entry(ip)?;
+        let entries: Vec<HostEntry> = self.entries().collect();
+        let up_to_date = entry.names.iter().all(|name| {
+            let mut addrs = entries
+                .iter()
+                .filter(|other| other.names.iter().any(|n| hostname_eq(n, name)))
+                .map(|other| other.ip)
+                .peekable();
+            addrs.peek().is_some() && addrs.all(|ip| ip == entry.ip)
+        });
+        if up_to_date {
+            return Ok(entry);
+        }
+
+        self.edit_unchecked(|file| {
+            for name in &entry.names {
+                file.check_name_removal(name)?;
+            }
+            file.check_append()?;
+            for name in &entry.names {
+                file.remove_name_unchecked(name);
+            }
+            file.push_line(&entry.to_string());
+            Ok(())
+        })
+        .map_err(HostfileError::Protected)?;
+        Ok(entry)
+    }
+
+    /// Exempt the names that `allowlist` allows from the entries of the file. Returns what
+    /// was exempted, one entry per line that was changed.
+    ///
+    /// Entries whose names are all allowed are commented out, like
+    /// [`HostsFile::disable_entry`] does, and only the allowed names are removed from other
+    /// entries. Nothing is changed if any of the entries are in a protected region.
+    pub fn apply_allowlist(
+        &mut self,
+        allowlist: &Allowlist,
+    ) -> Result<Vec<HostEntry>, ProtectedRegion> {
+        self.edit(|file| {
+            let mut changes = Vec::new();
+            for index in 0..file.lines.len() {
+                if let Some((entry, true)) = file.line_entry(index) {
+                    let (allowed, kept): (Vec<String>, Vec<String>) = entry
+                        .names
+                        .into_iter()
+                        .partition(|name| allowlist.is_allowed(name));
+                    if !allowed.is_empty() {
+                        file.check_lines(index..index + 1)?;
+                        changes.push((index, kept.is_empty(), allowed, entry.ip));
+                    }
+                }
+            }
+
+            let mut exempted = Vec::new();
+            for (index, comment_out, names, ip) in changes {
+                let line = &mut file.lines[index].text;
+                if comment_out {
+                    line.insert_str(0, "# ");
+                } else {
+                    for name in &names {
+                        if let Some(range) = name_removal_range(line, name) {
+                            line.replace_range(range, "");
+                        }
+                    }
+                }
+                exempted.push(HostEntry { ip, names });
+            }
+            Ok(exempted)
+        })
+    }
+
+    /// Keep only the entries for which `f` returns true, removing the lines of the others.
+    /// Returns the number of entries removed.
+    ///
+    /// Comments, blank lines, invalid lines and commented out entries are kept, as are the
+    /// lines of retained entries along with their spacing and comments. Nothing is removed if
+    /// any of the entries to remove are in a protected region.
+    pub fn retain(
+        &mut self,
+        mut f: impl FnMut(&HostEntry) -> bool,
+    ) -> Result<usize, ProtectedRegion> {
+        self.edit(|file| {
+            let mut removed = Vec::new();
+            for index in 0..file.lines.len() {
+                if let Some((entry, true)) = file.line_entry(index) {
+                    if !f(&entry) {
+                        file.check_lines(index..index + 1)?;
+                        removed.push(index);
+                    }
+                }
+            }
+            for &index in removed.iter().rev() {
+                file.lines.remove(index);
+            }
+            Ok(removed.len())
+        })
+    }
+
+    /// Replace the address of every entry with what `f` returns for it. Returns the number of
+    /// entries whose address changed.
+    ///
+    /// Only the addresses are rewritten, so names, spacing and comments are kept. Nothing is
+    /// changed if any of the entries to change are in a protected region.
+    pub fn map_ips(
+        &mut self,
+        mut f: impl FnMut(IpAddr) -> IpAddr,
+    ) -> Result<usize, ProtectedRegion> {
+        self.map_tokens(
+            TokenKind::Ip,
+            |text| {
+                let ip: IpAddr = text.parse().ok()?;
+                let mapped = f(ip);
+                (mapped != ip).then(|| mapped.to_string())
+            },
+            |_, _| Ok(()),
+        )
+    }
+
+    /// Replace every name of every entry with what `f` returns for it. Returns the number of
+    /// names changed.
+    ///
+    /// Only the names are rewritten, so addresses, spacing and comments are kept. Nothing is
+    /// changed if any of the entries to change are in a protected region, or if a rewritten
+    /// entry wouldn't read back as the same entry, e.g. because a name is empty or contains a
+    /// newline, see [`HostfileError::InvalidEntry`].
+    pub fn map_names(&mut self, mut f: impl FnMut(&str) -> String) -> Result<usize, HostfileError> {
+        self.map_tokens(
+            TokenKind::Name,
+            |name| {
+                let mapped = f(name);
+                (mapped != name).then_some(mapped)
+            },
+            |entry, names| {
+                check_writable(&HostEntry {
+                    ip: entry.ip,
+                    names,
+                })
+            },
+        )
+    }
+
+    /// Replace the tokens of kind `kind` in the entries of the file with what `f` returns for
+    /// their text, where it returns one. Returns the number of tokens replaced.
+    ///
+    /// `check` is given each entry to change and the texts of its tokens of kind `kind` after
+    /// the change, and nothing is changed if it fails for any of them.
+    fn map_tokens<E: From<ProtectedRegion>>(
+        &mut self,
+        kind: TokenKind,
+        mut f: impl FnMut(&str) -> Option<String>,
+        mut check: impl FnMut(&HostEntry, Vec<String>) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        let mut changes = Vec::new();
+        let mut tokens = Vec::new();
+        for index in 0..self.lines.len() {
+            let entry = match self.line_entry(index) {
+                Some((entry, true)) => entry,
+                _ => continue,
+            };
+            let line = &self.lines[index].text;
+            tokens.clear();
+            lex_line(line, 0..line.len(), &mut tokens);
+            let mut texts = Vec::new();
+            let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+            for token in tokens.iter().filter(|token| token.kind == kind) {
+                let text = &line[token.range.clone()];
+                match f(text) {
+                    Some(mapped) => {
+                        texts.push(mapped.clone());
+                        replacements.push((token.range.clone(), mapped));
+                    }
+                    None => texts.push(text.to_string()),
+                }
+            }
+            if !replacements.is_empty() {
+                check(&entry, texts)?;
+                self.check_lines(index..index + 1)?;
+                changes.push((index, replacements));
+            }
+        }
+
+        let replaced = self.edit(|file| {
+            let mut replaced = 0;
+            for (index, replacements) in changes {
+                let line = &mut file.lines[index].text;
+                // Last first, so the earlier ranges stay valid
+                for (range, text) in replacements.into_iter().rev() {
+                    line.replace_range(range, &text);
+                    replaced += 1;
+                }
+            }
+            Ok(replaced)
+        })?;
+        Ok(replaced)
+    }
+
+    /// Change the file to match `state`, returning how its entries changed.
+    ///
+    /// Managed sections are replaced as a whole, names to remove are removed like
+    /// [`HostsFile::remove_name`] does, and the entries of any name that doesn't resolve to
+    /// exactly its addresses in `state` are replaced by appending the desired entries. Parts
+    /// of the file that already match are left alone, so applying the same state again
+    /// changes nothing. Fails if a name is declared more than once in `state` or the changes
+    /// would touch a protected region, leaving the file as it was.
+    pub fn apply_state(&mut self, state: &DesiredState) -> Result<Vec<WatchEvent>, HostfileError> {
+        state.check()?;
+        let before: Vec<HostEntry> = self.entries().collect();
+        let mut changed = self.clone();
+        changed.clear_history();
+        changed.apply_state_steps(state)?;
+        let after: Vec<HostEntry> = changed.entries().collect();
+        // Record the whole change as one edit
+        self.edit_unchecked(|file| {
+            file.lines = changed.lines;
+            file.next_id = changed.next_id;
+        });
+        Ok(diff_entries(&before, &after))
+    }
+
+    /// The edits of [`HostsFile::apply_state`], each recorded separately
+    fn apply_state_steps(&mut self, state: &DesiredState) -> Result<(), HostfileError> {
+        for (name, entries) in &state.sections {
+            if self.managed_section(name).as_ref() != Some(entries) {
+                self.set_managed_section(name, entries)?;
+            }
+        }
+        for name in &state.remove {
+            self.remove_name(name)?;
+        }
+
+        let current: Vec<HostEntry> = self.entries().collect();
+        let addrs = |entries: &[HostEntry], name: &str| -> HashSet<IpAddr> {
+            entries
+                .iter()
+                .filter(|entry| entry.names.iter().any(|n| hostname_eq(n, name)))
+                .map(|entry| entry.ip)
+                .collect()
+        };
+        let stale: Vec<&HostEntry> = state
+            .entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .names
+                    .iter()
+                    .any(|name| addrs(&current, name) != addrs(&state.entries, name))
+            })
+            .collect();
+        for entry in &stale {
+            for name in &entry.names {
+                self.remove_name(name)?;
+            }
+        }
+        for entry in stale {
+            self.add_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure `localhost` resolves to `127.0.0.1` and `::1`, which many programs rely on.
+    /// Returns whether the file was changed.
+    ///
+    /// `localhost` is removed from entries that give it any other address, dropping lines
+    /// left without names, and the missing entries are inserted before the first entry of the
+    /// file. Other lines are left alone. Nothing is changed if any of the lines to change are
+    /// in a protected region.
+    pub fn ensure_localhost(&mut self) -> Result<bool, ProtectedRegion> {
+        let loopback = [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ];
+        self.edit_unchecked(|file| {
+            let mut missing = loopback.to_vec();
+            let mut wrong = Vec::new();
+            let mut first_entry = None;
+            for index in 0..file.lines.len() {
+                if let Some((entry, true)) = file.line_entry(index) {
+                    first_entry.get_or_insert(index);
+                    if !entry
+                        .names
+                        .iter()
+                        .any(|name| hostname_eq(name, "localhost"))
+                    {
+                        continue;
+                    }
+                    if loopback.contains(&entry.ip) {
+                        missing.retain(|&ip| ip != entry.ip);
+                    } else {
+                        file.check_lines(index..index + 1)?;
+                        wrong.push(index);
+                    }
+                }
+            }
+            // Only lines from the first entry on are removed, so this stays in place
+            let at = first_entry.unwrap_or(file.lines.len());
+            if !missing.is_empty() {
+                file.check_insert(at)?;
+            }
+
+            for &index in wrong.iter().rev() {
+                let line = &mut file.lines[index].text;
+                while let Some(range) = name_removal_range(line, "localhost") {
+                    line.replace_range(range, "");
+                }
+                let mut tokens = Vec::new();
+                lex_line(line, 0..line.len(), &mut tokens);
+                if !tokens.iter().any(|token| token.kind == TokenKind::Name) {
+                    file.lines.remove(index);
+                }
+            }
+            if at == file.lines.len() {
+                file.terminate_last_line();
+            }
+            let newline = file.newline();
+            for (offset, &ip) in missing.iter().enumerate() {
+                let entry = HostEntry {
+                    ip,
+                    names: vec![String::from("localhost")],
+                };
+                let line = file.new_line(format!("{entry}{newline}"));
+                file.lines.insert(at + offset, line);
+            }
+            Ok(!missing.is_empty() || !wrong.is_empty())
+        })
+    }
+
+    /// Whether `hostname` resolves to `127.0.1.1`, as Debian and Ubuntu set up machines
+    /// without a permanent address. See [`HostsFile::ensure_debian_hostname`].
+    pub fn debian_hostname(&self, hostname: &str) -> HostnameStatus {
+        let mut addrs: Vec<IpAddr> = Vec::new();
+        for entry in self.entries() {
+            if !addrs.contains(&entry.ip) && entry.names.iter().any(|n| hostname_eq(n, hostname)) {
+                addrs.push(entry.ip);
+            }
+        }
+        match addrs.first() {
+            None => HostnameStatus::Missing,
+            Some(&ip) if ip == DEBIAN_HOSTNAME_IP => HostnameStatus::Present,
+            Some(_) => HostnameStatus::Elsewhere(addrs),
+        }
+    }
+
+    /// Map `hostname` to `127.0.1.1` like Debian and Ubuntu do, followed by its first label
+    /// if it is fully qualified, as in `127.0.1.1 box.example.org box`. Returns whether the
+    /// file was changed.
+    ///
+    /// The names of the first `127.0.1.1` entry are replaced, keeping its spacing and
+    /// comment. Without one, an entry is added after the first `127.0.0.1` entry. Other
+    /// entries for `hostname` are left alone, so one that comes first still takes precedence,
+    /// see [`HostsFile::debian_hostname`].
+    ///
+    /// This fails if a line to change is in a protected region, or if `hostname` can't be
+    /// written, see [`HostfileError::InvalidEntry`].
+    pub fn ensure_debian_hostname(&mut self, hostname: &str) -> Result<bool, HostfileError> {
+        let mut names = vec![hostname.to_string()];
+        if let Some((short, _)) = hostname
+            .split_once('.')
+            .filter(|(short, _)| !short.is_empty())
+        {
+            names.push(short.to_string());
+        }
+        let entry = HostEntry {
+            ip: DEBIAN_HOSTNAME_IP,
+            names,
+        };
+        check_writable(&entry)?;
+        let names = &entry.names;
+        let changed = self.edit_unchecked(|file| -> Result<bool, ProtectedRegion> {
+            let mut loopback = None;
+            for index in 0..file.lines.len() {
+                match file.line_entry(index) {
+                    Some((entry, true)) if entry.ip == DEBIAN_HOSTNAME_IP => {
+                        let same = entry.names.len() == names.len()
+                            && entry
+                                .names
+                                .iter()
+                                .zip(names)
+                                .all(|(a, b)| hostname_eq(a, b));
+                        if same {
+                            return Ok(false);
+                        }
+                        file.check_lines(index..index + 1)?;
+                        let line = &mut file.lines[index].text;
+                        let mut tokens = Vec::new();
+                        lex_line(line, 0..line.len(), &mut tokens);
+                        let is_name = |token: &&Token| token.kind == TokenKind::Name;
+                        let first = tokens.iter().find(is_name);
+                        let last = tokens.iter().rev().find(is_name);
+                        if let (Some(first), Some(last)) = (first, last) {
+                            line.replace_range(first.range.start..last.range.end, &names.join(" "));
+                        }
+                        return Ok(true);
+                    }
+                    Some((entry, true)) if entry.ip == IpAddr::V4(Ipv4Addr::LOCALHOST) => {
+                        loopback.get_or_insert(index + 1);
+                    }
+                    _ => {}
+                }
+            }
+
+            let at = loopback
+                .or_else(|| (0..file.lines.len()).find(|&i| file.line_entry(i).is_some()))
+                .unwrap_or(file.lines.len());
+            file.check_insert(at)?;
+            if at == file.lines.len() {
+                file.terminate_last_line();
+            }
+            let newline = file.newline();
+            let line = file.new_line(format!("{entry}{newline}"));
+            file.lines.insert(at, line);
+            Ok(true)
+        })?;
+        Ok(changed)
+    }
+
+    /// The number of lines in the file
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The handle of the line at `index`
+    pub fn line_id(&self, index: usize) -> Option<LineId> {
+        self.lines.get(index).map(|line| line.id)
+    }
+
+    /// The current index of the line `id`, or `None` if it has been removed
+    pub fn line_index(&self, id: LineId) -> Option<usize> {
+        self.lines.iter().position(|line| line.id == id)
+    }
+
+    /// The index of the first line with an entry for `name`, comparing names with
+    /// [`hostname_eq`]
+    pub fn position_of(&self, name: &str) -> Option<usize> {
+        self.lines.iter().position(|line| {
+            match parse_line_bytes(strip_newline(line.text.as_bytes())) {
+                Ok(Some(entry)) => entry.names.iter().any(|n| hostname_eq(n, name)),
+                _ => false,
+            }
+        })
+    }
+
+    /// Insert `entry` as a new line before the line at `index`, or at the end of the file if
+    /// `index` is [`HostsFile::line_count`]. Returns the handle of the new line.
+    ///
+    /// This fails if the line would be inside a protected region, or if the entry wouldn't
+    /// read back as the same entry, see [`HostfileError::InvalidEntry`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of lines.
+    pub fn insert_at(&mut self, index: usize, entry: &HostEntry) -> Result<LineId, HostfileError> {
+        check_writable(entry)?;
+        let id = self.edit(|file| {
+            assert!(
+                index <= file.lines.len(),
+                "insertion index is out of bounds"
+            );
+            file.check_insert(index)?;
+            if index == file.lines.len() {
+                file.terminate_last_line();
+            }
+            let newline = file.newline();
+            let line = file.new_line(format!("{entry}{newline}"));
+            let id = line.id;
+            file.lines.insert(index, line);
+            Ok(id)
+        })?;
+        Ok(id)
+    }
+
+    /// Move the line `id` so that it ends up at `index`, keeping its handle. Returns whether
+    /// the line exists.
+    ///
+    /// This fails if the line is in a protected region, or would be moved into one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than the number of lines.
+    pub fn move_entry(&mut self, id: LineId, index: usize) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| {
+            assert!(index < file.lines.len(), "move index is out of bounds");
+            let from = match file.line_index(id) {
+                Some(from) => from,
+                None => return Ok(false),
+            };
+            file.check_lines(from..from + 1)?;
+
+            let mut line = file.lines.remove(from);
+            if let Err(region) = file.check_insert(index) {
+                file.lines.insert(from, line);
+                return Err(region);
+            }
+            if !line.text.ends_with('\n') {
+                line.text.push_str(file.newline());
+            }
+            if index == file.lines.len() {
+                file.terminate_last_line();
+            }
+            file.lines.insert(index, line);
+            Ok(true)
+        })
+    }
+
+    /// The text of the line at `index`, without its newline
+    pub fn line(&self, index: usize) -> Option<&str> {
+        let line = self.lines.get(index)?;
+        let text = line.text.strip_suffix('\n').unwrap_or(&line.text);
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    /// Every line of the file with its handle, in order
+    ///
+    /// ```
+    /// # use hostfile::{HostsFile, Line};
+    /// let file = HostsFile::parse("# dev\n\n10.0.0.1 web # staging\n");
+    /// let comments = file
+    ///     .lines()
+    ///     .filter(|(_, line)| matches!(line, Line::Entry { comment: Some(_), .. }))
+    ///     .count();
+    /// assert_eq!(comments, 1);
+    /// ```
+    pub fn lines(&self) -> impl Iterator<Item = (LineId, Line<'_>)> + '_ {
+        (0..self.lines.len()).map(move |index| {
+            let line = self.line(index).unwrap_or_default();
+            let kind = match parse_line_bytes(line.as_bytes()) {
+                Ok(Some(entry)) => Line::Entry {
+                    entry: entry.into_owned(),
+                    comment: inline_comment(line),
+                },
+                Ok(None) => match line.trim_start().strip_prefix('#') {
+                    Some(text) => Line::Comment(text.trim_matches([' ', '\t'])),
+                    None => Line::Blank,
+                },
+                Err(_) => Line::Invalid(line),
+            };
+            (self.lines[index].id, kind)
+        })
+    }
+
+    /// Replace the entry on the line `id` with `entry`, keeping the line's indentation and
+    /// the comment after its names. Returns whether the line is an enabled entry.
+    ///
+    /// This fails if the line is in a protected region, or if the entry wouldn't read back as
+    /// the same entry, see [`HostfileError::InvalidEntry`].
+    pub fn set_entry(&mut self, id: LineId, entry: &HostEntry) -> Result<bool, HostfileError> {
+        check_writable(entry)?;
+        let set = self.edit(|file| {
+            let index = match file.line_index(id) {
+                Some(index) if matches!(file.line_entry(index), Some((_, true))) => index,
+                _ => return Ok(false),
+            };
+            file.check_lines(index..index + 1)?;
+            let content = file.line(index).unwrap_or_default();
+            let mut tokens = Vec::new();
+            lex_line(content, 0..content.len(), &mut tokens);
+            let entry_tokens: Vec<_> = tokens
+                .iter()
+                .filter(|token| matches!(token.kind, TokenKind::Ip | TokenKind::Name))
+                .collect();
+            let start = entry_tokens.first().map_or(0, |token| token.range.start);
+            let end = entry_tokens.last().map_or(0, |token| token.range.end);
+            file.lines[index]
+                .text
+                .replace_range(start..end, &entry.to_string());
+            Ok(true)
+        })?;
+        Ok(set)
+    }
+
+    /// The entry on the line at `index` and whether it is enabled. Disabled entries are
+    /// commented out, as with [`HostsFile::disable_entry`].
+    pub fn line_entry(&self, index: usize) -> Option<(HostEntry, bool)> {
+        let line = self.line(index)?;
+        if let Ok(Some(entry)) = parse_line_bytes(line.as_bytes()) {
+            return Some((entry.into_owned(), true));
+        }
+        disabled_entry(line).map(|entry| (entry, false))
+    }
+
+    /// The comment after the names of the entry on the line at `index`, without the `#` and
+    /// surrounding whitespace. Tools can tag the entries they add with a comment to find
+    /// them again later.
+    pub fn entry_comment(&self, index: usize) -> Option<&str> {
+        inline_comment(self.line(index)?)
+    }
+
+    /// Replace the comment after the names of the entry on the line `id`, or remove it if
+    /// `comment` is `None`. Returns whether the line is an enabled entry.
+    ///
+    /// This fails if the line is in a protected region.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `comment` contains a line break.
+    pub fn set_entry_comment(
+        &mut self,
+        id: LineId,
+        comment: Option<&str>,
+    ) -> Result<bool, ProtectedRegion> {
+        assert!(
+            !comment.is_some_and(|comment| comment.contains(['\n', '\r'])),
+            "comment contains a line break"
+        );
+        self.edit(|file| {
+            let index = match file.line_index(id) {
+                Some(index) if matches!(file.line_entry(index), Some((_, true))) => index,
+                _ => return Ok(false),
+            };
+            file.check_lines(index..index + 1)?;
+            let content = file.line(index).unwrap_or_default();
+            let len = content.len();
+            let end = comment_range(content).map_or(len, |range| range.start);
+            let mut text = content[..end].trim_end().to_string();
+            if let Some(comment) = comment {
+                text.push_str(" # ");
+                text.push_str(comment);
+            }
+            file.lines[index].text.replace_range(..len, &text);
+            Ok(true)
+        })
+    }
+
+    /// Remove the line `id`. Returns whether it existed.
+    pub fn remove_line(&mut self, id: LineId) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| match file.line_index(id) {
+            Some(index) => {
+                file.check_lines(index..index + 1)?;
+                file.lines.remove(index);
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    /// Comment out the entry on the line `id`, so that it can be enabled again later with
+    /// [`HostsFile::enable_entry`]. Returns whether the line was an enabled entry.
+    pub fn disable_entry(&mut self, id: LineId) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| {
+            let index = match file.line_index(id) {
+                Some(index) if matches!(file.line_entry(index), Some((_, true))) => index,
+                _ => return Ok(false),
+            };
+            file.check_lines(index..index + 1)?;
+            file.lines[index].text.insert_str(0, "# ");
+            Ok(true)
+        })
+    }
+
+    /// Uncomment the entry on the line `id` that was disabled with
+    /// [`HostsFile::disable_entry`]. Returns whether the line was a disabled entry.
+    pub fn enable_entry(&mut self, id: LineId) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| {
+            let index = match file.line_index(id) {
+                Some(index) if matches!(file.line_entry(index), Some((_, false))) => index,
+                _ => return Ok(false),
+            };
+            file.check_lines(index..index + 1)?;
+            let text = &mut file.lines[index].text;
+            let hash = text.find('#').unwrap_or_default();
+            let rest = text[hash + 1..].trim_start_matches([' ', '\t']);
+            let end = text.len() - rest.len();
+            text.replace_range(..end, "");
+            Ok(true)
+        })
+    }
+
+    /// The lines that are only a comment, other than disabled entries, as their index and the
+    /// text of the comment after the `#` and any spaces
+    pub fn comments(&self) -> impl Iterator<Item = (usize, &str)> + '_ {
+        (0..self.lines.len()).filter_map(move |index| {
+            let line = self.line(index)?;
+            let text = line.trim_start().strip_prefix('#')?;
+            if disabled_entry(line).is_some() {
+                return None;
+            }
+            Some((index, text.trim_matches([' ', '\t'])))
+        })
+    }
+
+    /// Add a comment line with `text` just before the line `entry`, indented like it. Each line
+    /// of `text` becomes a comment line of its own. Returns the handle of the first new line,
+    /// or `None` if `entry` has been removed.
+    ///
+    /// This fails if the comment would be inside a protected region.
+    pub fn add_comment_before(
+        &mut self,
+        entry: LineId,
+        text: &str,
+    ) -> Result<Option<LineId>, ProtectedRegion> {
+        self.edit(|file| {
+            let index = match file.line_index(entry) {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+            file.check_insert(index)?;
+            let line = &file.lines[index].text;
+            let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            let indent = indent.to_string();
+            let newline = file.newline();
+            let comments: Vec<TextLine> = text
+                .lines()
+                .map(|text| {
+                    let comment = format!("{indent}# {text}");
+                    file.new_line(format!("{}{newline}", comment.trim_end()))
+                })
+                .collect();
+            let first = comments.first().map(|line| line.id);
+            file.lines.splice(index..index, comments);
+            Ok(first)
+        })
+    }
+
+    /// Remove the comment line at `index`, as listed by [`HostsFile::comments`]. Returns whether
+    /// the line was such a comment.
+    pub fn remove_comment(&mut self, index: usize) -> Result<bool, ProtectedRegion> {
+        self.edit(|file| {
+            if !file.comments().any(|(comment, _)| comment == index) {
+                return Ok(false);
+            }
+            file.check_lines(index..index + 1)?;
+            file.lines.remove(index);
+            Ok(true)
+        })
+    }
+
+    /// Check that a line may be inserted before the line at `index`
+    fn check_insert(&self, index: usize) -> Result<(), ProtectedRegion> {
+        self.check_append()?;
+        if !self.force {
+            let regions = self.protected_regions();
+            if let Some(region) = regions
+                .into_iter()
+                .find(|region| region.lines.start < index && index < region.lines.end)
+            {
+                return Err(region);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the file, exactly as parsed apart from any edits
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for line in &self.lines {
+            w.write_all(line.text.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Async version of [`HostsFile::write_to`]
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, mut w: W) -> io::Result<()> {
+        for line in &self.lines {
+            w.write_all(line.text.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Atomically replace the file at `path` with this file, see [`crate::write_file`]
+    pub fn write(
+        &self,
+        path: &Path,
+        options: &WriteOptions,
+    ) -> Result<Vec<WriteWarning>, HostfileError> {
+        replace_file(path, self.to_string().as_bytes(), options)
+    }
+}
+
+impl fmt::Display for HostsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            f.write_str(&line.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The entry in `line` if it is a comment containing only an entry
+fn disabled_entry(line: &str) -> Option<HostEntry> {
+    let rest = line.trim_start().strip_prefix('#')?;
+    match parse_line_bytes(rest.as_bytes()) {
+        Ok(Some(entry)) => Some(entry.into_owned()),
+        _ => None,
+    }
+}
+
+/// Refuse `entry` if writing it wouldn't read back as the same entry, e.g. because a name
+/// contains a newline, see [`HostfileError::InvalidEntry`]
+fn check_writable(entry: &HostEntry) -> Result<(), HostfileError> {
+    entry
+        .check_writable()
+        .map_err(|kind| HostfileError::InvalidEntry {
+            entry: entry.clone(),
+            kind,
+        })
+}
+
+/// Start of the begin marker of every managed section
+const MANAGED_BEGIN: &str = "# BEGIN hostfile section ";
+
+/// The addresses of `name` in `mappings`, see [`HostsFile::check_safe_mode`]
+fn addresses<'a>(
+    mappings: &'a HashMap<String, Vec<(IpAddr, usize)>>,
+    name: &str,
+) -> &'a [(IpAddr, usize)] {
+    mappings.get(name).map_or(&[], Vec::as_slice)
+}
+
+/// Whether `name` can be used for a managed section, see [`HostsFile::set_managed_section`]
+pub(crate) fn is_section_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// The begin and end markers of the managed section `name`
+fn section_markers(name: &str) -> (String, String) {
+    (
+        format!("{MANAGED_BEGIN}{name}"),
+        format!("# END hostfile section {name}"),
+    )
+}
+
+/// The byte range to delete from `line` to remove the first occurrence of `name`.
+///
+/// The whitespace before the name is removed with it, or the whitespace after it if it's the
+/// first name on the line, so that the rest of the line keeps its spacing. Lines with only one
+/// name return the range of the name alone.
+fn name_removal_range(line: &str, name: &str) -> Option<Range<usize>> {
+    let mut tokens = Vec::new();
+    lex_line(line, 0..line.len(), &mut tokens);
+
+    let idx = tokens.iter().position(|token| {
+        token.kind == TokenKind::Name && hostname_eq(&line[token.range.clone()], name)
+    })?;
+    let is_ws = |idx: usize| {
+        tokens
+            .get(idx)
+            .filter(|token| token.kind == TokenKind::Whitespace)
+    };
+
+    let range = tokens[idx].range.clone();
+    let is_first_name = !tokens[..idx]
+        .iter()
+        .any(|token| token.kind == TokenKind::Name);
+    let next_is_name = tokens
+        .get(idx + 2)
+        .is_some_and(|token| token.kind == TokenKind::Name);
+
+    if is_first_name && next_is_name {
+        let end = is_ws(idx + 1).map_or(range.end, |ws| ws.range.end);
+        Some(range.start..end)
+    } else if !is_first_name {
+        let start = is_ws(idx - 1).map_or(range.start, |ws| ws.range.start);
+        Some(start..range.end)
+    } else {
+        Some(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseErrorKind;
+
+    const WEIRD: &str = "# my hosts\r\n\
+        127.0.0.1\t  localhost   loopback\t# keep me\r\n\
+        \r\n\
+        not a valid line\r\n\
+        10.0.0.1 web  www   api\r\n";
+
+    #[test]
+    fn unedited_round_trip() {
+        let file = HostsFile::parse(WEIRD);
+        assert_eq!(file.to_string(), WEIRD);
+        assert_eq!(
+            file.entries().collect::<Vec<_>>(),
+            vec![
+                "127.0.0.1 localhost loopback".parse().unwrap(),
+                "10.0.0.1 web www api".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_name_splices_minimally() {
+        let mut file = HostsFile::parse(WEIRD);
+        assert_eq!(file.remove_name("WWW"), Ok(1));
+        assert_eq!(file.remove_name("loopback"), Ok(1));
+        assert_eq!(
+            file.to_string(),
+            "# my hosts\r\n\
+             127.0.0.1\t  localhost\t# keep me\r\n\
+             \r\n\
+             not a valid line\r\n\
+             10.0.0.1 web   api\r\n"
+        );
+
+        assert_eq!(file.remove_name("web"), Ok(1));
+        assert_eq!(file.remove_name("missing"), Ok(0));
+        let refused = file.remove_name("localhost").unwrap_err();
+        assert_eq!(refused.generator, Generator::SafeMode);
+        assert_eq!(refused.lines, 1..2);
+        file.set_safe_mode(false);
+        assert_eq!(file.remove_name("localhost"), Ok(1));
+        assert_eq!(
+            file.to_string(),
+            "# my hosts\r\n\
+             \r\n\
+             not a valid line\r\n\
+             10.0.0.1 api\r\n"
+        );
+    }
+
+    #[test]
+    fn add_entry_keeps_line_endings() {
+        let mut file = HostsFile::parse("# crlf\r\n127.0.0.1 localhost");
+        file.add_entry(&"10.0.0.1 web".parse().unwrap()).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "# crlf\r\n127.0.0.1 localhost\r\n10.0.0.1\tweb\r\n"
+        );
+
+        let mut file = HostsFile::new();
+        file.add_entry(&"10.0.0.1 web".parse().unwrap()).unwrap();
+        assert_eq!(file.to_string(), "10.0.0.1\tweb\n");
+    }
+
+    #[test]
+    fn unwritable_entries_are_refused() {
+        let source = "127.0.0.1 localhost\n";
+        let mut file = HostsFile::parse(source);
+        let injected = HostEntry {
+            ip: "10.0.0.1".parse().unwrap(),
+            names: vec![String::from("x\n0.0.0.0 bank.example")],
+        };
+        let invalid = |err: Option<HostfileError>| {
+            matches!(
+                err,
+                Some(HostfileError::InvalidEntry {
+                    kind: ParseErrorKind::InvalidHostnameChar('\n'),
+                    ..
+                })
+            )
+        };
+        assert!(invalid(file.add_entry(&injected).err()));
+        assert!(invalid(file.insert_at(0, &injected).err()));
+        let loopback = file.line_id(0).unwrap();
+        assert!(invalid(file.set_entry(loopback, &injected).err()));
+        assert_eq!(file.to_string(), source);
+        assert!(!file.can_undo());
+
+        let source = "## vpn ##\n10.8.0.1 gateway\n";
+        let mut file = HostsFile::parse(source);
+        assert!(invalid(
+            file.replace_section("vpn", std::slice::from_ref(&injected))
+                .err()
+        ));
+        assert_eq!(file.to_string(), source);
+        assert!(!file.can_undo());
+    }
+
+    #[test]
+    fn protected_regions_are_kept() {
+        let contents = "127.0.0.1 localhost\n\
+            # Added by Docker Desktop\n\
+            192.168.1.2 host.docker.internal localhost\n\
+            # End of section\n";
+        let mut file = HostsFile::parse(contents);
+        let err = file.remove_name("localhost").unwrap_err();
+        assert_eq!(err.lines, 1..4);
+        assert_eq!(file.to_string(), contents);
+        assert_eq!(file.remove_name("missing"), Ok(0));
+        file.add_entry(&"10.0.0.1 web".parse().unwrap()).unwrap();
+
+        file.set_force(true);
+        assert_eq!(file.remove_name("localhost"), Ok(2));
+
+        let mut file =
+            HostsFile::parse("# This file was automatically generated by WSL.\n::1 localhost\n");
+        assert!(file.add_entry(&"10.0.0.1 web".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn managed_sections() {
+        let mut file = HostsFile::parse("127.0.0.1 localhost");
+        let entries: Vec<HostEntry> = vec!["10.0.0.1 web".parse().unwrap()];
+        file.set_managed_section("vpn", &entries).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n\
+             # BEGIN hostfile section vpn\n\
+             10.0.0.1\tweb\n\
+             # END hostfile section vpn\n"
+        );
+        assert_eq!(file.managed_section("vpn"), Some(entries));
+        assert_eq!(file.managed_section("other"), None);
+
+        file.add_entry(&"::1 localhost".parse().unwrap()).unwrap();
+        file.set_managed_section("vpn", &[]).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n\
+             # BEGIN hostfile section vpn\n\
+             # END hostfile section vpn\n\
+             ::1\tlocalhost\n"
+        );
+        assert_eq!(file.remove_managed_section("vpn"), Ok(true));
+        assert_eq!(file.remove_managed_section("vpn"), Ok(false));
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n::1\tlocalhost\n");
+
+        let injected = HostEntry {
+            ip: "10.0.0.1".parse().unwrap(),
+            names: vec![String::from("web\n0.0.0.0 bank.example")],
+        };
+        assert_eq!(
+            file.set_managed_section("vpn", std::slice::from_ref(&injected)),
+            Err(HostfileError::InvalidEntry {
+                entry: injected,
+                kind: ParseErrorKind::InvalidHostnameChar('\n'),
+            })
+        );
+        let unnamed = HostEntry {
+            ip: "10.0.0.1".parse().unwrap(),
+            names: Vec::new(),
+        };
+        assert!(file.set_managed_section("vpn", &[unnamed]).is_err());
+        assert_eq!(
+            file.set_managed_section("vpn\n# END", &[]),
+            Err(HostfileError::InvalidSectionName(String::from(
+                "vpn\n# END"
+            )))
+        );
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n::1\tlocalhost\n");
+    }
+
+    #[test]
+    fn header_sections() {
+        let contents = "127.0.0.1 localhost\n\
+            ## vpn ##\n\
+            10.8.0.1 gateway\n\
+            # office printer\n\
+            10.8.0.5 printer\n\
+            \n\
+            # --- blocklist ---\n\
+            0.0.0.0 ads.example\n\
+            # BEGIN hostfile section tool\n\
+            10.0.0.1 web\n\
+            # END hostfile section tool\n";
+        let mut file = HostsFile::parse(contents);
+        let sections = file.sections();
+        assert_eq!(sections.len(), 2);
+        assert_eq!((sections[0].name(), sections[0].lines()), ("vpn", 1..6));
+        assert_eq!(
+            (sections[1].name(), sections[1].lines()),
+            ("blocklist", 6..8)
+        );
+        assert_eq!(
+            file.section("VPN").unwrap().entries(),
+            vec![
+                "10.8.0.1 gateway".parse().unwrap(),
+                "10.8.0.5 printer".parse().unwrap(),
+            ]
+        );
+
+        let entries: Vec<HostEntry> = vec!["10.9.0.1 gateway".parse().unwrap()];
+        assert_eq!(file.replace_section("vpn", &entries), Ok(true));
+        assert_eq!(file.remove_section("blocklist"), Ok(true));
+        assert_eq!(file.remove_section("blocklist"), Ok(false));
+        assert_eq!(file.replace_section("missing", &entries), Ok(false));
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n\
+             ## vpn ##\n\
+             10.9.0.1\tgateway\n\
+             # office printer\n\
+             \n\
+             # BEGIN hostfile section tool\n\
+             10.0.0.1 web\n\
+             # END hostfile section tool\n"
+        );
+
+        let mut file = HostsFile::parse("# [office]\n# nothing yet");
+        file.set_section_patterns(vec![SectionPattern::new("# [", "]")]);
+        file.replace_section("office", &entries).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "# [office]\n10.9.0.1\tgateway\n# nothing yet\n"
+        );
+    }
+
+    #[test]
+    fn stable_line_ids() {
+        let mut file = HostsFile::parse(
+            "# Added by Docker Desktop\n\
+             192.168.1.2 host.docker.internal\n\
+             # End of section\n\
+             10.0.0.1 web www\n\
+             127.0.0.1 localhost",
+        );
+        let web = file.line_id(3).unwrap();
+        let localhost = file.line_id(4).unwrap();
+        assert_eq!(file.position_of("LOCALHOST"), Some(4));
+        assert_eq!(file.position_of("missing"), None);
+
+        assert!(file.insert_at(1, &"10.0.0.9 x".parse().unwrap()).is_err());
+        let db = file.insert_at(3, &"10.0.0.2 db".parse().unwrap()).unwrap();
+        assert_eq!(file.move_entry(localhost, 0), Ok(true));
+        assert_eq!(file.remove_name("www"), Ok(1));
+        assert_eq!(file.line_index(web), Some(5));
+        assert_eq!(file.line_index(db), Some(4));
+        assert!(file.move_entry(db, 2).is_err());
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n\
+             # Added by Docker Desktop\n\
+             192.168.1.2 host.docker.internal\n\
+             # End of section\n\
+             10.0.0.2\tdb\n\
+             10.0.0.1 web\n"
+        );
+
+        assert_eq!(file.remove_name("db"), Ok(1));
+        assert_eq!(file.line_index(db), None);
+        assert_eq!(file.move_entry(db, 0), Ok(false));
+    }
+
+    #[test]
+    fn allowlist_comments_out_entries() {
+        let mut file = HostsFile::parse(
+            "0.0.0.0 ads.example.org example.com # merged\n\
+             0.0.0.0 www.example.com\n\
+             # 0.0.0.0 example.com\n",
+        );
+        let allowlist: Allowlist = ["*.example.com", "example.com"].iter().collect();
+        let exempted = file.apply_allowlist(&allowlist).unwrap();
+        assert_eq!(
+            file.to_string(),
+            "0.0.0.0 ads.example.org # merged\n\
+             # 0.0.0.0 www.example.com\n\
+             # 0.0.0.0 example.com\n"
+        );
+        assert_eq!(
+            exempted,
+            vec![
+                "0.0.0.0 example.com".parse().unwrap(),
+                "0.0.0.0 www.example.com".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn bulk_transforms() {
+        let mut file = HostsFile::parse(
+            "# lab\n\
+             10.0.0.1\tweb.lab  web # frontend\n\
+             10.0.0.2 db.lab db\n\
+             # 10.0.0.3 old.lab\n\
+             192.168.1.1 router\n",
+        );
+        let moved = file
+            .map_ips(|ip| match ip {
+                IpAddr::V4(v4) if v4.octets()[0] == 10 => {
+                    IpAddr::V4(Ipv4Addr::from(u32::from(v4) + 256))
+                }
+                ip => ip,
+            })
+            .unwrap();
+        assert_eq!(moved, 2);
+        let renamed = file
+            .map_names(|name| name.replace(".lab", ".example.org"))
+            .unwrap();
+        assert_eq!(renamed, 2);
+        assert_eq!(file.retain(|entry| entry.names.len() > 1), Ok(1));
+        assert_eq!(
+            file.to_string(),
+            "# lab\n\
+             10.0.1.1\tweb.example.org  web # frontend\n\
+             10.0.1.2 db.example.org db\n\
+             # 10.0.0.3 old.lab\n"
+        );
+        assert_eq!(file.map_names(str::to_string), Ok(0));
+        assert!(file.undo());
+        assert!(file.to_string().ends_with("192.168.1.1 router\n"));
+    }
+
+    #[test]
+    fn invalid_mapped_names_are_refused() {
+        let source = "10.0.0.1 web\n10.0.0.2 db\n";
+        let mut file = HostsFile::parse(source);
+        for (mapped, kind) in [
+            (
+                "web\n1.2.3.4 injected",
+                ParseErrorKind::InvalidHostnameChar('\n'),
+            ),
+            ("", ParseErrorKind::MissingHostname),
+        ] {
+            let err = file
+                .map_names(|name| match name {
+                    "web" => String::from(mapped),
+                    name => name.to_uppercase(),
+                })
+                .unwrap_err();
+            assert!(
+                matches!(&err, HostfileError::InvalidEntry { kind: found, .. } if *found == kind),
+                "{:?}",
+                err
+            );
+            assert_eq!(file.to_string(), source);
+            assert!(!file.can_undo());
+        }
+    }
+
+    #[test]
+    fn collect_entries() {
+        let mut file: HostsFile = ["127.0.0.1 localhost", "10.0.0.1 web www"]
+            .iter()
+            .map(|line| line.parse::<HostEntry>().unwrap())
+            .collect();
+        assert!(!file.can_undo());
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1\tlocalhost\n10.0.0.1\tweb www\n"
+        );
+
+        let mut crlf = HostsFile::parse("# mine\r\n::1 localhost");
+        crlf.extend(&file.entries().collect::<Vec<_>>()[1..]);
+        assert_eq!(
+            crlf.to_string(),
+            "# mine\r\n::1 localhost\r\n10.0.0.1\tweb www\r\n"
+        );
+        assert!(crlf.undo());
+        file.extend(std::iter::empty::<HostEntry>());
+        assert!(!file.can_undo());
+
+        let injected = HostEntry {
+            ip: "10.0.0.2".parse().unwrap(),
+            names: vec![String::from("x\n0.0.0.0 bank.example")],
+        };
+        let valid: HostEntry = "10.0.0.3 db".parse().unwrap();
+        assert!(matches!(
+            file.try_extend([valid, injected]),
+            Err(HostfileError::InvalidEntry { .. })
+        ));
+        assert!(!file.can_undo());
+    }
+
+    #[test]
+    #[should_panic(expected = "can't extend the hosts file")]
+    fn extend_panics_on_unwritable_entries() {
+        HostsFile::new().extend([HostEntry {
+            ip: "10.0.0.2".parse().unwrap(),
+            names: vec![String::from("x\n0.0.0.0 bank.example")],
+        }]);
+    }
+
+    #[test]
+    fn repair_localhost() {
+        let mut file = HostsFile::parse(
+            "# my hosts\r\n\
+             10.0.0.1 localhost\r\n\
+             ::1 localhost ip6-localhost\r\n\
+             10.0.0.2 web LOCALHOST",
+        );
+        assert_eq!(file.ensure_localhost(), Ok(true));
+        assert_eq!(
+            file.to_string(),
+            "# my hosts\r\n\
+             127.0.0.1\tlocalhost\r\n\
+             ::1 localhost ip6-localhost\r\n\
+             10.0.0.2 web"
+        );
+        assert_eq!(file.ensure_localhost(), Ok(false));
+
+        let mut file = HostsFile::parse("# empty\n");
+        assert_eq!(file.ensure_localhost(), Ok(true));
+        assert_eq!(
+            file.to_string(),
+            "# empty\n127.0.0.1\tlocalhost\n::1\tlocalhost\n"
+        );
+    }
+
+    #[test]
+    fn safe_mode_guards_critical_entries() {
+        let source = "127.0.0.1 localhost app\n::1 localhost ip6-localhost\n10.0.0.1 web\n";
+        let mut file = HostsFile::parse(source);
+        let refused = |region: Option<ProtectedRegion>| {
+            region.is_some_and(|region| region.generator == Generator::SafeMode)
+        };
+        let protected = |err: Option<HostfileError>| match err {
+            Some(HostfileError::Protected(region)) => Some(region),
+            _ => None,
+        };
+        assert!(refused(file.remove_name("ip6-localhost").err()));
+        assert!(refused(file.retain(|entry| !entry.ip.is_loopback()).err()));
+        assert!(refused(
+            file.map_ips(|ip| if ip.is_loopback() {
+                "10.0.0.9".parse().unwrap()
+            } else {
+                ip
+            })
+            .err()
+        ));
+        let other: HostEntry = "10.0.0.2 localhost".parse().unwrap();
+        assert!(refused(protected(file.insert_at(0, &other).err())));
+        assert_eq!(file.to_string(), source);
+        assert!(!file.can_undo());
+
+        // Other names on loopback addresses and extra addresses after the first are fine
+        assert_eq!(file.remove_name("app"), Ok(1));
+        assert!(file.insert_at(3, &other).is_ok());
+        assert!(file
+            .apply_state(&DesiredState {
+                remove: vec![String::from("localhost")],
+                ..DesiredState::default()
+            })
+            .is_err());
+
+        let mut file = HostsFile::parse("10.0.0.3 box.example box\n10.0.0.4 web\n");
+        file.set_protected_names(vec![String::from("box.example"), String::from("box")]);
+        assert_eq!(file.remove_name("web"), Ok(1));
+        assert!(refused(file.remove_name("BOX").err()));
+        assert!(refused(protected(
+            file.insert_at(0, &"10.0.0.5 box".parse().unwrap()).err()
+        )));
+        file.set_force(true);
+        assert_eq!(file.remove_name("box"), Ok(1));
+    }
+
+    #[test]
+    fn debian_hostname_entry() {
+        let mut file = HostsFile::parse("127.0.0.1 localhost\n::1 localhost\n");
+        assert_eq!(file.debian_hostname("box"), HostnameStatus::Missing);
+        assert_eq!(file.ensure_debian_hostname("box.example.org"), Ok(true));
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n127.0.1.1\tbox.example.org box\n::1 localhost\n"
+        );
+        assert_eq!(file.debian_hostname("BOX"), HostnameStatus::Present);
+        assert_eq!(file.ensure_debian_hostname("box.example.org"), Ok(false));
+
+        let mut file = HostsFile::parse("10.0.0.5 new\n127.0.1.1   old  # installer\n");
+        assert_eq!(file.ensure_debian_hostname("new"), Ok(true));
+        assert_eq!(
+            file.to_string(),
+            "10.0.0.5 new\n127.0.1.1   new  # installer\n"
+        );
+        assert_eq!(
+            file.debian_hostname("new"),
+            HostnameStatus::Elsewhere(vec!["10.0.0.5".parse().unwrap(), DEBIAN_HOSTNAME_IP])
+        );
+        assert!(matches!(
+            file.ensure_debian_hostname("box\n0.0.0.0 bank.example"),
+            Err(HostfileError::InvalidEntry { .. })
+        ));
+        assert!(file.ensure_debian_hostname("").is_err());
+        assert_eq!(
+            file.to_string(),
+            "10.0.0.5 new\n127.0.1.1   new  # installer\n"
+        );
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn add_self_once() {
+        let ip = "10.0.0.7".parse().unwrap();
+        let mut file = HostsFile::parse("127.0.0.1 localhost\n");
+        let entry = file.add_self(Some(ip)).unwrap();
+        let added = file.to_string();
+        assert_eq!(added, format!("127.0.0.1 localhost\n{entry}\n"));
+        file.add_self(Some(ip)).unwrap();
+        assert_eq!(file.to_string(), added);
+
+        file.add_self(Some("10.0.0.8".parse().unwrap())).unwrap();
+        assert_eq!(file.entries().count(), 2);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut file = HostsFile::parse("127.0.0.1 localhost\n");
+        assert!(!file.undo());
+        file.add_entry(&"10.0.0.1 web".parse().unwrap()).unwrap();
+        let web = file.line_id(1).unwrap();
+        assert_eq!(file.remove_name("missing"), Ok(0));
+        assert_eq!(file.remove_name("web"), Ok(1));
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n");
+
+        assert!(file.undo());
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n10.0.0.1\tweb\n");
+        assert_eq!(file.line_index(web), Some(1));
+        assert!(file.undo());
+        assert!(!file.can_undo());
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n");
+        assert!(file.redo());
+        assert!(file.can_redo());
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n10.0.0.1\tweb\n");
+
+        // A new edit discards what could be redone
+        file.add_entry(&"10.0.0.2 db".parse().unwrap()).unwrap();
+        assert!(!file.redo());
+
+        file.set_history_limit(1);
+        assert!(file.undo());
+        assert!(!file.undo());
+        assert_eq!(file.to_string(), "127.0.0.1 localhost\n10.0.0.1\tweb\n");
+
+        // Only the lines an edit changed are kept to undo it
+        let source: String = (0..50).map(|i| format!("10.0.1.{i} host{i}\n")).collect();
+        let mut file = HostsFile::parse(&source);
+        assert_eq!(file.remove_name("host20"), Ok(1));
+        let splice = file.undo.back().unwrap();
+        assert_eq!((splice.start, splice.len, splice.lines.len()), (20, 0, 1));
+        assert!(file.undo());
+        assert_eq!(file.to_string(), source);
+        assert!(file.redo());
+        assert_eq!(file.entries().count(), 49);
+    }
+
+    #[test]
+    fn disable_and_enable_entries() {
+        let mut file =
+            HostsFile::parse("127.0.0.1 localhost\r\n#  10.0.0.1 web\r\n# just a comment\r\n");
+        assert_eq!(file.line(1), Some("#  10.0.0.1 web"));
+        assert_eq!(
+            file.line_entry(0),
+            Some(("127.0.0.1 localhost".parse().unwrap(), true))
+        );
+        assert_eq!(
+            file.line_entry(1),
+            Some(("10.0.0.1 web".parse().unwrap(), false))
+        );
+        assert_eq!(file.line_entry(2), None);
+
+        let localhost = file.line_id(0).unwrap();
+        let web = file.line_id(1).unwrap();
+        let comment = file.line_id(2).unwrap();
+        assert!(file.disable_entry(localhost).is_err());
+        file.set_force(true);
+        assert_eq!(file.disable_entry(localhost), Ok(true));
+        assert_eq!(file.disable_entry(localhost), Ok(false));
+        assert_eq!(file.enable_entry(web), Ok(true));
+        assert_eq!(file.enable_entry(comment), Ok(false));
+        assert_eq!(
+            file.to_string(),
+            "# 127.0.0.1 localhost\r\n10.0.0.1 web\r\n# just a comment\r\n"
+        );
+        assert_eq!(file.remove_line(comment), Ok(true));
+        assert_eq!(file.remove_line(comment), Ok(false));
+        assert_eq!(file.line_count(), 2);
+    }
+
+    #[test]
+    fn edit_comments() {
+        let mut file = HostsFile::parse(
+            "# hosts\n\
+             127.0.0.1 localhost # loopback\n\
+             #\t10.0.0.9 old\n\
+             \t10.0.0.1 web\n",
+        );
+        assert_eq!(file.comments().collect::<Vec<_>>(), vec![(0, "hosts")]);
+
+        let web = file.line_id(3).unwrap();
+        let comment = file.add_comment_before(web, "Managed by deploy\n").unwrap();
+        assert_eq!(file.line_index(comment.unwrap()), Some(3));
+        assert_eq!(
+            file.add_comment_before(web, "two\nlines").unwrap(),
+            file.line_id(4)
+        );
+        assert_eq!(
+            file.comments().collect::<Vec<_>>(),
+            vec![
+                (0, "hosts"),
+                (3, "Managed by deploy"),
+                (4, "two"),
+                (5, "lines")
+            ]
+        );
+        assert_eq!(
+            file.to_string(),
+            "# hosts\n\
+             127.0.0.1 localhost # loopback\n\
+             #\t10.0.0.9 old\n\
+             \t# Managed by deploy\n\
+             \t# two\n\
+             \t# lines\n\
+             \t10.0.0.1 web\n"
+        );
+
+        assert_eq!(file.remove_comment(2), Ok(false));
+        assert_eq!(file.remove_comment(4), Ok(true));
+        assert_eq!(file.remove_comment(4), Ok(true));
+        assert_eq!(file.remove_comment(0), Ok(true));
+        assert!(file.undo());
+        assert_eq!(file.line(0), Some("# hosts"));
+        assert_eq!(file.remove_line(web), Ok(true));
+        assert_eq!(file.add_comment_before(web, "gone"), Ok(None));
+    }
+
+    #[test]
+    fn line_model_round_trip() {
+        let mut file = HostsFile::parse(WEIRD);
+        let lines: Vec<_> = file.lines().map(|(_, line)| line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                Line::Comment("my hosts"),
+                Line::Entry {
+                    entry: "127.0.0.1 localhost loopback".parse().unwrap(),
+                    comment: Some("keep me"),
+                },
+                Line::Blank,
+                Line::Invalid("not a valid line"),
+                Line::Entry {
+                    entry: "10.0.0.1 web www api".parse().unwrap(),
+                    comment: None,
+                },
+            ]
+        );
+
+        let (loopback, _) = file.lines().nth(1).unwrap();
+        let entry = "127.0.0.2 localhost".parse().unwrap();
+        file.set_force(true);
+        assert_eq!(file.set_entry(loopback, &entry), Ok(true));
+        let blank = file.line_id(2).unwrap();
+        assert_eq!(file.set_entry(blank, &entry), Ok(false));
+        assert_eq!(
+            file.to_string(),
+            WEIRD.replace("127.0.0.1\t  localhost   loopback", &entry.to_string())
+        );
+    }
+
+    #[test]
+    fn inline_entry_comments() {
+        let mut file = HostsFile::parse(
+            "# managed by mytool\r\n\
+             10.0.0.1 web\t#  managed by mytool \r\n\
+             10.0.0.2 db\r\n",
+        );
+        assert_eq!(file.entry_comment(0), None);
+        assert_eq!(file.entry_comment(1), Some("managed by mytool"));
+        assert_eq!(file.entry_comment(2), None);
+
+        let web = file.line_id(1).unwrap();
+        let db = file.line_id(2).unwrap();
+        assert_eq!(file.set_entry_comment(web, Some("mytool v2")), Ok(true));
+        assert_eq!(file.set_entry_comment(db, Some("mytool")), Ok(true));
+        assert_eq!(
+            file.to_string(),
+            "# managed by mytool\r\n\
+             10.0.0.1 web # mytool v2\r\n\
+             10.0.0.2 db # mytool\r\n"
+        );
+        assert_eq!(file.set_entry_comment(web, None), Ok(true));
+        assert_eq!(file.line(1), Some("10.0.0.1 web"));
+        let header = file.line_id(0).unwrap();
+        assert_eq!(file.set_entry_comment(header, Some("x")), Ok(false));
+    }
+
+    #[test]
+    fn hostctl_profiles() {
+        let mut file = HostsFile::parse(
+            "127.0.0.1 localhost\n\
+             # profile.on dev\n\
+             127.0.0.1 app.dev api.dev\n\
+             # end\n\
+             # profile.off staging\n\
+             # 10.0.0.5 app.dev\n\
+             # end\n\
+             # profile.online\n",
+        );
+        let profiles = file.hostctl_profiles();
+        assert_eq!(
+            profiles,
+            vec![
+                HostctlProfile::new("dev", vec!["127.0.0.1 app.dev api.dev".parse().unwrap()]),
+                HostctlProfile {
+                    enabled: false,
+                    ..HostctlProfile::new("staging", vec!["10.0.0.5 app.dev".parse().unwrap()])
+                },
+            ]
+        );
+        assert_eq!(file.entries().count(), 2);
+
+        let mut dev = profiles[0].clone();
+        dev.enabled = false;
+        file.set_hostctl_profile(&dev).unwrap();
+        file.set_hostctl_profile(&HostctlProfile::new("qa", Vec::new()))
+            .unwrap();
+        assert_eq!(file.remove_hostctl_profile("staging"), Ok(true));
+        assert_eq!(file.remove_hostctl_profile("staging"), Ok(false));
+        assert_eq!(
+            file.to_string(),
+            "127.0.0.1 localhost\n\
+             # profile.off dev\n\
+             # 127.0.0.1\tapp.dev api.dev\n\
+             # end\n\
+             # profile.online\n\
+             # profile.on qa\n\
+             # end\n"
+        );
+        assert_eq!(file.hostctl_profiles()[0], dev);
+    }
+
+    #[test]
+    fn invalid_hostctl_profiles_are_refused() {
+        let source = "127.0.0.1 localhost\n";
+        let mut file = HostsFile::parse(source);
+        for name in ["", "my dev", "dev#1", "dev\n0.0.0.0 bank.example"] {
+            assert_eq!(
+                file.set_hostctl_profile(&HostctlProfile::new(name, Vec::new())),
+                Err(HostfileError::InvalidSectionName(String::from(name)))
+            );
+        }
+
+        let injected = HostEntry {
+            ip: "10.0.0.3".parse().unwrap(),
+            names: vec![String::from("a\n0.0.0.0 evil.example")],
+        };
+        let err = file
+            .set_hostctl_profile(&HostctlProfile::new("dev", vec![injected]))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            HostfileError::InvalidEntry {
+                kind: ParseErrorKind::InvalidHostnameChar('\n'),
+                ..
+            }
+        ));
+        assert_eq!(file.to_string(), source);
+        assert!(!file.can_undo());
+    }
+}
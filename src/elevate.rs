@@ -0,0 +1,255 @@
+use std::io;
+use std::path::Path;
+
+use crate::HostfileError;
+
+/// How to get the privileges needed to write a file, see [`crate::WriteOptions::elevate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Elevation {
+    /// UAC on Windows. On Unix, `pkexec` in a graphical session if it's installed and `sudo`
+    /// otherwise.
+    #[default]
+    Auto,
+    /// Run `sudo`, which prompts on the terminal (Unix only)
+    Sudo,
+    /// Run `pkexec`, which prompts with a polkit dialog (Unix only)
+    Pkexec,
+    /// Run an elevated copy through a UAC prompt (Windows only)
+    Uac,
+}
+
+impl Elevation {
+    /// The method [`Elevation::Auto`] stands for on this system
+    fn resolve(self) -> Self {
+        if self != Elevation::Auto {
+            return self;
+        }
+        if cfg!(windows) {
+            Elevation::Uac
+        } else {
+            let graphical = std::env::var_os("DISPLAY").is_some()
+                || std::env::var_os("WAYLAND_DISPLAY").is_some();
+            if graphical && find_in_path("pkexec").is_some() {
+                Elevation::Pkexec
+            } else {
+                Elevation::Sudo
+            }
+        }
+    }
+}
+
+/// Overwrite the file at `path` with `contents` in a process with elevated privileges
+pub(crate) fn write_elevated(
+    path: &Path,
+    #[cfg_attr(not(any(unix, windows)), allow(unused_variables))] contents: &[u8],
+    elevation: Elevation,
+) -> Result<(), HostfileError> {
+    let elevation = elevation.resolve();
+    let result = match elevation {
+        #[cfg(unix)]
+        Elevation::Sudo | Elevation::Pkexec => unix::write(path, contents, elevation),
+        #[cfg(windows)]
+        Elevation::Uac => windows::write(path, contents),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{:?} is not available on this platform", elevation),
+        )),
+    };
+    result.map_err(|err| crate::io_error(path, err))
+}
+
+/// The first executable called `name` in `PATH`
+fn find_in_path(name: &str) -> Option<std::path::PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{self, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use super::Elevation;
+
+    /// Where `tee` is looked for. It runs as root, so it's never taken from the caller's `PATH`.
+    const TEE_PATHS: [&str; 2] = ["/usr/bin/tee", "/bin/tee"];
+
+    /// The command that copies its stdin to `path`. `tee` truncates and writes the existing
+    /// file, so its owner, mode and SELinux label are kept.
+    pub(super) fn command(path: &Path, elevation: Elevation) -> io::Result<Command> {
+        let program = match elevation {
+            Elevation::Pkexec => "pkexec",
+            _ => "sudo",
+        };
+        // pkexec only runs programs given by absolute path
+        let tee = TEE_PATHS
+            .iter()
+            .map(Path::new)
+            .find(|path| path.is_file())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "tee is not installed"))?;
+
+        let mut command = Command::new(program);
+        command.arg(tee).arg("--").arg(path);
+        command.stdin(Stdio::piped()).stdout(Stdio::null());
+        Ok(command)
+    }
+
+    pub(super) fn write(path: &Path, contents: &[u8], elevation: Elevation) -> io::Result<()> {
+        let mut child = command(path, elevation)?.spawn()?;
+        let written = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(contents);
+        let status = child.wait()?;
+        written?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "elevated write failed ({status})"
+            )))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::{OsStr, OsString};
+    use std::io::{self, Write};
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+
+    use windows_sys::Win32::{
+        Foundation::CloseHandle,
+        System::SystemInformation::GetSystemDirectoryW,
+        System::Threading::{GetExitCodeProcess, WaitForSingleObject, INFINITE},
+        UI::Shell::{
+            ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW,
+        },
+        UI::WindowsAndMessaging::SW_HIDE,
+    };
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+
+    /// The system directory, e.g. `C:\Windows\System32`
+    fn system_directory() -> io::Result<PathBuf> {
+        let mut buffer = vec![0u16; 260];
+        loop {
+            let len = unsafe { GetSystemDirectoryW(buffer.as_mut_ptr(), buffer.len() as u32) };
+            if len == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if (len as usize) < buffer.len() {
+                buffer.truncate(len as usize);
+                return Ok(OsString::from_wide(&buffer).into());
+            }
+            buffer.resize(len as usize, 0);
+        }
+    }
+
+    /// `path` as a single-quoted PowerShell string, in which nothing is expanded. PowerShell
+    /// also takes the typographic single quotes as quotes, so those are doubled as well.
+    fn quote(path: &Path) -> String {
+        let mut quoted = String::from("'");
+        for c in path.display().to_string().chars() {
+            if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+                quoted.push(c);
+            }
+            quoted.push(c);
+        }
+        quoted.push('\'');
+        quoted
+    }
+
+    /// Write `contents` to a temporary file and copy it over `path` from an elevated
+    /// PowerShell. The existing file is truncated and rewritten, so its ACL is kept.
+    pub(super) fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+        // A random name, created exclusively, so nothing else can swap in its own contents
+        let mut temp = tempfile::Builder::new()
+            .prefix("hostfile.")
+            .suffix(".tmp")
+            .tempfile()?;
+        temp.write_all(contents)?;
+        temp.flush()?;
+        copy_elevated(temp.path(), path)
+    }
+
+    fn copy_elevated(from: &Path, to: &Path) -> io::Result<()> {
+        let powershell = system_directory()?
+            .join("WindowsPowerShell")
+            .join("v1.0")
+            .join("powershell.exe");
+        let verb = wide(OsStr::new("runas"));
+        let file = wide(powershell.as_os_str());
+        let parameters = wide(OsStr::new(&format!(
+            "-NoProfile -NonInteractive -Command \"$ErrorActionPreference = 'Stop'; \
+             try {{ [IO.File]::WriteAllBytes({}, [IO.File]::ReadAllBytes({})) }} \
+             catch {{ exit 1 }}\"",
+            quote(to),
+            quote(from)
+        )));
+
+        let mut info: SHELLEXECUTEINFOW = unsafe { std::mem::zeroed() };
+        info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = parameters.as_ptr();
+        info.nShow = SW_HIDE;
+
+        // Fails if the user declines the UAC prompt
+        if unsafe { ShellExecuteExW(&mut info) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if info.hProcess.is_null() {
+            return Err(io::Error::other("elevated process was not started"));
+        }
+
+        let mut exit_code = 0;
+        let ok = unsafe {
+            WaitForSingleObject(info.hProcess, INFINITE);
+            let ok = GetExitCodeProcess(info.hProcess, &mut exit_code);
+            CloseHandle(info.hProcess);
+            ok
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if exit_code != 0 {
+            return Err(io::Error::other(format!(
+                "elevated copy failed with exit code {exit_code}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_commands() {
+        let path = Path::new("/etc/hosts");
+        for (elevation, program) in [(Elevation::Sudo, "sudo"), (Elevation::Pkexec, "pkexec")] {
+            let command = unix::command(path, elevation).unwrap();
+            assert_eq!(command.get_program(), program);
+            let args: Vec<_> = command.get_args().collect();
+            assert!(["/usr/bin/tee", "/bin/tee"].contains(&&*args[0].to_string_lossy()));
+            assert_eq!(&args[1..], ["--", "/etc/hosts"]);
+        }
+    }
+
+    #[test]
+    fn unsupported_elevation() {
+        let err = write_elevated(Path::new("/etc/hosts"), b"", Elevation::Uac)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not available"), "{}", err);
+    }
+}
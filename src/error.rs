@@ -0,0 +1,849 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::AddrParseError;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::Utf8Error;
+
+use crate::{HostEntry, ProtectedRegion};
+
+/// An error from reading, parsing, editing or writing a hosts file or one of the files like it.
+///
+/// Use [`HostfileError::code`] to match on the kind of error without its details.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HostfileError {
+    /// A file could not be opened, read or written
+    Io {
+        /// The file, if the error happened opening or writing it
+        path: Option<PathBuf>,
+        /// The 1-based line being read, if the error happened reading it
+        line: Option<usize>,
+        error: io::Error,
+    },
+    /// A path that should be a hosts file doesn't exist or is not a regular file
+    NotAFile(PathBuf),
+    /// The first token of an entry is not a valid IP address
+    InvalidIp {
+        error: AddrParseError,
+        /// The first word of the line
+        token: String,
+        /// 1-based byte column of `token` in the line
+        column: usize,
+        location: Option<ErrorLocation>,
+    },
+    /// The IP address of an entry is directly followed by something other than whitespace
+    MissingWhitespace {
+        /// What follows the IP address up to the next whitespace, empty at the end of the line
+        token: String,
+        /// 1-based byte column of `token` in the line
+        column: usize,
+        location: Option<ErrorLocation>,
+    },
+    /// A hostname is not valid UTF-8
+    InvalidUtf8 {
+        error: Utf8Error,
+        /// The hostname, with invalid UTF-8 replaced
+        token: String,
+        /// 1-based byte column of `token` in the line
+        column: usize,
+        location: Option<ErrorLocation>,
+    },
+    /// A hostname is missing or isn't allowed by the checks that parsing was asked to make, see
+    /// [`crate::ParseOptions::strict`] and [`crate::ParseOptions::hostname_policy`]
+    InvalidHostname {
+        kind: ParseErrorKind,
+        /// The hostname, or the part of it that is invalid
+        token: String,
+        /// 1-based byte column of `token` in the line
+        column: usize,
+        location: Option<ErrorLocation>,
+    },
+    /// An edit was refused because it would change lines that are protected
+    Protected(ProtectedRegion),
+    /// An entry can't be written because it has no hostnames or one of them would not read
+    /// back as the same entry
+    InvalidEntry {
+        entry: HostEntry,
+        kind: ParseErrorKind,
+    },
+    /// A managed section or hostctl profile name that can't be written in its markers
+    InvalidSectionName(String),
+    /// A socket address isn't a host and a port separated by `:`
+    InvalidSocketAddr {
+        addr: String,
+        /// What is wrong with it
+        reason: String,
+    },
+    /// A hostname to resolve isn't in the hosts file
+    HostNotFound(String),
+    /// A file has the Windows read-only attribute, which needs to be cleared to write it, see
+    /// [`crate::WriteOptions::clear_readonly`]
+    ReadOnly(PathBuf),
+    /// Any other error, such as the hosts file not being found
+    Other(String),
+}
+
+/// The line of a file that a [`HostfileError`] is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    /// 1-based line number
+    pub line: usize,
+    /// The line without leading whitespace or its line ending. Invalid UTF-8 is replaced.
+    pub content: String,
+}
+
+impl HostfileError {
+    /// The line this error is about, if it is about a line of a file
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            HostfileError::InvalidIp { location, .. }
+            | HostfileError::MissingWhitespace { location, .. }
+            | HostfileError::InvalidUtf8 { location, .. }
+            | HostfileError::InvalidHostname { location, .. } => location.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The offending text, if this error is about the contents of a line
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            HostfileError::InvalidIp { token, .. }
+            | HostfileError::MissingWhitespace { token, .. }
+            | HostfileError::InvalidUtf8 { token, .. }
+            | HostfileError::InvalidHostname { token, .. } => Some(token),
+            _ => None,
+        }
+    }
+
+    /// The 1-based byte column of [`HostfileError::token`] in its line
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            HostfileError::InvalidIp { column, .. }
+            | HostfileError::MissingWhitespace { column, .. }
+            | HostfileError::InvalidUtf8 { column, .. }
+            | HostfileError::InvalidHostname { column, .. } => Some(*column),
+            _ => None,
+        }
+    }
+
+    /// The byte range of [`HostfileError::token`] in its line
+    pub fn span(&self) -> Option<Range<usize>> {
+        let start = self.column()? - 1;
+        Some(start..start + self.token()?.len())
+    }
+
+    /// The variant of this error without its details
+    pub fn code(&self) -> HostfileErrorCode {
+        match self {
+            HostfileError::Io { .. } => HostfileErrorCode::Io,
+            HostfileError::NotAFile(_) => HostfileErrorCode::NotAFile,
+            HostfileError::InvalidIp { .. } => HostfileErrorCode::InvalidIp,
+            HostfileError::MissingWhitespace { .. } => HostfileErrorCode::MissingWhitespace,
+            HostfileError::InvalidUtf8 { .. } => HostfileErrorCode::InvalidUtf8,
+            HostfileError::InvalidHostname { .. } => HostfileErrorCode::InvalidHostname,
+            HostfileError::Protected(_) => HostfileErrorCode::Protected,
+            HostfileError::InvalidEntry { .. } => HostfileErrorCode::InvalidEntry,
+            HostfileError::InvalidSectionName(_) => HostfileErrorCode::InvalidSectionName,
+            HostfileError::InvalidSocketAddr { .. } => HostfileErrorCode::InvalidSocketAddr,
+            HostfileError::HostNotFound(_) => HostfileErrorCode::HostNotFound,
+            HostfileError::ReadOnly(_) => HostfileErrorCode::ReadOnly,
+            HostfileError::Other(_) => HostfileErrorCode::Other,
+        }
+    }
+
+    /// The stable numeric code of this error, see [`HostfileErrorCode`]
+    pub fn as_code(&self) -> u32 {
+        self.code().as_code()
+    }
+
+    /// This error located at `location`, if it is about the contents of a line
+    pub(crate) fn at(mut self, at: ErrorLocation) -> Self {
+        match &mut self {
+            HostfileError::InvalidIp { location, .. }
+            | HostfileError::MissingWhitespace { location, .. }
+            | HostfileError::InvalidUtf8 { location, .. }
+            | HostfileError::InvalidHostname { location, .. } => *location = Some(at),
+            _ => {}
+        }
+        self
+    }
+
+    /// This error for a line with `offset` more bytes before the token
+    pub(crate) fn shifted(mut self, offset: usize) -> Self {
+        match &mut self {
+            HostfileError::InvalidIp { column, .. }
+            | HostfileError::MissingWhitespace { column, .. }
+            | HostfileError::InvalidUtf8 { column, .. }
+            | HostfileError::InvalidHostname { column, .. } => *column += offset,
+            _ => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for HostfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostfileError::Io {
+                path: Some(path),
+                error,
+                ..
+            } => return write!(f, "Could not access file ({:?}): {error}", path),
+            HostfileError::Io {
+                line: Some(line),
+                error,
+                ..
+            } => return write!(f, "Error reading file at line {line}: {error}"),
+            HostfileError::Io { error, .. } => return write!(f, "Could not read: {error}"),
+            HostfileError::NotAFile(path) => {
+                return write!(
+                    f,
+                    "File ({:?}) does not exist or is not a regular file",
+                    path
+                )
+            }
+            HostfileError::InvalidIp { error, .. } => {
+                write!(f, "Couldn't parse a valid IP address: {error}")?
+            }
+            HostfileError::MissingWhitespace { .. } => write!(f, "Expected whitespace after IP")?,
+            HostfileError::InvalidUtf8 { error, .. } => {
+                write!(f, "Hostname is not valid UTF-8: {error}")?
+            }
+            HostfileError::InvalidHostname {
+                kind,
+                column,
+                location: Some(location),
+                ..
+            } => return write!(f, "{kind} at line {}, column {column}", location.line),
+            HostfileError::InvalidHostname { kind, .. } => return write!(f, "{kind}"),
+            HostfileError::Protected(region) => return write!(f, "{region}"),
+            HostfileError::InvalidEntry { entry, kind } => {
+                return write!(f, "Can't write the entry for {}: {kind}", entry.ip)
+            }
+            HostfileError::InvalidSectionName(name) => {
+                return write!(f, "Invalid section name {:?}", name)
+            }
+            HostfileError::InvalidSocketAddr { addr, reason } => {
+                return write!(f, "Invalid socket address {addr}: {reason}")
+            }
+            HostfileError::HostNotFound(host) => {
+                return write!(f, "Could not resolve {host}: not in the hosts file")
+            }
+            HostfileError::ReadOnly(path) => {
+                return write!(
+                    f,
+                    "File ({:?}) is read-only, clear its read-only attribute to write it",
+                    path
+                )
+            }
+            HostfileError::Other(err) => return write!(f, "{err}"),
+        }
+        if let Some(location) = self.location() {
+            write!(
+                f,
+                " at line {} with content: '{}'",
+                location.line, location.content
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for HostfileError {
+    /// I/O errors are equal if they are of the same kind
+    fn eq(&self, other: &Self) -> bool {
+        use HostfileError::*;
+        match (self, other) {
+            (
+                Io { path, line, error },
+                Io {
+                    path: other_path,
+                    line: other_line,
+                    error: other_error,
+                },
+            ) => path == other_path && line == other_line && error.kind() == other_error.kind(),
+            (NotAFile(path), NotAFile(other)) => path == other,
+            (InvalidIp { error, .. }, InvalidIp { error: other, .. }) if error != other => false,
+            (InvalidUtf8 { error, .. }, InvalidUtf8 { error: other, .. }) if error != other => {
+                false
+            }
+            (InvalidHostname { kind, .. }, InvalidHostname { kind: other, .. })
+                if kind != other =>
+            {
+                false
+            }
+            (InvalidIp { .. }, InvalidIp { .. })
+            | (MissingWhitespace { .. }, MissingWhitespace { .. })
+            | (InvalidUtf8 { .. }, InvalidUtf8 { .. })
+            | (InvalidHostname { .. }, InvalidHostname { .. }) => {
+                self.token() == other.token()
+                    && self.column() == other.column()
+                    && self.location() == other.location()
+            }
+            (Protected(region), Protected(other)) => region == other,
+            (
+                InvalidEntry { entry, kind },
+                InvalidEntry {
+                    entry: other_entry,
+                    kind: other_kind,
+                },
+            ) => entry == other_entry && kind == other_kind,
+            (InvalidSectionName(name), InvalidSectionName(other)) => name == other,
+            (
+                InvalidSocketAddr { addr, reason },
+                InvalidSocketAddr {
+                    addr: other_addr,
+                    reason: other_reason,
+                },
+            ) => addr == other_addr && reason == other_reason,
+            (HostNotFound(host), HostNotFound(other)) => host == other,
+            (ReadOnly(path), ReadOnly(other)) => path == other,
+            (Other(err), Other(other)) => err == other,
+            _ => false,
+        }
+    }
+}
+
+impl Error for HostfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HostfileError::Io { error, .. } => Some(error),
+            HostfileError::InvalidIp { error, .. } => Some(error),
+            HostfileError::InvalidUtf8 { error, .. } => Some(error),
+            HostfileError::Protected(region) => Some(region),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for HostfileError {
+    fn from(err: String) -> Self {
+        HostfileError::Other(err)
+    }
+}
+
+impl From<&str> for HostfileError {
+    fn from(err: &str) -> Self {
+        HostfileError::Other(err.to_string())
+    }
+}
+
+impl From<ProtectedRegion> for HostfileError {
+    fn from(region: ProtectedRegion) -> Self {
+        HostfileError::Protected(region)
+    }
+}
+
+/// The reason a line could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The first token on the line is not a valid IP address
+    InvalidIp(AddrParseError),
+    /// The IP address is directly followed by something other than whitespace
+    MissingWhitespace,
+    /// The line has an IP address but no hostnames
+    MissingHostname,
+    /// A hostname contains a character that isn't allowed, which unless a
+    /// [`crate::HostnamePolicy`] allows more is one outside of `[a-zA-Z0-9.-]`
+    InvalidHostnameChar(char),
+    /// A label of a hostname, the part between two dots, is empty or starts or ends with
+    /// `-`, which RFC 1123 doesn't allow
+    InvalidLabel(String),
+    /// A hostname is longer than the 253 characters DNS allows, not counting a dot at the
+    /// end. Holds the length.
+    NameTooLong(usize),
+    /// A label of a hostname is longer than the 63 characters DNS allows
+    LabelTooLong(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidIp(err) => write!(f, "Couldn't parse a valid IP address: {err}"),
+            ParseErrorKind::MissingWhitespace => write!(f, "Expected whitespace after IP"),
+            ParseErrorKind::MissingHostname => write!(f, "Expected at least one hostname after IP"),
+            ParseErrorKind::InvalidHostnameChar(c) => {
+                write!(f, "Invalid character {c:?} in hostname")
+            }
+            ParseErrorKind::InvalidLabel(label) => write!(f, "Invalid label {label:?} in hostname"),
+            ParseErrorKind::NameTooLong(len) => {
+                write!(f, "Hostname is {len} characters long, the limit is 253")
+            }
+            ParseErrorKind::LabelTooLong(label) => write!(
+                f,
+                "Label {label:?} is {} characters long, the limit is 63",
+                label.len()
+            ),
+        }
+    }
+}
+
+impl ParseErrorKind {
+    /// The variant of this error without its details
+    pub fn code(&self) -> ParseErrorCode {
+        match self {
+            ParseErrorKind::InvalidIp(_) => ParseErrorCode::InvalidIp,
+            ParseErrorKind::MissingWhitespace => ParseErrorCode::MissingWhitespace,
+            ParseErrorKind::MissingHostname => ParseErrorCode::MissingHostname,
+            ParseErrorKind::InvalidHostnameChar(_) => ParseErrorCode::InvalidHostnameChar,
+            ParseErrorKind::InvalidLabel(_) => ParseErrorCode::InvalidLabel,
+            ParseErrorKind::NameTooLong(_) => ParseErrorCode::NameTooLong,
+            ParseErrorKind::LabelTooLong(_) => ParseErrorCode::LabelTooLong,
+        }
+    }
+
+    /// The stable numeric code of this error, see [`ParseErrorCode`]
+    pub fn as_code(&self) -> u32 {
+        self.code().as_code()
+    }
+
+    /// A suggestion for how to fix this error
+    pub fn hint(&self) -> &'static str {
+        match self {
+            ParseErrorKind::InvalidIp(_) => "entries must start with an IPv4 or IPv6 address",
+            ParseErrorKind::MissingWhitespace => {
+                "separate the IP address from the hostnames with a space or tab"
+            }
+            ParseErrorKind::MissingHostname => "add a hostname after the IP address",
+            ParseErrorKind::InvalidHostnameChar(_) => {
+                "remove or replace the character, it isn't allowed in hostnames"
+            }
+            ParseErrorKind::InvalidLabel(_) => {
+                "the parts of hostnames between dots can't be empty or start or end with '-'"
+            }
+            ParseErrorKind::NameTooLong(_) => "shorten the hostname to at most 253 characters",
+            ParseErrorKind::LabelTooLong(_) => {
+                "shorten each part of the hostname between dots to at most 63 characters"
+            }
+        }
+    }
+}
+
+/// The kind of a [`ParseErrorKind`] without its details, for matching on errors across
+/// versions and languages.
+///
+/// Each kind has a numeric code that is part of the API: a code is never reused or
+/// reassigned, and new kinds get new codes.
+///
+/// | Code | Kind                  |
+/// |------|-----------------------|
+/// | 1    | `InvalidIp`           |
+/// | 2    | `MissingWhitespace`   |
+/// | 3    | `MissingHostname`     |
+/// | 4    | `InvalidHostnameChar` |
+/// | 5    | `InvalidLabel`        |
+/// | 6    | `NameTooLong`         |
+/// | 7    | `LabelTooLong`        |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseErrorCode {
+    InvalidIp = 1,
+    MissingWhitespace = 2,
+    MissingHostname = 3,
+    InvalidHostnameChar = 4,
+    InvalidLabel = 5,
+    NameTooLong = 6,
+    LabelTooLong = 7,
+}
+
+impl ParseErrorCode {
+    /// Every kind, in the order of their codes
+    pub const ALL: &'static [ParseErrorCode] = &[
+        ParseErrorCode::InvalidIp,
+        ParseErrorCode::MissingWhitespace,
+        ParseErrorCode::MissingHostname,
+        ParseErrorCode::InvalidHostnameChar,
+        ParseErrorCode::InvalidLabel,
+        ParseErrorCode::NameTooLong,
+        ParseErrorCode::LabelTooLong,
+    ];
+
+    /// The stable numeric code of this kind
+    pub fn as_code(&self) -> u32 {
+        *self as u32
+    }
+
+    /// The kind with the numeric code `code`, if there is one
+    pub fn from_code(code: u32) -> Option<ParseErrorCode> {
+        ParseErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_code() == code)
+    }
+}
+
+/// The kind of a [`HostfileError`] without its details, for matching on errors across
+/// versions and languages.
+///
+/// Like [`ParseErrorCode`], each kind has a numeric code that is part of the API: a code is
+/// never reused or reassigned, and new kinds get new codes.
+///
+/// | Code | Kind                 |
+/// |------|----------------------|
+/// | 1    | `Io`                 |
+/// | 2    | `NotAFile`           |
+/// | 3    | `InvalidIp`          |
+/// | 4    | `MissingWhitespace`  |
+/// | 5    | `InvalidUtf8`        |
+/// | 6    | `Protected`          |
+/// | 7    | `Other`              |
+/// | 8    | `InvalidEntry`       |
+/// | 9    | `InvalidSectionName` |
+/// | 10   | `InvalidHostname`    |
+/// | 11   | `InvalidSocketAddr`  |
+/// | 12   | `HostNotFound`       |
+/// | 13   | `ReadOnly`           |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HostfileErrorCode {
+    Io = 1,
+    NotAFile = 2,
+    InvalidIp = 3,
+    MissingWhitespace = 4,
+    InvalidUtf8 = 5,
+    Protected = 6,
+    Other = 7,
+    InvalidEntry = 8,
+    InvalidSectionName = 9,
+    InvalidHostname = 10,
+    InvalidSocketAddr = 11,
+    HostNotFound = 12,
+    ReadOnly = 13,
+}
+
+impl HostfileErrorCode {
+    /// Every kind, in the order of their codes
+    pub const ALL: &'static [HostfileErrorCode] = &[
+        HostfileErrorCode::Io,
+        HostfileErrorCode::NotAFile,
+        HostfileErrorCode::InvalidIp,
+        HostfileErrorCode::MissingWhitespace,
+        HostfileErrorCode::InvalidUtf8,
+        HostfileErrorCode::Protected,
+        HostfileErrorCode::Other,
+        HostfileErrorCode::InvalidEntry,
+        HostfileErrorCode::InvalidSectionName,
+        HostfileErrorCode::InvalidHostname,
+        HostfileErrorCode::InvalidSocketAddr,
+        HostfileErrorCode::HostNotFound,
+        HostfileErrorCode::ReadOnly,
+    ];
+
+    /// The stable numeric code of this kind
+    pub fn as_code(&self) -> u32 {
+        *self as u32
+    }
+
+    /// The kind with the numeric code `code`, if there is one
+    pub fn from_code(code: u32) -> Option<HostfileErrorCode> {
+        HostfileErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_code() == code)
+    }
+}
+
+/// A parse error pointing at the offending text in the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    /// Byte range of the offending text within the line
+    pub span: Range<usize>,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// 1-based byte column where the offending text starts
+    pub fn column(&self) -> usize {
+        self.span.start + 1
+    }
+
+    /// Render this error for humans, quoting the offending line of `source` with the bad text
+    /// underlined:
+    ///
+    /// ```text
+    /// error: Expected whitespace after IP
+    ///  --> line 1, column 10
+    ///   |
+    /// 1 | 127.0.0.1localhost
+    ///   |          ^ separate the IP address from the hostnames with a space or tab
+    /// ```
+    ///
+    /// `source` must be the input that was parsed. If it doesn't contain the line this error
+    /// refers to, only the message is rendered.
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!(
+            "error: {}\n --> line {}, column {}\n",
+            self.kind,
+            self.line,
+            self.column()
+        );
+
+        let line = match source.split('\n').nth(self.line.wrapping_sub(1)) {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line),
+            None => return out,
+        };
+        if self.span.start > line.len() || !line.is_char_boundary(self.span.start) {
+            return out;
+        }
+        let end = self.span.end.min(line.len());
+        let end = if line.is_char_boundary(end) {
+            end
+        } else {
+            line.len()
+        };
+
+        // Tabs are expanded so that the caret lines up regardless of the terminal's tab width
+        let width = |text: &str| {
+            text.chars()
+                .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+                .sum::<usize>()
+        };
+        let padding = width(&line[..self.span.start]);
+        let underline = width(&line[self.span.start..end]).max(1);
+
+        let gutter = self.line.to_string();
+        let empty_gutter = " ".repeat(gutter.len());
+        out.push_str(&format!("{empty_gutter} |\n"));
+        out.push_str(&format!(
+            "{gutter} | {}\n",
+            line.replace('\t', &" ".repeat(TAB_WIDTH))
+        ));
+        out.push_str(&format!(
+            "{empty_gutter} | {}{} {}\n",
+            " ".repeat(padding),
+            "^".repeat(underline),
+            self.kind.hint()
+        ));
+        out
+    }
+}
+
+const TAB_WIDTH: usize = 4;
+
+impl ParseError {
+    /// This error as a [`HostfileError`] located in `line`, the text of the line it is about
+    pub(crate) fn into_hostfile_error(self, line: &str) -> HostfileError {
+        let token = line.get(self.span.clone()).unwrap_or_default().to_string();
+        let column = self.column();
+        let location = Some(ErrorLocation {
+            line: self.line,
+            content: line.trim_start().to_string(),
+        });
+        match self.kind {
+            ParseErrorKind::InvalidIp(error) => HostfileError::InvalidIp {
+                error,
+                token,
+                column,
+                location,
+            },
+            ParseErrorKind::MissingWhitespace => HostfileError::MissingWhitespace {
+                token,
+                column,
+                location,
+            },
+            kind => HostfileError::InvalidHostname {
+                kind,
+                token,
+                column,
+                location,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind,
+            self.line,
+            self.column()
+        )
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::InvalidIp(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io;
+    use std::net::AddrParseError;
+    use std::path::Path;
+
+    use super::{HostfileError, HostfileErrorCode, ParseErrorCode, ParseErrorKind};
+    use crate::{parse_file, parse_str, parse_str_strict, parse_str_with, ParseOptions};
+
+    #[test]
+    fn stable_codes() {
+        let err = parse_str_strict("1.2.3.4 a_b").unwrap_err();
+        assert_eq!(err.kind.code(), ParseErrorCode::InvalidHostnameChar);
+        assert_eq!(err.kind.as_code(), 4);
+        for code in ParseErrorCode::ALL {
+            assert_eq!(ParseErrorCode::from_code(code.as_code()), Some(*code));
+        }
+        assert_eq!(ParseErrorCode::from_code(0), None);
+
+        assert_eq!(
+            err_code("127.0.0.1localhost"),
+            Some(HostfileErrorCode::MissingWhitespace)
+        );
+        assert_eq!(HostfileError::from("gone").as_code(), 7);
+        for code in HostfileErrorCode::ALL {
+            assert_eq!(HostfileErrorCode::from_code(code.as_code()), Some(*code));
+        }
+        assert_eq!(HostfileErrorCode::from_code(0), None);
+    }
+
+    fn err_code(input: &str) -> Option<HostfileErrorCode> {
+        parse_str(input).err().map(|err| err.code())
+    }
+
+    #[test]
+    fn hostfile_error_variants() {
+        let err = parse_str("127.0.0.1 localhost\n127.0.0.1localhost\n").unwrap_err();
+        match &err {
+            HostfileError::MissingWhitespace {
+                location: Some(location),
+                ..
+            } => assert_eq!(location.line, 2),
+            _ => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(
+            err.to_string(),
+            "Expected whitespace after IP at line 2 with content: '127.0.0.1localhost'"
+        );
+        assert!(matches!(
+            "bad".parse::<crate::HostEntry>(),
+            Err(HostfileError::InvalidIp { location: None, .. })
+        ));
+
+        let options = ParseOptions::new().rfc1123(true);
+        let err = parse_str_with(
+            "::1 localhost
+::1 _dmarc
+",
+            &options,
+        )
+        .unwrap_err();
+        match &err {
+            HostfileError::InvalidHostname {
+                kind: ParseErrorKind::InvalidHostnameChar('_'),
+                location: Some(location),
+                ..
+            } => assert_eq!(location.line, 2),
+            _ => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!((err.token(), err.column()), (Some("_"), Some(5)));
+        assert_eq!(err.as_code(), 10);
+    }
+
+    #[test]
+    fn error_columns_and_tokens() {
+        let err = parse_str("# header\n\t10.0.0.1web.local web\n").unwrap_err();
+        assert_eq!(err.location().unwrap().line, 2);
+        assert_eq!(err.token(), Some("web.local"));
+        assert_eq!(err.column(), Some(10));
+        assert_eq!(err.span(), Some(9..18));
+
+        let err = "  localhost myhost"
+            .parse::<crate::HostEntry>()
+            .unwrap_err();
+        assert_eq!((err.token(), err.column()), (Some("localhost"), Some(3)));
+        let err = crate::parse_bytes_borrowed(b"1.2.3.4 ok caf\xe9 x").unwrap_err();
+        assert_eq!((err.token(), err.column()), (Some("caf\u{fffd}"), Some(12)));
+        assert!(matches!(
+            parse_file(Path::new("/nonexistent/hosts")),
+            Err(HostfileError::NotAFile(_))
+        ));
+    }
+
+    #[test]
+    fn error_sources() {
+        let err = parse_str("10.0.0.256 web\n").unwrap_err();
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<AddrParseError>().is_some());
+        assert_eq!(source.to_string(), "invalid IP address syntax");
+
+        let err = crate::parse_reader(FailingReader).unwrap_err();
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+
+        let err = parse_str_strict("1.2.3 web").unwrap_err();
+        assert!(err.source().unwrap().is::<AddrParseError>());
+        assert!(parse_str_strict("1.2.3.4 a_b")
+            .unwrap_err()
+            .source()
+            .is_none());
+    }
+
+    struct FailingReader;
+
+    impl io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+    }
+
+    impl io::BufRead for FailingReader {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
+
+        fn consume(&mut self, _: usize) {}
+    }
+
+    #[test]
+    fn render_points_at_token() {
+        let source = "127.0.0.1 localhost\n127.0.0.1localhost\n";
+        let err = parse_str_strict(source).unwrap_err();
+        assert_eq!(
+            err.render(source),
+            "\
+error: Expected whitespace after IP
+ --> line 2, column 10
+  |
+2 | 127.0.0.1localhost
+  |          ^ separate the IP address from the hostnames with a space or tab
+"
+        );
+    }
+
+    #[test]
+    fn render_expands_tabs() {
+        let source = "\t10.0.0.256\tweb\r\n";
+        let err = parse_str_strict(source).unwrap_err();
+        assert_eq!(
+            err.render(source),
+            "\
+error: Couldn't parse a valid IP address: invalid IP address syntax
+ --> line 1, column 2
+  |
+1 |     10.0.0.256    web
+  |     ^^^^^^^^^^ entries must start with an IPv4 or IPv6 address
+"
+        );
+    }
+
+    #[test]
+    fn render_without_matching_source() {
+        let err = parse_str_strict("\n\n1.2.3.4 a_b").unwrap_err();
+        assert_eq!(
+            err.render(""),
+            "error: Invalid character '_' in hostname\n --> line 3, column 10\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, hostname_eq, parse_table, HostEntry, HostfileError};
+
+/// A 48-bit Ethernet (MAC) address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr([u8; 6]);
+
+impl MacAddr {
+    pub const fn new(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8) -> Self {
+        MacAddr([a, b, c, d, e, f])
+    }
+
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> Self {
+        MacAddr(octets)
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = HostfileError;
+
+    /// Parse six hexadecimal octets separated by `:` or `-`. As in `ether_aton(3)`, octets
+    /// may be written with a single digit.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Couldn't parse a valid MAC address {s}");
+        let separator = if s.contains('-') { '-' } else { ':' };
+        let mut octets = [0; 6];
+        let mut parts = s.split(separator);
+        for octet in &mut octets {
+            let part = parts.next().ok_or_else(invalid)?;
+            if part.is_empty() || part.len() > 2 {
+                return Err(invalid().into());
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid().into());
+        }
+        Ok(MacAddr(octets))
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+/// A line from `/etc/ethers`, as described in `man ethers(5)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EtherEntry {
+    pub mac: MacAddr,
+    /// A hostname, or an IP address
+    pub host: String,
+}
+
+impl EtherEntry {
+    /// Whether this entry refers to the host of `entry`, by one of its names or its IP
+    pub fn matches(&self, entry: &HostEntry) -> bool {
+        match self.host.parse::<IpAddr>() {
+            Ok(ip) => ip == entry.ip,
+            Err(_) => entry.names.iter().any(|name| hostname_eq(name, &self.host)),
+        }
+    }
+}
+
+impl FromStr for EtherEntry {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Account for comments at the end of the line
+        let line = s.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let mac = tokens.next().ok_or("Expected a MAC address")?.parse()?;
+        let host = tokens.next().ok_or("Expected a hostname")?;
+        if tokens.next().is_some() {
+            return Err("Expected a single hostname".into());
+        }
+        Ok(EtherEntry {
+            mac,
+            host: host.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for EtherEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.mac, self.host)
+    }
+}
+
+/// Parse the contents of an ethers file
+pub fn parse_ethers_str(input: &str) -> Result<Vec<EtherEntry>, HostfileError> {
+    parse_table(input)
+}
+
+/// Parse an ethers file using the format described in `man ethers(5)`
+pub fn parse_ethers_file(path: &Path) -> Result<Vec<EtherEntry>, HostfileError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    parse_ethers_str(&contents)
+}
+
+/// Get path to the system ethers file, which is next to the hosts file
+pub fn get_ethers_path() -> Result<PathBuf, HostfileError> {
+    Ok(get_hostfile_path()?.with_file_name("ethers"))
+}
+
+/// Pair every entry of `ethers` with the entries of `hosts` for the same host, in the order
+/// of `ethers`. Ethers entries without a matching hosts entry are left out.
+pub fn join_ethers<'a>(
+    ethers: &'a [EtherEntry],
+    hosts: &'a [HostEntry],
+) -> Vec<(&'a EtherEntry, &'a HostEntry)> {
+    ethers
+        .iter()
+        .flat_map(|ether| {
+            hosts
+                .iter()
+                .filter(move |host| ether.matches(host))
+                .map(move |host| (ether, host))
+        })
+        .collect()
+}
+
+/// The MAC addresses of the host with address `ip`, found by its IP or by any name `hosts`
+/// gives it
+pub fn macs_for_ip(ethers: &[EtherEntry], hosts: &[HostEntry], ip: IpAddr) -> Vec<MacAddr> {
+    let mut macs = Vec::new();
+    for (ether, _) in join_ethers(ethers, hosts)
+        .into_iter()
+        .filter(|(_, host)| host.ip == ip)
+    {
+        if !macs.contains(&ether.mac) {
+            macs.push(ether.mac);
+        }
+    }
+    macs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mac() {
+        let mac: MacAddr = "8:0:20:1:2:3".parse().unwrap();
+        assert_eq!(mac, MacAddr::new(8, 0, 0x20, 1, 2, 3));
+        assert_eq!(mac.to_string(), "08:00:20:01:02:03");
+        assert_eq!("08-00-20-01-02-03".parse(), Ok(mac));
+        assert!("08:00:20:01:02".parse::<MacAddr>().is_err());
+        assert!("08:00:20:01:02:03:04".parse::<MacAddr>().is_err());
+        assert!("08:00:20:01:02:100".parse::<MacAddr>().is_err());
+        assert!("08:00-20:01:02:03".parse::<MacAddr>().is_err());
+    }
+
+    #[test]
+    fn join_with_hosts() {
+        let ethers = parse_ethers_str(
+            "# comment\n\
+             08:00:20:00:61:ca printer # office\n\
+             08:00:20:00:61:cb 10.0.0.2\n\
+             08:00:20:00:61:cc unknown\n",
+        )
+        .unwrap();
+        let hosts: Vec<HostEntry> = vec![
+            "10.0.0.1 Printer printer.lan".parse().unwrap(),
+            "10.0.0.2 nas".parse().unwrap(),
+        ];
+        let joined = join_ethers(&ethers, &hosts);
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0], (&ethers[0], &hosts[0]));
+        assert_eq!(joined[1], (&ethers[1], &hosts[1]));
+        assert_eq!(
+            macs_for_ip(&ethers, &hosts, "10.0.0.1".parse().unwrap()),
+            vec![ethers[0].mac]
+        );
+
+        let err = parse_ethers_str("08:00:20:00:61:ca\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Expected a hostname at line 1 with content: '08:00:20:00:61:ca'"
+        );
+    }
+}
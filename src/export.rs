@@ -0,0 +1,362 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::{hostname_eq, HostEntry, HostfileError};
+
+/// A format that [`export_entries`] can write entries in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExportFormat {
+    /// An array of `{"ip": ..., "names": [...]}` objects, like `HostEntry` serializes to
+    Json,
+    /// The same list as `Json`, as a YAML sequence
+    Yaml,
+    /// An `ip,names` header followed by a row per entry, with the names separated by spaces
+    Csv,
+    /// A dnsmasq `host-record=` line per entry
+    Dnsmasq,
+    /// Unbound `local-data:` lines for each name and a `local-data-ptr:` line for the first
+    /// name of each entry
+    Unbound,
+    /// DNS zone file records. Names with a dot are written as absolute names and other names
+    /// are left relative to the zone's origin.
+    Zone,
+    /// `--add-host` options for `docker run`, one per line
+    Docker,
+    /// The `hostAliases` of a Kubernetes pod spec
+    Kubernetes,
+    /// The names as a comma separated `NO_PROXY` list, see [`no_proxy_list`]
+    NoProxy,
+    /// The names as a semicolon separated Windows proxy bypass list, see
+    /// [`proxy_bypass_list`]
+    ProxyBypass,
+}
+
+impl ExportFormat {
+    /// Every format, in the order they are listed in help
+    pub const ALL: &'static [ExportFormat] = &[
+        ExportFormat::Json,
+        ExportFormat::Yaml,
+        ExportFormat::Csv,
+        ExportFormat::Dnsmasq,
+        ExportFormat::Unbound,
+        ExportFormat::Zone,
+        ExportFormat::Docker,
+        ExportFormat::Kubernetes,
+        ExportFormat::NoProxy,
+        ExportFormat::ProxyBypass,
+    ];
+
+    /// The name of the format on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Yaml => "yaml",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Dnsmasq => "dnsmasq",
+            ExportFormat::Unbound => "unbound",
+            ExportFormat::Zone => "zone",
+            ExportFormat::Docker => "docker",
+            ExportFormat::Kubernetes => "k8s",
+            ExportFormat::NoProxy => "no-proxy",
+            ExportFormat::ProxyBypass => "proxy-bypass",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExportFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.as_str() == s)
+            .ok_or_else(|| HostfileError::Other(format!("Unknown export format '{s}'")))
+    }
+}
+
+/// Write `entries` in `format`, one record per line
+pub fn export_entries(entries: &[HostEntry], format: ExportFormat) -> String {
+    let mut out = String::new();
+    match format {
+        ExportFormat::Json => {
+            out.push('[');
+            for (idx, entry) in entries.iter().enumerate() {
+                let names: Vec<String> = entry.names.iter().map(|name| quote(name)).collect();
+                out.push_str(if idx == 0 { "\n" } else { ",\n" });
+                out.push_str(&format!(
+                    "  {{\"ip\": \"{}\", \"names\": [{}]}}",
+                    entry.ip,
+                    names.join(", ")
+                ));
+            }
+            out.push_str(if entries.is_empty() { "]\n" } else { "\n]\n" });
+        }
+        ExportFormat::Yaml if entries.is_empty() => out.push_str("[]\n"),
+        ExportFormat::Yaml => {
+            for entry in entries {
+                out.push_str(&format!("- ip: \"{}\"\n  names:\n", entry.ip));
+                for name in &entry.names {
+                    out.push_str(&format!("    - {}\n", quote(name)));
+                }
+            }
+        }
+        ExportFormat::Csv => {
+            out.push_str("ip,names\n");
+            for entry in entries {
+                out.push_str(&format!(
+                    "{},{}\n",
+                    entry.ip,
+                    csv_field(&entry.names.join(" "))
+                ));
+            }
+        }
+        ExportFormat::Dnsmasq => {
+            for entry in entries {
+                out.push_str(&format!(
+                    "host-record={},{}\n",
+                    entry.names.join(","),
+                    entry.ip
+                ));
+            }
+        }
+        ExportFormat::Unbound => {
+            for entry in entries {
+                for name in &entry.names {
+                    out.push_str(&format!(
+                        "local-data: \"{} IN {} {}\"\n",
+                        absolute(name),
+                        record_type(entry.ip),
+                        entry.ip
+                    ));
+                }
+                if let Some(name) = entry.names.first() {
+                    out.push_str(&format!(
+                        "local-data-ptr: \"{} {}\"\n",
+                        entry.ip,
+                        absolute(name)
+                    ));
+                }
+            }
+        }
+        ExportFormat::Zone => {
+            for entry in entries {
+                for name in &entry.names {
+                    let name = if name.contains('.') {
+                        absolute(name)
+                    } else {
+                        name.clone()
+                    };
+                    out.push_str(&format!(
+                        "{name}\tIN\t{}\t{}\n",
+                        record_type(entry.ip),
+                        entry.ip
+                    ));
+                }
+            }
+        }
+        ExportFormat::Docker => {
+            for entry in entries {
+                for name in &entry.names {
+                    out.push_str(&format!("--add-host={name}:{}\n", entry.ip));
+                }
+            }
+        }
+        ExportFormat::Kubernetes => {
+            out.push_str("hostAliases:\n");
+            for entry in entries {
+                out.push_str(&format!("  - ip: \"{}\"\n    hostnames:\n", entry.ip));
+                for name in &entry.names {
+                    out.push_str(&format!("      - {}\n", quote(name)));
+                }
+            }
+        }
+        ExportFormat::NoProxy => {
+            out.push_str(&no_proxy_list(entries, &[]));
+            out.push('\n');
+        }
+        ExportFormat::ProxyBypass => {
+            out.push_str(&proxy_bypass_list(entries, &[]));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// A `NO_PROXY` list of the names of `entries` and the domains in `suffixes`, so that
+/// clients connect to them directly instead of through a proxy.
+///
+/// Each suffix, such as `corp.example`, is listed both as itself and with a leading dot,
+/// which is how the tools that only match subdomains with a dot expect it. Names are only
+/// listed once and left out if a suffix already covers them.
+pub fn no_proxy_list(entries: &[HostEntry], suffixes: &[&str]) -> String {
+    let (suffixes, names) = bypassed(entries, suffixes);
+    let mut list: Vec<String> = suffixes
+        .iter()
+        .flat_map(|suffix| [suffix.to_string(), format!(".{suffix}")])
+        .collect();
+    list.extend(names.into_iter().map(String::from));
+    list.join(",")
+}
+
+/// A Windows proxy bypass list, as set in the proxy settings or the `ProxyOverride` registry
+/// value, of the names of `entries` and the domains in `suffixes`. See [`no_proxy_list`].
+///
+/// Each suffix is listed as itself and as a `*.` wildcard for its subdomains.
+pub fn proxy_bypass_list(entries: &[HostEntry], suffixes: &[&str]) -> String {
+    let (suffixes, names) = bypassed(entries, suffixes);
+    let mut list: Vec<String> = suffixes
+        .iter()
+        .flat_map(|suffix| [suffix.to_string(), format!("*.{suffix}")])
+        .collect();
+    list.extend(names.into_iter().map(String::from));
+    list.join(";")
+}
+
+/// `suffixes` without the dots or wildcards they may start with, and the names of `entries`
+/// they don't cover, each only once
+fn bypassed<'a>(entries: &'a [HostEntry], suffixes: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut domains: Vec<&str> = Vec::new();
+    for suffix in suffixes {
+        let suffix = suffix.trim_start_matches('*').trim_start_matches('.');
+        if !suffix.is_empty() && !domains.iter().any(|d| hostname_eq(d, suffix)) {
+            domains.push(suffix);
+        }
+    }
+    let covered = |name: &str| {
+        domains.iter().any(|domain| {
+            let start = name.len().wrapping_sub(domain.len());
+            name.is_char_boundary(start)
+                && hostname_eq(&name[start..], domain)
+                && (start == 0 || name[..start].ends_with('.'))
+        })
+    };
+    let mut names: Vec<&str> = Vec::new();
+    for name in entries.iter().flat_map(|entry| &entry.names) {
+        if !covered(name) && !names.iter().any(|n| hostname_eq(n, name)) {
+            names.push(name);
+        }
+    }
+    (domains, names)
+}
+
+/// `text` as a JSON string, which YAML also reads
+fn quote(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `text` as a CSV field, quoted if it needs to be
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// `name` with the trailing dot of an absolute DNS name
+fn absolute(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{name}.")
+    }
+}
+
+fn record_type(ip: IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "A",
+        IpAddr::V6(_) => "AAAA",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_formats() {
+        let entries: Vec<HostEntry> = ["10.0.0.1 web web.lab", "fd00::1 db"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let export = |format: &str| export_entries(&entries, format.parse().unwrap());
+        assert_eq!(
+            export("json"),
+            "[\n  {\"ip\": \"10.0.0.1\", \"names\": [\"web\", \"web.lab\"]},\n  \
+             {\"ip\": \"fd00::1\", \"names\": [\"db\"]}\n]\n"
+        );
+        assert_eq!(
+            export("dnsmasq"),
+            "host-record=web,web.lab,10.0.0.1\nhost-record=db,fd00::1\n"
+        );
+        assert_eq!(
+            export("zone"),
+            "web\tIN\tA\t10.0.0.1\nweb.lab.\tIN\tA\t10.0.0.1\ndb\tIN\tAAAA\tfd00::1\n"
+        );
+        assert_eq!(
+            export("unbound"),
+            "local-data: \"web. IN A 10.0.0.1\"\n\
+             local-data: \"web.lab. IN A 10.0.0.1\"\n\
+             local-data-ptr: \"10.0.0.1 web.\"\n\
+             local-data: \"db. IN AAAA fd00::1\"\n\
+             local-data-ptr: \"fd00::1 db.\"\n"
+        );
+        assert_eq!(
+            export("k8s"),
+            "hostAliases:\n  - ip: \"10.0.0.1\"\n    hostnames:\n      - \"web\"\n      \
+             - \"web.lab\"\n  - ip: \"fd00::1\"\n    hostnames:\n      - \"db\"\n"
+        );
+        assert_eq!(export("no-proxy"), "web,web.lab,db\n");
+        assert_eq!(export_entries(&[], ExportFormat::Json), "[]\n");
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn proxy_bypass_lists() {
+        let entries: Vec<HostEntry> = ["10.0.0.1 web.corp.example WEB", "10.0.0.2 web db.lab"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let suffixes = ["*.corp.example", ".lab", "corp.example"];
+        assert_eq!(
+            no_proxy_list(&entries, &suffixes),
+            "corp.example,.corp.example,lab,.lab,WEB"
+        );
+        assert_eq!(
+            proxy_bypass_list(&entries, &suffixes),
+            "corp.example;*.corp.example;lab;*.lab;WEB"
+        );
+        assert_eq!(
+            proxy_bypass_list(&entries, &[]),
+            "web.corp.example;WEB;db.lab"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_matches_serde() {
+        let entries: Vec<HostEntry> = vec!["10.0.0.1 we\"b".parse().unwrap()];
+        let json = export_entries(&entries, ExportFormat::Json);
+        let parsed: Vec<HostEntry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, entries);
+    }
+}
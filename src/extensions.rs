@@ -0,0 +1,154 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Data of any type that tools attach to a [`crate::HostsFile`] or its lines, such as sync
+/// state or UI flags, holding at most one value of each type.
+///
+/// Extensions are never written to the file and don't affect whether files are equal.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn AnyClone>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach `value`, returning the value of the same type it replaces
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.into_any().downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| (**value).as_any().downcast_ref())
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| (**value).as_any_mut().downcast_mut())
+    }
+
+    /// The value of type `T`, attaching `T::default()` first if there isn't one
+    pub fn get_or_default<T: Clone + Default + Send + Sync + 'static>(&mut self) -> &mut T {
+        let value = self
+            .map
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()));
+        (**value)
+            .as_any_mut()
+            .downcast_mut()
+            .expect("values are stored under their own type")
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.into_any().downcast().ok())
+            .map(|value| *value)
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+/// Values are opaque, so only their number is shown
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A value that can be cloned behind a `Box<dyn Any>`.
+///
+/// `Box<dyn AnyClone>` implements this too, so methods are called on the value inside it.
+trait AnyClone: Send + Sync {
+    fn clone_box(&self) -> Box<dyn AnyClone>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + Sync + 'static> AnyClone for T {
+    fn clone_box(&self) -> Box<dyn AnyClone> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn AnyClone> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HostsFile;
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Synced(bool);
+
+    #[test]
+    fn typed_slots() {
+        let mut extensions = Extensions::new();
+        assert_eq!(extensions.insert(Synced(false)), None);
+        assert_eq!(extensions.insert(Synced(true)), Some(Synced(false)));
+        extensions.insert(String::from("remote"));
+        extensions.get_or_default::<Vec<u32>>().push(7);
+        assert_eq!(extensions.get::<Vec<u32>>(), Some(&vec![7]));
+        assert_eq!(extensions.len(), 3);
+
+        let copy = extensions.clone();
+        extensions.get_mut::<Synced>().unwrap().0 = false;
+        assert_eq!(copy.get::<Synced>(), Some(&Synced(true)));
+        assert_eq!(extensions.remove::<String>().as_deref(), Some("remote"));
+        assert!(!extensions.contains::<String>());
+
+        let mut file = HostsFile::parse("10.0.0.1 web\n10.0.0.2 db\n");
+        let db = file.line_id(1).unwrap();
+        file.line_extensions_mut(db).unwrap().insert(Synced(true));
+        file.extensions_mut().insert(Synced(false));
+        assert_eq!(file.move_entry(db, 0), Ok(true));
+        assert_eq!(file, HostsFile::parse("10.0.0.2 db\n10.0.0.1 web\n"));
+        assert_eq!(file.remove_name("db"), Ok(1));
+        assert!(file.line_extensions(db).is_none());
+        assert!(file.undo());
+        let synced = file.line_extensions(db).and_then(Extensions::get::<Synced>);
+        assert_eq!(synced, Some(&Synced(true)));
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures_lite::io::{AsyncBufRead, AsyncBufReadExt};
+use futures_lite::Stream;
+
+use crate::{
+    locate_error, parse_line_bytes, strip_newline, HostEntry, HostfileError, WatchEvent,
+    WatchHandle, Watcher,
+};
+
+/// Parse hosts file contents from an async reader a line at a time, like
+/// [`crate::parse_file`] does for files.
+///
+/// The reader can come from any runtime that implements the `futures-io` traits, such as
+/// smol and async-std, or tokio through `tokio-util`'s compat layer.
+pub async fn parse_reader_async<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+) -> Result<Vec<HostEntry>, HostfileError> {
+    let mut entries = Vec::new();
+    let mut buf = Vec::new();
+    let mut line_count = 0;
+    loop {
+        buf.clear();
+        line_count += 1;
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(error) => {
+                return Err(HostfileError::Io {
+                    path: None,
+                    line: Some(line_count),
+                    error,
+                })
+            }
+        }
+
+        let line = strip_newline(&buf);
+        match parse_line_bytes(line) {
+            Ok(Some(entry)) => entries.push(entry.into_owned()),
+            Ok(None) => {}
+            Err(err) => return Err(locate_error(err, line_count, line)),
+        }
+    }
+    Ok(entries)
+}
+
+/// The changes a [`Watcher`] finds, as a stream. See [`Watcher::into_stream`].
+///
+/// Dropping the stream stops the watcher.
+#[derive(Debug)]
+pub struct WatchStream {
+    shared: Arc<Mutex<Shared>>,
+    handle: Option<WatchHandle>,
+}
+
+/// What the polling thread hands to the stream
+#[derive(Debug, Default)]
+struct Shared {
+    results: VecDeque<Result<Vec<WatchEvent>, HostfileError>>,
+    waker: Option<Waker>,
+}
+
+impl Watcher {
+    /// Poll the file every `interval` on a background thread like [`Watcher::spawn`], yielding
+    /// the events of each poll that found changes, or the error if the file couldn't be read.
+    ///
+    /// The stream doesn't need a timer from the async runtime, so it works with any of them.
+    pub fn into_stream(self, interval: Duration) -> WatchStream {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let handle = {
+            let shared = Arc::clone(&shared);
+            self.spawn(interval, move |result| {
+                let mut shared = shared.lock().unwrap_or_else(|err| err.into_inner());
+                shared.results.push_back(result);
+                if let Some(waker) = shared.waker.take() {
+                    waker.wake();
+                }
+            })
+        };
+        WatchStream {
+            shared,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Stream for WatchStream {
+    type Item = Result<Vec<WatchEvent>, HostfileError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        match shared.results.pop_front() {
+            Some(result) => Poll::Ready(Some(result)),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for WatchStream {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use futures_lite::future::block_on;
+    use futures_lite::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn async_parse_and_watch() {
+        let source: &[u8] = b"# hosts\r\n127.0.0.1 localhost\n10.0.0.1 web www";
+        let entries = block_on(parse_reader_async(source)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].names, vec!["web", "www"]);
+        let err = block_on(parse_reader_async(&b"10.0.0.1 ok\nbad\n"[..])).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        std::fs::write(&path, "10.0.0.1 web\n").unwrap();
+        let mut stream = Watcher::new(&path)
+            .unwrap()
+            .into_stream(Duration::from_millis(10));
+        std::fs::write(&path, "10.0.0.2 web\n").unwrap();
+        let events = block_on(stream.next()).unwrap().unwrap();
+        assert_eq!(
+            events,
+            vec![WatchEvent::IpChanged {
+                name: String::from("web"),
+                old: vec!["10.0.0.1".parse().unwrap()],
+                new: vec!["10.0.0.2".parse().unwrap()],
+            }]
+        );
+    }
+}
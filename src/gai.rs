@@ -0,0 +1,272 @@
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, parse_table, read_file, HostfileError, Policy, PolicyTable};
+
+/// A `label`, `precedence` or `scopev4` line of `gai.conf`: a value for the addresses within a
+/// prefix. IPv4 prefixes are kept as IPv4-mapped IPv6 prefixes, as `gai.conf` writes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GaiRule {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    pub value: u32,
+}
+
+impl GaiRule {
+    pub fn new(prefix: Ipv6Addr, prefix_len: u8, value: u32) -> Self {
+        GaiRule {
+            prefix,
+            prefix_len: prefix_len.min(128),
+            value,
+        }
+    }
+
+    fn contains(&self, other: &GaiRule) -> bool {
+        self.prefix_len <= other.prefix_len
+            && Policy::new(self.prefix, self.prefix_len, 0, 0).matches(&other.prefix)
+    }
+}
+
+/// The configuration of glibc's `getaddrinfo` address ordering from `/etc/gai.conf`, as
+/// described in `man gai.conf(5)`.
+///
+/// Each kind of rule replaces glibc's built-in table for that kind when the file has any of
+/// them, see [`GaiConf::policy_table`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GaiConf {
+    pub labels: Vec<GaiRule>,
+    pub precedences: Vec<GaiRule>,
+    /// The scopes of IPv4 addresses, with prefixes of at least 96 bits
+    pub ipv4_scopes: Vec<GaiRule>,
+    /// Whether the file is reread when it changes
+    pub reload: bool,
+}
+
+enum GaiLine {
+    Label(GaiRule),
+    Precedence(GaiRule),
+    ScopeV4(GaiRule),
+    Reload(bool),
+}
+
+impl FromStr for GaiLine {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+        let keyword = tokens.next().ok_or("Expected a keyword")?;
+        let mut arg = || {
+            tokens
+                .next()
+                .ok_or(format!("Expected a value for {keyword}"))
+        };
+        let line = match keyword {
+            "label" => GaiLine::Label(parse_rule(arg()?, arg()?, false)?),
+            "precedence" => GaiLine::Precedence(parse_rule(arg()?, arg()?, false)?),
+            "scopev4" => GaiLine::ScopeV4(parse_rule(arg()?, arg()?, true)?),
+            "reload" => GaiLine::Reload(match arg()? {
+                "yes" | "true" => true,
+                "no" | "false" => false,
+                value => return Err(format!("Expected yes or no for reload, not {value}").into()),
+            }),
+            _ => return Err(format!("Unknown keyword {keyword}").into()),
+        };
+        Ok(line)
+    }
+}
+
+/// Parse a `netmask value` pair. A `scopev4` netmask may also be a plain IPv4 prefix.
+fn parse_rule(netmask: &str, value: &str, ipv4: bool) -> Result<GaiRule, HostfileError> {
+    let (addr, len) = netmask.split_once('/').unwrap_or((netmask, ""));
+    let len = |max: u8| -> Result<u8, HostfileError> {
+        if len.is_empty() {
+            return Ok(max);
+        }
+        match len.parse() {
+            Ok(len) if len <= max => Ok(len),
+            _ => Err(format!("Invalid prefix length in {netmask}").into()),
+        }
+    };
+    let (prefix, prefix_len) = match addr.parse::<IpAddr>() {
+        Ok(IpAddr::V6(prefix)) => (prefix, len(128)?),
+        Ok(IpAddr::V4(prefix)) if ipv4 => (prefix.to_ipv6_mapped(), 96 + len(32)?),
+        _ => return Err(format!("Invalid netmask {netmask}").into()),
+    };
+    if ipv4 && (prefix_len < 96 || prefix.to_ipv4_mapped().is_none()) {
+        return Err(format!("Expected an IPv4 netmask, not {netmask}").into());
+    }
+    let value = value
+        .parse()
+        .map_err(|err| format!("Invalid value {value}: {err}"))?;
+    Ok(GaiRule::new(prefix, prefix_len, value))
+}
+
+impl GaiConf {
+    /// Read the system's `gai.conf`, which is next to the hosts file. If it doesn't exist,
+    /// glibc's built-in tables apply.
+    pub fn read() -> Result<Self, HostfileError> {
+        let path = get_gai_conf_path()?;
+        if !path.exists() {
+            return Ok(GaiConf::default());
+        }
+        parse_gai_conf_file(&path)
+    }
+
+    /// The policy table `getaddrinfo` orders addresses by with this configuration, for
+    /// [`crate::LookupOptions::policy_table`].
+    ///
+    /// Rules missing from the configuration are taken from glibc's built-in tables, which
+    /// follow RFC 3484 rather than the [`PolicyTable::default`] of RFC 6724.
+    pub fn policy_table(&self) -> PolicyTable {
+        let or_builtin = |rules: &[GaiRule], builtin: &[(&str, u8, u32)]| -> Vec<GaiRule> {
+            if !rules.is_empty() {
+                return rules.to_vec();
+            }
+            builtin
+                .iter()
+                .map(|&(prefix, len, value)| GaiRule::new(prefix.parse().unwrap(), len, value))
+                .collect()
+        };
+        let labels = or_builtin(&self.labels, GLIBC_LABELS);
+        let precedences = or_builtin(&self.precedences, GLIBC_PRECEDENCES);
+
+        // glibc looks labels and precedences up separately. A row for each prefix of either,
+        // with the values of the longest prefixes containing it, looks up the same.
+        let value = |rules: &[GaiRule], row: &GaiRule| {
+            rules
+                .iter()
+                .filter(|rule| rule.contains(row))
+                .max_by_key(|rule| rule.prefix_len)
+                .map_or(0, |rule| rule.value)
+        };
+        let mut rows: Vec<GaiRule> = Vec::new();
+        for rule in labels.iter().chain(&precedences) {
+            if !rows
+                .iter()
+                .any(|row| (row.prefix, row.prefix_len) == (rule.prefix, rule.prefix_len))
+            {
+                rows.push(*rule);
+            }
+        }
+        let policies = rows
+            .iter()
+            .map(|row| {
+                Policy::new(
+                    row.prefix,
+                    row.prefix_len,
+                    value(&precedences, row),
+                    value(&labels, row),
+                )
+            })
+            .collect();
+
+        let mut table = PolicyTable::new(policies);
+        for rule in &self.ipv4_scopes {
+            if let Some(prefix) = rule.prefix.to_ipv4_mapped() {
+                let scope = rule.value.min(u32::from(u8::MAX)) as u8;
+                table = table.ipv4_scope(prefix, rule.prefix_len - 96, scope);
+            }
+        }
+        table
+    }
+}
+
+/// glibc's built-in label table, used when `gai.conf` has no `label` lines
+const GLIBC_LABELS: &[(&str, u8, u32)] = &[
+    ("::1", 128, 0),
+    ("2002::", 16, 2),
+    ("::", 96, 3),
+    ("::ffff:0:0", 96, 4),
+    ("fec0::", 10, 5),
+    ("fc00::", 7, 6),
+    ("2001::", 32, 7),
+    ("::", 0, 1),
+];
+
+/// glibc's built-in precedence table, used when `gai.conf` has no `precedence` lines
+const GLIBC_PRECEDENCES: &[(&str, u8, u32)] = &[
+    ("::1", 128, 50),
+    ("2002::", 16, 30),
+    ("::", 96, 20),
+    ("::ffff:0:0", 96, 10),
+    ("::", 0, 40),
+];
+
+/// Parse the contents of a `gai.conf` file
+pub fn parse_gai_conf_str(input: &str) -> Result<GaiConf, HostfileError> {
+    let mut conf = GaiConf::default();
+    for line in parse_table(input)? {
+        match line {
+            GaiLine::Label(rule) => conf.labels.push(rule),
+            GaiLine::Precedence(rule) => conf.precedences.push(rule),
+            GaiLine::ScopeV4(rule) => conf.ipv4_scopes.push(rule),
+            GaiLine::Reload(reload) => conf.reload = reload,
+        }
+    }
+    Ok(conf)
+}
+
+/// Parse a `gai.conf` file using the format described in `man gai.conf(5)`
+pub fn parse_gai_conf_file(path: &Path) -> Result<GaiConf, HostfileError> {
+    parse_gai_conf_str(&read_file(path)?)
+}
+
+/// Get path to the system `gai.conf`, which is next to the hosts file
+pub fn get_gai_conf_path() -> Result<PathBuf, HostfileError> {
+    Ok(get_hostfile_path()?.with_file_name("gai.conf"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lookup, AddressOrder, HostEntry, LookupOptions};
+
+    #[test]
+    fn gai_conf_orders_lookups() {
+        let entries: Vec<HostEntry> = ["2001:db8::1 web", "192.0.2.1 web", "10.0.0.1 web"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let ordered = |conf: &GaiConf| {
+            let options = LookupOptions::new()
+                .order(AddressOrder::Rfc6724)
+                .policy_table(conf.policy_table());
+            let addrs = lookup(&entries, "web", options);
+            addrs.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ordered(&GaiConf::default()),
+            ["2001:db8::1", "192.0.2.1", "10.0.0.1"]
+        );
+
+        let conf = parse_gai_conf_str(
+            "# Prefer IPv4\n\
+             reload yes\n\
+             precedence ::ffff:0:0/96 100 # mapped IPv4\n\
+             scopev4 ::ffff:10.0.0.0/104 5\n\
+             scopev4 192.0.2.0/24 14\n",
+        )
+        .unwrap();
+        assert!(conf.reload);
+        assert_eq!(conf.ipv4_scopes[1].prefix_len, 120);
+        assert_eq!(ordered(&conf), ["10.0.0.1", "192.0.2.1", "2001:db8::1"]);
+        // Labels still come from the built-in table
+        let table = conf.policy_table();
+        assert_eq!(table.lookup("2002::1".parse().unwrap()).unwrap().label, 2);
+        assert_eq!(
+            table.lookup("2002::1".parse().unwrap()).unwrap().precedence,
+            0
+        );
+
+        let err = parse_gai_conf_str("label ::1/129 0\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Invalid prefix length in ::1/129 at line 1 with content: 'label ::1/129 0'"
+        );
+        assert!(parse_gai_conf_str("scopev4 ::1 2\n").is_err());
+        assert!(parse_gai_conf_str("sortlist 10.0.0.0\n").is_err());
+    }
+}
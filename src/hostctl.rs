@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use crate::{
+    parse_line_bytes, read_file, write_file, HostEntry, HostfileError, HostsFile, WriteOptions,
+    WriteWarning,
+};
+
+/// A profile of [hostctl](https://github.com/guumaster/hostctl): a named group of entries
+/// that can be turned on and off together.
+///
+/// In a hosts file, a profile is a block between a `# profile.on <name>` or
+/// `# profile.off <name>` comment and an `# end` comment. The entries of profiles that are
+/// off are commented out. See [`HostsFile::hostctl_profiles`] and
+/// [`HostsFile::set_hostctl_profile`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostctlProfile {
+    pub name: String,
+    pub enabled: bool,
+    pub entries: Vec<HostEntry>,
+}
+
+impl HostctlProfile {
+    /// An enabled profile
+    pub fn new(name: impl Into<String>, entries: Vec<HostEntry>) -> Self {
+        HostctlProfile {
+            name: name.into(),
+            enabled: true,
+            entries,
+        }
+    }
+
+    /// Read a profile file, such as `dev.hosts`, that hostctl adds profiles from. Its entries
+    /// are plain hosts file lines and its name is the name of the file without its extension.
+    /// The profile is enabled.
+    pub fn read(path: &Path) -> Result<Self, HostfileError> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("Path ({:?}) does not name a file", path))?;
+        let file = HostsFile::parse(&read_file(path)?);
+        Ok(HostctlProfile::new(name, file.entries().collect()))
+    }
+
+    /// Write the entries of the profile to a profile file, see [`HostctlProfile::read`]
+    pub fn write(
+        &self,
+        path: &Path,
+        options: &WriteOptions,
+    ) -> Result<Vec<WriteWarning>, HostfileError> {
+        write_file(path, &self.entries, options)
+    }
+
+    /// The lines of the profile block, without newlines
+    pub(crate) fn block(&self) -> Vec<String> {
+        let state = if self.enabled { "on" } else { "off" };
+        let mut lines = vec![format!("# profile.{state} {}", self.name)];
+        for entry in &self.entries {
+            lines.push(if self.enabled {
+                entry.to_string()
+            } else {
+                format!("# {entry}")
+            });
+        }
+        lines.push(String::from(PROFILE_END));
+        lines
+    }
+}
+
+/// The comment that ends a profile block
+const PROFILE_END: &str = "# end";
+
+/// The name of the profile that `line` starts and whether it is on
+pub(crate) fn profile_header(line: &str) -> Option<(&str, bool)> {
+    let rest = line.trim().strip_prefix('#')?.trim_start();
+    let (enabled, name) = if let Some(name) = rest.strip_prefix("profile.on") {
+        (true, name)
+    } else {
+        (false, rest.strip_prefix("profile.off")?)
+    };
+    // The name must be separated from the marker, so `# profile.online` isn't a header
+    if !name.starts_with([' ', '\t']) || name.trim().is_empty() {
+        return None;
+    }
+    Some((name.trim(), enabled))
+}
+
+/// Whether `name` can be written in a profile header and read back, see
+/// [`HostsFile::set_hostctl_profile`]
+pub(crate) fn is_profile_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(|c| c.is_whitespace() || c == '#')
+}
+
+/// Whether `line` ends a profile block
+pub(crate) fn is_profile_end(line: &str) -> bool {
+    line.trim() == PROFILE_END
+}
+
+/// The entry on `line` of a profile, which is commented out if the profile is off
+pub(crate) fn profile_entry(line: &str, enabled: bool) -> Option<HostEntry> {
+    let line = if enabled {
+        line
+    } else {
+        line.trim_start().strip_prefix('#')?
+    };
+    match parse_line_bytes(line.as_bytes()) {
+        Ok(Some(entry)) => Some(entry.into_owned()),
+        _ => None,
+    }
+}
@@ -0,0 +1,533 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::document::is_section_name;
+use crate::{
+    accept_backoff, hostname_eq, is_transient, HostEntry, HostfileError, HostsFile, WriteOptions,
+};
+
+/// Largest request body accepted
+const MAX_BODY_LEN: usize = 1 << 20;
+/// Longest request line or header accepted, including the line ending
+const MAX_LINE_LEN: usize = 8 << 10;
+/// Most headers accepted in a request
+const MAX_HEADERS: usize = 100;
+/// How long a client may take to send each part of its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A callback for the errors an [`HttpServer`] keeps serving after, see
+/// [`HttpServer::on_error`]
+type ErrorHandler = dyn Fn(&io::Error) + Send + Sync;
+
+/// A small HTTP API for reading a hosts file and updating its managed sections.
+///
+/// - `GET /entries`: the entries of the file as JSON
+/// - `GET /hosts`: the file itself
+/// - `GET /sections/<name>`: the entries of a managed section as JSON
+/// - `PUT /sections/<name>`: replace the entries of a managed section with a JSON list
+/// - `PATCH /sections/<name>`: change a managed section with a JSON object of entries to
+///   `"add"` and names to `"remove"`
+/// - `DELETE /sections/<name>`: remove a managed section
+///
+/// Entries are JSON objects such as `{"ip": "10.0.0.1", "names": ["web"]}`. Requests that
+/// change the file need an `Authorization: Bearer <token>` header with the token set by
+/// [`HttpServer::token`]; without a token they are refused. See
+/// [`HostsFile::set_managed_section`] for managed sections. Hostnames of new entries must
+/// pass [`HostEntry::validate`].
+pub struct HttpServer {
+    path: PathBuf,
+    token: Option<String>,
+    write_options: WriteOptions,
+    max_connections: usize,
+    on_error: Option<Box<ErrorHandler>>,
+    /// Connections being answered by [`HttpServer::serve`]
+    connections: AtomicUsize,
+    /// Held while the file is read and written back, so that concurrent updates aren't lost
+    write_lock: Mutex<()>,
+}
+
+impl fmt::Debug for HttpServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpServer")
+            .field("path", &self.path)
+            .field("write_options", &self.write_options)
+            .field("max_connections", &self.max_connections)
+            .field("connections", &self.connections)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let mut body = message.into();
+        body.push('\n');
+        Response::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Response::new(200, "application/json", body),
+            Err(err) => Response::error(500, err.to_string()),
+        }
+    }
+
+    fn no_content() -> Self {
+        Response::new(204, "text/plain; charset=utf-8", String::new())
+    }
+
+    fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        write!(
+            w,
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        w.flush()
+    }
+}
+
+/// Body of a `PATCH /sections/<name>` request
+#[derive(Deserialize)]
+struct SectionPatch {
+    #[serde(default)]
+    add: Vec<HostEntry>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+impl HttpServer {
+    /// Serve the hosts file at `path`, read-only until a token is set
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        HttpServer {
+            path: path.into(),
+            token: None,
+            write_options: WriteOptions::new(),
+            max_connections: 64,
+            on_error: None,
+            connections: AtomicUsize::new(0),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Allow requests that change the file if they present `token`
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// How the file is written, see [`crate::write_file`]
+    pub fn write_options(mut self, options: WriteOptions) -> Self {
+        self.write_options = options;
+        self
+    }
+
+    /// How many connections [`HttpServer::serve`] answers at once, 64 by default. Connections
+    /// beyond that are answered with `503 Service Unavailable`.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Call `f` with the errors of accepting single connections, which the server otherwise
+    /// ignores to keep serving, e.g. to log them
+    pub fn on_error(mut self, f: impl Fn(&io::Error) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Serve connections from `listener`, each on its own thread, until accepting connections
+    /// fails for a reason other than a single bad connection
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) if is_transient(&err) => {
+                    if let Some(on_error) = &server.on_error {
+                        on_error(&err);
+                    }
+                    accept_backoff(&err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if server.connections.fetch_add(1, Ordering::SeqCst) >= server.max_connections {
+                server.connections.fetch_sub(1, Ordering::SeqCst);
+                let _ = Response::error(503, "Too many connections").write_to(&stream);
+                continue;
+            }
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let _ = server.handle_connection(stream);
+                server.connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+
+    /// Answer a single request on `stream`
+    pub fn handle_connection(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let response = match read_request(&mut reader) {
+            Ok(request) => self.respond(&request),
+            Err(response) => response,
+        };
+        response.write_to(&stream)
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        let section = request.path.strip_prefix("/sections/");
+        match (request.method.as_str(), request.path.as_str(), section) {
+            ("GET", "/entries", _) => match HostsFile::read(&self.path) {
+                Ok(file) => Response::json(&file.entries().collect::<Vec<_>>()),
+                Err(err) => Response::error(500, err.to_string()),
+            },
+            ("GET", "/hosts", _) => match HostsFile::read(&self.path) {
+                Ok(file) => Response::new(200, "text/plain; charset=utf-8", file.to_string()),
+                Err(err) => Response::error(500, err.to_string()),
+            },
+            (_, _, Some(name)) if !is_section_name(name) => {
+                Response::error(400, format!("Invalid section name {:?}", name))
+            }
+            ("GET", _, Some(name)) => match HostsFile::read(&self.path) {
+                Ok(file) => match file.managed_section(name) {
+                    Some(entries) => Response::json(&entries),
+                    None => Response::error(404, format!("No section named {:?}", name)),
+                },
+                Err(err) => Response::error(500, err.to_string()),
+            },
+            ("PUT", _, Some(name)) | ("PATCH", _, Some(name)) | ("DELETE", _, Some(name)) => {
+                if let Err(response) = self.authorize(request) {
+                    return response;
+                }
+                self.update_section(&request.method, name, &request.body)
+            }
+            (_, "/entries", _) | (_, "/hosts", _) | (_, _, Some(_)) => {
+                Response::error(405, "Method not allowed")
+            }
+            _ => Response::error(404, "Not found"),
+        }
+    }
+
+    fn authorize(&self, request: &Request) -> Result<(), Response> {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return Err(Response::error(403, "Changes are disabled")),
+        };
+        let presented = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(Response::error(401, "Missing or invalid token")),
+        }
+    }
+
+    fn update_section(&self, method: &str, name: &str, body: &[u8]) -> Response {
+        let _guard = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let mut file = match HostsFile::read(&self.path) {
+            Ok(file) => file,
+            Err(err) => return Response::error(500, err.to_string()),
+        };
+
+        let result = match method {
+            "PUT" => match serde_json::from_slice::<Vec<HostEntry>>(body) {
+                Ok(entries) => {
+                    if let Err(response) = validate(&entries) {
+                        return response;
+                    }
+                    file.set_managed_section(name, &entries)
+                }
+                Err(err) => return Response::error(400, err.to_string()),
+            },
+            "PATCH" => {
+                let patch = match serde_json::from_slice::<SectionPatch>(body) {
+                    Ok(patch) => patch,
+                    Err(err) => return Response::error(400, err.to_string()),
+                };
+                if let Err(response) = validate(&patch.add) {
+                    return response;
+                }
+                let mut entries = file.managed_section(name).unwrap_or_default();
+                for entry in &mut entries {
+                    entry
+                        .names
+                        .retain(|n| !patch.remove.iter().any(|name| hostname_eq(n, name)));
+                }
+                entries.retain(|entry| !entry.names.is_empty());
+                entries.extend(patch.add);
+                file.set_managed_section(name, &entries)
+            }
+            _ => match file.remove_managed_section(name) {
+                Ok(true) => Ok(()),
+                Ok(false) => return Response::error(404, format!("No section named {:?}", name)),
+                Err(err) => Err(err.into()),
+            },
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(err @ HostfileError::Protected(_)) => return Response::error(409, err.to_string()),
+            Err(err) => return Response::error(400, err.to_string()),
+        }
+        match file.write(&self.path, &self.write_options) {
+            Ok(_) => Response::no_content(),
+            Err(err) => Response::error(500, err.to_string()),
+        }
+    }
+}
+
+/// Check the entries of a request before they are written
+fn validate(entries: &[HostEntry]) -> Result<(), Response> {
+    for entry in entries {
+        entry.validate().map_err(|kind| {
+            Response::error(400, format!("Invalid entry for {}: {kind}", entry.ip))
+        })?;
+    }
+    Ok(())
+}
+
+/// Read a line of at most [`MAX_LINE_LEN`] bytes into `line`, failing with `too_long` if it is
+/// longer
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String,
+    too_long: impl FnOnce() -> Response,
+) -> Result<(), Response> {
+    line.clear();
+    reader
+        .take(MAX_LINE_LEN as u64)
+        .read_line(line)
+        .map_err(|_| Response::error(400, "Could not read request"))?;
+    if line.len() == MAX_LINE_LEN && !line.ends_with('\n') {
+        return Err(too_long());
+    }
+    Ok(())
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, Response> {
+    let bad_request = |message: &str| Response::error(400, message);
+
+    let mut line = String::new();
+    read_line(reader, &mut line, || {
+        bad_request("Request line is too long")
+    })?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(bad_request("Malformed request line")),
+    };
+
+    let mut content_len = 0;
+    let mut authorization = None;
+    for count in 0.. {
+        read_line(reader, &mut line, || {
+            Response::error(431, "Header is too long")
+        })?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if count == MAX_HEADERS {
+            return Err(Response::error(431, "Too many headers"));
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| bad_request("Malformed header"))?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_len = value
+                .parse()
+                .map_err(|_| bad_request("Invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        }
+    }
+
+    if content_len > MAX_BODY_LEN {
+        return Err(Response::error(413, "Request body is too large"));
+    }
+    let mut body = vec![0; content_len];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("Could not read body"))?;
+
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+/// Compare secrets without leaking where they differ through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use std::fs;
+    use std::io::Read;
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn request(addr: SocketAddr, method: &str, path: &str, token: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nAuthorization: Bearer {token}\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serve_and_update_sections() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "127.0.0.1 localhost\n").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::new(&path).token("secret");
+        thread::spawn(move || server.serve(listener));
+
+        let response = request(addr, "GET", "/entries", "", "");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"[{"ip":"127.0.0.1","names":["localhost"]}]"#));
+
+        let body = r#"[{"ip": "10.0.0.1", "names": ["web", "www"]}]"#;
+        let response = request(addr, "PUT", "/sections/vpn", "wrong", body);
+        assert!(response.starts_with("HTTP/1.1 401"));
+        let response = request(addr, "PUT", "/sections/vpn", "secret", body);
+        assert!(response.starts_with("HTTP/1.1 204"));
+
+        let body = r#"{"add": [{"ip": "10.0.0.2", "names": ["db"]}], "remove": ["WWW"]}"#;
+        let response = request(addr, "PATCH", "/sections/vpn", "secret", body);
+        assert!(response.starts_with("HTTP/1.1 204"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "127.0.0.1 localhost\n\
+             # BEGIN hostfile section vpn\n\
+             10.0.0.1\tweb\n\
+             10.0.0.2\tdb\n\
+             # END hostfile section vpn\n"
+        );
+        let response = request(addr, "GET", "/hosts", "", "");
+        assert!(response.ends_with("# END hostfile section vpn\n"));
+
+        let response = request(addr, "PUT", "/sections/bad%20name", "secret", "[]");
+        assert!(response.starts_with("HTTP/1.1 400"));
+        let body = r#"[{"ip": "10.0.0.3", "names": ["web\n0.0.0.0 bank.example"]}]"#;
+        let response = request(addr, "PUT", "/sections/vpn", "secret", body);
+        assert!(response.starts_with("HTTP/1.1 400"));
+        let body = r#"{"add": [{"ip": "10.0.0.3", "names": []}]}"#;
+        let response = request(addr, "PATCH", "/sections/vpn", "secret", body);
+        assert!(response.starts_with("HTTP/1.1 400"));
+        let response = request(addr, "DELETE", "/sections/vpn", "secret", "");
+        assert!(response.starts_with("HTTP/1.1 204"));
+        let response = request(addr, "GET", "/sections/vpn", "", "");
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "127.0.0.1 localhost\n");
+    }
+
+    #[test]
+    fn read_only_without_token() {
+        let server = HttpServer::new("/nonexistent");
+        let request = Request {
+            method: String::from("DELETE"),
+            path: String::from("/sections/vpn"),
+            authorization: Some(String::from("Bearer anything")),
+            body: Vec::new(),
+        };
+        assert_eq!(server.respond(&request).status, 403);
+    }
+
+    #[test]
+    fn request_limits() {
+        let status = |request: &str| match read_request(&mut request.as_bytes()) {
+            Ok(_) => 200,
+            Err(response) => response.status,
+        };
+        assert_eq!(status("GET /hosts HTTP/1.1\r\nHost: x\r\n\r\n"), 200);
+
+        let long = "a".repeat(MAX_LINE_LEN);
+        assert_eq!(status(&format!("GET /{long} HTTP/1.1\r\n\r\n")), 400);
+        assert_eq!(status(&format!("GET / HTTP/1.1\r\nX: {long}\r\n\r\n")), 431);
+        let headers = "X: y\r\n".repeat(MAX_HEADERS);
+        assert_eq!(status(&format!("GET / HTTP/1.1\r\n{headers}\r\n")), 200);
+        assert_eq!(
+            status(&format!("GET / HTTP/1.1\r\n{headers}X: y\r\n\r\n")),
+            431
+        );
+    }
+
+    #[test]
+    fn transient_accept_errors() {
+        assert!(is_transient(&io::ErrorKind::ConnectionAborted.into()));
+        #[cfg(unix)]
+        assert!(is_transient(&io::Error::from_raw_os_error(libc::EMFILE)));
+        assert!(!is_transient(&io::ErrorKind::InvalidInput.into()));
+    }
+}
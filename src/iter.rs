@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::{
+    check_regular_file, io_error, is_blank_or_comment, locate_error, parse_line_bytes,
+    strip_newline,
+};
+use crate::{HostEntry, HostEntryCow, HostfileError};
+
+/// The entries of a hosts file, parsed a line at a time as they are read, so that large files
+/// can be filtered without holding all of their entries in memory.
+///
+/// An error ends the iteration, since later lines would be reported at the wrong line
+/// numbers if reading failed.
+///
+/// ```
+/// # use hostfile::HostEntryIter;
+/// let input = "0.0.0.0 ads.example\n0.0.0.0 tracker.example\n127.0.0.1 localhost\n";
+/// let blocked = HostEntryIter::new(input.as_bytes())
+///     .filter(|entry| matches!(entry, Ok(entry) if entry.ip.is_unspecified()))
+///     .count();
+/// assert_eq!(blocked, 2);
+/// ```
+#[derive(Debug)]
+pub struct HostEntryIter<R> {
+    reader: R,
+    buf: Vec<u8>,
+    line_count: usize,
+    done: bool,
+}
+
+impl<R: BufRead> HostEntryIter<R> {
+    pub fn new(reader: R) -> Self {
+        HostEntryIter {
+            reader,
+            buf: Vec::new(),
+            line_count: 0,
+            done: false,
+        }
+    }
+
+    /// The number of lines read so far
+    pub fn line_count(&self) -> usize {
+        self.line_count
+    }
+
+    /// The next entry, borrowing its names from the line just read
+    pub(crate) fn next_borrowed(&mut self) -> Option<Result<HostEntryCow<'_>, HostfileError>> {
+        // Skip to the next line with an entry before parsing it, so that the entry can
+        // borrow from the buffer
+        loop {
+            if self.done {
+                return None;
+            }
+            self.buf.clear();
+            self.line_count += 1;
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => self.done = true,
+                Ok(_) if is_blank_or_comment(strip_newline(&self.buf)) => {}
+                Ok(_) => break,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(HostfileError::Io {
+                        path: None,
+                        line: Some(self.line_count),
+                        error,
+                    }));
+                }
+            }
+        }
+        let line = strip_newline(&self.buf);
+        match parse_line_bytes(line) {
+            Ok(entry) => entry.map(Ok),
+            Err(err) => {
+                self.done = true;
+                Some(Err(locate_error(err, self.line_count, line)))
+            }
+        }
+    }
+}
+
+impl HostEntryIter<BufReader<File>> {
+    /// Iterate over the entries of the file at `path`
+    pub fn from_file(path: &Path) -> Result<Self, HostfileError> {
+        check_regular_file(path)?;
+        let file = File::open(path).map_err(|error| io_error(path, error))?;
+        Ok(HostEntryIter::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> Iterator for HostEntryIter<R> {
+    type Item = Result<HostEntry, HostfileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_borrowed()
+            .map(|entry| entry.map(HostEntryCow::into_owned))
+    }
+}
+
+impl<R: BufRead> std::iter::FusedIterator for HostEntryIter<R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterate_lazily() {
+        let mut iter = HostEntryIter::new(&b"# hosts\n10.0.0.1 a\n\nbad\n10.0.0.2 b\n"[..]);
+        assert_eq!(iter.next().unwrap().unwrap().names, ["a"]);
+        assert_eq!(iter.line_count(), 2);
+        let err = iter.next().unwrap().unwrap_err();
+        assert_eq!(err.location().unwrap().line, 4);
+        assert!(iter.next().is_none());
+    }
+}
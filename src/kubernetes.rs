@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{HostEntry, HostfileError};
+
+/// Annotation external-dns reads the hostnames of a service from
+const HOSTNAME_ANNOTATION: &str = "external-dns.alpha.kubernetes.io/hostname";
+
+/// The parts of a Kubernetes object, or list of objects, that give hostnames and addresses
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Object {
+    #[serde(default)]
+    kind: String,
+    #[serde(default)]
+    metadata: Metadata,
+    #[serde(default)]
+    spec: Spec,
+    #[serde(default)]
+    status: Status,
+    #[serde(default)]
+    items: Vec<Object>,
+}
+
+#[derive(Default, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    namespace: String,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Spec {
+    #[serde(rename = "type", default)]
+    service_type: String,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+struct Rule {
+    host: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    #[serde(default)]
+    load_balancer: LoadBalancer,
+}
+
+#[derive(Default, Deserialize)]
+struct LoadBalancer {
+    #[serde(default)]
+    ingress: Vec<LoadBalancerIngress>,
+}
+
+#[derive(Deserialize)]
+struct LoadBalancerIngress {
+    ip: Option<IpAddr>,
+}
+
+impl Object {
+    fn hostnames(&self) -> Vec<String> {
+        let mut names: Vec<String> = match self.kind.as_str() {
+            "Ingress" => self
+                .spec
+                .rules
+                .iter()
+                .filter_map(|rule| rule.host.clone())
+                // Wildcard hosts can't be listed in a hosts file
+                .filter(|host| !host.starts_with('*'))
+                .collect(),
+            "Service" if self.spec.service_type == "LoadBalancer" => {
+                match self.metadata.annotations.get(HOSTNAME_ANNOTATION) {
+                    Some(hosts) => hosts
+                        .split(',')
+                        .map(|host| host.trim().trim_end_matches('.').to_string())
+                        .collect(),
+                    None => vec![format!(
+                        "{}.{}",
+                        self.metadata.name, self.metadata.namespace
+                    )],
+                }
+            }
+            _ => Vec::new(),
+        };
+        names.retain(|name| !name.is_empty());
+        names.dedup();
+        names
+    }
+
+    fn collect_entries(self, entries: &mut Vec<HostEntry>) {
+        let names = self.hostnames();
+        if !names.is_empty() {
+            for ip in self
+                .status
+                .load_balancer
+                .ingress
+                .iter()
+                .filter_map(|lb| lb.ip)
+            {
+                entries.push(HostEntry {
+                    ip,
+                    names: names.clone(),
+                });
+            }
+        }
+        for item in self.items {
+            item.collect_entries(entries);
+        }
+    }
+}
+
+/// Convert the output of `kubectl get ingress,svc -o json` into hosts entries.
+///
+/// Each ingress gets an entry per external IP naming the hosts of its rules. Each
+/// `LoadBalancer` service gets an entry per external IP naming the hosts in its external-dns
+/// hostname annotation, or `<name>.<namespace>` without one. Load balancers that only have
+/// a hostname are left out.
+pub fn parse_kubectl_json(json: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let object: Object = serde_json::from_str(json)
+        .map_err(|err| format!("Could not parse kubectl output: {err}"))?;
+    let mut entries = Vec::new();
+    object.collect_entries(&mut entries);
+    Ok(entries)
+}
+
+/// Run `kubectl get ingress,svc -o json` for all namespaces of the current context and
+/// convert its output with [`parse_kubectl_json`]
+pub fn import_kubernetes() -> Result<Vec<HostEntry>, HostfileError> {
+    let output = Command::new("kubectl")
+        .args(["get", "ingress,svc", "--all-namespaces", "-o", "json"])
+        .output()
+        .map_err(|err| format!("Could not run kubectl: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "kubectl get failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    parse_kubectl_json(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ingresses_and_load_balancers() {
+        let json = r#"{
+            "apiVersion": "v1",
+            "kind": "List",
+            "items": [
+                {
+                    "kind": "Ingress",
+                    "metadata": {"name": "web", "namespace": "default"},
+                    "spec": {"rules": [{"host": "app.example.test"}, {"host": "*.example.test"}, {}]},
+                    "status": {"loadBalancer": {"ingress": [{"ip": "203.0.113.10"}]}}
+                },
+                {
+                    "kind": "Service",
+                    "metadata": {
+                        "name": "db",
+                        "namespace": "data",
+                        "annotations": {
+                            "external-dns.alpha.kubernetes.io/hostname": "db.example.test.,pg.example.test"
+                        }
+                    },
+                    "spec": {"type": "LoadBalancer"},
+                    "status": {"loadBalancer": {"ingress": [{"ip": "203.0.113.11"}]}}
+                },
+                {
+                    "kind": "Service",
+                    "metadata": {"name": "cache", "namespace": "data"},
+                    "spec": {"type": "LoadBalancer"},
+                    "status": {"loadBalancer": {"ingress": [{"hostname": "lb.example.test"}, {"ip": "203.0.113.12"}]}}
+                },
+                {
+                    "kind": "Service",
+                    "metadata": {"name": "internal", "namespace": "data"},
+                    "spec": {"type": "ClusterIP", "clusterIP": "10.96.0.10"},
+                    "status": {"loadBalancer": {}}
+                }
+            ]
+        }"#;
+        let entries = parse_kubectl_json(json).unwrap();
+        let expected: Vec<HostEntry> = vec![
+            "203.0.113.10 app.example.test".parse().unwrap(),
+            "203.0.113.11 db.example.test pg.example.test"
+                .parse()
+                .unwrap(),
+            "203.0.113.12 cache.data".parse().unwrap(),
+        ];
+        assert_eq!(entries, expected);
+        assert!(parse_kubectl_json("kind: List").is_err());
+    }
+}
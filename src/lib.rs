@@ -1,57 +1,250 @@
+//! A crate for parsing hosts files such as `/etc/hosts`.
+//!
+//! Host file format:
+//!
+//! ```text
+//!   File:
+//!     Line |
+//!     Line newline File
+//!
+//!   Line:
+//!     Blank | Comment | Entry
+//!
+//!   Blank:
+//!     ws*
+//!
+//!   Comment:
+//!     ws* # .*
+//!
+//!   Entry:
+//!     ws* ip ws+ Names (ws+ Comment | ws*)
+//!        (where ip is parsed according to std::net)
+//!
+//!   ws: space | tab
+//!
+//!   newline: \n | \r\n
+//!
+//!   Name:
+//!     [a-zA-Z0-9.-]+
+//!
+//!   Names:
+//!     Name | Name ws+ Names
+//! ```
+//!
+//! [`parse_file`] is lenient and accepts any whitespace separated token as a name.
+//! [`parse_file_strict`] and [`parse_str_strict`] reject anything outside of the grammar
+//! above. [`parse_lossless`] keeps every byte of the input, including comments, whitespace and
+//! invalid lines, for tools that need to edit or highlight hosts files. [`ParseOptions`]
+//! chooses between these behaviors and more for [`parse_str_with`] and
+//! [`parse_file_with_options`].
+
+use std::borrow::Cow;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::net::{AddrParseError, IpAddr};
-use std::path::{PathBuf, Path};
+use std::net::{AddrParseError, IpAddr, SocketAddr};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-/**
- * Host file format:
- *   File:
- *     Line |
- *     Line newline File
- *
- *   Line:
- *     Comment | Entry
- *
- *   Comment:
- *     # .* newline
- *
- *   Entry:
- *     ws* ip ws+ Name (ws+ Names | $)
- *        (where ip is parsed according to std::net)
- *
- *   ws: space | tab
- *
- *   Name:
- *     [a-z.-]+
- *
- *   Names:
- *     Name ws* | Name ws+ Names
- */
+mod allowlist;
+mod cloud_init;
+mod config;
+mod convert;
+#[cfg(feature = "dns")]
+mod dns;
+mod dnsmasq;
+#[cfg(feature = "docker")]
+mod docker;
+mod document;
+#[cfg(feature = "elevate")]
+mod elevate;
+mod error;
+mod ethers;
+mod export;
+mod extensions;
+#[cfg(feature = "futures")]
+mod futures;
+mod gai;
+mod hostctl;
+#[cfg(feature = "http")]
+mod http;
+mod iter;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
+mod lint;
+mod lmhosts;
+mod location;
+mod lookup;
+mod machine;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod minimize;
+mod name;
+mod networks;
+mod options;
+mod order;
+mod parallel;
+mod patch;
+mod protect;
+mod root;
+mod section;
+mod services;
+mod shared;
+#[cfg(feature = "ssh")]
+mod ssh;
+mod stock;
+mod strict;
+mod syntax;
+#[cfg(feature = "tailscale")]
+mod tailscale;
+mod tcp_wrappers;
+#[cfg(feature = "metrics")]
+pub mod telemetry;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "url")]
+mod url_host;
+#[cfg(feature = "clap")]
+mod value_parser;
+mod watch;
+mod wildcard;
+mod write;
+
+pub use allowlist::{apply_allowlist, Allowlist};
+pub use cloud_init::{cloud_init_manages_etc_hosts, ManageEtcHosts};
+#[cfg(feature = "config")]
+pub use config::apply_config;
+pub use config::DesiredState;
+pub use convert::{group_by_ip_with, HostEntries};
+#[cfg(feature = "dns")]
+pub use dns::DnsServer;
+pub use dnsmasq::{
+    parse_dhcp_hostsfile, parse_dhcp_hostsfile_file, parse_dnsmasq_leases,
+    parse_dnsmasq_leases_file,
+};
+#[cfg(feature = "docker")]
+pub use docker::{import_docker, parse_docker_inspect, refresh_docker_section, DOCKER_SECTION};
+pub use document::{HostnameStatus, HostsFile, Line, LineId};
+#[cfg(feature = "elevate")]
+pub use elevate::Elevation;
+pub use error::{
+    ErrorLocation, HostfileError, HostfileErrorCode, ParseError, ParseErrorCode, ParseErrorKind,
+};
+pub use ethers::{
+    get_ethers_path, join_ethers, macs_for_ip, parse_ethers_file, parse_ethers_str, EtherEntry,
+    MacAddr,
+};
+pub use export::{export_entries, no_proxy_list, proxy_bypass_list, ExportFormat};
+pub use extensions::Extensions;
+#[cfg(feature = "futures")]
+pub use futures::{parse_reader_async, WatchStream};
+pub use gai::{get_gai_conf_path, parse_gai_conf_file, parse_gai_conf_str, GaiConf, GaiRule};
+pub use hostctl::HostctlProfile;
+#[cfg(feature = "http")]
+pub use http::HttpServer;
+pub use iter::HostEntryIter;
+#[cfg(feature = "kubernetes")]
+pub use kubernetes::{import_kubernetes, parse_kubectl_json};
+#[cfg(feature = "sarif")]
+pub use lint::to_sarif;
+pub use lint::{
+    lint_file, lint_fix, lint_str, lint_str_with, Finding, LintOptions, Rule, Severity,
+};
+pub use lmhosts::{
+    get_lmhosts_path, merge_lmhosts, parse_lmhosts_file, parse_lmhosts_str, Lmhosts, LmhostsEntry,
+    LmhostsInclude, MergedName, NameSource,
+};
+pub use location::{hostfile_location, HostfileLocation, LocationSource, Platform};
+pub use lookup::{IpEquivalence, LookupOptions};
+pub use machine::self_entry;
+#[cfg(feature = "mdns")]
+pub use mdns::{discover_mdns_hosts, MdnsPublisher};
+pub use minimize::{minimize, MinimizeOptions};
+pub use name::{hostname_eq, hostname_key, validate_hostname, HostnamePolicy};
+pub use networks::{get_networks_path, parse_networks_file, parse_networks_str, NetworkEntry};
+pub use options::{
+    parse_file_with_options, parse_str_lenient, parse_str_with, EntrySpans, ParseOptions,
+    ParsedEntry, SkippedLine,
+};
+pub use order::{sort_addresses, AddressOrder, Policy, PolicyTable};
+pub use parallel::parse_files_parallel;
+pub use patch::{apply_patch, diff_patch, PatchOp};
+pub use protect::{Generator, ProtectedRegion};
+pub use root::{hostfile_path_in_root, parse_file_in_root};
+#[cfg(target_os = "linux")]
+pub use root::{parse_process_hostfile, process_root};
+pub use section::{Section, SectionPattern};
+pub use services::{
+    get_services_path, parse_services_file, parse_services_str, service_by_name, service_by_port,
+    ServiceEntry,
+};
+pub use shared::{HostsSnapshot, SharedHosts};
+#[cfg(feature = "ssh")]
+pub use ssh::{parse_remote, read_remote, write_remote, RemoteHost};
+pub use stock::{differences_from_default, is_default};
+pub use strict::{parse_file_strict, parse_str_strict, validate_file, validate_str};
+pub use syntax::{parse_lossless, tokenize, SyntaxLine, SyntaxTree, Token, TokenKind, Tokens};
+#[cfg(feature = "tailscale")]
+pub use tailscale::{import_tailscale, parse_tailscale_status};
+pub use tcp_wrappers::{parse_access_file, parse_access_str, AccessRule, TcpWrappers};
+#[cfg(feature = "testing")]
+pub use testing::{HostfileFixture, TempHostsFile};
+#[cfg(feature = "url")]
+pub use url_host::overrides_url;
+#[cfg(feature = "clap")]
+pub use value_parser::{HostEntryParser, HostNameParser};
+pub use watch::{diff_entries, WatchEvent, WatchHandle, Watcher};
+pub use wildcard::{expand_wildcards, parse_wildcard_rules, WildcardRule};
+#[cfg(feature = "tokio")]
+pub use write::write_entries_async;
+pub use write::{
+    write_entries, write_file, write_file_streaming, BackupPolicy, WriteOptions, WriteWarning,
+};
+
+/// Length of the (possibly invalid) IP address token at the start of `input`
+fn ip_len(input: &str) -> usize {
+    input
+        .find(|c: char| c != '.' && c != ':' && !c.is_ascii_hexdigit())
+        .unwrap_or(input.len())
+}
 
 fn parse_ip(input: &str) -> Result<(IpAddr, &str), AddrParseError> {
-    let non_ip_char_idx = input.find(|c: char| c != '.' && c != ':' && !c.is_digit(16));
-    let (ip, remainder) = input.split_at(non_ip_char_idx.unwrap_or(input.len()));
+    let (ip, remainder) = input.split_at(ip_len(input));
     Ok((ip.parse()?, remainder))
 }
 
 /// A struct representing a line from /etc/hosts that has a host on it
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HostEntry {
     pub ip: IpAddr,
     pub names: Vec<String>,
 }
 
 impl FromStr for HostEntry {
-    type Err = String;
+    type Err = HostfileError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut input = s;
         input = input.trim_start();
+        // 1-based column of the start of `input`
+        let column = |input: &str| s.len() - input.len() + 1;
+        let word = |input: &str| {
+            let end = input
+                .find(|c: char| c.is_ascii() && is_ws_byte(c as u8))
+                .unwrap_or(input.len());
+            input[..end].to_string()
+        };
 
         let ip = parse_ip(input);
-        if let Err(err) = ip {
-            return Err(format!("Couldn't parse a valid IP address: {err}"));
+        if let Err(error) = ip {
+            return Err(HostfileError::InvalidIp {
+                error,
+                token: word(input),
+                column: column(input),
+                location: None,
+            });
         }
         let ip = ip.unwrap();
         input = ip.1;
@@ -60,137 +253,508 @@ impl FromStr for HostEntry {
         match input.chars().next() {
             Some(' ') | Some('\t') => {}
             _ => {
-                return Err("Expected whitespace after IP".to_string());
+                return Err(HostfileError::MissingWhitespace {
+                    token: word(input),
+                    column: column(input),
+                    location: None,
+                });
             }
         }
         input = input.trim_start();
 
-        let mut names = Vec::new();
-        for name in input.split_whitespace() {
-            // Account for comments at the end of the line
-            match name.chars().next() {
-                Some('#') => break,
-                Some(_) => {}
-                None => unreachable!(),
-            }
-            names.push(name.to_string());
-        }
+        // Account for comments at the end of the line
+        let names = input
+            .split_whitespace()
+            .take_while(|name| !name.starts_with('#'))
+            .map(String::from)
+            .collect();
 
         Ok(HostEntry { ip, names })
     }
 }
 
-/// Parse a file using the format described in `man hosts(7)`
-pub fn parse_file(path: &Path) -> Result<Vec<HostEntry>, String> {
-    if !path.exists() || !path.is_file() {
-        return Err(format!(
-            "File ({:?}) does not exist or is not a regular file",
-            path
-        ));
+/// Formats the entry as a hosts file line (without a newline), e.g. `127.0.0.1\tlocalhost`
+impl fmt::Display for HostEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.ip, self.names.join(" "))
+    }
+}
+
+/// A [`HostEntry`] whose names can borrow from the input they were parsed from.
+///
+/// [`parse_bytes_borrowed`] produces entries that borrow, avoiding an allocation per name,
+/// while entries built by hand can own their names. Use [`HostEntryCow::into_owned`] or
+/// `HostEntry::from` to convert into a fully owned [`HostEntry`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostEntryCow<'a> {
+    pub ip: IpAddr,
+    pub names: Vec<Cow<'a, str>>,
+}
+
+impl HostEntryCow<'_> {
+    /// Convert into a [`HostEntry`], copying any borrowed names
+    pub fn into_owned(self) -> HostEntry {
+        HostEntry {
+            ip: self.ip,
+            names: self.names.into_iter().map(Cow::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> From<HostEntryCow<'a>> for HostEntry {
+    fn from(entry: HostEntryCow<'a>) -> Self {
+        entry.into_owned()
+    }
+}
+
+impl From<HostEntry> for HostEntryCow<'static> {
+    fn from(entry: HostEntry) -> Self {
+        HostEntryCow {
+            ip: entry.ip,
+            names: entry.names.into_iter().map(Cow::Owned).collect(),
+        }
     }
+}
 
-    let file = File::open(path);
-    if file.is_err() {
-        return Err(format!("Could not open file ({:?})", path));
+impl<'a> From<&'a HostEntry> for HostEntryCow<'a> {
+    fn from(entry: &'a HostEntry) -> Self {
+        HostEntryCow {
+            ip: entry.ip,
+            names: entry
+                .names
+                .iter()
+                .map(|name| Cow::Borrowed(name.as_str()))
+                .collect(),
+        }
     }
-    let file = file.unwrap();
+}
 
-    let mut entries = Vec::new();
+/// Iterate over every name in `entries` as `(ip, name, index of the entry in entries)`.
+///
+/// This is the name-level view of a hosts file, with one record per hostname rather than one
+/// per line.
+pub fn flatten(entries: &[HostEntry]) -> impl Iterator<Item = (IpAddr, &str, usize)> {
+    entries.iter().enumerate().flat_map(|(idx, entry)| {
+        entry
+            .names
+            .iter()
+            .map(move |name| (entry.ip, name.as_str(), idx))
+    })
+}
 
-    let lines = BufReader::new(file).lines();
-    let mut line_count = 1;
-    for line in lines {
-        if let Err(err) = line {
-            return Err(format!("Error reading file at line {line_count}: {err}"));
+/// All addresses for `name` in `entries`, ordered according to `options`.
+///
+/// Names are compared with [`hostname_eq`] and each address is only returned once, in the
+/// form it is first listed in. Pass an [`AddressOrder`] to only change the order.
+pub fn lookup(entries: &[HostEntry], name: &str, options: impl Into<LookupOptions>) -> Vec<IpAddr> {
+    let options = options.into();
+    let same = |a, b| options.ip_equivalence.same_target(a, b);
+    let mut addrs: Vec<IpAddr> = Vec::new();
+    for entry in entries {
+        if !addrs.iter().any(|&ip| same(ip, entry.ip))
+            && entry.names.iter().any(|n| hostname_eq(n, name))
+        {
+            addrs.push(entry.ip);
         }
+    }
+    match (options.order, &options.policy_table) {
+        (AddressOrder::Rfc6724, Some(table)) => table.sort(&mut addrs),
+        (order, _) => sort_addresses(&mut addrs, order),
+    }
+    addrs
+}
+
+/// The socket addresses `addr` stands for when its host is resolved from `entries`, like
+/// [`std::net::ToSocketAddrs`] does for a `"host:port"` string.
+///
+/// `addr` is a host and a port separated by `:`, such as `web:8080`. Literal addresses, such
+/// as `10.0.0.1:80` or `[::1]:443`, stand for themselves. Names are resolved with [`lookup`]
+/// and fail if they aren't in `entries`.
+pub fn resolve_socket_addr(
+    entries: &[HostEntry],
+    addr: &str,
+) -> Result<Vec<SocketAddr>, HostfileError> {
+    if let Ok(addr) = addr.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    }
+    let invalid = |reason: String| HostfileError::InvalidSocketAddr {
+        addr: addr.to_string(),
+        reason,
+    };
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| invalid(String::from("missing port")))?;
+    let port: u16 = port
+        .parse()
+        .map_err(|err| invalid(format!("invalid port: {err}")))?;
+    if host.contains(':') {
+        return Err(invalid(String::from("IPv6 addresses must be in brackets")));
+    }
+    let addrs = lookup(entries, host, LookupOptions::default());
+    if addrs.is_empty() {
+        return Err(HostfileError::HostNotFound(host.to_string()));
+    }
+    Ok(addrs
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect())
+}
+
+/// Check that `path` is a regular file or a symlink to one
+fn check_regular_file(path: &Path) -> Result<(), HostfileError> {
+    // `is_file` follows symlinks
+    if !path.is_file() {
+        return Err(HostfileError::NotAFile(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Read a whole file into memory, producing the same errors as [`parse_file`]
+fn read_file(path: &Path) -> Result<String, HostfileError> {
+    check_regular_file(path)?;
+    std::fs::read_to_string(path).map_err(|error| HostfileError::Io {
+        path: Some(path.to_path_buf()),
+        line: None,
+        error,
+    })
+}
+
+/// Whitespace as understood by `char::is_whitespace`, restricted to ASCII
+fn is_ws_byte(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r')
+}
+
+fn trim_start_bytes(input: &[u8]) -> &[u8] {
+    let start = input
+        .iter()
+        .position(|&b| !is_ws_byte(b))
+        .unwrap_or(input.len());
+    &input[start..]
+}
 
-        let line = line.unwrap();
-        let line = line.trim_start();
-        match line.chars().next() {
-            // comment
-            Some('#') => continue,
-            // empty line
-            None => continue,
-            // valid line
-            Some(_) => {}
+/// Whether `line` is blank or a comment, which [`parse_line_bytes`] parses as `None`
+fn is_blank_or_comment(line: &[u8]) -> bool {
+    let line = trim_start_bytes(line);
+    match line.first() {
+        None | Some(b'#') => true,
+        Some(b) if !b.is_ascii() => std::str::from_utf8(line).is_ok_and(|line| {
+            let line = line.trim_start();
+            line.is_empty() || line.starts_with('#')
+        }),
+        Some(_) => false,
+    }
+}
+
+/// Parse a single line (without its newline) the same way as [`HostEntry::from_str`].
+///
+/// This works on bytes so that only names need to be valid UTF-8; blank lines and comments
+/// produce `Ok(None)`.
+fn parse_line_bytes(line: &[u8]) -> Result<Option<HostEntryCow<'_>>, HostfileError> {
+    if is_blank_or_comment(line) {
+        return Ok(None);
+    }
+    let indent = line.len() - trim_start_bytes(line).len();
+    let line = trim_start_bytes(line);
+    // 1-based column of the start of `input` in the line as given
+    let column = |input: &[u8]| indent + line.len() - input.len() + 1;
+    let word = |input: &[u8]| {
+        let end = input
+            .iter()
+            .position(|&b| is_ws_byte(b))
+            .unwrap_or(input.len());
+        String::from_utf8_lossy(&input[..end]).into_owned()
+    };
+    // Leading non-ASCII whitespace, let the str parser deal with it
+    if !line[0].is_ascii() {
+        return match std::str::from_utf8(line) {
+            Ok(line) => line
+                .parse::<HostEntry>()
+                .map(|entry| Some(entry.into()))
+                .map_err(|err| err.shifted(indent)),
+            Err(error) => Err(HostfileError::InvalidUtf8 {
+                error,
+                token: word(line),
+                column: column(line),
+                location: None,
+            }),
         };
-        match line.parse() {
-            Ok(parsed_host_entry) => entries.push(parsed_host_entry),
-            Err(err) => {
-                return Err(format!("{err} at line {line_count} with content: '{line}'"));
+    }
+
+    let ip_end = line
+        .iter()
+        .position(|&b| b != b'.' && b != b':' && !b.is_ascii_hexdigit())
+        .unwrap_or(line.len());
+    // The IP is made of ASCII characters only, so this can't fail
+    let ip = std::str::from_utf8(&line[..ip_end]).unwrap_or_default();
+    let ip = ip.parse().map_err(|error| HostfileError::InvalidIp {
+        error,
+        token: word(line),
+        column: column(line),
+        location: None,
+    })?;
+
+    let mut rest = match line.get(ip_end) {
+        Some(b' ') | Some(b'\t') => &line[ip_end..],
+        _ => {
+            return Err(HostfileError::MissingWhitespace {
+                token: word(&line[ip_end..]),
+                column: column(&line[ip_end..]),
+                location: None,
+            })
+        }
+    };
+
+    let mut names = Vec::new();
+    'tokens: loop {
+        rest = trim_start_bytes(rest);
+        if rest.is_empty() || rest[0] == b'#' {
+            break;
+        }
+
+        let mut end = memchr::memchr2(b' ', b'\t', rest).unwrap_or(rest.len());
+        if let Some(other_ws) = memchr::memchr3(b'\r', b'\x0b', b'\x0c', &rest[..end]) {
+            end = other_ws;
+        }
+        let (token, remainder) = rest.split_at(end);
+
+        // Validate UTF-8 only now that we know we're looking at a name
+        let token = std::str::from_utf8(token).map_err(|error| HostfileError::InvalidUtf8 {
+            error,
+            token: String::from_utf8_lossy(token).into_owned(),
+            column: column(rest),
+            location: None,
+        })?;
+        rest = remainder;
+        if token.is_ascii() {
+            names.push(Cow::Borrowed(token));
+            continue;
+        }
+        // Non-ASCII whitespace may separate several names or a comment within this token
+        for name in token.split_whitespace() {
+            if name.starts_with('#') {
+                break 'tokens;
             }
+            names.push(Cow::Borrowed(name));
+        }
+    }
+
+    Ok(Some(HostEntryCow { ip, names }))
+}
+
+/// Strip the `\n` or `\r\n` from the end of a line
+fn strip_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn line_error(err: impl fmt::Display, line_count: usize, line: &[u8]) -> HostfileError {
+    let line = String::from_utf8_lossy(trim_start_bytes(line));
+    HostfileError::Other(format!("{err} at line {line_count} with content: '{line}'"))
+}
+
+/// `err` located at `line`, the line numbered `line_count`
+fn locate_error(err: HostfileError, line_count: usize, line: &[u8]) -> HostfileError {
+    err.at(ErrorLocation {
+        line: line_count,
+        content: String::from_utf8_lossy(trim_start_bytes(line)).into_owned(),
+    })
+}
+
+/// Parse every line of `input` that isn't blank or a comment as a `T`, for the files in the
+/// same format family as the hosts file
+pub(crate) fn parse_table<T: FromStr<Err = HostfileError>>(
+    input: &str,
+) -> Result<Vec<T>, HostfileError> {
+    let mut entries = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or_default();
+        if content.trim().is_empty() {
+            continue;
         }
+        let entry = content
+            .parse()
+            .map_err(|err| line_error(err, idx + 1, line.as_bytes()))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Parse hosts file contents that are already in memory without copying the names.
+///
+/// Only hostnames need to be valid UTF-8. Errors are reported like [`parse_file`]'s.
+pub fn parse_bytes_borrowed(input: &[u8]) -> Result<Vec<HostEntryCow<'_>>, HostfileError> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut line_count = 0;
+    while start < input.len() {
         line_count += 1;
+        let end = memchr::memchr(b'\n', &input[start..]).map_or(input.len(), |idx| start + idx + 1);
+        let line = strip_newline(&input[start..end]);
+        match parse_line_bytes(line) {
+            Ok(Some(parsed_host_entry)) => entries.push(parsed_host_entry),
+            Ok(None) => {}
+            Err(err) => return Err(locate_error(err, line_count, line)),
+        }
+        start = end;
     }
 
     Ok(entries)
 }
 
-/// Parse system hostfile.
+/// Parse hosts file contents that are already in memory, such as a file fetched over the
+/// network. Errors are reported like [`parse_file`]'s.
+pub fn parse_str(input: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let entries = parse_bytes_borrowed(input.as_bytes())?;
+    Ok(entries.into_iter().map(HostEntryCow::into_owned).collect())
+}
+
+/// How a file is read by [`parse_file_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadMode {
+    /// Read the file a line at a time through a buffered reader
+    #[default]
+    Buffered,
+    /// Read the whole file with a single call and parse it from memory. This is usually faster
+    /// for small to medium sized files.
+    WholeFile,
+}
+
+/// Parse a file using the format described in `man hosts(7)`.
 ///
-/// - `/etc/hosts` on Unix.
-/// - `C:\Windows\system32\drivers\etc\hosts` on Windows.
-pub fn parse_hostfile() -> Result<Vec<HostEntry>, String> {
-    parse_file(&get_hostfile_path()?)
+/// `path` may be a symlink to the hosts file, as on NixOS.
+pub fn parse_file(path: &Path) -> Result<Vec<HostEntry>, HostfileError> {
+    parse_file_with(path, ReadMode::Buffered)
+}
+
+/// Parse a file like [`parse_file`], reading it according to `mode`
+pub fn parse_file_with(path: &Path, mode: ReadMode) -> Result<Vec<HostEntry>, HostfileError> {
+    check_regular_file(path)?;
+
+    if mode == ReadMode::WholeFile {
+        let contents = std::fs::read(path).map_err(|error| io_error(path, error))?;
+        let entries = parse_bytes_borrowed(&contents)?;
+        return Ok(entries.into_iter().map(HostEntryCow::into_owned).collect());
+    }
+
+    let file = File::open(path).map_err(|error| io_error(path, error))?;
+    parse_reader(BufReader::new(file))
+}
+
+/// Parse entries from any buffered reader, such as a socket, a decompressing stream or an
+/// in-memory cursor, a line at a time. Errors are reported like [`parse_file`]'s.
+///
+/// With the `futures` feature, `parse_reader_async` does the same for async readers.
+pub fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<HostEntry>, HostfileError> {
+    HostEntryIter::new(reader).collect()
+}
+
+/// Stream the entries of the file at `path` to `f` a line at a time, until `f` breaks
+fn scan_file<F>(path: &Path, f: F) -> Result<(), HostfileError>
+where
+    F: FnMut(HostEntryCow<'_>) -> ControlFlow<()>,
+{
+    let file = File::open(path).map_err(|error| io_error(path, error))?;
+    scan_reader(BufReader::new(file), f)
+}
+
+/// The error of failing to open, read or write the file at `path`
+fn io_error(path: &Path, error: std::io::Error) -> HostfileError {
+    HostfileError::Io {
+        path: Some(path.to_path_buf()),
+        line: None,
+        error,
+    }
 }
 
-/// Get path to the system hostfile.
-pub fn get_hostfile_path() -> Result<PathBuf, String> {
-    #[cfg(not(windows))]
-    {
-        Ok(PathBuf::from("/etc/hosts"))
+/// Whether `err` is about a single packet or connection rather than the socket it came from,
+/// so that a server can keep serving after it. Running out of file descriptors counts too, as
+/// they are freed again when connections close.
+#[cfg(any(feature = "dns", feature = "http"))]
+pub(crate) fn is_transient(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+    ) || out_of_descriptors(err)
+}
+
+/// Pause after accepting a connection failed with `err`, so that running out of file
+/// descriptors doesn't make the accept loop spin until some are freed
+#[cfg(any(feature = "dns", feature = "http"))]
+pub(crate) fn accept_backoff(err: &std::io::Error) {
+    if out_of_descriptors(err) {
+        std::thread::sleep(std::time::Duration::from_millis(100));
     }
+}
 
+#[cfg(any(feature = "dns", feature = "http"))]
+fn out_of_descriptors(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    let codes = [libc::EMFILE, libc::ENFILE, libc::ENOBUFS, libc::ENOMEM];
+    // WSAEMFILE and WSAENOBUFS
     #[cfg(windows)]
-    {
-        // Implementation adapted from cargo's `home`.
-        // See https://crates.io/crates/home
-        use std::ffi::OsString;
-        use std::os::windows::ffi::OsStringExt;
-        use std::ptr::null_mut;
-        use std::slice;
-        use windows_sys::Win32::{
-            Foundation::S_OK,
-            System::Com::CoTaskMemFree,
-            UI::Shell::{FOLDERID_System, SHGetKnownFolderPath, KF_FLAG_DONT_VERIFY},
-        };
+    let codes = [10024, 10055];
+    #[cfg(not(any(unix, windows)))]
+    let codes: [i32; 0] = [];
+    err.raw_os_error().is_some_and(|code| codes.contains(&code))
+}
 
-        extern "C" {
-            fn wcslen(buf: *const u16) -> usize;
+/// Stream the entries read from `reader` to `f` a line at a time, until `f` breaks
+fn scan_reader<R, F>(reader: R, mut f: F) -> Result<(), HostfileError>
+where
+    R: BufRead,
+    F: FnMut(HostEntryCow<'_>) -> ControlFlow<()>,
+{
+    let mut entries = HostEntryIter::new(reader);
+    while let Some(entry) = entries.next_borrowed() {
+        if f(entry?).is_break() {
+            break;
         }
+    }
+    Ok(())
+}
 
-        let mut ptr = null_mut::<u16>();
-        let ret = unsafe {
-            SHGetKnownFolderPath(
-                &FOLDERID_System,
-                KF_FLAG_DONT_VERIFY as u32,
-                null_mut(),
-                &mut ptr,
-            )
-        };
+/// Find the first entry for `name` in the file at `path`, like the system resolver does.
+///
+/// The file is read only up to the matching entry, so this is much cheaper than
+/// [`parse_file`] for one-off lookups in large files. Names are compared with
+/// [`hostname_eq`]. Malformed lines before the match are reported as errors, just like
+/// [`parse_file`] does.
+pub fn find_host_in_file(path: &Path, name: &str) -> Result<Option<HostEntry>, HostfileError> {
+    check_regular_file(path)?;
 
-        match ret {
-            S_OK => {
-                let path_slice = unsafe { slice::from_raw_parts(ptr, wcslen(ptr)) };
-                let os_str = OsString::from_wide(path_slice);
-                unsafe { CoTaskMemFree(ptr.cast()) };
-                let mut pathbuf = PathBuf::from(&os_str);
-                pathbuf.push("drivers\\etc\\hosts");
-                Ok(pathbuf)
-            }
-            _ => {
-                // free any allocated memory even on failure (a null ptr is a no-op for `CoTaskMemFree`)
-                unsafe { CoTaskMemFree(ptr.cast()) };
-                Err(format!(
-                    "Could not get path to Windows hosts file: {}",
-                    std::io::Error::last_os_error(),
-                ))
-            }
+    let mut found = None;
+    scan_file(path, |entry| {
+        if entry.names.iter().any(|n| hostname_eq(n, name)) {
+            found = Some(entry.into_owned());
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
         }
-    }
+    })?;
+    Ok(found)
+}
+
+/// Parse system hostfile.
+///
+/// - `/etc/hosts` on Unix.
+/// - `C:\Windows\system32\drivers\etc\hosts` on Windows.
+pub fn parse_hostfile() -> Result<Vec<HostEntry>, HostfileError> {
+    parse_file(&get_hostfile_path()?)
+}
+
+/// Get path to the system hostfile, see [`hostfile_location`] for how it is found.
+pub fn get_hostfile_path() -> Result<PathBuf, HostfileError> {
+    Ok(hostfile_location()?.path)
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::write_with_newline)]
 mod tests {
     extern crate mktemp;
     use mktemp::Temp;
@@ -344,7 +908,7 @@ mod tests {
 
         write!(file, "127.0.0.1localhost\n").expect("");
         assert_eq!(
-            parse_file(&temp_path),
+            parse_file(&temp_path).map_err(|err| err.to_string()),
             Err(
                 "Expected whitespace after IP at line 1 with content: '127.0.0.1localhost'"
                     .to_string()
@@ -355,7 +919,7 @@ mod tests {
         file.seek(SeekFrom::Start(0)).expect("");
         write!(file, "127.0.0 localhost\n").expect("");
         assert_eq!(
-            parse_file(&temp_path),
+            parse_file(&temp_path).map_err(|err| err.to_string()),
             Err("Couldn't parse a valid IP address: invalid IP address syntax at line 1 with content: '127.0.0 localhost'".to_string())
         );
 
@@ -363,7 +927,7 @@ mod tests {
         file.seek(SeekFrom::Start(0)).expect("");
         write!(file, "127.0.0 local\nhost\n").expect("");
         assert_eq!(
-            parse_file(&temp_path),
+            parse_file(&temp_path).map_err(|err| err.to_string()),
             Err("Couldn't parse a valid IP address: invalid IP address syntax at line 1 with content: '127.0.0 local'".to_string())
         );
 
@@ -371,14 +935,14 @@ mod tests {
         file.seek(SeekFrom::Start(0)).expect("");
         write!(file, "127.0.0.1 localhost\nlocalhost myhost").expect("");
         assert_eq!(
-            parse_file(&temp_path),
+            parse_file(&temp_path).map_err(|err| err.to_string()),
             Err("Couldn't parse a valid IP address: invalid IP address syntax at line 2 with content: 'localhost myhost'".to_string())
         );
 
         let temp_dir = Temp::new_dir().unwrap();
         let temp_dir_path = temp_dir.as_path();
         assert_eq!(
-            parse_file(&temp_dir_path),
+            parse_file(&temp_dir_path).map_err(|err| err.to_string()),
             Err(format!(
                 "File ({:?}) does not exist or is not a regular file",
                 temp_dir_path
@@ -386,6 +950,218 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_line_bytes_matches_from_str() {
+        let lines = [
+            "127.0.0.1 localhost",
+            "  ::1\tlocalhost ip6-localhost  ",
+            "1.2.3.4 a\x0bb\x0cc\rd",
+            "1.2.3.4 a # comment",
+            "1.2.3.4 foo#bar",
+            "1.2.3.4 b\u{fc}cher.example",
+            "1.2.3.4 a\u{a0}b\u{a0}#comment",
+            "\u{a0}1.2.3.4 a",
+            "1.2.3.4 ",
+            "127.0.0.1localhost",
+            "127.0.0 localhost",
+        ];
+        for line in lines.iter() {
+            assert_eq!(
+                parse_line_bytes(line.as_bytes()).map(|entry| entry.map(HostEntry::from)),
+                line.parse().map(Some),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn parse_file_invalid_utf8() {
+        let temp_file = Temp::new_file().unwrap();
+        let temp_path = temp_file.as_path();
+        let mut file = File::create(temp_path).unwrap();
+
+        // Only names need to be valid UTF-8
+        file.write_all(b"# caf\xe9\n1.2.3.4 host # caf\xe9\r\n1.2.3.5 caf\xe9s\n")
+            .unwrap();
+        assert_eq!(
+            parse_file(temp_path).map_err(|err| err.to_string()),
+            Err("Hostname is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 3 at line 3 with content: '1.2.3.5 caf\u{fffd}s'".to_string())
+        );
+
+        file.set_len(0).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"# caf\xe9\n1.2.3.4 host # caf\xe9\r\n")
+            .unwrap();
+        assert_eq!(
+            parse_file(temp_path),
+            Ok(vec![HostEntry {
+                ip: IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+                names: vec![String::from("host")],
+            }])
+        );
+    }
+
+    #[test]
+    fn parse_file_counts_all_lines() {
+        let temp_file = Temp::new_file().unwrap();
+        let temp_path = temp_file.as_path();
+        let mut file = File::create(temp_path).unwrap();
+
+        write!(file, "# comment\n\n127.0.0.1 localhost\nbad line\n").unwrap();
+        assert_eq!(
+            parse_file(temp_path).map_err(|err| err.to_string()),
+            Err("Couldn't parse a valid IP address: invalid IP address syntax at line 4 with content: 'bad line'".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_file_whole() {
+        let temp_file = Temp::new_file().unwrap();
+        let temp_path = temp_file.as_path();
+        let mut file = File::create(temp_path).unwrap();
+
+        write!(
+            file,
+            "# comment\r\n127.0.0.1 localhost\r\n\n  ::1 localhost ip6-localhost # c\n1.2.3.4 last"
+        )
+        .unwrap();
+        let entries = parse_file_with(temp_path, ReadMode::WholeFile);
+        assert_eq!(entries.as_ref().map(Vec::len), Ok(3));
+        assert_eq!(entries, parse_file(temp_path));
+
+        writeln!(file, "\nbad").unwrap();
+        assert_eq!(
+            parse_file_with(temp_path, ReadMode::WholeFile).map_err(|err| err.to_string()),
+            Err("Couldn't parse a valid IP address: invalid IP address syntax at line 6 with content: 'bad'".to_string())
+        );
+        assert_eq!(
+            parse_file_with(temp_path, ReadMode::WholeFile),
+            parse_file(temp_path)
+        );
+    }
+
+    #[test]
+    fn parse_borrowed() {
+        let input = b"127.0.0.1 localhost loopback\n::1 localhost\n";
+        let entries = parse_bytes_borrowed(input).unwrap();
+        assert!(matches!(entries[0].names[0], Cow::Borrowed("localhost")));
+
+        let owned: Vec<HostEntry> = entries.into_iter().map(HostEntry::from).collect();
+        assert_eq!(
+            owned[0],
+            HostEntry {
+                ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                names: vec![String::from("localhost"), String::from("loopback")],
+            }
+        );
+
+        let mut entry = HostEntryCow::from(&owned[1]);
+        entry.names.push(Cow::Owned(String::from("ip6-localhost")));
+        assert_eq!(entry.into_owned().names, vec!["localhost", "ip6-localhost"]);
+    }
+
+    #[test]
+    fn parse_in_memory() {
+        let entries = parse_str("# comment\n10.0.0.1 web www # site\r\n\n::1 localhost").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].names, ["web", "www"]);
+        assert_eq!(
+            parse_str("127.0.0.1 localhost\nbad line\n").unwrap_err(),
+            parse_bytes_borrowed(b"127.0.0.1 localhost\nbad line\n").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_from_reader() {
+        let input = "127.0.0.1 localhost\r\n# comment\n10.0.0.1 web www";
+        let entries = parse_reader(std::io::Cursor::new(input)).unwrap();
+        assert_eq!(entries, parse_str(input).unwrap());
+        let err = parse_reader(&b"10.0.0.1 ok\nbad\n"[..]).unwrap_err();
+        assert_eq!(err.location().map(|location| location.line), Some(2));
+    }
+
+    #[test]
+    fn test_flatten() {
+        let entries = vec![
+            "127.0.0.1 localhost loopback".parse().unwrap(),
+            "::1 localhost".parse().unwrap(),
+        ];
+        let localhost_v4 = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let localhost_v6 = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1));
+        assert_eq!(
+            flatten(&entries).collect::<Vec<_>>(),
+            vec![
+                (localhost_v4, "localhost", 0),
+                (localhost_v4, "loopback", 0),
+                (localhost_v6, "localhost", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_host_in_file() {
+        let temp_file = Temp::new_file().unwrap();
+        let temp_path = temp_file.as_path();
+        let mut file = File::create(temp_path).unwrap();
+
+        write!(
+            file,
+            "# comment\n127.0.0.1 localhost\n10.0.0.1 web WWW\n10.0.0.2 www\nnot an entry\n"
+        )
+        .unwrap();
+        assert_eq!(
+            find_host_in_file(temp_path, "www"),
+            Ok(Some(HostEntry {
+                ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                names: vec![String::from("web"), String::from("WWW")],
+            }))
+        );
+        // The lookup stops before reaching the malformed line...
+        assert!(parse_file(temp_path).is_err());
+        // ...unless the name isn't found earlier
+        assert_eq!(
+            find_host_in_file(temp_path, "missing").map_err(|err| err.to_string()),
+            Err("Couldn't parse a valid IP address: invalid IP address syntax at line 5 with content: 'not an entry'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let entries: Vec<HostEntry> = vec![
+            "10.0.0.1 web".parse().unwrap(),
+            "2001:db8::1 web".parse().unwrap(),
+            "10.0.0.2 db".parse().unwrap(),
+            "10.0.0.1 WEB www".parse().unwrap(),
+        ];
+        assert_eq!(
+            lookup(&entries, "web", AddressOrder::AsListed),
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            ]
+        );
+        assert_eq!(
+            lookup(&entries, "web", AddressOrder::Rfc6724),
+            vec![
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            ]
+        );
+        assert!(lookup(&entries, "missing", AddressOrder::AsListed).is_empty());
+
+        let entries: Vec<HostEntry> = vec![
+            "::ffff:192.0.2.1 web".parse().unwrap(),
+            "192.0.2.1 web".parse().unwrap(),
+        ];
+        assert_eq!(lookup(&entries, "web", AddressOrder::AsListed).len(), 2);
+        let options = LookupOptions::new().ip_equivalence(IpEquivalence::Ipv4Mapped);
+        assert_eq!(
+            lookup(&entries, "web", options),
+            vec!["::ffff:192.0.2.1".parse::<IpAddr>().unwrap()]
+        );
+    }
+
     #[test]
     fn test_clone() {
         let host_entry = HostEntry {
@@ -396,6 +1172,29 @@ mod tests {
         assert_eq!(host_entry, cloned)
     }
 
+    #[test]
+    fn test_resolve_socket_addr() {
+        let entries: Vec<HostEntry> = ["10.0.0.1 web", "fd00::1 web", "10.0.0.2 db"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let addrs = |addr| resolve_socket_addr(&entries, addr).map_err(|err| err.code());
+        assert_eq!(
+            addrs("WEB:8080"),
+            Ok(vec![
+                "10.0.0.1:8080".parse().unwrap(),
+                "[fd00::1]:8080".parse().unwrap()
+            ])
+        );
+        assert_eq!(addrs("[::1]:443"), Ok(vec!["[::1]:443".parse().unwrap()]));
+        assert_eq!(addrs("db:0"), Ok(vec!["10.0.0.2:0".parse().unwrap()]));
+        let invalid = Err(HostfileErrorCode::InvalidSocketAddr);
+        assert_eq!(addrs("db"), invalid);
+        assert_eq!(addrs("db:http"), invalid);
+        assert_eq!(addrs("fd00::1:80"), invalid);
+        assert_eq!(addrs("cache:6379"), Err(HostfileErrorCode::HostNotFound));
+    }
+
     #[test]
     fn test_get_hostfile_path() {
         let maybe_path = get_hostfile_path();
@@ -0,0 +1,658 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::strict::parse_line_strict;
+use crate::{hostname_key, read_file, HostfileError, IpEquivalence};
+
+/// How serious a [`Finding`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The name SARIF uses for this severity
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A check made by [`lint_str`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// The line doesn't follow the grammar in the crate docs
+    InvalidEntry,
+    /// The same address is listed for a name more than once
+    DuplicateEntry,
+    /// A name is listed with more than one address of the same family, so resolvers only
+    /// return the first
+    ConflictingAddress,
+    /// The line ends in spaces or tabs
+    TrailingWhitespace,
+    /// The entry starts with whitespace, or its names are separated by something other than
+    /// a single space
+    InconsistentSpacing,
+    /// `localhost` doesn't resolve to `127.0.0.1` or `::1`, see
+    /// [`crate::HostsFile::ensure_localhost`]
+    MissingLocalhost,
+}
+
+impl Rule {
+    /// Every rule, in the order of their IDs
+    pub const ALL: &'static [Rule] = &[
+        Rule::InvalidEntry,
+        Rule::DuplicateEntry,
+        Rule::ConflictingAddress,
+        Rule::TrailingWhitespace,
+        Rule::InconsistentSpacing,
+        Rule::MissingLocalhost,
+    ];
+
+    /// A stable identifier for this rule, such as `HF001`
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rule::InvalidEntry => "HF001",
+            Rule::DuplicateEntry => "HF002",
+            Rule::ConflictingAddress => "HF003",
+            Rule::TrailingWhitespace => "HF004",
+            Rule::InconsistentSpacing => "HF005",
+            Rule::MissingLocalhost => "HF006",
+        }
+    }
+
+    /// The stable numeric code of this rule, the number in its [`id`](Rule::id).
+    ///
+    /// Codes are part of the API: a code is never reused or reassigned, and new rules get new
+    /// codes.
+    pub fn as_code(&self) -> u32 {
+        match self {
+            Rule::InvalidEntry => 1,
+            Rule::DuplicateEntry => 2,
+            Rule::ConflictingAddress => 3,
+            Rule::TrailingWhitespace => 4,
+            Rule::InconsistentSpacing => 5,
+            Rule::MissingLocalhost => 6,
+        }
+    }
+
+    /// The rule with the numeric code `code`, if there is one
+    pub fn from_code(code: u32) -> Option<Rule> {
+        Rule::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.as_code() == code)
+    }
+
+    /// A short human readable name, such as `invalid-entry`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::InvalidEntry => "invalid-entry",
+            Rule::DuplicateEntry => "duplicate-entry",
+            Rule::ConflictingAddress => "conflicting-address",
+            Rule::TrailingWhitespace => "trailing-whitespace",
+            Rule::InconsistentSpacing => "inconsistent-spacing",
+            Rule::MissingLocalhost => "missing-localhost",
+        }
+    }
+
+    /// A one sentence description of what this rule checks
+    pub fn description(&self) -> &'static str {
+        match self {
+            Rule::InvalidEntry => {
+                "Lines must be blank, a comment, or an IP address followed by hostnames"
+            }
+            Rule::DuplicateEntry => "A hostname should only be listed once for each address",
+            Rule::ConflictingAddress => {
+                "A hostname should only have one address of each family, as resolvers only \
+                 return the first"
+            }
+            Rule::TrailingWhitespace => "Lines should not end in spaces or tabs",
+            Rule::InconsistentSpacing => {
+                "Entries should not be indented, and their names should be separated by a \
+                 single space"
+            }
+            Rule::MissingLocalhost => {
+                "localhost should resolve to 127.0.0.1 and ::1, as many programs expect"
+            }
+        }
+    }
+
+    /// The severity of findings for this rule
+    pub fn severity(&self) -> Severity {
+        match self {
+            Rule::InvalidEntry => Severity::Error,
+            Rule::DuplicateEntry | Rule::ConflictingAddress | Rule::MissingLocalhost => {
+                Severity::Warning
+            }
+            Rule::TrailingWhitespace | Rule::InconsistentSpacing => Severity::Note,
+        }
+    }
+
+    /// Whether [`lint_fix`] can fix findings for this rule
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Rule::InvalidEntry | Rule::ConflictingAddress | Rule::MissingLocalhost => false,
+            Rule::DuplicateEntry | Rule::TrailingWhitespace | Rule::InconsistentSpacing => true,
+        }
+    }
+}
+
+/// A problem found by [`lint_str`], pointing at the offending text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// 1-based line number
+    pub line: usize,
+    /// Byte range of the offending text within the line
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(rule: Rule, line: usize, span: Range<usize>, message: String) -> Self {
+        Finding {
+            rule,
+            severity: rule.severity(),
+            line,
+            span,
+            message,
+        }
+    }
+
+    /// 1-based byte column where the offending text starts
+    pub fn column(&self) -> usize {
+        self.span.start + 1
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} at line {}, column {} [{}]",
+            self.severity,
+            self.message,
+            self.line,
+            self.column(),
+            self.rule.id()
+        )
+    }
+}
+
+/// Options for [`lint_str_with`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintOptions {
+    ip_equivalence: IpEquivalence,
+    check_localhost: bool,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        LintOptions {
+            ip_equivalence: IpEquivalence::Ipv4Mapped,
+            check_localhost: false,
+        }
+    }
+}
+
+impl LintOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Which addresses are the same target when looking for duplicate and conflicting
+    /// entries. By default an IPv4-mapped address such as `::ffff:10.0.0.1` is the same as
+    /// `10.0.0.1`.
+    pub fn ip_equivalence(mut self, ip_equivalence: IpEquivalence) -> Self {
+        self.ip_equivalence = ip_equivalence;
+        self
+    }
+
+    /// Check that `localhost` resolves to `127.0.0.1` and `::1`, see [`Rule::MissingLocalhost`].
+    /// This only makes sense for a whole system hosts file, not for snippets of one, so it is
+    /// off by default.
+    pub fn check_localhost(mut self, check_localhost: bool) -> Self {
+        self.check_localhost = check_localhost;
+        self
+    }
+}
+
+/// Byte ranges of the hostnames of a line that parsed as an entry
+fn name_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    let mut words = 0;
+    for word in line.split([' ', '\t']) {
+        let start = pos;
+        pos += word.len() + 1;
+        if word.is_empty() {
+            continue;
+        }
+        if word.starts_with('#') {
+            break;
+        }
+        words += 1;
+        // The first word is the IP address
+        if words > 1 {
+            spans.push(start..start + word.len());
+        }
+    }
+    spans
+}
+
+/// Check hosts file contents for problems, in the order they appear.
+///
+/// Unlike [`crate::parse_str_strict`], every line is checked, so all invalid lines are
+/// reported.
+pub fn lint_str(input: &str) -> Vec<Finding> {
+    lint_str_with(input, &LintOptions::new())
+}
+
+/// Check hosts file contents for problems like [`lint_str`], according to `options`.
+///
+/// Problems with the file as a whole, such as a missing `localhost` entry, come last and
+/// point at its first line.
+pub fn lint_str_with(input: &str, options: &LintOptions) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    // First address of each family for each name, and the line it was given on
+    let mut seen: HashMap<(String, bool), (IpAddr, usize)> = HashMap::new();
+    for (idx, line) in input.split('\n').enumerate() {
+        let line_no = idx + 1;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        let trimmed = line.trim_end_matches([' ', '\t']);
+        if trimmed.len() != line.len() && !trimmed.is_empty() {
+            findings.push(Finding::new(
+                Rule::TrailingWhitespace,
+                line_no,
+                trimmed.len()..line.len(),
+                "Trailing whitespace".to_string(),
+            ));
+        }
+
+        let entry = match parse_line_strict(line, line_no) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(err) => {
+                findings.push(Finding::new(
+                    Rule::InvalidEntry,
+                    line_no,
+                    err.span,
+                    err.kind.to_string(),
+                ));
+                continue;
+            }
+        };
+
+        let spans = name_spans(line);
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        if indent > 0 {
+            findings.push(Finding::new(
+                Rule::InconsistentSpacing,
+                line_no,
+                0..indent,
+                "Indented entry".to_string(),
+            ));
+        }
+        for pair in spans.windows(2) {
+            let gap = pair[0].end..pair[1].start;
+            if &line[gap.clone()] != " " {
+                findings.push(Finding::new(
+                    Rule::InconsistentSpacing,
+                    line_no,
+                    gap,
+                    "Names should be separated by a single space".to_string(),
+                ));
+            }
+        }
+
+        let target = options.ip_equivalence.canonical(entry.ip);
+        for (name, span) in entry.names.iter().zip(spans) {
+            let key = (hostname_key(name).into_owned(), target.is_ipv4());
+            match seen.get(&key) {
+                Some(&(ip, first)) if options.ip_equivalence.same_target(ip, entry.ip) => findings
+                    .push(Finding::new(
+                        Rule::DuplicateEntry,
+                        line_no,
+                        span,
+                        format!("{name} is already listed for {ip} on line {first}"),
+                    )),
+                Some(&(ip, first)) => findings.push(Finding::new(
+                    Rule::ConflictingAddress,
+                    line_no,
+                    span,
+                    format!(
+                        "{name} already resolves to {ip} on line {first}, so {} is ignored",
+                        entry.ip
+                    ),
+                )),
+                None => {
+                    seen.insert(key, (entry.ip, line_no));
+                }
+            }
+        }
+    }
+
+    let localhost: &[IpAddr] = if options.check_localhost {
+        &[
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ]
+    } else {
+        &[]
+    };
+    for &ip in localhost {
+        let key = (String::from("localhost"), ip.is_ipv4());
+        if seen
+            .get(&key)
+            .is_none_or(|&(first, _)| !options.ip_equivalence.same_target(first, ip))
+        {
+            findings.push(Finding::new(
+                Rule::MissingLocalhost,
+                1,
+                0..0,
+                format!("localhost doesn't resolve to {ip}"),
+            ));
+        }
+    }
+    findings
+}
+
+/// Fix the findings of [`lint_str`] that can be fixed mechanically, see [`Rule::is_fixable`].
+/// Returns the fixed contents and the findings that were fixed.
+///
+/// Trailing whitespace and indentation are removed, names are separated by single spaces,
+/// and names already listed for the same address, earlier on the line or on an earlier line,
+/// are removed. Lines left without names are removed entirely.
+pub fn lint_fix(input: &str) -> (String, Vec<Finding>) {
+    let mut findings = lint_str(input)
+        .into_iter()
+        .filter(|finding| finding.rule.is_fixable())
+        .peekable();
+    let mut out = String::with_capacity(input.len());
+    let mut fixed = Vec::new();
+    for (idx, line) in input.split_inclusive('\n').enumerate() {
+        let mut line_findings = Vec::new();
+        while let Some(finding) = findings.next_if(|finding| finding.line == idx + 1) {
+            line_findings.push(finding);
+        }
+        if line_findings.is_empty() {
+            out.push_str(line);
+            continue;
+        }
+
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let duplicates = line_findings
+            .iter()
+            .filter(|finding| finding.rule == Rule::DuplicateEntry)
+            .count();
+        if duplicates < name_spans(text).len() {
+            out.push_str(&fix_line(text, &line_findings));
+            out.push_str(&line[text.len()..]);
+        }
+        fixed.extend(line_findings);
+    }
+    (out, fixed)
+}
+
+/// `line` with `findings` on it fixed
+fn fix_line(line: &str, findings: &[Finding]) -> String {
+    let is_ws = |c: char| c == ' ' || c == '\t';
+    let mut edits: Vec<(Range<usize>, &str)> = findings
+        .iter()
+        .map(|finding| {
+            let span = finding.span.clone();
+            match finding.rule {
+                Rule::DuplicateEntry => {
+                    // Remove the name along with the space that separates it from the next
+                    // name, or from the previous one if it is the last
+                    let rest = &line[span.end..];
+                    let next = rest.trim_start_matches(is_ws);
+                    if next.len() < rest.len() && !next.is_empty() && !next.starts_with('#') {
+                        (span.start..line.len() - next.len(), "")
+                    } else {
+                        (
+                            line[..span.start].trim_end_matches(is_ws).len()..span.end,
+                            "",
+                        )
+                    }
+                }
+                Rule::InconsistentSpacing if span.start > 0 => (span, " "),
+                _ => (span, ""),
+            }
+        })
+        .collect();
+    // Edits that overlap an earlier, longer one are already covered by it
+    edits.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+    let mut out = String::with_capacity(line.len());
+    let mut pos = 0;
+    for (range, replacement) in edits {
+        if range.start < pos {
+            continue;
+        }
+        out.push_str(&line[pos..range.start]);
+        out.push_str(replacement);
+        pos = range.end;
+    }
+    out.push_str(&line[pos..]);
+    out
+}
+
+/// Check the hosts file at `path` for problems, see [`lint_str`]
+pub fn lint_file(path: &Path) -> Result<Vec<Finding>, HostfileError> {
+    Ok(lint_str(&read_file(path)?))
+}
+
+/// Render `findings` for `source` as a SARIF 2.1.0 log, for code scanning tools such as
+/// GitHub's and GitLab's.
+///
+/// `uri` is the location of the linted file that results are reported against, usually a
+/// path relative to the root of the repository.
+#[cfg(feature = "sarif")]
+pub fn to_sarif(findings: &[Finding], source: &str, uri: &str) -> String {
+    use serde_json::json;
+
+    let lines: Vec<&str> = source.split('\n').collect();
+    // SARIF columns count characters by default rather than bytes
+    let column = |line: usize, offset: usize| {
+        let text = lines.get(line.wrapping_sub(1)).copied().unwrap_or_default();
+        let offset = offset.min(text.len());
+        text.char_indices().take_while(|&(i, _)| i < offset).count() + 1
+    };
+
+    let rules: Vec<_> = Rule::ALL
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id(),
+                "name": rule.name(),
+                "shortDescription": { "text": rule.description() },
+                "defaultConfiguration": { "level": rule.severity().as_str() },
+            })
+        })
+        .collect();
+    let results: Vec<_> = findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "ruleId": finding.rule.id(),
+                "ruleIndex": Rule::ALL.iter().position(|rule| *rule == finding.rule),
+                "level": finding.severity.as_str(),
+                "message": { "text": finding.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri },
+                        "region": {
+                            "startLine": finding.line,
+                            "startColumn": column(finding.line, finding.span.start),
+                            "endColumn": column(finding.line, finding.span.end),
+                        },
+                    },
+                }],
+            })
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn findings() {
+        let source = "127.0.0.1 localhost \n\
+                      10.0.0.1 web Web\n\
+                      10.0.0.2 web\n\
+                      ::1 web\n\
+                      10.0.0.3 bad_name\n";
+        let findings = lint_str(source);
+        let summary: Vec<_> = findings
+            .iter()
+            .map(|f| (f.rule, f.line, f.span.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Rule::TrailingWhitespace, 1, 19..20),
+                (Rule::DuplicateEntry, 2, 13..16),
+                (Rule::ConflictingAddress, 3, 9..12),
+                (Rule::InvalidEntry, 5, 12..13),
+            ]
+        );
+        assert_eq!(
+            Rule::from_code(findings[2].rule.as_code()),
+            Some(findings[2].rule)
+        );
+        assert_eq!(
+            findings[2].to_string(),
+            "warning: web already resolves to 10.0.0.1 on line 2, so 10.0.0.2 is ignored \
+             at line 3, column 10 [HF003]"
+        );
+    }
+
+    #[test]
+    fn mapped_addresses() {
+        let source = "10.0.0.1 web\n::ffff:10.0.0.1 web\n::ffff:10.0.0.2 web\n";
+        let rules = |options: &LintOptions| {
+            lint_str_with(source, options)
+                .into_iter()
+                .map(|f| (f.rule, f.line))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rules(&LintOptions::new()),
+            vec![(Rule::DuplicateEntry, 2), (Rule::ConflictingAddress, 3)]
+        );
+        let exact = LintOptions::new().ip_equivalence(IpEquivalence::Exact);
+        assert_eq!(rules(&exact), vec![(Rule::ConflictingAddress, 3)]);
+    }
+
+    #[test]
+    fn localhost_check() {
+        let source = "127.0.0.1 localhost\n10.0.0.1 web\n";
+        assert!(lint_str(source).is_empty());
+        let findings = lint_str_with(source, &LintOptions::new().check_localhost(true));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, Rule::MissingLocalhost);
+        assert_eq!(findings[0].message, "localhost doesn't resolve to ::1");
+    }
+
+    #[test]
+    fn fix_findings() {
+        let source = "127.0.0.1 localhost \r\n\
+                      \t10.0.0.1\tweb  www Web\t# main\n\
+                      10.0.0.1 web WWW\n\
+                      10.0.0.2 web db\tcache\n\
+                      10.0.0.3 bad_name \n";
+        let (fixed, findings) = lint_fix(source);
+        assert_eq!(
+            fixed,
+            "127.0.0.1 localhost\r\n\
+             10.0.0.1\tweb www\t# main\n\
+             10.0.0.2 web db cache\n\
+             10.0.0.3 bad_name\n"
+        );
+        let rules: Vec<_> = findings.iter().map(|f| (f.rule, f.line)).collect();
+        assert_eq!(
+            rules,
+            vec![
+                (Rule::TrailingWhitespace, 1),
+                (Rule::InconsistentSpacing, 2),
+                (Rule::InconsistentSpacing, 2),
+                (Rule::DuplicateEntry, 2),
+                (Rule::DuplicateEntry, 3),
+                (Rule::DuplicateEntry, 3),
+                (Rule::InconsistentSpacing, 4),
+                (Rule::TrailingWhitespace, 5),
+            ]
+        );
+        let (refixed, findings) = lint_fix(&fixed);
+        assert_eq!(refixed, fixed);
+        assert!(findings.is_empty());
+    }
+
+    #[cfg(feature = "sarif")]
+    #[test]
+    fn sarif_log() {
+        let source = "# café \n10.0.0.1 web\n10.0.0.1 web\n";
+        let sarif = to_sarif(&lint_str(source), source, "etc/hosts");
+        let log: serde_json::Value = serde_json::from_str(&sarif).unwrap();
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][1]["id"], "HF002");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "HF004");
+        assert_eq!(result["level"], "note");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "etc/hosts");
+        assert_eq!(location["region"]["startLine"], 1);
+        assert_eq!(location["region"]["startColumn"], 7);
+        assert_eq!(location["region"]["endColumn"], 8);
+
+        let result = &run["results"][1];
+        assert_eq!(result["ruleId"], "HF002");
+        assert_eq!(result["ruleIndex"], 1);
+        assert_eq!(result["level"], "warning");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"]["startLine"],
+            3
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, hostname_eq, line_error, read_file, HostEntry, HostfileError};
+
+/// A line of the Windows `lmhosts` file mapping a NetBIOS name to an address, as described
+/// in the `lmhosts.sam` sample that ships with Windows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LmhostsEntry {
+    pub ip: Ipv4Addr,
+    /// The name without quotes. Escapes like `\0x14` in quoted names are kept as written.
+    pub name: String,
+    /// `#PRE`: the entry is loaded into the name cache at startup
+    pub preload: bool,
+    /// `#DOM:<domain>`: the entry is a domain controller of this domain
+    pub domain: Option<String>,
+    /// `#MH`: one of several addresses of a multihomed computer
+    pub multihomed: bool,
+}
+
+impl LmhostsEntry {
+    pub fn new(ip: Ipv4Addr, name: impl Into<String>) -> Self {
+        LmhostsEntry {
+            ip,
+            name: name.into(),
+            preload: false,
+            domain: None,
+            multihomed: false,
+        }
+    }
+}
+
+/// NetBIOS names have 15 characters, the 16th byte is the service suffix
+const MAX_NAME_LEN: usize = 15;
+
+impl FromStr for LmhostsEntry {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (ip, rest) = s.split_once([' ', '\t']).unwrap_or((s, ""));
+        let ip = ip
+            .parse()
+            .map_err(|err| format!("Couldn't parse a valid IPv4 address {ip}: {err}"))?;
+        let rest = rest.trim_start();
+        let (name, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+            quoted.split_once('"').ok_or("Expected a closing quote")?
+        } else {
+            let (name, rest) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+            if name.starts_with('#') {
+                return Err("Expected a name".into());
+            }
+            if name.chars().count() > MAX_NAME_LEN {
+                return Err(format!(
+                    "NetBIOS name {name} is longer than {MAX_NAME_LEN} characters"
+                )
+                .into());
+            }
+            (name, rest)
+        };
+        if name.is_empty() {
+            return Err("Expected a name".into());
+        }
+
+        let mut entry = LmhostsEntry::new(ip, name);
+        for token in rest.split_whitespace() {
+            let upper = token.to_ascii_uppercase();
+            if upper == "#PRE" {
+                entry.preload = true;
+            } else if upper == "#MH" {
+                entry.multihomed = true;
+            } else if upper.starts_with("#DOM:") {
+                entry.domain = Some(token["#DOM:".len()..].to_string());
+            } else {
+                // Anything else starts a comment
+                break;
+            }
+        }
+        Ok(entry)
+    }
+}
+
+impl fmt::Display for LmhostsEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.contains([' ', '\t', '\\']) {
+            write!(f, "{}\t\"{}\"", self.ip, self.name)?;
+        } else {
+            write!(f, "{}\t{}", self.ip, self.name)?;
+        }
+        if self.preload {
+            write!(f, "\t#PRE")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "\t#DOM:{domain}")?;
+        }
+        if self.multihomed {
+            write!(f, "\t#MH")?;
+        }
+        Ok(())
+    }
+}
+
+/// An `#INCLUDE` directive of an `lmhosts` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LmhostsInclude {
+    /// The file to include, usually a UNC path like `\\server\share\lmhosts`
+    pub path: String,
+    /// Whether the directive is in a `#BEGIN_ALTERNATE` block, where only the first file
+    /// that can be read is included
+    pub alternate: bool,
+}
+
+/// The contents of an `lmhosts` file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lmhosts {
+    pub entries: Vec<LmhostsEntry>,
+    /// The files included by the file, which are not read since they are usually on other
+    /// machines
+    pub includes: Vec<LmhostsInclude>,
+}
+
+/// Parse the contents of an `lmhosts` file
+pub fn parse_lmhosts_str(input: &str) -> Result<Lmhosts, HostfileError> {
+    let mut lmhosts = Lmhosts::default();
+    let mut alternate = false;
+    for (idx, line) in input.lines().enumerate() {
+        let error = |err: HostfileError| line_error(err, idx + 1, line.as_bytes());
+        let content = line.trim();
+        if content.is_empty() {
+            continue;
+        }
+        if !content.starts_with('#') {
+            lmhosts.entries.push(content.parse().map_err(error)?);
+            continue;
+        }
+        let (directive, arg) = content.split_once([' ', '\t']).unwrap_or((content, ""));
+        match directive.to_ascii_uppercase().as_str() {
+            "#INCLUDE" => {
+                let path = arg.trim().trim_matches('"');
+                if path.is_empty() {
+                    return Err(error(HostfileError::from("Expected a file to include")));
+                }
+                lmhosts.includes.push(LmhostsInclude {
+                    path: path.to_string(),
+                    alternate,
+                });
+            }
+            "#BEGIN_ALTERNATE" if !alternate => alternate = true,
+            "#BEGIN_ALTERNATE" => {
+                return Err(error(HostfileError::from(
+                    "Alternate blocks can't be nested",
+                )));
+            }
+            "#END_ALTERNATE" if alternate => alternate = false,
+            "#END_ALTERNATE" => {
+                return Err(error(HostfileError::from(
+                    "#END_ALTERNATE without #BEGIN_ALTERNATE",
+                )));
+            }
+            // Anything else is a comment
+            _ => {}
+        }
+    }
+    if alternate {
+        return Err("#BEGIN_ALTERNATE without #END_ALTERNATE".into());
+    }
+    Ok(lmhosts)
+}
+
+/// Parse an `lmhosts` file, see [`parse_lmhosts_str`]
+pub fn parse_lmhosts_file(path: &Path) -> Result<Lmhosts, HostfileError> {
+    parse_lmhosts_str(&read_file(path)?)
+}
+
+/// Get path to the system `lmhosts` file, which is next to the hosts file
+pub fn get_lmhosts_path() -> Result<PathBuf, HostfileError> {
+    Ok(get_hostfile_path()?.with_file_name("lmhosts"))
+}
+
+/// The file a name in [`merge_lmhosts`] comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameSource {
+    Hosts,
+    Lmhosts,
+}
+
+/// A name and address from the hosts file or `lmhosts`, see [`merge_lmhosts`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedName {
+    pub name: String,
+    pub ip: IpAddr,
+    pub source: NameSource,
+    /// Whether the hosts file also has the name, so that Windows resolves it from there and
+    /// this `lmhosts` entry is never used for it
+    pub shadowed: bool,
+}
+
+/// Every name of `hosts` and `lmhosts` in the order Windows resolves them: the hosts file is
+/// consulted before NetBIOS resolution, which `lmhosts` is part of. Names are compared without
+/// regard to case, as both Windows resolvers do.
+pub fn merge_lmhosts(hosts: &[HostEntry], lmhosts: &[LmhostsEntry]) -> Vec<MergedName> {
+    let mut merged: Vec<MergedName> = hosts
+        .iter()
+        .flat_map(|entry| {
+            entry.names.iter().map(move |name| MergedName {
+                name: name.clone(),
+                ip: entry.ip,
+                source: NameSource::Hosts,
+                shadowed: false,
+            })
+        })
+        .collect();
+    for entry in lmhosts {
+        let shadowed = hosts
+            .iter()
+            .any(|host| host.names.iter().any(|name| hostname_eq(name, &entry.name)));
+        merged.push(MergedName {
+            name: entry.name.clone(),
+            ip: IpAddr::V4(entry.ip),
+            source: NameSource::Lmhosts,
+            shadowed,
+        });
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lmhosts() {
+        let lmhosts = parse_lmhosts_str(
+            "# Sample lmhosts\n\
+             102.54.94.97     rhino         #PRE #DOM:networking  #net group's DC\n\
+             102.54.94.102    \"appname  \\0x14\"                    #special app server\n\
+             102.54.94.123    popular            #PRE             #source server\n\
+             102.54.94.117    localsrv           #PRE\n\
+             \n\
+             #BEGIN_ALTERNATE\n\
+             #INCLUDE \\\\localsrv\\public\\lmhosts\n\
+             #INCLUDE \\\\rhino\\public\\lmhosts\n\
+             #END_ALTERNATE\n\
+             #INCLUDE c:\\lmhosts\n",
+        )
+        .unwrap();
+        assert_eq!(lmhosts.entries.len(), 4);
+        let rhino = &lmhosts.entries[0];
+        assert!(rhino.preload && !rhino.multihomed);
+        assert_eq!(rhino.domain.as_deref(), Some("networking"));
+        assert_eq!(lmhosts.entries[1].name, "appname  \\0x14");
+        assert_eq!(
+            lmhosts.entries[1].to_string(),
+            "102.54.94.102\t\"appname  \\0x14\""
+        );
+        assert_eq!(
+            rhino.to_string(),
+            "102.54.94.97\trhino\t#PRE\t#DOM:networking"
+        );
+        assert_eq!(lmhosts.includes.len(), 3);
+        assert!(lmhosts.includes[1].alternate && !lmhosts.includes[2].alternate);
+        assert_eq!(lmhosts.includes[2].path, "c:\\lmhosts");
+
+        let hosts: Vec<HostEntry> = vec!["10.0.0.1 Rhino".parse().unwrap()];
+        let merged = merge_lmhosts(&hosts, &lmhosts.entries);
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged[0].source, NameSource::Hosts);
+        assert!(merged[1].shadowed && !merged[2].shadowed);
+
+        let err = parse_lmhosts_str("fe80::1 box\n").unwrap_err().to_string();
+        assert!(err.starts_with("Couldn't parse a valid IPv4 address fe80::1"));
+        assert!(parse_lmhosts_str("10.0.0.1 averyveryverylongname\n").is_err());
+        assert!(parse_lmhosts_str("#BEGIN_ALTERNATE\n").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::HostfileError;
+
+/// Environment variable that overrides the path of the system hosts file
+const PATH_VARIABLE: &str = "HOSTFILE_PATH";
+
+/// The kind of system a [`HostfileLocation`] was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Platform {
+    Unix,
+    Windows,
+    /// Linux running under the Windows Subsystem for Linux
+    Wsl,
+    Wasi,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Unix => "Unix",
+            Platform::Windows => "Windows",
+            Platform::Wsl => "WSL",
+            Platform::Wasi => "WASI",
+        })
+    }
+}
+
+/// How the path of a [`HostfileLocation`] was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LocationSource {
+    /// The usual path for the platform
+    Default,
+    /// The `HOSTFILE_PATH` environment variable
+    Environment,
+    /// The `DataBasePath` value of the TCP/IP parameters in the Windows registry, which is
+    /// where the Windows resolver reads the file from
+    Registry,
+    /// The usual path, but WSL generates the file from the Windows hosts file, so changes to
+    /// it are lost when WSL restarts
+    Wsl,
+}
+
+impl fmt::Display for LocationSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LocationSource::Default => "the default path",
+            LocationSource::Environment => "the HOSTFILE_PATH environment variable",
+            LocationSource::Registry => "the DataBasePath registry value",
+            LocationSource::Wsl => "the default path, generated by WSL from the Windows hosts file",
+        })
+    }
+}
+
+/// Where the system hosts file is, and why, see [`hostfile_location`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostfileLocation {
+    pub path: PathBuf,
+    pub platform: Platform,
+    pub source: LocationSource,
+}
+
+impl fmt::Display for HostfileLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, from {})",
+            self.path.display(),
+            self.platform,
+            self.source
+        )
+    }
+}
+
+/// Find the system hosts file, explaining how it was found.
+///
+/// The `HOSTFILE_PATH` environment variable takes precedence everywhere. Otherwise:
+///
+/// - On Windows, the directory in the `DataBasePath` registry value, falling back to
+///   `drivers\etc` in the system directory.
+/// - On WASI, `/etc/hosts`. The host only exposes the directories it preopens, which may be
+///   mounted anywhere in the guest, so `HOSTFILE_PATH` may be needed.
+/// - Elsewhere, `/etc/hosts`.
+pub fn hostfile_location() -> Result<HostfileLocation, HostfileError> {
+    let platform = platform();
+    if let Some(path) = std::env::var_os(PATH_VARIABLE).filter(|path| !path.is_empty()) {
+        return Ok(HostfileLocation {
+            path: PathBuf::from(path),
+            platform,
+            source: LocationSource::Environment,
+        });
+    }
+
+    #[cfg(windows)]
+    {
+        let (path, source) = match windows::registry_database_path() {
+            Some(dir) => (dir.join("hosts"), LocationSource::Registry),
+            None => (windows::system_hosts_path()?, LocationSource::Default),
+        };
+        Ok(HostfileLocation {
+            path,
+            platform,
+            source,
+        })
+    }
+
+    #[cfg(not(windows))]
+    {
+        let source = if platform == Platform::Wsl && wsl_generates_hosts() {
+            LocationSource::Wsl
+        } else {
+            LocationSource::Default
+        };
+        Ok(HostfileLocation {
+            path: PathBuf::from("/etc/hosts"),
+            platform,
+            source,
+        })
+    }
+}
+
+fn platform() -> Platform {
+    if cfg!(windows) {
+        Platform::Windows
+    } else if cfg!(target_os = "wasi") {
+        Platform::Wasi
+    } else if is_wsl() {
+        Platform::Wsl
+    } else {
+        Platform::Unix
+    }
+}
+
+/// Whether this is Linux running under WSL, whose kernels name Microsoft in their release
+fn is_wsl() -> bool {
+    cfg!(target_os = "linux")
+        && std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_ascii_lowercase().contains("microsoft"))
+}
+
+/// Whether WSL generates `/etc/hosts`, which it does unless `generateHosts` is turned off in
+/// the `[network]` section of `/etc/wsl.conf`
+#[cfg(not(windows))]
+fn wsl_generates_hosts() -> bool {
+    let conf = std::fs::read_to_string("/etc/wsl.conf").unwrap_or_default();
+    !wsl_conf_disables_hosts(&conf)
+}
+
+#[cfg(not(windows))]
+fn wsl_conf_disables_hosts(conf: &str) -> bool {
+    let mut in_network = false;
+    for line in conf.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_network = section.trim().eq_ignore_ascii_case("network");
+        } else if let Some((key, value)) = line.split_once('=') {
+            if in_network && key.trim().eq_ignore_ascii_case("generateHosts") {
+                return value.trim().eq_ignore_ascii_case("false");
+            }
+        }
+    }
+    false
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+    use std::ptr::null_mut;
+    use std::slice;
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_SUCCESS, S_OK},
+        System::Com::CoTaskMemFree,
+        System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ},
+        UI::Shell::{FOLDERID_System, SHGetKnownFolderPath, KF_FLAG_DONT_VERIFY},
+    };
+
+    use crate::HostfileError;
+
+    fn wide(s: &str) -> Vec<u16> {
+        std::ffi::OsStr::new(s)
+            .encode_wide()
+            .chain(Some(0))
+            .collect()
+    }
+
+    /// The directory the Windows resolver reads the hosts file from, with environment
+    /// variables such as `%SystemRoot%` expanded
+    pub(super) fn registry_database_path() -> Option<PathBuf> {
+        let key = wide(r"SYSTEM\CurrentControlSet\Services\Tcpip\Parameters");
+        let value = wide("DataBasePath");
+        let mut buf = vec![0u16; 512];
+        let mut len = (buf.len() * 2) as u32;
+        let ret = unsafe {
+            RegGetValueW(
+                HKEY_LOCAL_MACHINE,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_SZ,
+                null_mut(),
+                buf.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        if ret != ERROR_SUCCESS {
+            return None;
+        }
+        // The length is in bytes and includes the terminating null
+        buf.truncate((len as usize / 2).saturating_sub(1));
+        let path = PathBuf::from(OsString::from_wide(&buf));
+        if path.as_os_str().is_empty() {
+            None
+        } else {
+            Some(path)
+        }
+    }
+
+    /// `drivers\etc\hosts` in the system directory.
+    ///
+    /// Implementation adapted from cargo's `home`.
+    /// See https://crates.io/crates/home
+    pub(super) fn system_hosts_path() -> Result<PathBuf, HostfileError> {
+        extern "C" {
+            fn wcslen(buf: *const u16) -> usize;
+        }
+
+        let mut ptr = null_mut::<u16>();
+        let ret = unsafe {
+            SHGetKnownFolderPath(
+                &FOLDERID_System,
+                KF_FLAG_DONT_VERIFY as u32,
+                null_mut(),
+                &mut ptr,
+            )
+        };
+
+        match ret {
+            S_OK => {
+                let path_slice = unsafe { slice::from_raw_parts(ptr, wcslen(ptr)) };
+                let os_str = OsString::from_wide(path_slice);
+                unsafe { CoTaskMemFree(ptr.cast()) };
+                let mut pathbuf = PathBuf::from(&os_str);
+                pathbuf.push("drivers\\etc\\hosts");
+                Ok(pathbuf)
+            }
+            _ => {
+                // free any allocated memory even on failure (a null ptr is a no-op for `CoTaskMemFree`)
+                unsafe { CoTaskMemFree(ptr.cast()) };
+                Err(format!(
+                    "Could not get path to Windows hosts file: {}",
+                    std::io::Error::last_os_error(),
+                )
+                .into())
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wsl_conf() {
+        assert!(!wsl_conf_disables_hosts(""));
+        assert!(!wsl_conf_disables_hosts(
+            "[boot]\ngenerateHosts = false\n[network]\ngenerateResolvConf = false\n"
+        ));
+        assert!(wsl_conf_disables_hosts(
+            "[automount]\nenabled = true\n\n[network]\n# keep my edits\ngenerateHosts = false\n"
+        ));
+        assert!(!wsl_conf_disables_hosts(
+            "[network]\ngenerateHosts=true ; default\n"
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use std::net::IpAddr;
+
+use crate::{AddressOrder, PolicyTable};
+
+/// Which addresses are considered the same target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpEquivalence {
+    /// Addresses are only the same if they are equal
+    #[default]
+    Exact,
+    /// An IPv4-mapped IPv6 address such as `::ffff:192.0.2.1` is the same as the IPv4 address
+    /// it maps, `192.0.2.1`
+    Ipv4Mapped,
+}
+
+impl IpEquivalence {
+    /// The address `ip` is compared as
+    pub fn canonical(self, ip: IpAddr) -> IpAddr {
+        match self {
+            IpEquivalence::Exact => ip,
+            IpEquivalence::Ipv4Mapped => ip.to_canonical(),
+        }
+    }
+
+    /// Whether `a` and `b` are the same target
+    pub fn same_target(self, a: IpAddr, b: IpAddr) -> bool {
+        self.canonical(a) == self.canonical(b)
+    }
+}
+
+/// Options for [`crate::lookup`]. An [`AddressOrder`] can be used on its own for the defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LookupOptions {
+    pub(crate) order: AddressOrder,
+    pub(crate) ip_equivalence: IpEquivalence,
+    pub(crate) policy_table: Option<PolicyTable>,
+}
+
+impl LookupOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How to order the addresses found
+    pub fn order(mut self, order: AddressOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The policy table [`AddressOrder::Rfc6724`] orders by instead of the default one, such
+    /// as [`crate::GaiConf::policy_table`] to order like `getaddrinfo` does
+    pub fn policy_table(mut self, policy_table: PolicyTable) -> Self {
+        self.policy_table = Some(policy_table);
+        self
+    }
+
+    /// Which addresses are returned only once
+    pub fn ip_equivalence(mut self, ip_equivalence: IpEquivalence) -> Self {
+        self.ip_equivalence = ip_equivalence;
+        self
+    }
+}
+
+impl From<AddressOrder> for LookupOptions {
+    fn from(order: AddressOrder) -> Self {
+        LookupOptions::new().order(order)
+    }
+}
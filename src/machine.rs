@@ -0,0 +1,190 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use crate::{HostEntry, HostfileError};
+
+/// An entry for this machine: its fully qualified name if one is configured, followed by its
+/// hostname, for `ip` or the address of the interface with the default route.
+///
+/// On Unix, the fully qualified name is the hostname if it has a dot, or the hostname in the
+/// `domain` of `/etc/resolv.conf`. On Windows, it is the DNS name the machine is configured
+/// with.
+pub fn self_entry(ip: Option<IpAddr>) -> Result<HostEntry, HostfileError> {
+    let ip = match ip {
+        Some(ip) => ip,
+        None => primary_address()?,
+    };
+    Ok(HostEntry {
+        ip,
+        names: machine_names()?,
+    })
+}
+
+/// The address of the interface that traffic to the internet leaves from.
+///
+/// Connecting a UDP socket only picks a route, so nothing is sent.
+fn primary_address() -> Result<IpAddr, HostfileError> {
+    // Documentation addresses, which are routed like any other remote address
+    let remotes = [
+        (
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+        ),
+        (
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        ),
+    ];
+    for (local, remote) in remotes {
+        let addr = UdpSocket::bind(SocketAddr::new(local, 0))
+            .and_then(|socket| {
+                socket.connect(SocketAddr::new(remote, 9))?;
+                socket.local_addr()
+            })
+            .map(|addr| addr.ip());
+        if let Ok(ip) = addr {
+            return Ok(ip);
+        }
+    }
+    Err(String::from(
+        "Could not find the primary address of this machine, as it has no default route",
+    )
+    .into())
+}
+
+/// The fully qualified name of this machine followed by its hostname, or just the hostname
+pub(crate) fn machine_names() -> Result<Vec<String>, HostfileError> {
+    #[cfg(unix)]
+    {
+        let hostname = unix::hostname()?;
+        let conf = std::fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+        Ok(names(&hostname, resolv_domain(&conf)))
+    }
+
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::SystemInformation::{
+            ComputerNameDnsFullyQualified, ComputerNameDnsHostname,
+        };
+        let hostname = windows::computer_name(ComputerNameDnsHostname)?;
+        let fqdn = windows::computer_name(ComputerNameDnsFullyQualified)?;
+        let domain = fqdn
+            .strip_prefix(&hostname)
+            .and_then(|rest| rest.strip_prefix('.'));
+        Ok(names(&hostname, domain))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(HostfileError::from(
+            "The hostname of this machine is not available on this platform",
+        ))
+    }
+}
+
+/// `hostname` qualified with `domain` followed by `hostname` itself, split like Debian
+/// writes them
+#[cfg_attr(not(any(unix, windows)), allow(dead_code))]
+fn names(hostname: &str, domain: Option<&str>) -> Vec<String> {
+    let domain = domain.map(|domain| domain.trim_end_matches('.'));
+    match (hostname.split_once('.'), domain) {
+        (Some((short, _)), _) if !short.is_empty() => {
+            vec![hostname.to_string(), short.to_string()]
+        }
+        (_, Some(domain)) if !domain.is_empty() => {
+            vec![format!("{hostname}.{domain}"), hostname.to_string()]
+        }
+        _ => vec![hostname.to_string()],
+    }
+}
+
+/// The `domain` set in `resolv.conf`
+#[cfg_attr(not(unix), allow(dead_code))]
+fn resolv_domain(conf: &str) -> Option<&str> {
+    conf.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("domain") => words.next(),
+                _ => None,
+            }
+        })
+        // The last one wins
+        .next_back()
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::CStr;
+
+    use crate::HostfileError;
+
+    pub(super) fn hostname() -> Result<String, HostfileError> {
+        let mut buf = [0u8; 256];
+        let ret = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+        if ret != 0 {
+            return Err(format!(
+                "Could not get the hostname: {}",
+                std::io::Error::last_os_error()
+            )
+            .into());
+        }
+        // Make sure a truncated name is still terminated
+        buf[buf.len() - 1] = 0;
+        let name = CStr::from_bytes_until_nul(&buf).unwrap_or_default();
+        Ok(name.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::ptr::null_mut;
+
+    use windows_sys::Win32::System::SystemInformation::{GetComputerNameExW, COMPUTER_NAME_FORMAT};
+
+    use crate::HostfileError;
+
+    pub(super) fn computer_name(format: COMPUTER_NAME_FORMAT) -> Result<String, HostfileError> {
+        let error = || {
+            HostfileError::Other(format!(
+                "Could not get the computer name: {}",
+                std::io::Error::last_os_error()
+            ))
+        };
+        // The first call fails with the size needed, including the terminating null
+        let mut len = 0u32;
+        unsafe { GetComputerNameExW(format, null_mut(), &mut len) };
+        let mut buf = vec![0u16; len as usize];
+        if unsafe { GetComputerNameExW(format, buf.as_mut_ptr(), &mut len) } == 0 {
+            return Err(error());
+        }
+        buf.truncate(len as usize);
+        Ok(OsString::from_wide(&buf).to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_names_with_domain() {
+        let conf = "nameserver 10.0.0.53\ndomain lab.example.org\nsearch example.org\n";
+        assert_eq!(resolv_domain(conf), Some("lab.example.org"));
+        assert_eq!(
+            names("box", resolv_domain(conf)),
+            ["box.lab.example.org", "box"]
+        );
+        assert_eq!(
+            names("box.example.com", Some("lab.example.org")),
+            ["box.example.com", "box"]
+        );
+        assert_eq!(names("box", resolv_domain("search example.org\n")), ["box"]);
+
+        let entry = self_entry(Some("10.0.0.7".parse().unwrap()));
+        if cfg!(any(unix, windows)) {
+            assert_eq!(entry.unwrap().ip.to_string(), "10.0.0.7");
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+
+use crate::{hostname_key, HostEntry, HostfileError};
+
+/// Service type the hosts are registered under. mDNS publishes addresses as part of a
+/// service, and this is the type used for describing devices rather than services.
+const SERVICE_TYPE: &str = "_device-info._tcp.local.";
+
+/// Query that lists the types of services announced on the network
+const META_QUERY: &str = "_services._dns-sd._udp.local.";
+
+/// Publishes hostnames from a hosts file as mDNS records, so that devices on the local
+/// network that can't read the file can still resolve them.
+///
+/// Only names in the `.local` domain can be published.
+pub struct MdnsPublisher {
+    daemon: ServiceDaemon,
+    /// Full service names of the registered hosts
+    registered: Vec<String>,
+}
+
+impl MdnsPublisher {
+    /// Start an mDNS responder on all interfaces
+    pub fn new() -> Result<Self, HostfileError> {
+        let daemon =
+            ServiceDaemon::new().map_err(|err| format!("Could not start mDNS daemon: {err}"))?;
+        Ok(MdnsPublisher {
+            daemon,
+            registered: Vec::new(),
+        })
+    }
+
+    /// Publish `addrs` for `name`, which must be in the `.local` domain
+    pub fn publish(&mut self, name: &str, addrs: &[IpAddr]) -> Result<(), HostfileError> {
+        let host = name.trim_end_matches('.');
+        let instance = host
+            .strip_suffix(".local")
+            .filter(|instance| !instance.is_empty())
+            .ok_or_else(|| format!("Can only publish names in the .local domain, not {name}"))?;
+
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            instance,
+            &format!("{host}."),
+            addrs,
+            0,
+            None::<HashMap<String, String>>,
+        )
+        .map_err(|err| format!("Could not publish {name}: {err}"))?;
+        let fullname = info.get_fullname().to_string();
+        self.daemon
+            .register(info)
+            .map_err(|err| format!("Could not publish {name}: {err}"))?;
+        self.registered.push(fullname);
+        Ok(())
+    }
+
+    /// Publish every `.local` name of `entries` that `filter` accepts, with all of its
+    /// addresses. Returns the names that were published.
+    pub fn publish_entries<F>(
+        &mut self,
+        entries: &[HostEntry],
+        mut filter: F,
+    ) -> Result<Vec<String>, HostfileError>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut published = Vec::new();
+        for (name, addrs) in local_names(entries) {
+            if filter(&name) {
+                self.publish(&name, &addrs)?;
+                published.push(name);
+            }
+        }
+        Ok(published)
+    }
+
+    /// Stop publishing all names published so far
+    pub fn unpublish_all(&mut self) -> Result<(), HostfileError> {
+        for fullname in self.registered.drain(..) {
+            self.daemon
+                .unregister(&fullname)
+                .map_err(|err| format!("Could not unpublish {fullname}: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// Unpublish all names and stop the responder
+    pub fn shutdown(mut self) -> Result<(), HostfileError> {
+        self.unpublish_all()?;
+        self.daemon
+            .shutdown()
+            .map_err(|err| format!("Could not stop mDNS daemon: {err}"))?;
+        Ok(())
+    }
+}
+
+/// Browse the local network for `duration` and return an entry for each address of every
+/// host that announces a service, such as `printer.local`.
+///
+/// The entries are candidates for review before they are merged into a hosts file, for
+/// example with [`crate::HostsFile::set_managed_section`]. They are sorted by name.
+pub fn discover_mdns_hosts(duration: Duration) -> Result<Vec<HostEntry>, HostfileError> {
+    let daemon =
+        ServiceDaemon::new().map_err(|err| format!("Could not start mDNS daemon: {err}"))?;
+    let browse = |service_type: &str| {
+        daemon
+            .browse(service_type)
+            .map_err(|err| format!("Could not browse for {service_type}: {err}"))
+    };
+
+    let deadline = Instant::now() + duration;
+    let mut browsing: Vec<(String, Receiver<ServiceEvent>)> =
+        vec![(META_QUERY.to_string(), browse(META_QUERY)?)];
+    let mut hosts: BTreeMap<String, BTreeSet<IpAddr>> = BTreeMap::new();
+    while Instant::now() < deadline {
+        let mut found = Vec::new();
+        for (service_type, receiver) in &browsing {
+            for event in receiver.try_iter() {
+                match event {
+                    // Answers to the meta query name service types rather than instances
+                    ServiceEvent::ServiceFound(_, name) if service_type == META_QUERY => {
+                        found.push(name)
+                    }
+                    ServiceEvent::ServiceResolved(info) => {
+                        let name = info.get_hostname().trim_end_matches('.');
+                        hosts
+                            .entry(hostname_key(name).into_owned())
+                            .or_default()
+                            .extend(info.get_addresses());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for service_type in found {
+            if !browsing.iter().any(|(browsed, _)| *browsed == service_type) {
+                let receiver = browse(&service_type)?;
+                browsing.push((service_type, receiver));
+            }
+        }
+        thread::sleep(
+            Duration::from_millis(50).min(deadline.saturating_duration_since(Instant::now())),
+        );
+    }
+
+    for (service_type, _) in &browsing {
+        let _ = daemon.stop_browse(service_type);
+    }
+    let _ = daemon.shutdown();
+    Ok(hosts
+        .into_iter()
+        .filter(|(name, _)| name.ends_with(".local"))
+        .flat_map(|(name, addrs)| {
+            addrs.into_iter().map(move |ip| HostEntry {
+                ip,
+                names: vec![name.clone()],
+            })
+        })
+        .collect())
+}
+
+/// The `.local` names of `entries` with their addresses, in order of first appearance
+fn local_names(entries: &[HostEntry]) -> Vec<(String, Vec<IpAddr>)> {
+    let mut names: Vec<(String, Vec<IpAddr>)> = Vec::new();
+    let mut idx: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        for name in &entry.names {
+            let key = hostname_key(name.trim_end_matches('.')).into_owned();
+            if !key.ends_with(".local") {
+                continue;
+            }
+            let (_, addrs) = match idx.get(&key) {
+                Some(&i) => &mut names[i],
+                None => {
+                    idx.insert(key.clone(), names.len());
+                    names.push((key, Vec::new()));
+                    names.last_mut().unwrap()
+                }
+            };
+            if !addrs.contains(&entry.ip) {
+                addrs.push(entry.ip);
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_names() {
+        let entries: Vec<HostEntry> = vec![
+            "192.168.1.5 printer.local printer".parse().unwrap(),
+            "fe80::5 Printer.local.".parse().unwrap(),
+            "10.0.0.1 web.example".parse().unwrap(),
+        ];
+        assert_eq!(
+            local_names(&entries),
+            vec![(
+                String::from("printer.local"),
+                vec!["192.168.1.5".parse().unwrap(), "fe80::5".parse().unwrap()]
+            )]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::{hostname_key, HostEntry};
+
+/// Options for [`minimize`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinimizeOptions {
+    short_sinkhole: bool,
+}
+
+impl MinimizeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the `0.0.0.0` sinkhole address as `0`.
+    ///
+    /// glibc and musl read `0` as `0.0.0.0`, but resolvers that only accept dotted quads,
+    /// such as dnsmasq's and Windows', don't, and neither does this crate's parser.
+    pub fn short_sinkhole(mut self, short: bool) -> Self {
+        self.short_sinkhole = short;
+        self
+    }
+}
+
+/// Render `entries` as the smallest hosts file that resolves the same way.
+///
+/// There are no comments or blank lines, and names are separated by single spaces. Repeated
+/// names for an address are dropped, and the names of each address are gathered onto as few
+/// lines as possible. An address is only split across lines when gathering its names would
+/// change the order in which another of their addresses is returned.
+pub fn minimize(entries: &[HostEntry], options: &MinimizeOptions) -> String {
+    let mut groups: Vec<(IpAddr, Vec<&str>)> = Vec::new();
+    // The last group of each address, and the last group each name was added to
+    let mut last_group: HashMap<IpAddr, usize> = HashMap::new();
+    let mut name_group: HashMap<String, usize> = HashMap::new();
+    let mut seen = HashSet::new();
+    for entry in entries {
+        for name in &entry.names {
+            let key = hostname_key(name).into_owned();
+            if !seen.insert((entry.ip, key.clone())) {
+                continue;
+            }
+            let previous = name_group.get(&key).copied();
+            let idx = match last_group.get(&entry.ip) {
+                Some(&idx) if previous.is_none_or(|previous| previous < idx) => idx,
+                _ => {
+                    groups.push((entry.ip, Vec::new()));
+                    last_group.insert(entry.ip, groups.len() - 1);
+                    groups.len() - 1
+                }
+            };
+            groups[idx].1.push(name);
+            name_group.insert(key, idx);
+        }
+    }
+
+    let mut out = String::new();
+    for (ip, names) in groups {
+        if options.short_sinkhole && ip == IpAddr::V4(Ipv4Addr::UNSPECIFIED) {
+            out.push('0');
+        } else {
+            out.push_str(&ip.to_string());
+        }
+        for name in names {
+            out.push(' ');
+            out.push_str(name);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_bytes_borrowed;
+
+    #[test]
+    fn minimize_blocklist() {
+        let source = "\
+# Blocklist
+0.0.0.0\tads.example.test
+
+0.0.0.0 tracker.example.test ADS.example.test  # again
+127.0.0.1 localhost
+10.0.0.2 web
+10.0.0.1 web db
+0.0.0.0 metrics.example.test
+";
+        let entries: Vec<HostEntry> = parse_bytes_borrowed(source.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(HostEntry::from)
+            .collect();
+        assert_eq!(
+            minimize(&entries, &MinimizeOptions::new()),
+            "0.0.0.0 ads.example.test tracker.example.test metrics.example.test\n\
+             127.0.0.1 localhost\n\
+             10.0.0.2 web\n\
+             10.0.0.1 web db\n"
+        );
+
+        // web must keep resolving to 10.0.0.2 first
+        let entries: Vec<HostEntry> = vec![
+            "10.0.0.1 db".parse().unwrap(),
+            "10.0.0.2 web".parse().unwrap(),
+            "10.0.0.1 web".parse().unwrap(),
+            "0.0.0.0 ads".parse().unwrap(),
+        ];
+        assert_eq!(
+            minimize(&entries, &MinimizeOptions::new().short_sinkhole(true)),
+            "10.0.0.1 db\n10.0.0.2 web\n10.0.0.1 web\n0 ads\n"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::{HostEntry, ParseErrorKind};
+
+/// Whether `a` and `b` name the same host.
+///
+/// Names are compared ASCII case-insensitively. With the `idna` feature, internationalized
+/// names are also compared by their punycode form, so `bücher.example` and
+/// `xn--bcher-kva.example` are the same host.
+pub fn hostname_eq(a: &str, b: &str) -> bool {
+    if a.eq_ignore_ascii_case(b) {
+        return true;
+    }
+    // Only names that may be internationalized need the more expensive comparison
+    cfg!(feature = "idna") && (maybe_idn(a) || maybe_idn(b)) && hostname_key(a) == hostname_key(b)
+}
+
+/// A canonical form of `name` for hashing and sorting, consistent with [`hostname_eq`]
+pub fn hostname_key(name: &str) -> Cow<'_, str> {
+    #[cfg(feature = "idna")]
+    if maybe_idn(name) {
+        if let Ok(ascii) = idna::domain_to_ascii(name) {
+            return Cow::Owned(ascii);
+        }
+    }
+
+    if name.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(name.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Whether `name` may have a different punycode form: it isn't ASCII, or it is already
+/// punycode and its Unicode form might be compared against
+fn maybe_idn(name: &str) -> bool {
+    !name.is_ascii()
+        || name
+            .split('.')
+            .any(|label| label.len() >= 4 && label[..4].eq_ignore_ascii_case("xn--"))
+}
+
+/// Check `name` against the hostname rules of RFC 1123 (and RFC 952 before it): labels of
+/// letters, digits and `-` separated by dots, which are not empty and don't start or end with
+/// `-`. A single dot at the end, as in fully qualified names, is allowed.
+///
+/// Names may have at most 253 characters, not counting the dot at the end, and labels at most
+/// 63, as in DNS.
+///
+/// Use a [`HostnamePolicy`] to allow other characters.
+pub fn validate_hostname(name: &str) -> Result<(), ParseErrorKind> {
+    HostnamePolicy::rfc1123().validate(name)
+}
+
+/// The rules names are checked against by [`crate::ParseOptions::hostname_policy`]: those of
+/// [`validate_hostname`], with characters beyond RFC 1123's allowed for local conventions.
+///
+/// ```
+/// # use hostfile::HostnamePolicy;
+/// let policy = HostnamePolicy::rfc1123().allow_chars("_").allow_wildcards(true);
+/// assert!(policy.validate("_dmarc.example").is_ok());
+/// assert!(policy.validate("*.dev.example").is_ok());
+/// assert!(policy.validate("web.*.example").is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostnamePolicy {
+    extra_chars: String,
+    wildcards: bool,
+}
+
+impl HostnamePolicy {
+    /// The rules of RFC 1123, which is the default
+    pub fn rfc1123() -> Self {
+        Self::default()
+    }
+
+    /// Also allow each character of `chars` anywhere in names, e.g. `_` for service names
+    pub fn allow_chars(mut self, chars: &str) -> Self {
+        self.extra_chars.push_str(chars);
+        self
+    }
+
+    /// Allow `*` as the first label of names, as in `*.dev.example`, for files read by
+    /// resolvers with wildcard support like dnsmasq. See also [`crate::WildcardRule`].
+    pub fn allow_wildcards(mut self, wildcards: bool) -> Self {
+        self.wildcards = wildcards;
+        self
+    }
+
+    /// Check `name` against this policy
+    pub fn validate(&self, name: &str) -> Result<(), ParseErrorKind> {
+        self.check(name).map_err(|(_, kind)| kind)
+    }
+
+    /// [`HostnamePolicy::validate`], along with the byte range of the offending text in `name`
+    pub(crate) fn check(&self, name: &str) -> Result<(), (Range<usize>, ParseErrorKind)> {
+        let invalid_char = |idx: usize, c: char| {
+            Err((
+                idx..idx + c.len_utf8(),
+                ParseErrorKind::InvalidHostnameChar(c),
+            ))
+        };
+        if let Some((idx, c)) = name.char_indices().find(|&(_, c)| !self.is_allowed(c)) {
+            return invalid_char(idx, c);
+        }
+        let labels = name.strip_suffix('.').unwrap_or(name);
+        if labels.len() > MAX_NAME_LEN {
+            return Err((
+                MAX_NAME_LEN..labels.len(),
+                ParseErrorKind::NameTooLong(labels.len()),
+            ));
+        }
+        let mut start = 0;
+        for label in labels.split('.') {
+            let span = start..start + label.len();
+            if label.is_empty() || label.starts_with('-') || label.ends_with('-') {
+                return Err((span, ParseErrorKind::InvalidLabel(label.to_string())));
+            }
+            if label.len() > MAX_LABEL_LEN {
+                return Err((span, ParseErrorKind::LabelTooLong(label.to_string())));
+            }
+            let is_wildcard = start == 0 && label == "*";
+            if let Some(idx) = label.find('*').filter(|_| !is_wildcard) {
+                if !self.extra_chars.contains('*') {
+                    return invalid_char(start + idx, '*');
+                }
+            }
+            start += label.len() + 1;
+        }
+        Ok(())
+    }
+
+    fn is_allowed(&self, c: char) -> bool {
+        c.is_ascii_alphanumeric()
+            || c == '.'
+            || c == '-'
+            || (c == '*' && self.wildcards)
+            || self.extra_chars.contains(c)
+    }
+}
+
+/// The longest name DNS allows, without the dot at the end
+const MAX_NAME_LEN: usize = 253;
+
+/// The longest label DNS allows
+const MAX_LABEL_LEN: usize = 63;
+
+impl HostEntry {
+    /// Check that the entry has names and that they follow RFC 1123, see
+    /// [`validate_hostname`], e.g. before writing an entry built from user input
+    pub fn validate(&self) -> Result<(), ParseErrorKind> {
+        if self.names.is_empty() {
+            return Err(ParseErrorKind::MissingHostname);
+        }
+        self.names
+            .iter()
+            .try_for_each(|name| validate_hostname(name))
+    }
+
+    /// Check that the entry has names and that writing it reads back as the same entry, which
+    /// is less than [`HostEntry::validate`] asks for: names just can't be empty or contain
+    /// whitespace, control characters or `#`
+    pub(crate) fn check_writable(&self) -> Result<(), ParseErrorKind> {
+        if self.names.iter().any(String::is_empty) || self.names.is_empty() {
+            return Err(ParseErrorKind::MissingHostname);
+        }
+        let invalid = |c: char| c.is_whitespace() || c.is_control() || c == '#';
+        match self
+            .names
+            .iter()
+            .flat_map(|name| name.chars())
+            .find(|&c| invalid(c))
+        {
+            Some(c) => Err(ParseErrorKind::InvalidHostnameChar(c)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive() {
+        assert!(hostname_eq("LocalHost", "localhost"));
+        assert!(!hostname_eq("localhost", "localhost2"));
+        assert_eq!(hostname_key("Web.Example"), "web.example");
+        assert!(matches!(hostname_key("web"), Cow::Borrowed("web")));
+    }
+
+    #[test]
+    fn rfc1123_names() {
+        for name in ["localhost", "web-1.example.com", "3com.example.", "A"] {
+            assert_eq!(validate_hostname(name), Ok(()), "{}", name);
+        }
+        assert_eq!(
+            HostnamePolicy::rfc1123().check("db_1.example"),
+            Err((2..3, ParseErrorKind::InvalidHostnameChar('_')))
+        );
+        assert_eq!(
+            HostnamePolicy::rfc1123().check("web.-api.example"),
+            Err((4..8, ParseErrorKind::InvalidLabel(String::from("-api"))))
+        );
+        assert!(validate_hostname("web-.example").is_err());
+        assert!(validate_hostname("web..example").is_err());
+        assert!(validate_hostname("").is_err());
+        assert!(validate_hostname(".").is_err());
+    }
+
+    #[test]
+    fn custom_policies() {
+        let policy = HostnamePolicy::rfc1123().allow_chars("_");
+        assert_eq!(policy.validate("_sip._tcp.example"), Ok(()));
+        assert_eq!(
+            policy.validate("-sip.example"),
+            Err(ParseErrorKind::InvalidLabel(String::from("-sip")))
+        );
+        assert_eq!(
+            policy.check("*.example"),
+            Err((0..1, ParseErrorKind::InvalidHostnameChar('*')))
+        );
+        let policy = policy.allow_wildcards(true);
+        assert_eq!(policy.validate("*.example"), Ok(()));
+        assert_eq!(
+            policy.check("a.*.example"),
+            Err((2..3, ParseErrorKind::InvalidHostnameChar('*')))
+        );
+        assert!(policy.validate("*web.example").is_err());
+    }
+
+    #[test]
+    fn name_lengths() {
+        let label = "a".repeat(63);
+        let name = [label.as_str(); 4].join(".");
+        assert_eq!(validate_hostname(&name[..253]), Ok(()));
+        assert_eq!(validate_hostname(&format!("{}.", &name[..253])), Ok(()));
+        assert_eq!(
+            HostnamePolicy::rfc1123().check(&name[..254]),
+            Err((253..254, ParseErrorKind::NameTooLong(254)))
+        );
+        let long_label = format!("{label}a");
+        assert_eq!(
+            HostnamePolicy::rfc1123().check(&format!("www.{long_label}.example")),
+            Err((4..68, ParseErrorKind::LabelTooLong(long_label.clone())))
+        );
+
+        let entry = HostEntry {
+            ip: "10.0.0.1".parse().unwrap(),
+            names: vec![String::from("web"), long_label],
+        };
+        assert_eq!(
+            entry.validate().unwrap_err().code(),
+            crate::ParseErrorCode::LabelTooLong
+        );
+        let entry = HostEntry {
+            names: Vec::new(),
+            ..entry
+        };
+        assert_eq!(entry.validate(), Err(ParseErrorKind::MissingHostname));
+    }
+
+    #[cfg(feature = "idna")]
+    #[test]
+    fn punycode() {
+        assert!(hostname_eq("bücher.example", "xn--bcher-kva.example"));
+        assert!(hostname_eq("XN--BCHER-KVA.example", "BÜCHER.example"));
+        assert!(!hostname_eq("bücher.example", "bucher.example"));
+        assert_eq!(hostname_key("Bücher.example"), "xn--bcher-kva.example");
+    }
+}
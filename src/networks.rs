@@ -0,0 +1,115 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, parse_table, HostfileError};
+
+/// A line from `/etc/networks`, naming a network, as described in `man networks(5)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkEntry {
+    pub name: String,
+    /// The network number. Octets left out of the file, as in `10` or `192.168`, are zero.
+    pub number: Ipv4Addr,
+    pub aliases: Vec<String>,
+}
+
+impl FromStr for NetworkEntry {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Account for comments at the end of the line
+        let line = s.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let name = tokens.next().ok_or("Expected a network name")?;
+        let number = tokens.next().ok_or("Expected a network number")?;
+        let number = parse_network_number(number)?;
+        Ok(NetworkEntry {
+            name: name.to_string(),
+            number,
+            aliases: tokens.map(str::to_string).collect(),
+        })
+    }
+}
+
+impl fmt::Display for NetworkEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.name, self.number)?;
+        for alias in &self.aliases {
+            write!(f, " {alias}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a network number in dotted notation with one to four octets
+fn parse_network_number(number: &str) -> Result<Ipv4Addr, HostfileError> {
+    let parts: Vec<&str> = number.split('.').collect();
+    if parts.len() > 4 {
+        return Err(format!("Network number {number} has more than four octets").into());
+    }
+    let mut octets = [0; 4];
+    for (octet, part) in octets.iter_mut().zip(parts) {
+        *octet = part
+            .parse()
+            .map_err(|err| format!("Couldn't parse a valid network number {number}: {err}"))?;
+    }
+    Ok(Ipv4Addr::from(octets))
+}
+
+/// Parse the contents of a networks file
+pub fn parse_networks_str(input: &str) -> Result<Vec<NetworkEntry>, HostfileError> {
+    parse_table(input)
+}
+
+/// Parse a networks file using the format described in `man networks(5)`
+pub fn parse_networks_file(path: &Path) -> Result<Vec<NetworkEntry>, HostfileError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    parse_networks_str(&contents)
+}
+
+/// Get path to the system networks file, which is next to the hosts file
+pub fn get_networks_path() -> Result<PathBuf, HostfileError> {
+    Ok(get_hostfile_path()?.with_file_name("networks"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_networks() {
+        let input = "# comment\n\
+                     default 0.0.0.0\n\
+                     loopback 127\t# the loopback network\n\
+                     link-local 169.254.0.0 linklocal\n\
+                     \n\
+                     private 192.168 lan home\n";
+        let entries = parse_networks_str(input).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1].number, Ipv4Addr::new(127, 0, 0, 0));
+        assert_eq!(
+            entries[3],
+            NetworkEntry {
+                name: String::from("private"),
+                number: Ipv4Addr::new(192, 168, 0, 0),
+                aliases: vec![String::from("lan"), String::from("home")],
+            }
+        );
+        assert_eq!(entries[3].to_string(), "private\t192.168.0.0 lan home");
+        assert_eq!(entries[3].to_string().parse(), Ok(entries[3].clone()));
+    }
+
+    #[test]
+    fn invalid_networks() {
+        let err = parse_networks_str("loopback\n").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Expected a network number at line 1 with content: 'loopback'"
+        );
+        assert!(parse_networks_str("net 1.2.3.4.5\n").is_err());
+        assert!(parse_networks_str("net 256\n").is_err());
+        assert!(parse_networks_str("net 10..1\n").is_err());
+    }
+}
@@ -0,0 +1,442 @@
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+
+use crate::strict::parse_line_strict;
+use crate::syntax::{inline_comment, lex_line};
+use crate::{
+    check_regular_file, hostname_key, locate_error, parse_line_bytes, strip_newline, HostEntry,
+    HostEntryCow, HostfileError, HostnamePolicy, ParseError, TokenKind,
+};
+
+/// Options for [`parse_str_with`] and [`parse_file_with_options`]. The defaults parse like
+/// [`crate::parse_file`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    keep_raw: bool,
+    lenient: bool,
+    spans: bool,
+    strict: bool,
+    drop_comments: bool,
+    normalize: bool,
+    hostname_policy: Option<HostnamePolicy>,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the text of the line each entry was parsed from in [`ParsedEntry::raw`]
+    pub fn keep_raw(mut self, keep_raw: bool) -> Self {
+        self.keep_raw = keep_raw;
+        self
+    }
+
+    /// Skip lines that don't parse instead of failing, like the system resolver does. Use
+    /// [`parse_str_lenient`] to find out which lines were skipped.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Record where the parts of each entry are in the input in [`ParsedEntry::spans`], e.g.
+    /// for diagnostics in an editor. [`crate::parse_lossless`] gives the spans of the other
+    /// lines too.
+    pub fn spans(mut self, spans: bool) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    /// Reject lines outside of the grammar in the crate docs, like [`crate::parse_str_strict`]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Keep the comment after the names of each entry in [`ParsedEntry::comment`], which is
+    /// the default
+    pub fn keep_comments(mut self, keep_comments: bool) -> Self {
+        self.drop_comments = !keep_comments;
+        self
+    }
+
+    /// Replace names with their canonical form from [`hostname_key`], so that names that
+    /// compare equal with [`crate::hostname_eq`] are also spelled the same
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Reject names that don't follow RFC 1123, see [`crate::validate_hostname`]. This works
+    /// with and without [`ParseOptions::strict`].
+    pub fn rfc1123(mut self, rfc1123: bool) -> Self {
+        self.hostname_policy = rfc1123.then(HostnamePolicy::rfc1123);
+        self
+    }
+
+    /// Reject names that don't follow `policy`, like [`ParseOptions::rfc1123`] with other
+    /// characters allowed. [`ParseOptions::strict`] still only allows the characters of the
+    /// grammar in the crate docs.
+    pub fn hostname_policy(mut self, policy: HostnamePolicy) -> Self {
+        self.hostname_policy = Some(policy);
+        self
+    }
+
+    /// Parse `line`, numbered `line_no`. Errors are given both on their own, as the reason a
+    /// line is skipped, and with their location.
+    fn parse_line(
+        &self,
+        line: &[u8],
+        line_no: usize,
+    ) -> Result<Option<HostEntry>, (String, HostfileError)> {
+        // Only names need to be valid UTF-8, and replacement characters aren't valid in them
+        let text = || String::from_utf8_lossy(line);
+        let entry = if self.strict {
+            let text = text();
+            parse_line_strict(&text, line_no)
+                .map_err(|err| (err.kind.to_string(), err.into_hostfile_error(&text)))?
+        } else {
+            parse_line_bytes(line)
+                .map_err(|err| (err.to_string(), locate_error(err, line_no, line)))?
+                .map(HostEntryCow::into_owned)
+        };
+        if let (Some(entry), Some(policy)) = (&entry, &self.hostname_policy) {
+            let text = text();
+            check_names(entry, policy, &text, line_no)
+                .map_err(|err| (err.kind.to_string(), err.into_hostfile_error(&text)))?;
+        }
+        Ok(entry.map(|mut entry| {
+            if self.normalize {
+                for name in &mut entry.names {
+                    if let Cow::Owned(key) = hostname_key(name) {
+                        *name = key;
+                    }
+                }
+            }
+            entry
+        }))
+    }
+}
+
+/// The byte ranges of the parts of an entry in the input it was parsed from, see
+/// [`ParseOptions::spans`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySpans {
+    /// The whole line, without its line ending
+    pub line: Range<usize>,
+    pub ip: Range<usize>,
+    pub names: Vec<Range<usize>>,
+    /// The comment after the names, including the `#`
+    pub comment: Option<Range<usize>>,
+}
+
+impl EntrySpans {
+    /// The spans of the entry on `line`, which starts at `offset` in the input
+    fn new(line: &[u8], offset: usize) -> Self {
+        // Only comments may be invalid UTF-8, and they run to the end of the line
+        let text = String::from_utf8_lossy(line);
+        let mut tokens = Vec::new();
+        lex_line(&text, 0..text.len(), &mut tokens);
+        let shift = |range: &Range<usize>| offset + range.start..offset + range.end;
+        let mut spans = EntrySpans {
+            line: offset..offset + line.len(),
+            ip: offset..offset,
+            names: Vec::new(),
+            comment: None,
+        };
+        for token in &tokens {
+            match token.kind {
+                TokenKind::Ip => spans.ip = shift(&token.range),
+                TokenKind::Name => spans.names.push(shift(&token.range)),
+                TokenKind::Comment => {
+                    spans.comment = Some(offset + token.range.start..spans.line.end);
+                }
+                _ => {}
+            }
+        }
+        spans
+    }
+}
+
+/// Check the names of `entry`, parsed from `line`, against `policy`
+fn check_names(
+    entry: &HostEntry,
+    policy: &HostnamePolicy,
+    line: &str,
+    line_no: usize,
+) -> Result<(), ParseError> {
+    let mut tokens = Vec::new();
+    lex_line(line, 0..line.len(), &mut tokens);
+    let starts = tokens
+        .iter()
+        .filter(|token| token.kind == TokenKind::Name)
+        .map(|token| token.range.start);
+    for (name, start) in entry.names.iter().zip(starts) {
+        policy.check(name).map_err(|(span, kind)| ParseError {
+            line: line_no,
+            span: start + span.start..start + span.end,
+            kind,
+        })?;
+    }
+    Ok(())
+}
+
+/// A line that [`parse_str_lenient`] skipped because it doesn't parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedLine {
+    /// 1-based line number
+    pub line: usize,
+    /// The line as written, without its line ending
+    pub text: String,
+    /// Why the line doesn't parse
+    pub reason: String,
+}
+
+/// An entry parsed by [`parse_str_with`] or [`parse_file_with_options`], along with where it
+/// came from. Derefs to the [`HostEntry`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ParsedEntry {
+    pub entry: HostEntry,
+    /// The line the entry was parsed from exactly as written, including whitespace and
+    /// comments but not the line ending, if [`ParseOptions::keep_raw`] is set
+    pub raw: Option<String>,
+    /// 1-based number of the line the entry was parsed from
+    pub line: usize,
+    /// The file the entry was parsed from, if it was parsed from a file
+    pub path: Option<PathBuf>,
+    /// The comment after the names, without the `#` and surrounding whitespace, e.g.
+    /// `managed by mytool` for `10.0.0.1 web # managed by mytool`
+    pub comment: Option<String>,
+    /// Where the parts of the entry are in the input, if [`ParseOptions::spans`] is set
+    pub spans: Option<EntrySpans>,
+}
+
+impl ParsedEntry {
+    /// Where the entry is defined, for messages like "defined at /etc/hosts:42". Entries that
+    /// weren't parsed from a file give "line 42".
+    pub fn location(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{}:{}", path.display(), self.line),
+            None => format!("line {}", self.line),
+        }
+    }
+}
+
+impl Deref for ParsedEntry {
+    type Target = HostEntry;
+
+    fn deref(&self) -> &HostEntry {
+        &self.entry
+    }
+}
+
+impl From<ParsedEntry> for HostEntry {
+    fn from(parsed: ParsedEntry) -> Self {
+        parsed.entry
+    }
+}
+
+/// Parse hosts file contents like [`crate::parse_file`], according to `options`
+pub fn parse_str_with(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<Vec<ParsedEntry>, HostfileError> {
+    parse_bytes_with(input.as_bytes(), options, &mut Vec::new())
+}
+
+/// Parse hosts file contents like the system resolver does, skipping lines that don't parse.
+/// Returns the entries and the lines that were skipped.
+pub fn parse_str_lenient(input: &str) -> (Vec<HostEntry>, Vec<SkippedLine>) {
+    let mut skipped = Vec::new();
+    let options = ParseOptions::new().lenient(true);
+    // Lenient parsing has no errors to return
+    let entries = parse_bytes_with(input.as_bytes(), &options, &mut skipped).unwrap_or_default();
+    (entries.into_iter().map(HostEntry::from).collect(), skipped)
+}
+
+/// Parse the file at `path` like [`crate::parse_file`], according to `options`
+pub fn parse_file_with_options(
+    path: &Path,
+    options: &ParseOptions,
+) -> Result<Vec<ParsedEntry>, HostfileError> {
+    check_regular_file(path)?;
+    let contents =
+        std::fs::read(path).map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    let mut entries = parse_bytes_with(&contents, options, &mut Vec::new())?;
+    for entry in &mut entries {
+        entry.path = Some(path.to_path_buf());
+    }
+    Ok(entries)
+}
+
+/// Parse `input`, adding the lines skipped in lenient mode to `skipped`
+fn parse_bytes_with(
+    input: &[u8],
+    options: &ParseOptions,
+    skipped: &mut Vec<SkippedLine>,
+) -> Result<Vec<ParsedEntry>, HostfileError> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for (idx, line) in input.split_inclusive(|&b| b == b'\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let line = strip_newline(line);
+        match options.parse_line(line, idx + 1) {
+            Ok(Some(entry)) => entries.push(ParsedEntry {
+                entry,
+                // Lines of files are only required to be UTF-8 in their names
+                raw: options
+                    .keep_raw
+                    .then(|| String::from_utf8_lossy(line).into_owned()),
+                line: idx + 1,
+                path: None,
+                comment: (!options.drop_comments)
+                    .then(|| inline_comment(&String::from_utf8_lossy(line)).map(String::from))
+                    .flatten(),
+                spans: options.spans.then(|| EntrySpans::new(line, start)),
+            }),
+            Ok(None) => {}
+            Err((reason, _)) if options.lenient => skipped.push(SkippedLine {
+                line: idx + 1,
+                text: String::from_utf8_lossy(line).into_owned(),
+                reason,
+            }),
+            Err((_, err)) => return Err(err),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_raw_lines() {
+        let source = "# header\r\n  10.0.0.1   web\tWWW  # the web server \r\n::1 localhost";
+        let entries = parse_str_with(source, &ParseOptions::new().keep_raw(true)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].raw.as_deref(),
+            Some("  10.0.0.1   web\tWWW  # the web server ")
+        );
+        assert_eq!(entries[0].names, vec!["web", "WWW"]);
+        assert_eq!(entries[1].raw.as_deref(), Some("::1 localhost"));
+
+        assert_eq!(entries[0].comment.as_deref(), Some("the web server"));
+        assert_eq!(entries[1].comment, None);
+
+        let entries = parse_str_with(source, &ParseOptions::new()).unwrap();
+        assert_eq!(entries[0].raw, None);
+        assert_eq!(entries[1].location(), "line 3");
+        assert_eq!(
+            HostEntry::from(entries[1].clone()),
+            "::1 localhost".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn lenient_skips_bad_lines() {
+        let source = "127.0.0.1 localhost\nlocalhost myhost\n10.0.0.1web\n10.0.0.2 db\n";
+        assert!(parse_str_with(source, &ParseOptions::new()).is_err());
+        let entries = parse_str_with(source, &ParseOptions::new().lenient(true)).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let (entries, skipped) = parse_str_lenient(source);
+        assert_eq!(entries[1].names, ["db"]);
+        assert_eq!(
+            skipped.iter().map(|line| line.line).collect::<Vec<_>>(),
+            [2, 3]
+        );
+        assert_eq!(skipped[1].text, "10.0.0.1web");
+        assert_eq!(skipped[1].reason, "Expected whitespace after IP");
+    }
+
+    #[test]
+    fn configurable_parsing() {
+        let source = "10.0.0.1 Web WWW.Example # prod\n10.0.0.2 db_1\n";
+        let entries = parse_str_with(source, &ParseOptions::new()).unwrap();
+        assert_eq!(entries[1].names, ["db_1"]);
+
+        let err = parse_str_with(source, &ParseOptions::new().strict(true))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Invalid character '_' in hostname at line 2, column 12"
+        );
+        let (entries, skipped) = {
+            let mut skipped = Vec::new();
+            let options = ParseOptions::new().strict(true).lenient(true);
+            let entries = parse_bytes_with(source.as_bytes(), &options, &mut skipped).unwrap();
+            (entries, skipped)
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(skipped[0].reason, "Invalid character '_' in hostname");
+
+        let options = ParseOptions::new().normalize(true).keep_comments(false);
+        let entries = parse_str_with(source, &options).unwrap();
+        assert_eq!(entries[0].names, ["web", "www.example"]);
+        assert_eq!(entries[0].comment, None);
+    }
+
+    #[test]
+    fn rfc1123_names() {
+        let source = "10.0.0.1 web web-1.example\n10.0.0.2 db  -db.example\n";
+        assert!(parse_str_with(source, &ParseOptions::new()).is_ok());
+        let err = parse_str_with(source, &ParseOptions::new().rfc1123(true))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Invalid label \"-db\" in hostname at line 2, column 14"
+        );
+        let err = parse_str_with("::1 _dmarc", &ParseOptions::new().rfc1123(true))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "Invalid character '_' in hostname at line 1, column 5");
+
+        let policy = HostnamePolicy::rfc1123()
+            .allow_chars("_")
+            .allow_wildcards(true);
+        let options = ParseOptions::new().hostname_policy(policy);
+        let entries = parse_str_with("::1 _dmarc *.dev.example\n", &options).unwrap();
+        assert_eq!(entries[0].names, ["_dmarc", "*.dev.example"]);
+        assert!(parse_str_with("::1 a*b", &options).is_err());
+    }
+
+    #[test]
+    fn entry_spans() {
+        let source = "# hosts\r\n  10.0.0.1\tweb  www # caf\u{e9}\r\n::1 localhost";
+        let entries = parse_str_with(source, &ParseOptions::new().spans(true)).unwrap();
+        let spans = entries[0].spans.as_ref().unwrap();
+        assert_eq!(&source[spans.ip.clone()], "10.0.0.1");
+        let names: Vec<_> = spans
+            .names
+            .iter()
+            .map(|name| &source[name.clone()])
+            .collect();
+        assert_eq!(names, ["web", "www"]);
+        assert_eq!(&source[spans.comment.clone().unwrap()], "# caf\u{e9}");
+        assert_eq!(
+            &source[spans.line.clone()],
+            "  10.0.0.1\tweb  www # caf\u{e9}"
+        );
+        let spans = entries[1].spans.as_ref().unwrap();
+        assert_eq!(&source[spans.names[0].clone()], "localhost");
+        assert_eq!(spans.comment, None);
+    }
+
+    #[test]
+    fn file_locations() {
+        let temp_dir = mktemp::Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        std::fs::write(&path, "# header\n10.0.0.1 web\n").unwrap();
+        let entries = parse_file_with_options(&path, &ParseOptions::new()).unwrap();
+        assert_eq!(entries[0].line, 2);
+        assert_eq!(entries[0].path.as_deref(), Some(path.as_path()));
+        assert_eq!(entries[0].location(), format!("{}:2", path.display()));
+    }
+}
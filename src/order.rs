@@ -0,0 +1,232 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// How to order the addresses returned by a lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressOrder {
+    /// Keep the order of the hosts file
+    #[default]
+    AsListed,
+    /// IPv4 addresses first, otherwise keeping the order of the hosts file
+    Ipv4First,
+    /// IPv6 addresses first, otherwise keeping the order of the hosts file
+    Ipv6First,
+    /// Destination address selection from RFC 6724, with the default policy table unless
+    /// [`crate::LookupOptions::policy_table`] sets another, such as the one of
+    /// [`crate::GaiConf`]
+    Rfc6724,
+}
+
+/// A row of an RFC 6724 policy table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    pub precedence: u32,
+    pub label: u32,
+}
+
+impl Policy {
+    pub fn new(prefix: Ipv6Addr, prefix_len: u8, precedence: u32, label: u32) -> Self {
+        Policy {
+            prefix,
+            prefix_len: prefix_len.min(128),
+            precedence,
+            label,
+        }
+    }
+
+    /// Whether `ip` is within this policy's prefix
+    pub fn matches(&self, ip: &Ipv6Addr) -> bool {
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(self.prefix_len))
+            .unwrap_or(0);
+        u128::from(*ip) & mask == u128::from(self.prefix) & mask
+    }
+}
+
+/// An RFC 6724 policy table used to order destination addresses.
+///
+/// IPv4 addresses are looked up as IPv4-mapped IPv6 addresses, as described in the RFC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyTable {
+    policies: Vec<Policy>,
+    ipv4_scopes: Vec<(Ipv4Addr, u8, u8)>,
+}
+
+impl PolicyTable {
+    pub fn new(policies: Vec<Policy>) -> Self {
+        PolicyTable {
+            policies,
+            ipv4_scopes: Vec::new(),
+        }
+    }
+
+    /// Give IPv4 addresses within `prefix/prefix_len` the scope `scope` instead of the one
+    /// from RFC 6724 section 3.2, like a `scopev4` line of `gai.conf`. The longest matching
+    /// prefix wins.
+    pub fn ipv4_scope(mut self, prefix: Ipv4Addr, prefix_len: u8, scope: u8) -> Self {
+        self.ipv4_scopes.push((prefix, prefix_len.min(32), scope));
+        self
+    }
+
+    pub fn policies(&self) -> &[Policy] {
+        &self.policies
+    }
+
+    /// The policy with the longest prefix matching `ip`
+    pub fn lookup(&self, ip: IpAddr) -> Option<&Policy> {
+        let ip = to_ipv6(ip);
+        self.policies
+            .iter()
+            .filter(|policy| policy.matches(&ip))
+            .max_by_key(|policy| policy.prefix_len)
+    }
+
+    /// Sort `addrs` by RFC 6724 destination address selection.
+    ///
+    /// Without knowing the source addresses of the machine only the rules that depend on the
+    /// destination alone apply: higher precedence first (rule 6), then smaller scope first
+    /// (rule 8). Otherwise the original order is kept (rule 10).
+    pub fn sort(&self, addrs: &mut [IpAddr]) {
+        addrs.sort_by_key(|&ip| {
+            let precedence = self.lookup(ip).map_or(0, |policy| policy.precedence);
+            (std::cmp::Reverse(precedence), self.scope(ip))
+        });
+    }
+
+    fn scope(&self, ip: IpAddr) -> u8 {
+        let v4 = match ip {
+            IpAddr::V4(ip) => u32::from(ip),
+            IpAddr::V6(_) => return scope(ip),
+        };
+        self.ipv4_scopes
+            .iter()
+            .filter(|(prefix, prefix_len, _)| {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(*prefix_len))
+                    .unwrap_or(0);
+                v4 & mask == u32::from(*prefix) & mask
+            })
+            .max_by_key(|(_, prefix_len, _)| *prefix_len)
+            .map_or_else(|| scope(ip), |(_, _, scope)| *scope)
+    }
+}
+
+impl Default for PolicyTable {
+    /// The default policy table from RFC 6724 section 2.1
+    fn default() -> Self {
+        let policy = |prefix: &str, prefix_len, precedence, label| {
+            Policy::new(prefix.parse().unwrap(), prefix_len, precedence, label)
+        };
+        PolicyTable::new(vec![
+            policy("::1", 128, 50, 0),
+            policy("::", 0, 40, 1),
+            policy("::ffff:0:0", 96, 35, 4),
+            policy("2002::", 16, 30, 2),
+            policy("2001::", 32, 5, 5),
+            policy("fc00::", 7, 3, 13),
+            policy("::", 96, 1, 3),
+            policy("fec0::", 10, 1, 11),
+            policy("3ffe::", 16, 1, 12),
+        ])
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+const SCOPE_LINK_LOCAL: u8 = 0x2;
+const SCOPE_SITE_LOCAL: u8 = 0x5;
+const SCOPE_GLOBAL: u8 = 0xe;
+
+/// The scope of an address as defined in RFC 6724 section 3.1
+fn scope(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(ip) if ip.is_loopback() || ip.is_link_local() => SCOPE_LINK_LOCAL,
+        IpAddr::V4(_) => SCOPE_GLOBAL,
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            if ip.is_multicast() {
+                (first & 0xf) as u8
+            } else if ip.is_loopback() || first & 0xffc0 == 0xfe80 {
+                SCOPE_LINK_LOCAL
+            } else if first & 0xffc0 == 0xfec0 {
+                SCOPE_SITE_LOCAL
+            } else {
+                SCOPE_GLOBAL
+            }
+        }
+    }
+}
+
+/// Sort `addrs` in place according to `order`. The sort is stable.
+pub fn sort_addresses(addrs: &mut [IpAddr], order: AddressOrder) {
+    match order {
+        AddressOrder::AsListed => {}
+        AddressOrder::Ipv4First => addrs.sort_by_key(|ip| ip.is_ipv6()),
+        AddressOrder::Ipv6First => addrs.sort_by_key(|ip| ip.is_ipv4()),
+        AddressOrder::Rfc6724 => PolicyTable::default().sort(addrs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn family_first() {
+        let mut addrs = ips(&["10.0.0.1", "2001:db8::1", "10.0.0.2", "::1"]);
+        sort_addresses(&mut addrs, AddressOrder::Ipv6First);
+        assert_eq!(addrs, ips(&["2001:db8::1", "::1", "10.0.0.1", "10.0.0.2"]));
+        sort_addresses(&mut addrs, AddressOrder::Ipv4First);
+        assert_eq!(addrs, ips(&["10.0.0.1", "10.0.0.2", "2001:db8::1", "::1"]));
+        sort_addresses(&mut addrs, AddressOrder::AsListed);
+        assert_eq!(addrs, ips(&["10.0.0.1", "10.0.0.2", "2001:db8::1", "::1"]));
+    }
+
+    #[test]
+    fn rfc6724_precedence_and_scope() {
+        let mut addrs = ips(&[
+            "fd00::1",
+            "192.0.2.1",
+            "2002:c000:0201::1",
+            "2a00::1",
+            "fe80::1",
+            "::1",
+            "2001::1",
+        ]);
+        sort_addresses(&mut addrs, AddressOrder::Rfc6724);
+        assert_eq!(
+            addrs,
+            ips(&[
+                "::1",
+                "fe80::1",
+                "2a00::1",
+                "192.0.2.1",
+                "2002:c000:0201::1",
+                "2001::1",
+                "fd00::1",
+            ])
+        );
+    }
+
+    #[test]
+    fn policy_lookup_longest_prefix() {
+        let table = PolicyTable::default();
+        let label = |ip: &str| table.lookup(ip.parse().unwrap()).unwrap().label;
+        assert_eq!(label("::1"), 0);
+        assert_eq!(label("::2"), 3);
+        assert_eq!(label("10.1.2.3"), 4);
+        assert_eq!(label("2001:0:1::1"), 5);
+        assert_eq!(label("2001:db8::1"), 1);
+        assert_eq!(label("2a00::1"), 1);
+    }
+}
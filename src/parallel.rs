@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{parse_file_with, HostEntry, HostfileError, ReadMode};
+
+/// Parse the files at `paths` on several threads and return their entries in the order of
+/// `paths`, as if each file were parsed with [`crate::parse_file`] in turn and the results
+/// concatenated.
+///
+/// If any file fails to parse, the error of the first such file in `paths` is returned. Where
+/// threads are not available, the files are parsed on the calling thread.
+pub fn parse_files_parallel<P: AsRef<Path> + Sync>(
+    paths: &[P],
+) -> Result<Vec<HostEntry>, HostfileError> {
+    let workers = thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(paths.len());
+    let next = AtomicUsize::new(0);
+
+    // Each worker takes the next file that nobody has started on, so a few large files don't
+    // hold up the rest
+    let work = || {
+        let mut parsed = Vec::new();
+        loop {
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            let path = match paths.get(idx) {
+                Some(path) => path.as_ref(),
+                None => return parsed,
+            };
+            parsed.push((idx, parse_file_with(path, ReadMode::WholeFile)));
+        }
+    };
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let handles: Vec<_> = (1..workers)
+            .map_while(|_| thread::Builder::new().spawn_scoped(scope, work).ok())
+            .collect();
+        let mut results = work();
+        for handle in handles {
+            match handle.join() {
+                Ok(parsed) => results.extend(parsed),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        results
+    });
+    results.sort_unstable_by_key(|(idx, _)| *idx);
+
+    let mut entries = Vec::new();
+    for (_, result) in results {
+        entries.extend(result?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use super::*;
+
+    #[test]
+    fn parallel_results_keep_file_order() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let paths: Vec<_> = (0..20)
+            .map(|i| {
+                let path = temp_dir.as_path().join(format!("source{i}"));
+                std::fs::write(&path, format!("10.0.0.{i} a{i}\n10.0.1.{i} b{i}\n")).unwrap();
+                path
+            })
+            .collect();
+
+        let entries = parse_files_parallel(&paths).unwrap();
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.names[0].as_str())
+            .collect();
+        let expected: Vec<String> = (0..20)
+            .flat_map(|i| [format!("a{i}"), format!("b{i}")])
+            .collect();
+        assert_eq!(names, expected);
+
+        std::fs::write(&paths[15], "not an address\n").unwrap();
+        std::fs::write(&paths[3], "bad\n").unwrap();
+        let err = parse_files_parallel(&paths).unwrap_err().to_string();
+        assert!(err.contains("'bad'"), "{}", err);
+        assert!(parse_files_parallel::<&Path>(&[]).unwrap().is_empty());
+    }
+}
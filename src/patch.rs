@@ -0,0 +1,238 @@
+use crate::{HostEntry, HostfileError};
+
+/// Changed ranges with more entries than this are replaced wholesale, since finding the
+/// smallest patch takes quadratic time
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// One operation of a patch from [`diff_patch`], applied with [`apply_patch`].
+///
+/// Indices refer to the list of entries as left by the operations before. With the `serde`
+/// feature, operations serialize like JSON Patch (RFC 6902) operations on the JSON array of
+/// entries, such as `{"op": "remove", "path": "/3"}`, so any JSON Patch implementation can
+/// replay them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum PatchOp {
+    /// Insert `value` before the entry at `index`, or at the end if `index` is the length
+    Add {
+        #[cfg_attr(feature = "serde", serde(rename = "path", with = "pointer"))]
+        index: usize,
+        value: HostEntry,
+    },
+    /// Remove the entry at `index`
+    Remove {
+        #[cfg_attr(feature = "serde", serde(rename = "path", with = "pointer"))]
+        index: usize,
+    },
+    /// Replace the entry at `index` with `value`
+    Replace {
+        #[cfg_attr(feature = "serde", serde(rename = "path", with = "pointer"))]
+        index: usize,
+        value: HostEntry,
+    },
+}
+
+/// The operations that turn `old` into `new`, keeping the entries they have in common.
+///
+/// Unlike [`crate::diff_entries`], which compares the addresses of names, this compares whole
+/// entries in order, so moving or reordering entries shows up in the patch.
+pub fn diff_patch(old: &[HostEntry], new: &[HostEntry]) -> Vec<PatchOp> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut patch = Patch {
+        ops: Vec::new(),
+        index: prefix,
+        removed: Vec::new(),
+        added: Vec::new(),
+    };
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        patch.removed.extend(a);
+        patch.added.extend(b);
+    } else {
+        // Longest common subsequence of the changed middle, from the end
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                patch.flush();
+                patch.index += 1;
+                i += 1;
+                j += 1;
+            } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                patch.added.push(&b[j]);
+                j += 1;
+            } else {
+                patch.removed.push(&a[i]);
+                i += 1;
+            }
+        }
+    }
+    patch.flush();
+    patch.ops
+}
+
+/// A patch being built from the removals and additions of each changed range
+struct Patch<'a> {
+    ops: Vec<PatchOp>,
+    /// Where the next operation applies
+    index: usize,
+    removed: Vec<&'a HostEntry>,
+    added: Vec<&'a HostEntry>,
+}
+
+impl Patch<'_> {
+    /// Emit operations for the pending changed range, replacing entries where possible
+    fn flush(&mut self) {
+        let replaced = self.removed.len().min(self.added.len());
+        for value in self.added.drain(..replaced) {
+            self.ops.push(PatchOp::Replace {
+                index: self.index,
+                value: value.clone(),
+            });
+            self.index += 1;
+        }
+        for _ in self.removed.drain(..).skip(replaced) {
+            self.ops.push(PatchOp::Remove { index: self.index });
+        }
+        for value in self.added.drain(..) {
+            self.ops.push(PatchOp::Add {
+                index: self.index,
+                value: value.clone(),
+            });
+            self.index += 1;
+        }
+    }
+}
+
+/// Apply `ops` to `entries` in order. Fails without changing `entries` if an index is out of
+/// range.
+pub fn apply_patch(entries: &mut Vec<HostEntry>, ops: &[PatchOp]) -> Result<(), HostfileError> {
+    let mut patched = entries.clone();
+    for (n, op) in ops.iter().enumerate() {
+        let (index, len) = match op {
+            PatchOp::Add { index, .. } => (*index, patched.len() + 1),
+            PatchOp::Remove { index } | PatchOp::Replace { index, .. } => (*index, patched.len()),
+        };
+        if index >= len {
+            return Err(format!(
+                "Operation {n} is out of range: index {index} of {} entries",
+                patched.len()
+            )
+            .into());
+        }
+        match op {
+            PatchOp::Add { value, .. } => patched.insert(index, value.clone()),
+            PatchOp::Remove { .. } => {
+                patched.remove(index);
+            }
+            PatchOp::Replace { value, .. } => patched[index] = value.clone(),
+        }
+    }
+    *entries = patched;
+    Ok(())
+}
+
+/// Indices as JSON Pointers into the array of entries, such as `/3`
+#[cfg(feature = "serde")]
+mod pointer {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(index: &usize, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format!("/{index}"))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<usize, D::Error> {
+        let path = String::deserialize(d)?;
+        path.strip_prefix('/')
+            .and_then(|index| index.parse().ok())
+            .ok_or_else(|| D::Error::custom(format!("Expected a path like '/0', found '{path}'")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(lines: &[&str]) -> Vec<HostEntry> {
+        lines.iter().map(|line| line.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn patch_round_trip() {
+        let old = entries(&[
+            "127.0.0.1 localhost",
+            "10.0.0.1 web",
+            "10.0.0.2 db",
+            "10.0.0.3 cache",
+            "10.0.0.4 queue",
+        ]);
+        let new = entries(&[
+            "127.0.0.1 localhost",
+            "10.0.0.1 web www",
+            "10.0.0.4 queue",
+            "10.0.0.5 search",
+        ]);
+        let ops = diff_patch(&old, &new);
+        assert_eq!(
+            ops,
+            vec![
+                PatchOp::Replace {
+                    index: 1,
+                    value: new[1].clone()
+                },
+                PatchOp::Remove { index: 2 },
+                PatchOp::Remove { index: 2 },
+                PatchOp::Add {
+                    index: 3,
+                    value: new[3].clone()
+                },
+            ]
+        );
+
+        let mut patched = old.clone();
+        apply_patch(&mut patched, &ops).unwrap();
+        assert_eq!(patched, new);
+        assert!(diff_patch(&new, &new).is_empty());
+        assert!(apply_patch(&mut patched, &[PatchOp::Remove { index: 4 }]).is_err());
+        assert_eq!(patched, new);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn patch_as_json() {
+        let ops = vec![
+            PatchOp::Add {
+                index: 0,
+                value: "10.0.0.1 web".parse().unwrap(),
+            },
+            PatchOp::Remove { index: 2 },
+        ];
+        let json = serde_json::to_string(&ops).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"op":"add","path":"/0","value":{"ip":"10.0.0.1","names":["web"]}},{"op":"remove","path":"/2"}]"#
+        );
+        assert_eq!(serde_json::from_str::<Vec<PatchOp>>(&json).unwrap(), ops);
+        assert!(serde_json::from_str::<PatchOp>(r#"{"op":"remove","path":"2"}"#).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// A tool that generates part or all of a hosts file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Generator {
+    NetworkManager,
+    DockerDesktop,
+    Wsl,
+    CloudInit,
+    Vagrant,
+    /// Not another tool, but the critical entries that
+    /// [`crate::HostsFile::set_safe_mode`] guards
+    SafeMode,
+}
+
+impl Generator {
+    /// Whether this tool regenerates the whole file, so that even added lines would be lost
+    pub fn owns_file(self) -> bool {
+        matches!(self, Generator::Wsl | Generator::CloudInit)
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Generator::NetworkManager => "NetworkManager",
+            Generator::DockerDesktop => "Docker Desktop",
+            Generator::Wsl => "WSL",
+            Generator::CloudInit => "cloud-init",
+            Generator::Vagrant => "Vagrant",
+            Generator::SafeMode => "safe mode",
+        })
+    }
+}
+
+/// Lines of a hosts file that are managed by another tool.
+///
+/// This is also the error returned when an edit would modify such lines, see
+/// [`crate::HostsFile::set_force`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectedRegion {
+    pub generator: Generator,
+    /// Zero based indices of the lines in the region
+    pub lines: Range<usize>,
+}
+
+impl fmt::Display for ProtectedRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.generator == Generator::SafeMode {
+            return write!(
+                f,
+                "Line {} has a localhost or hostname entry, which safe mode keeps",
+                self.lines.start + 1
+            );
+        }
+        write!(
+            f,
+            "Lines {} to {} are managed by {}",
+            self.lines.start + 1,
+            self.lines.end,
+            self.generator
+        )
+    }
+}
+
+impl Error for ProtectedRegion {}
+
+/// Markers of files generated as a whole
+const FILE_MARKERS: &[(&str, Generator)] = &[
+    (
+        "# This file was automatically generated by WSL",
+        Generator::Wsl,
+    ),
+    (
+        "# Your system has configured 'manage_etc_hosts' as True",
+        Generator::CloudInit,
+    ),
+];
+
+/// Markers of generated sections, as start and end of the section
+const SECTION_MARKERS: &[(&str, &str, Generator)] = &[
+    (
+        "# Added by Docker Desktop",
+        "# End of section",
+        Generator::DockerDesktop,
+    ),
+    (
+        "## vagrant-hostmanager-start",
+        "## vagrant-hostmanager-end",
+        Generator::Vagrant,
+    ),
+];
+
+/// Markers of single generated lines, found in their trailing comment
+const LINE_MARKERS: &[(&str, Generator)] = &[
+    ("# Added by NetworkManager", Generator::NetworkManager),
+    ("# VAGRANT: ", Generator::Vagrant),
+];
+
+/// Find the regions of `lines` that are managed by other tools, in order.
+///
+/// A section without an end marker runs to the end of the file.
+pub(crate) fn find_protected_regions<S: AsRef<str>>(lines: &[S]) -> Vec<ProtectedRegion> {
+    let whole_file = lines.iter().find_map(|line| {
+        let line = line.as_ref().trim();
+        FILE_MARKERS
+            .iter()
+            .find(|(marker, _)| line.starts_with(marker))
+    });
+    if let Some(&(_, generator)) = whole_file {
+        return vec![ProtectedRegion {
+            generator,
+            lines: 0..lines.len(),
+        }];
+    }
+
+    let mut regions = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx].as_ref();
+        let trimmed = line.trim();
+        if let Some(&(_, end, generator)) = SECTION_MARKERS
+            .iter()
+            .find(|(start, _, _)| trimmed.starts_with(start))
+        {
+            let len = lines[idx + 1..]
+                .iter()
+                .position(|line| line.as_ref().trim().starts_with(end))
+                .map_or(lines.len() - idx, |end_idx| end_idx + 2);
+            regions.push(ProtectedRegion {
+                generator,
+                lines: idx..idx + len,
+            });
+            idx += len;
+            continue;
+        }
+
+        if let Some(&(_, generator)) = LINE_MARKERS
+            .iter()
+            .find(|(marker, _)| line.contains(marker))
+        {
+            regions.push(ProtectedRegion {
+                generator,
+                lines: idx..idx + 1,
+            });
+        }
+        idx += 1;
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_and_lines() {
+        let lines = [
+            "127.0.0.1 localhost",
+            "# Added by Docker Desktop",
+            "192.168.1.2 host.docker.internal",
+            "# End of section",
+            "10.0.0.1 web # Added by NetworkManager",
+            "## vagrant-hostmanager-start id: 1234",
+            "192.168.56.10 box",
+        ];
+        assert_eq!(
+            find_protected_regions(&lines),
+            vec![
+                ProtectedRegion {
+                    generator: Generator::DockerDesktop,
+                    lines: 1..4,
+                },
+                ProtectedRegion {
+                    generator: Generator::NetworkManager,
+                    lines: 4..5,
+                },
+                ProtectedRegion {
+                    generator: Generator::Vagrant,
+                    lines: 5..7,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_file() {
+        let lines = [
+            "# This file was automatically generated by WSL. To stop automatic generation...",
+            "127.0.0.1 localhost",
+            "# Added by Docker Desktop",
+        ];
+        let regions = find_protected_regions(&lines);
+        assert_eq!(
+            regions,
+            vec![ProtectedRegion {
+                generator: Generator::Wsl,
+                lines: 0..3,
+            }]
+        );
+        assert_eq!(regions[0].to_string(), "Lines 1 to 3 are managed by WSL");
+        assert!(find_protected_regions(&["127.0.0.1 localhost"]).is_empty());
+    }
+}
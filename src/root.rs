@@ -0,0 +1,124 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{parse_file, HostEntry, HostfileError};
+
+/// Most symlinks followed while resolving a path, like Linux's limit
+const MAX_SYMLINKS: usize = 40;
+
+/// The path of the hosts file of the system whose root directory is `root`, such as a chroot,
+/// a mounted disk image or a container's root filesystem.
+///
+/// This is `etc/hosts` under `root`, or `Windows/System32/drivers/etc/hosts` for a Windows
+/// image. Symlinks are resolved as if `root` were `/`, so a link to an absolute path, as on
+/// NixOS, stays inside `root` rather than pointing at the host's files.
+pub fn hostfile_path_in_root(root: &Path) -> Result<PathBuf, HostfileError> {
+    let unix = resolve_in_root(root, Path::new("/etc/hosts"))?;
+    if unix.exists() {
+        return Ok(unix);
+    }
+    let windows = resolve_in_root(root, Path::new("/Windows/System32/drivers/etc/hosts"))?;
+    if windows.exists() {
+        return Ok(windows);
+    }
+    Ok(unix)
+}
+
+/// Parse the hosts file of the system whose root directory is `root`, see
+/// [`hostfile_path_in_root`]
+pub fn parse_file_in_root(root: &Path) -> Result<Vec<HostEntry>, HostfileError> {
+    parse_file(&hostfile_path_in_root(root)?)
+}
+
+/// The root directory of the process `pid` as seen from this one, which for a process in a
+/// container is the container's root filesystem. Reading through it usually needs the same
+/// privileges as tracing the process.
+#[cfg(target_os = "linux")]
+pub fn process_root(pid: u32) -> PathBuf {
+    PathBuf::from(format!("/proc/{pid}/root"))
+}
+
+/// Parse the hosts file that the process `pid` sees, such as a container's, see
+/// [`process_root`]
+#[cfg(target_os = "linux")]
+pub fn parse_process_hostfile(pid: u32) -> Result<Vec<HostEntry>, HostfileError> {
+    parse_file_in_root(&process_root(pid))
+}
+
+/// `path` under `root`, with symlinks resolved as if `root` were `/`
+fn resolve_in_root(root: &Path, path: &Path) -> Result<PathBuf, HostfileError> {
+    // Components left to resolve, last first. `..` can't be a file name, so it is kept as is.
+    let mut pending: Vec<OsString> = Vec::new();
+    let push_components = |pending: &mut Vec<OsString>, path: &Path| {
+        for component in path.components().rev() {
+            match component {
+                Component::Normal(name) => pending.push(name.to_os_string()),
+                Component::ParentDir => pending.push(OsString::from("..")),
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            }
+        }
+    };
+    push_components(&mut pending, path);
+
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+        let candidate = resolved.join(&name);
+        let full = root.join(&candidate);
+        match fs::symlink_metadata(&full) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(format!("Too many levels of symlinks at ({:?})", full).into());
+                }
+                let target = fs::read_link(&full)
+                    .map_err(|err| format!("Could not read symlink ({:?}): {err}", full))?;
+                if target.has_root() {
+                    resolved = PathBuf::new();
+                }
+                push_components(&mut pending, &target);
+            }
+            _ => resolved = candidate,
+        }
+    }
+    Ok(root.join(resolved))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn absolute_symlinks_stay_in_root() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let root = temp_dir.as_path();
+        fs::create_dir_all(root.join("etc/static")).unwrap();
+        fs::write(root.join("etc/static/hosts"), "10.0.0.1 container\n").unwrap();
+        symlink("/etc/static/hosts", root.join("etc/hosts")).unwrap();
+
+        assert_eq!(
+            hostfile_path_in_root(root).unwrap(),
+            root.join("etc/static/hosts")
+        );
+        assert_eq!(
+            parse_file_in_root(root).unwrap(),
+            vec!["10.0.0.1 container".parse().unwrap()]
+        );
+
+        symlink("../../../../etc/hosts", root.join("escape")).unwrap();
+        assert_eq!(
+            resolve_in_root(root, Path::new("escape")).unwrap(),
+            root.join("etc/static/hosts")
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+use crate::document::TextLine;
+use crate::{parse_line_bytes, strip_newline, HostEntry};
+
+/// The form of a comment line that starts a section of a hand-maintained hosts file, such
+/// as `## vpn ##`.
+///
+/// A line is a header if, once trimmed, it starts with the prefix and ends with the suffix.
+/// The text between them is the name of the section, and must contain a letter or digit so
+/// that banners made of `#`s aren't mistaken for headers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SectionPattern {
+    prefix: String,
+    suffix: String,
+}
+
+impl SectionPattern {
+    pub fn new(prefix: impl Into<String>, suffix: impl Into<String>) -> Self {
+        SectionPattern {
+            prefix: prefix.into(),
+            suffix: suffix.into(),
+        }
+    }
+
+    /// The patterns recognized by default: `## name ##` and `# --- name ---`
+    pub fn defaults() -> Vec<SectionPattern> {
+        vec![
+            SectionPattern::new("##", "##"),
+            SectionPattern::new("# ---", "---"),
+        ]
+    }
+
+    /// The section name in `line` if it is a header of this form
+    pub fn name_of<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let line = line.trim();
+        if line.len() < self.prefix.len() + self.suffix.len() {
+            return None;
+        }
+        let name = line
+            .strip_prefix(self.prefix.as_str())?
+            .strip_suffix(self.suffix.as_str())?
+            .trim();
+        if name.chars().any(char::is_alphanumeric) {
+            Some(name)
+        } else {
+            None
+        }
+    }
+}
+
+/// A section of a hosts file introduced by a header comment, see [`SectionPattern`].
+///
+/// A section runs from its header up to the next header or the end of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) range: Range<usize>,
+    pub(crate) lines: &'a [TextLine],
+}
+
+impl<'a> Section<'a> {
+    /// The name of the section as written in its header
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The lines of the section, starting with its header
+    pub fn lines(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// The valid entries of the section in order
+    pub fn entries(&self) -> Vec<HostEntry> {
+        self.lines
+            .iter()
+            .filter_map(
+                |line| match parse_line_bytes(strip_newline(line.text.as_bytes())) {
+                    Ok(Some(entry)) => Some(entry.into_owned()),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+}
+
+/// The section name of `line` if it is a header of any of `patterns`
+pub(crate) fn header_name<'a>(patterns: &[SectionPattern], line: &'a str) -> Option<&'a str> {
+    patterns.iter().find_map(|pattern| pattern.name_of(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_patterns() {
+        let patterns = SectionPattern::defaults();
+        assert_eq!(header_name(&patterns, "## vpn ##\n"), Some("vpn"));
+        assert_eq!(
+            header_name(&patterns, "  # --- ad blocklist ---"),
+            Some("ad blocklist")
+        );
+        assert_eq!(header_name(&patterns, "##########"), None);
+        assert_eq!(header_name(&patterns, "# --- ---"), None);
+        assert_eq!(header_name(&patterns, "# vpn"), None);
+        assert_eq!(header_name(&patterns, "##"), None);
+
+        let custom = [SectionPattern::new("# [", "]")];
+        assert_eq!(header_name(&custom, "# [office]"), Some("office"));
+    }
+}
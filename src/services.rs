@@ -0,0 +1,133 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, parse_table, HostfileError};
+
+/// A line from `/etc/services`, as described in `man services(5)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceEntry {
+    pub name: String,
+    pub port: u16,
+    /// The protocol, such as `tcp` or `udp`
+    pub protocol: String,
+    pub aliases: Vec<String>,
+}
+
+impl ServiceEntry {
+    /// Whether `name` is the name of this service or one of its aliases
+    pub fn has_name(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|alias| alias == name)
+    }
+}
+
+impl FromStr for ServiceEntry {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Account for comments at the end of the line
+        let line = s.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let name = tokens.next().ok_or("Expected a service name")?;
+        let port = tokens.next().ok_or("Expected a port and protocol")?;
+        let (port, protocol) = port
+            .split_once('/')
+            .filter(|(_, protocol)| !protocol.is_empty())
+            .ok_or_else(|| format!("Expected port/protocol, found {port}"))?;
+        let port = port
+            .parse()
+            .map_err(|err| format!("Couldn't parse a valid port {port}: {err}"))?;
+        Ok(ServiceEntry {
+            name: name.to_string(),
+            port,
+            protocol: protocol.to_string(),
+            aliases: tokens.map(str::to_string).collect(),
+        })
+    }
+}
+
+impl fmt::Display for ServiceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}/{}", self.name, self.port, self.protocol)?;
+        for alias in &self.aliases {
+            write!(f, " {alias}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the contents of a services file
+pub fn parse_services_str(input: &str) -> Result<Vec<ServiceEntry>, HostfileError> {
+    parse_table(input)
+}
+
+/// Parse a services file using the format described in `man services(5)`
+pub fn parse_services_file(path: &Path) -> Result<Vec<ServiceEntry>, HostfileError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read file ({:?}): {err}", path))?;
+    parse_services_str(&contents)
+}
+
+/// Get path to the system services file, which is next to the hosts file
+pub fn get_services_path() -> Result<PathBuf, HostfileError> {
+    Ok(get_hostfile_path()?.with_file_name("services"))
+}
+
+/// Find the first service called `name`, or with `name` as an alias, like
+/// `getservbyname(3)`. If `protocol` is given, only services for it match.
+pub fn service_by_name<'a>(
+    entries: &'a [ServiceEntry],
+    name: &str,
+    protocol: Option<&str>,
+) -> Option<&'a ServiceEntry> {
+    entries
+        .iter()
+        .find(|entry| entry.has_name(name) && protocol.is_none_or(|p| entry.protocol == p))
+}
+
+/// Find the first service on `port`, like `getservbyport(3)`. If `protocol` is given, only
+/// services for it match.
+pub fn service_by_port<'a>(
+    entries: &'a [ServiceEntry],
+    port: u16,
+    protocol: Option<&str>,
+) -> Option<&'a ServiceEntry> {
+    entries
+        .iter()
+        .find(|entry| entry.port == port && protocol.is_none_or(|p| entry.protocol == p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_services() {
+        let entries = parse_services_str(
+            "# Network services, Internet style\n\
+             ssh\t\t22/tcp\t\t\t# SSH Remote Login Protocol\n\
+             domain\t\t53/tcp\n\
+             domain\t\t53/udp\n\
+             http\t\t80/tcp\t\twww\t\t# WorldWideWeb HTTP\n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(service_by_name(&entries, "www", None), Some(&entries[3]));
+        assert_eq!(
+            service_by_name(&entries, "domain", Some("udp")),
+            Some(&entries[2])
+        );
+        assert_eq!(service_by_name(&entries, "ssh", Some("udp")), None);
+        assert_eq!(service_by_port(&entries, 53, None), Some(&entries[1]));
+        assert_eq!(service_by_port(&entries, 443, None), None);
+        assert_eq!(entries[3].to_string(), "http\t80/tcp www");
+    }
+
+    #[test]
+    fn invalid_services() {
+        assert!(parse_services_str("ssh 22\n").is_err());
+        assert!(parse_services_str("ssh 22/\n").is_err());
+        assert!(parse_services_str("ssh 65536/tcp\n").is_err());
+        assert!(parse_services_str("ssh\n").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::{lookup, HostEntry, HostfileError, HostsFile, LookupOptions};
+
+/// An immutable hosts file along with its parsed entries, as shared by [`SharedHosts`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostsSnapshot {
+    file: HostsFile,
+    entries: Vec<HostEntry>,
+}
+
+impl HostsSnapshot {
+    pub fn file(&self) -> &HostsFile {
+        &self.file
+    }
+
+    /// The valid entries of the file, see [`HostsFile::entries`]
+    pub fn entries(&self) -> &[HostEntry] {
+        &self.entries
+    }
+
+    /// All addresses for `name`, see [`crate::lookup`]
+    pub fn lookup(&self, name: &str, options: impl Into<LookupOptions>) -> Vec<IpAddr> {
+        lookup(&self.entries, name, options)
+    }
+}
+
+/// The undo history of the file is dropped, since snapshots can't be edited
+impl From<HostsFile> for HostsSnapshot {
+    fn from(mut file: HostsFile) -> Self {
+        file.clear_history();
+        let entries = file.entries().collect();
+        HostsSnapshot { file, entries }
+    }
+}
+
+/// A hosts file shared between threads, e.g. by a server answering queries from it.
+///
+/// Readers get the current [`HostsSnapshot`] without taking a lock, and keep using it for as
+/// long as they hold on to it. Replacing the file, e.g. on reload, swaps in a new snapshot
+/// atomically, so readers see either the old file or the new one but never a mix.
+#[derive(Debug)]
+pub struct SharedHosts {
+    current: ArcSwap<HostsSnapshot>,
+    path: Option<PathBuf>,
+}
+
+impl SharedHosts {
+    pub fn new(file: HostsFile) -> Self {
+        SharedHosts {
+            current: ArcSwap::from_pointee(file.into()),
+            path: None,
+        }
+    }
+
+    /// Read the hosts file at `path`, which [`SharedHosts::reload`] reads again
+    pub fn open(path: &Path) -> Result<Self, HostfileError> {
+        let mut shared = Self::new(HostsFile::read(path)?);
+        shared.path = Some(path.to_path_buf());
+        Ok(shared)
+    }
+
+    /// The current snapshot
+    pub fn load(&self) -> Arc<HostsSnapshot> {
+        self.current.load_full()
+    }
+
+    /// All addresses for `name` in the current snapshot, see [`crate::lookup`]
+    pub fn lookup(&self, name: &str, options: impl Into<LookupOptions>) -> Vec<IpAddr> {
+        self.current.load().lookup(name, options)
+    }
+
+    /// Replace the shared file with `file`
+    pub fn store(&self, file: HostsFile) {
+        self.current.store(Arc::new(file.into()));
+    }
+
+    /// Replace the shared file with an edited copy of it.
+    ///
+    /// If other threads replace the file concurrently, `f` is called again with their file so
+    /// that no edits are lost.
+    pub fn update<F: FnMut(&HostsFile) -> HostsFile>(&self, mut f: F) {
+        self.current
+            .rcu(|snapshot| HostsSnapshot::from(f(snapshot.file())));
+    }
+
+    /// Read the file passed to [`SharedHosts::open`] again. The current snapshot is kept if
+    /// it can't be read.
+    pub fn reload(&self) -> Result<(), HostfileError> {
+        let path = self
+            .path
+            .as_deref()
+            .ok_or_else(|| String::from("SharedHosts was not opened from a file"))?;
+        self.store(HostsFile::read(path)?);
+        Ok(())
+    }
+}
+
+impl From<HostsFile> for SharedHosts {
+    fn from(file: HostsFile) -> Self {
+        SharedHosts::new(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::AddressOrder;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn readers_keep_their_snapshot() {
+        assert_send_sync::<HostsFile>();
+        assert_send_sync::<SharedHosts>();
+
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        std::fs::write(&path, "10.0.0.1 web\n").unwrap();
+
+        let shared = Arc::new(SharedHosts::open(&path).unwrap());
+        let before = shared.load();
+        std::fs::write(&path, "10.0.0.2 web\n").unwrap();
+        shared.reload().unwrap();
+
+        let web = |snapshot: &HostsSnapshot| snapshot.lookup("web", AddressOrder::AsListed);
+        assert_eq!(web(&before), vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+        let reader = {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || shared.lookup("web", AddressOrder::AsListed))
+        };
+        assert_eq!(
+            reader.join().unwrap(),
+            vec!["10.0.0.2".parse::<IpAddr>().unwrap()]
+        );
+
+        shared.update(|file| {
+            let mut file = file.clone();
+            file.add_entry(&"10.0.0.3 db".parse().unwrap()).unwrap();
+            file
+        });
+        assert_eq!(shared.load().entries().len(), 2);
+        assert!(!shared.load().file().can_undo());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.load().entries().len(), 2);
+    }
+}
@@ -0,0 +1,301 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::{parse_str, parse_str_with, HostEntry, HostfileError, HostsFile, ParseOptions};
+
+/// A hosts file on another machine, reached with the system `ssh` client.
+///
+/// Authentication is whatever `ssh` would use for the destination, including the agent and
+/// `~/.ssh/config`, unless an identity file is given. `ssh` runs in batch mode, so a host
+/// that needs a password fails instead of prompting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHost {
+    destination: String,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+    path: String,
+    sudo: bool,
+    backup: bool,
+    parse_options: ParseOptions,
+}
+
+impl RemoteHost {
+    /// The machine at `destination`, which is `host` or `user@host` as `ssh` takes it
+    pub fn new(destination: &str) -> Self {
+        Self {
+            destination: destination.to_string(),
+            port: None,
+            identity_file: None,
+            path: String::from("/etc/hosts"),
+            sudo: false,
+            backup: true,
+            parse_options: ParseOptions::new(),
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Authenticate with the private key at `path`
+    pub fn identity_file(mut self, path: PathBuf) -> Self {
+        self.identity_file = Some(path);
+        self
+    }
+
+    /// Path of the hosts file on the remote machine, `/etc/hosts` by default
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Run commands on the remote machine through `sudo -n`, for when the destination user
+    /// can't write the hosts file
+    pub fn sudo(mut self, sudo: bool) -> Self {
+        self.sudo = sudo;
+        self
+    }
+
+    /// Copy the remote file to `<path>.<UTC timestamp>.bak` before [`write_remote`] replaces
+    /// it, which is the default. Old backups are not pruned.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// The rules [`write_remote`] checks files against before replacing the remote one,
+    /// those of [`crate::parse_file`] by default. Set e.g. [`ParseOptions::hostname_policy`] to
+    /// refuse names the machine's tools don't accept.
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    /// Check that `contents` parse according to [`RemoteHost::parse_options`]
+    fn check(&self, contents: &str) -> Result<(), HostfileError> {
+        parse_str_with(contents, &self.parse_options)?;
+        Ok(())
+    }
+
+    /// The arguments of `ssh` to run the shell command `command` on the remote machine
+    fn ssh_args(&self, command: &str) -> Vec<String> {
+        let mut args = vec![String::from("-o"), String::from("BatchMode=yes")];
+        if let Some(port) = self.port {
+            args.extend([String::from("-p"), port.to_string()]);
+        }
+        if let Some(identity_file) = &self.identity_file {
+            args.extend([
+                String::from("-i"),
+                identity_file.to_string_lossy().into_owned(),
+            ]);
+        }
+        // `--` keeps a destination starting with `-` from being read as an option
+        args.extend([String::from("--"), self.destination.clone()]);
+        let command = if self.sudo {
+            format!("sudo -n sh -c {}", quote(command))
+        } else {
+            command.to_string()
+        };
+        args.push(command);
+        args
+    }
+
+    /// A shell script that replaces the hosts file with its standard input atomically.
+    ///
+    /// A hosts file that is a symlink is resolved first, so its target is replaced and the
+    /// link kept. The new contents go to a temporary file created by `mktemp` next to the
+    /// hosts file, which takes the owner and mode of the original before being renamed over
+    /// it.
+    fn write_script(&self) -> String {
+        let mut script = format!(
+            "set -e; f={}; if [ -L \"$f\" ]; then f=$(readlink -f \"$f\"); fi; ",
+            quote(&self.path)
+        );
+        if self.backup {
+            script.push_str(
+                "if [ -e \"$f\" ]; then \
+                 cp -p \"$f\" \"$f.$(date -u +%Y%m%dT%H%M%SZ).bak\"; fi; ",
+            );
+        }
+        script.push_str(
+            "t=$(mktemp \"$(dirname \"$f\")/.hostfile.XXXXXX\"); trap 'rm -f \"$t\"' EXIT; \
+             if [ -e \"$f\" ]; then cp -p \"$f\" \"$t\"; else chmod 644 \"$t\"; fi; \
+             cat > \"$t\"; mv -f \"$t\" \"$f\"; trap - EXIT",
+        );
+        script
+    }
+
+    fn run(&self, command: &str, input: Option<&str>) -> Result<String, HostfileError> {
+        let mut child = Command::new("ssh")
+            .args(self.ssh_args(command))
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("Could not run ssh: {err}"))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|err| format!("Could not send to {}: {err}", self.destination))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("Could not run ssh: {err}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "ssh {} failed ({}): {}",
+                self.destination,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        String::from_utf8(output.stdout).map_err(|err| {
+            HostfileError::Other(format!(
+                "{}:{} is not UTF-8: {err}",
+                self.destination, self.path
+            ))
+        })
+    }
+}
+
+/// Read the hosts file of `host` for editing
+pub fn read_remote(host: &RemoteHost) -> Result<HostsFile, HostfileError> {
+    let contents = host.run(&format!("cat -- {}", quote(&host.path)), None)?;
+    Ok(HostsFile::parse(&contents))
+}
+
+/// Parse the hosts file of `host` like [`crate::parse_file`], so invalid lines are errors
+pub fn parse_remote(host: &RemoteHost) -> Result<Vec<HostEntry>, HostfileError> {
+    let contents = host.run(&format!("cat -- {}", quote(&host.path)), None)?;
+    parse_str(&contents)
+}
+
+/// Replace the hosts file of `host` with `file`.
+///
+/// `file` is checked to parse first, see [`RemoteHost::parse_options`]. On the remote machine the file is replaced atomically
+/// with a rename and, unless [`RemoteHost::backup`] is turned off, backed up first, so a
+/// dropped connection leaves either the old or the new file in place.
+pub fn write_remote(host: &RemoteHost, file: &HostsFile) -> Result<(), HostfileError> {
+    let contents = file.to_string();
+    host.check(&contents)?;
+    host.run(&host.write_script(), Some(&contents))?;
+    Ok(())
+}
+
+/// `text` quoted for a POSIX shell
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_command_line() {
+        assert_eq!(quote("/srv/it's/hosts"), r"'/srv/it'\''s/hosts'");
+        let host = RemoteHost::new("admin@web1").port(2222).sudo(true);
+        assert_eq!(
+            host.ssh_args("cat -- '/etc/hosts'"),
+            [
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "--",
+                "admin@web1",
+                r"sudo -n sh -c 'cat -- '\''/etc/hosts'\'''",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_before_writing() {
+        let file = HostsFile::parse("127.0.0.1 localhost\n10.0.0.5 db_1 # replica\n");
+        let host = RemoteHost::new("web1");
+        assert_eq!(host.check(&file.to_string()), Ok(()));
+        assert!(host.check("10.0.0.5db\n").is_err());
+        let host = host.parse_options(ParseOptions::new().rfc1123(true));
+        assert!(host.check(&file.to_string()).is_err());
+    }
+
+    /// Run the write script of `host` locally with `contents` as its input
+    #[cfg(unix)]
+    fn run_write_script(host: &RemoteHost, contents: &[u8]) {
+        let mut child = Command::new("sh")
+            .args(["-c", &host.write_script()])
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(contents).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    /// The names of the files in `dir` other than `skip`
+    #[cfg(unix)]
+    fn other_files(dir: &std::path::Path, skip: &str) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name != skip)
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_script_replaces_and_backs_up() {
+        extern crate mktemp;
+        use mktemp::Temp;
+
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        std::fs::write(&path, "10.0.0.1 old\n").unwrap();
+        let host = RemoteHost::new("web1").path(path.to_str().unwrap());
+        run_write_script(&host, b"10.0.0.2 new\n");
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "10.0.0.2 new\n");
+        let files = other_files(temp_dir.as_path(), "hosts");
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("hosts.") && files[0].ends_with(".bak"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_script_follows_symlinks() {
+        extern crate mktemp;
+        use mktemp::Temp;
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = Temp::new_dir().unwrap();
+        let target_dir = temp_dir.as_path().join("etc");
+        std::fs::create_dir(&target_dir).unwrap();
+        let target = target_dir.join("hosts");
+        std::fs::write(&target, "10.0.0.1 old\n").unwrap();
+        let link = temp_dir.as_path().join("hosts");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let host = RemoteHost::new("web1")
+            .path(link.to_str().unwrap())
+            .backup(false);
+        run_write_script(&host, b"10.0.0.2 new\n");
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "10.0.0.2 new\n");
+        assert!(other_files(&target_dir, "hosts").is_empty());
+
+        // A new file gets the usual mode rather than that of the temporary file
+        let path = target_dir.join("new");
+        run_write_script(&host.path(path.to_str().unwrap()), b"10.0.0.3 db\n");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+}
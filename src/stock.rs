@@ -0,0 +1,121 @@
+use crate::document::DEBIAN_HOSTNAME_IP;
+use crate::{diff_entries, HostEntry, HostsFile, WatchEvent};
+
+/// A hosts file as installed by an operating system
+struct Template {
+    entries: &'static str,
+    /// Whether the installer also adds a `127.0.1.1` line for the hostname
+    hostname_line: bool,
+}
+
+#[cfg(windows)]
+const TEMPLATES: &[Template] = &[
+    // Every example in the stock file is commented out
+    Template {
+        entries: "",
+        hostname_line: false,
+    },
+];
+
+#[cfg(target_os = "macos")]
+const TEMPLATES: &[Template] = &[Template {
+    entries: "127.0.0.1 localhost\n255.255.255.255 broadcasthost\n::1 localhost\n",
+    hostname_line: false,
+}];
+
+#[cfg(not(any(windows, target_os = "macos")))]
+const TEMPLATES: &[Template] = &[
+    // Debian and Ubuntu
+    Template {
+        entries: "127.0.0.1 localhost\n\
+                  ::1 localhost ip6-localhost ip6-loopback\n\
+                  ff02::1 ip6-allnodes\n\
+                  ff02::2 ip6-allrouters\n",
+        hostname_line: true,
+    },
+    // Fedora, RHEL and CentOS
+    Template {
+        entries: "127.0.0.1 localhost localhost.localdomain localhost4 localhost4.localdomain4\n\
+                  ::1 localhost localhost.localdomain localhost6 localhost6.localdomain6\n",
+        hostname_line: false,
+    },
+    // Alpine
+    Template {
+        entries: "127.0.0.1 localhost localhost.localdomain\n\
+                  ::1 localhost localhost.localdomain\n",
+        hostname_line: false,
+    },
+    // FreeBSD
+    Template {
+        entries: "::1 localhost localhost.my.domain\n\
+                  127.0.0.1 localhost localhost.my.domain\n",
+        hostname_line: false,
+    },
+    // Arch, whose file only has comments
+    Template {
+        entries: "",
+        hostname_line: false,
+    },
+];
+
+/// Whether `file` is a stock hosts file of this platform, see [`differences_from_default`]
+pub fn is_default(file: &HostsFile) -> bool {
+    differences_from_default(file).is_empty()
+}
+
+/// How the entries of `file` differ from the stock hosts file of this platform that they are
+/// closest to, as the changes from the stock file to `file`.
+///
+/// Only the addresses of names matter, so comments, whitespace and the order of entries are
+/// ignored. The stock files known for each platform are those of Windows, macOS, Debian,
+/// Ubuntu, Fedora, RHEL, Alpine, Arch and FreeBSD. On Debian and Ubuntu, the line the
+/// installer adds for the machine's hostname is considered part of the stock file.
+pub fn differences_from_default(file: &HostsFile) -> Vec<WatchEvent> {
+    let entries: Vec<HostEntry> = file.entries().collect();
+    TEMPLATES
+        .iter()
+        .map(|template| {
+            let mut stock: Vec<HostEntry> = template
+                .entries
+                .lines()
+                .map(|line| line.parse().expect("stock hosts files are valid"))
+                .collect();
+            if template.hostname_line {
+                stock.extend(
+                    entries
+                        .iter()
+                        .filter(|entry| entry.ip == DEBIAN_HOSTNAME_IP)
+                        .cloned(),
+                );
+            }
+            diff_entries(&stock, &entries)
+        })
+        .min_by_key(Vec::len)
+        .unwrap_or_default()
+}
+
+#[cfg(all(test, not(any(windows, target_os = "macos"))))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stock_debian_file() {
+        let debian = "127.0.0.1\tlocalhost\n\
+                      127.0.1.1\tmybox.example.org mybox\n\
+                      \n\
+                      # The following lines are desirable for IPv6 capable hosts\n\
+                      ::1     localhost ip6-localhost ip6-loopback\n\
+                      ff02::1 ip6-allnodes\n\
+                      ff02::2 ip6-allrouters\n";
+        assert!(is_default(&HostsFile::parse(debian)));
+
+        let customized = format!("{debian}10.0.0.1 nas\n");
+        assert_eq!(
+            differences_from_default(&HostsFile::parse(&customized)),
+            vec![WatchEvent::EntryAdded {
+                name: String::from("nas"),
+                addrs: vec!["10.0.0.1".parse().unwrap()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::path::Path;
+
+use crate::{ip_len, read_file, HostEntry, HostfileError, ParseError, ParseErrorKind};
+
+fn is_ws(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+pub(crate) fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '.' || c == '-'
+}
+
+/// Parse a single line (without its newline) according to the grammar in the crate docs.
+///
+/// Blank lines and comments produce `Ok(None)`.
+pub(crate) fn parse_line_strict(
+    line: &str,
+    line_no: usize,
+) -> Result<Option<HostEntry>, ParseError> {
+    let error = |span, kind| ParseError {
+        line: line_no,
+        span,
+        kind,
+    };
+
+    let mut pos = line.len() - line.trim_start_matches(is_ws).len();
+    match line[pos..].chars().next() {
+        None | Some('#') => return Ok(None),
+        Some(_) => {}
+    }
+
+    let ip_end = pos + ip_len(&line[pos..]);
+    let ip = match line[pos..ip_end].parse() {
+        Ok(ip) => ip,
+        Err(err) => {
+            // Point at the whole first word rather than just the characters that look like an IP
+            let word_end = line[pos..].find(is_ws).map_or(line.len(), |i| pos + i);
+            return Err(error(pos..word_end, ParseErrorKind::InvalidIp(err)));
+        }
+    };
+    pos = ip_end;
+
+    match line[pos..].chars().next() {
+        Some(c) if is_ws(c) => {}
+        Some(c) => {
+            return Err(error(
+                pos..pos + c.len_utf8(),
+                ParseErrorKind::MissingWhitespace,
+            ));
+        }
+        None => return Err(error(pos..pos, ParseErrorKind::MissingHostname)),
+    }
+
+    let mut names = Vec::new();
+    loop {
+        pos = line.len() - line[pos..].trim_start_matches(is_ws).len();
+        let rest = &line[pos..];
+        if rest.is_empty() || rest.starts_with('#') {
+            break;
+        }
+
+        let name_end = rest.find(is_ws).unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        if let Some((idx, c)) = name.char_indices().find(|&(_, c)| !is_name_char(c)) {
+            let start = pos + idx;
+            return Err(error(
+                start..start + c.len_utf8(),
+                ParseErrorKind::InvalidHostnameChar(c),
+            ));
+        }
+        names.push(name.to_string());
+        pos += name_end;
+    }
+
+    if names.is_empty() {
+        return Err(error(pos..pos, ParseErrorKind::MissingHostname));
+    }
+
+    Ok(Some(HostEntry { ip, names }))
+}
+
+/// Parse hosts file contents, rejecting anything outside of the grammar in the crate docs.
+pub fn parse_str_strict(input: &str) -> Result<Vec<HostEntry>, ParseError> {
+    let mut entries = Vec::new();
+    for (idx, line) in input.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(entry) = parse_line_strict(line, idx + 1)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Check every line of `input` against the grammar in the crate docs, returning an error for
+/// each line outside of it rather than stopping at the first, so that they can all be fixed
+/// in one pass. Valid input gives no errors.
+pub fn validate_str(input: &str) -> Vec<ParseError> {
+    input
+        .split('\n')
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            parse_line_strict(line, idx + 1).err()
+        })
+        .collect()
+}
+
+/// Check every line of the file at `path`, see [`validate_str`]. Fails only if the file can't
+/// be read.
+pub fn validate_file(path: &Path) -> Result<Vec<ParseError>, HostfileError> {
+    Ok(validate_str(&read_file(path)?))
+}
+
+/// Parse a file, rejecting anything outside of the grammar in the crate docs.
+///
+/// Use [`parse_str_strict`] to get a structured [`ParseError`].
+pub fn parse_file_strict(path: &Path) -> Result<Vec<HostEntry>, HostfileError> {
+    let contents = read_file(path)?;
+    parse_str_strict(&contents).map_err(|err| HostfileError::Other(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn entry(ip: [u8; 4], names: &[&str]) -> HostEntry {
+        HostEntry {
+            ip: IpAddr::V4(Ipv4Addr::from(ip)),
+            names: names.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn strict_accepts_grammar() {
+        assert_eq!(
+            parse_str_strict(
+                "# comment\r\n\
+                 \t\r\n\
+                 127.0.0.1\tlocalhost\r\n\
+                 \t10.0.0.1 web-1.example.com web # trailing comment\n\
+                 10.0.0.2 db   \n"
+            ),
+            Ok(vec![
+                entry([127, 0, 0, 1], &["localhost"]),
+                entry([10, 0, 0, 1], &["web-1.example.com", "web"]),
+                entry([10, 0, 0, 2], &["db"]),
+            ])
+        );
+    }
+
+    #[test]
+    fn strict_rejects_bad_name_chars() {
+        let err = parse_str_strict("# header\n1.1.1.5 skip_blank_line\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.span, 12..13);
+        assert_eq!(err.kind, ParseErrorKind::InvalidHostnameChar('_'));
+        assert_eq!(
+            err.to_string(),
+            "Invalid character '_' in hostname at line 2, column 13"
+        );
+
+        // A comment must be separated from the names by whitespace
+        let err = parse_str_strict("127.0.0.1 localhost#comment").unwrap_err();
+        assert_eq!(err.span, 19..20);
+        assert_eq!(err.kind, ParseErrorKind::InvalidHostnameChar('#'));
+    }
+
+    #[test]
+    fn strict_whitespace_rules() {
+        let err = parse_str_strict("127.0.0.1localhost").unwrap_err();
+        assert_eq!(
+            (err.span, err.kind),
+            (9..10, ParseErrorKind::MissingWhitespace)
+        );
+
+        // Only spaces and tabs are whitespace
+        let err = parse_str_strict("127.0.0.1\u{a0}localhost").unwrap_err();
+        assert_eq!(
+            (err.span, err.kind),
+            (9..11, ParseErrorKind::MissingWhitespace)
+        );
+
+        let err = parse_str_strict("127.0.0.1").unwrap_err();
+        assert_eq!(
+            (err.span, err.kind),
+            (9..9, ParseErrorKind::MissingHostname)
+        );
+
+        let err = parse_str_strict("127.0.0.1   # no names").unwrap_err();
+        assert_eq!(
+            (err.span, err.kind),
+            (12..12, ParseErrorKind::MissingHostname)
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_line() {
+        let errors = validate_str("127.0.0.1 localhost\nlocalhost\n10.0.0.1 a_b\r\n\n::1\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|err| (err.line, err.kind.code()))
+                .collect::<Vec<_>>(),
+            [
+                (2, crate::ParseErrorCode::InvalidIp),
+                (3, crate::ParseErrorCode::InvalidHostnameChar),
+                (5, crate::ParseErrorCode::MissingHostname),
+            ]
+        );
+        assert!(validate_str("# ok\n::1 localhost\n").is_empty());
+    }
+
+    #[test]
+    fn strict_rejects_bad_ip() {
+        let err = parse_str_strict("127.0.0.1 localhost\nlocalhost myhost").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.span, 0..9);
+        assert!(matches!(err.kind, ParseErrorKind::InvalidIp(_)));
+
+        let err = parse_str_strict("  127.0.0 localhost").unwrap_err();
+        assert_eq!(err.span, 2..9);
+    }
+}
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::ip_len;
+
+/// The kind of a [`Token`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TokenKind {
+    /// Spaces, tabs or other non-newline whitespace
+    Whitespace,
+    /// The IP address at the start of an entry
+    Ip,
+    /// A hostname following the IP address
+    Name,
+    /// A `#` and everything after it up to the end of the line
+    Comment,
+    /// `\n` or `\r\n`
+    Newline,
+    /// Text that could not be parsed; always runs to the end of the line
+    Error,
+}
+
+/// A token of a hosts file along with its byte range in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range<usize>,
+}
+
+/// A single line of a [`SyntaxTree`], including its trailing newline if there is one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxLine {
+    /// Byte range of the whole line in the source
+    pub range: Range<usize>,
+    pub tokens: Vec<Token>,
+}
+
+impl SyntaxLine {
+    fn find(&self, kind: TokenKind) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.kind == kind)
+    }
+
+    /// The IP address token, if this line is an entry
+    pub fn ip(&self) -> Option<&Token> {
+        self.find(TokenKind::Ip)
+    }
+
+    /// The hostname tokens of this line
+    pub fn names(&self) -> impl Iterator<Item = &Token> {
+        self.tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Name)
+    }
+
+    /// The comment token, either a comment line or a trailing comment after an entry
+    pub fn comment(&self) -> Option<&Token> {
+        self.find(TokenKind::Comment)
+    }
+
+    /// The error token, if part of this line could not be parsed
+    pub fn error(&self) -> Option<&Token> {
+        self.find(TokenKind::Error)
+    }
+}
+
+/// A lossless concrete syntax tree of a hosts file.
+///
+/// Every byte of the source belongs to exactly one token, so concatenating the text of all
+/// tokens (which is what the `Display` implementation does) reproduces the source exactly.
+/// Lines that cannot be parsed are kept with an [`TokenKind::Error`] token rather than
+/// rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'a> {
+    source: &'a str,
+    lines: Vec<SyntaxLine>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// The source this tree was parsed from
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub fn lines(&self) -> &[SyntaxLine] {
+        &self.lines
+    }
+
+    /// All tokens in source order
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.lines.iter().flat_map(|line| line.tokens.iter())
+    }
+
+    /// The source text covered by `token`
+    pub fn text(&self, token: &Token) -> &'a str {
+        &self.source[token.range.clone()]
+    }
+}
+
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(self.text(token))?;
+        }
+        Ok(())
+    }
+}
+
+/// End of the line starting at `start`, including the newline
+fn line_end(input: &str, start: usize) -> usize {
+    input[start..]
+        .find('\n')
+        .map_or(input.len(), |idx| start + idx + 1)
+}
+
+/// Parse `input` into a [`SyntaxTree`]. This never fails; invalid lines contain an error token.
+pub fn parse_lossless(input: &str) -> SyntaxTree<'_> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < input.len() {
+        let end = line_end(input, start);
+        let mut tokens = Vec::new();
+        lex_line(input, start..end, &mut tokens);
+        lines.push(SyntaxLine {
+            range: start..end,
+            tokens,
+        });
+        start = end;
+    }
+
+    SyntaxTree {
+        source: input,
+        lines,
+    }
+}
+
+/// Iterator over the tokens of a hosts file, created by [`tokenize`]
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    input: &'a str,
+    pos: usize,
+    line: Vec<Token>,
+    next_idx: usize,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = (TokenKind, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_idx == self.line.len() {
+            if self.pos >= self.input.len() {
+                return None;
+            }
+            let end = line_end(self.input, self.pos);
+            self.line.clear();
+            self.next_idx = 0;
+            lex_line(self.input, self.pos..end, &mut self.line);
+            self.pos = end;
+        }
+
+        let token = &self.line[self.next_idx];
+        self.next_idx += 1;
+        Some((token.kind, token.range.clone()))
+    }
+}
+
+/// Lazily split `input` into tokens with their byte ranges, e.g. for syntax highlighting.
+///
+/// This yields the same tokens as [`parse_lossless`] without building a [`SyntaxTree`]; only
+/// the current line is buffered.
+pub fn tokenize(input: &str) -> Tokens<'_> {
+    Tokens {
+        input,
+        pos: 0,
+        line: Vec::new(),
+        next_idx: 0,
+    }
+}
+
+/// Length of the run of whitespace at the start of `input`
+fn ws_len(input: &str) -> usize {
+    input
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(input.len())
+}
+
+/// Tokenize the line at `range` in `input`, which includes the trailing newline if present.
+///
+/// The tokens follow the lenient interpretation used by [`crate::parse_file`].
+pub(crate) fn lex_line(input: &str, range: Range<usize>, tokens: &mut Vec<Token>) {
+    let line = &input[range.clone()];
+    let content_len = if line.ends_with("\r\n") {
+        line.len() - 2
+    } else if line.ends_with('\n') {
+        line.len() - 1
+    } else {
+        line.len()
+    };
+    let content = &line[..content_len];
+    let mut push = |kind, start: usize, end: usize| {
+        if start < end {
+            tokens.push(Token {
+                kind,
+                range: range.start + start..range.start + end,
+            });
+        }
+    };
+
+    let mut pos = ws_len(content);
+    push(TokenKind::Whitespace, 0, pos);
+
+    if pos < content_len && !content[pos..].starts_with('#') {
+        let ip_end = pos + ip_len(&content[pos..]);
+        let valid_ip = content[pos..ip_end].parse::<std::net::IpAddr>().is_ok();
+        let followed_by_ws = matches!(content[ip_end..].chars().next(), Some(' ') | Some('\t'));
+        if valid_ip && followed_by_ws {
+            push(TokenKind::Ip, pos, ip_end);
+            pos = ip_end;
+        } else {
+            push(TokenKind::Error, pos, content_len);
+            pos = content_len;
+        }
+    }
+
+    while pos < content_len {
+        let ws_end = pos + ws_len(&content[pos..]);
+        push(TokenKind::Whitespace, pos, ws_end);
+        pos = ws_end;
+        if pos == content_len {
+            break;
+        }
+
+        if content[pos..].starts_with('#') {
+            push(TokenKind::Comment, pos, content_len);
+            pos = content_len;
+        } else {
+            let name_end = content[pos..]
+                .find(char::is_whitespace)
+                .map_or(content_len, |idx| pos + idx);
+            push(TokenKind::Name, pos, name_end);
+            pos = name_end;
+        }
+    }
+
+    push(TokenKind::Newline, content_len, line.len());
+}
+
+/// The byte range of the comment after the entry on `line`, including the `#`
+pub(crate) fn comment_range(line: &str) -> Option<Range<usize>> {
+    let mut tokens = Vec::new();
+    lex_line(line, 0..line.len(), &mut tokens);
+    if !tokens.iter().any(|token| token.kind == TokenKind::Ip) {
+        return None;
+    }
+    tokens
+        .into_iter()
+        .find(|token| token.kind == TokenKind::Comment)
+        .map(|token| token.range)
+}
+
+/// The text of the comment after the entry on `line`, without the `#` and surrounding
+/// whitespace
+pub(crate) fn inline_comment(line: &str) -> Option<&str> {
+    comment_range(line).map(|range| line[range.start + 1..range.end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_text<'a>(tree: &SyntaxTree<'a>) -> Vec<(TokenKind, &'a str)> {
+        tree.tokens()
+            .map(|token| (token.kind, tree.text(token)))
+            .collect()
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        let inputs = [
+            "",
+            "\n",
+            "# comment only",
+            "127.0.0.1 localhost",
+            "  127.0.0.1\t localhost  home # comment\r\n::1 localhost\n\n",
+            "127.0.0.1localhost\n  junk line\r\n\t\n",
+            "1.2.3.4 \u{a0}name\u{a0}\n",
+            "\r\n\r\r\n",
+        ];
+        for input in inputs.iter() {
+            let tree = parse_lossless(input);
+            assert_eq!(tree.to_string(), *input);
+
+            let mut expected_start = 0;
+            for token in tree.tokens() {
+                assert_eq!(token.range.start, expected_start);
+                assert!(token.range.start < token.range.end);
+                expected_start = token.range.end;
+            }
+            assert_eq!(expected_start, input.len());
+        }
+    }
+
+    #[test]
+    fn lossless_tokens() {
+        let tree = parse_lossless("  127.0.0.1\tlocalhost home # comment\r\n# c\n");
+        assert_eq!(
+            kinds_and_text(&tree),
+            vec![
+                (TokenKind::Whitespace, "  "),
+                (TokenKind::Ip, "127.0.0.1"),
+                (TokenKind::Whitespace, "\t"),
+                (TokenKind::Name, "localhost"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::Name, "home"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::Comment, "# comment"),
+                (TokenKind::Newline, "\r\n"),
+                (TokenKind::Comment, "# c"),
+                (TokenKind::Newline, "\n"),
+            ]
+        );
+
+        let line = &tree.lines()[0];
+        assert_eq!(line.range, 0..38);
+        assert_eq!(tree.text(line.ip().unwrap()), "127.0.0.1");
+        assert_eq!(
+            line.names().map(|name| tree.text(name)).collect::<Vec<_>>(),
+            vec!["localhost", "home"]
+        );
+        assert_eq!(tree.text(line.comment().unwrap()), "# comment");
+        assert!(tree.lines()[1].ip().is_none());
+    }
+
+    #[test]
+    fn tokenize_matches_lossless() {
+        let input = "# hosts\n127.0.0.1 localhost # c\r\n\nbad line\n\t::1\tip6-localhost";
+        let tree = parse_lossless(input);
+        let expected: Vec<_> = tree
+            .tokens()
+            .map(|token| (token.kind, token.range.clone()))
+            .collect();
+        assert_eq!(tokenize(input).collect::<Vec<_>>(), expected);
+        assert_eq!(tokenize("").next(), None);
+    }
+
+    #[test]
+    fn lossless_errors() {
+        let tree = parse_lossless("127.0.0.1localhost\n127.0.0 x # c\n  ::1");
+        assert_eq!(
+            kinds_and_text(&tree),
+            vec![
+                (TokenKind::Error, "127.0.0.1localhost"),
+                (TokenKind::Newline, "\n"),
+                (TokenKind::Error, "127.0.0 x # c"),
+                (TokenKind::Newline, "\n"),
+                (TokenKind::Whitespace, "  "),
+                (TokenKind::Error, "::1"),
+            ]
+        );
+        assert!(tree.lines()[0].error().is_some());
+    }
+}
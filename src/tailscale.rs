@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{HostEntry, HostfileError};
+
+/// The parts of `tailscale status --json` that name nodes
+#[derive(Deserialize)]
+struct Status {
+    #[serde(rename = "Self")]
+    this: Option<Node>,
+    #[serde(rename = "Peer", default)]
+    peers: BTreeMap<String, Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(rename = "HostName", default)]
+    host_name: String,
+    #[serde(rename = "DNSName", default)]
+    dns_name: String,
+    #[serde(rename = "TailscaleIPs", default)]
+    ips: Vec<IpAddr>,
+}
+
+impl Node {
+    /// The MagicDNS name of the node followed by its short hostname
+    fn names(&self) -> Vec<String> {
+        let dns_name = self.dns_name.trim_end_matches('.');
+        let short = match dns_name.split('.').next() {
+            Some(label) if !label.is_empty() => label.to_string(),
+            _ => self.host_name.to_ascii_lowercase(),
+        };
+        let mut names = Vec::new();
+        if !dns_name.is_empty() && dns_name != short {
+            names.push(dns_name.to_string());
+        }
+        if !short.is_empty() {
+            names.push(short);
+        }
+        names
+    }
+}
+
+/// Convert the output of `tailscale status --json` into hosts entries.
+///
+/// Each node of the tailnet, including this one, gets an entry per Tailscale address naming
+/// it by its MagicDNS name and its short hostname, such as
+/// `100.64.0.2 laptop.tailnet-1234.ts.net laptop`. This gives stable names where MagicDNS
+/// is disabled.
+pub fn parse_tailscale_status(json: &str) -> Result<Vec<HostEntry>, HostfileError> {
+    let status: Status = serde_json::from_str(json)
+        .map_err(|err| format!("Could not parse tailscale status: {err}"))?;
+    let mut nodes: Vec<Node> = status.this.into_iter().collect();
+    nodes.extend(status.peers.into_values());
+    nodes.sort_by(|a, b| a.dns_name.cmp(&b.dns_name));
+
+    let mut entries = Vec::new();
+    for node in nodes {
+        let names = node.names();
+        if names.is_empty() {
+            continue;
+        }
+        for ip in node.ips {
+            entries.push(HostEntry {
+                ip,
+                names: names.clone(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Run `tailscale status --json` and convert its output with [`parse_tailscale_status`]
+pub fn import_tailscale() -> Result<Vec<HostEntry>, HostfileError> {
+    let output = Command::new("tailscale")
+        .args(["status", "--json"])
+        .output()
+        .map_err(|err| format!("Could not run tailscale: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "tailscale status failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    parse_tailscale_status(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_and_self() {
+        let json = r#"{
+            "Version": "1.76.0",
+            "MagicDNSSuffix": "tailnet-1234.ts.net",
+            "Self": {
+                "HostName": "Desktop",
+                "DNSName": "desktop.tailnet-1234.ts.net.",
+                "TailscaleIPs": ["100.64.0.1", "fd7a:115c:a1e0::1"]
+            },
+            "Peer": {
+                "nodekey:abc": {
+                    "HostName": "My Laptop",
+                    "DNSName": "my-laptop.tailnet-1234.ts.net.",
+                    "TailscaleIPs": ["100.64.0.2"],
+                    "Online": false
+                },
+                "nodekey:def": {
+                    "HostName": "unnamed",
+                    "DNSName": "",
+                    "TailscaleIPs": ["100.64.0.3"]
+                }
+            }
+        }"#;
+        let entries = parse_tailscale_status(json).unwrap();
+        let expected: Vec<HostEntry> = vec![
+            "100.64.0.3 unnamed".parse().unwrap(),
+            "100.64.0.1 desktop.tailnet-1234.ts.net desktop"
+                .parse()
+                .unwrap(),
+            "fd7a:115c:a1e0::1 desktop.tailnet-1234.ts.net desktop"
+                .parse()
+                .unwrap(),
+            "100.64.0.2 my-laptop.tailnet-1234.ts.net my-laptop"
+                .parse()
+                .unwrap(),
+        ];
+        assert_eq!(entries, expected);
+        assert!(parse_tailscale_status("[]").is_err());
+    }
+}
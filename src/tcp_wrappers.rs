@@ -0,0 +1,348 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{get_hostfile_path, hostname_eq, line_error, read_file, HostfileError};
+
+/// A rule from `/etc/hosts.allow` or `/etc/hosts.deny`, as described in
+/// `man hosts_access(5)` and `man hosts_options(5)`:
+/// `daemon_list : client_list [ : option : option ... ]`.
+///
+/// The lists are kept as written, split on commas and whitespace, including any `EXCEPT`
+/// operators. Options are kept as written apart from unescaping `\:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRule {
+    pub daemons: Vec<String>,
+    pub clients: Vec<String>,
+    pub options: Vec<String>,
+}
+
+impl AccessRule {
+    /// Whether this rule applies to `client` connecting to `daemon`, see
+    /// [`TcpWrappers::is_client_allowed`]
+    pub fn matches(&self, daemon: &str, client: &str) -> bool {
+        let client = match client.parse() {
+            Ok(ip) => Client::Addr(ip),
+            Err(_) => Client::Name(client),
+        };
+        list_match(&self.daemons, &|pattern| daemon_match(pattern, daemon))
+            && list_match(&self.clients, &|pattern| client_match(pattern, &client))
+    }
+
+    /// The access this rule's `allow` or `deny` option grants, overriding the file it is in
+    fn access_option(&self) -> Option<bool> {
+        self.options
+            .iter()
+            .find_map(|option| match option.to_ascii_lowercase().as_str() {
+                "allow" => Some(true),
+                "deny" => Some(false),
+                _ => None,
+            })
+    }
+}
+
+impl FromStr for AccessRule {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = split_fields(s);
+        if fields.len() < 2 {
+            return Err(
+                String::from("Expected a daemon list and a client list separated by ':'").into(),
+            );
+        }
+        let list = |field: &str| -> Vec<String> {
+            field
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|pattern| !pattern.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let rule = AccessRule {
+            daemons: list(&fields[0]),
+            clients: list(&fields[1]),
+            options: fields[2..]
+                .iter()
+                .map(|option| option.trim().to_string())
+                .collect(),
+        };
+        if rule.daemons.is_empty() {
+            return Err("Expected a daemon list".into());
+        }
+        if rule.clients.is_empty() {
+            return Err("Expected a client list".into());
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for AccessRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : {}", self.daemons.join(" "), self.clients.join(" "))?;
+        for option in &self.options {
+            write!(f, " : {}", option.replace(':', "\\:"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The `:` separated fields of a rule, with `\:` unescaped. The colons of bracketed IPv6
+/// addresses don't separate fields.
+fn split_fields(rule: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut in_brackets = false;
+    let mut chars = rule.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&':') => {
+                fields.last_mut().unwrap().push(':');
+                chars.next();
+            }
+            ':' if !in_brackets => fields.push(String::new()),
+            c => {
+                match c {
+                    '[' => in_brackets = true,
+                    ']' => in_brackets = false,
+                    _ => {}
+                }
+                fields.last_mut().unwrap().push(c);
+            }
+        }
+    }
+    fields
+}
+
+/// Whether `patterns` match, where `A EXCEPT B` matches what `A` matches unless `B` does
+fn list_match(patterns: &[String], matches: &dyn Fn(&str) -> bool) -> bool {
+    match patterns.iter().position(|p| p == "EXCEPT") {
+        Some(idx) => {
+            patterns[..idx].iter().any(|p| matches(p)) && !list_match(&patterns[idx + 1..], matches)
+        }
+        None => patterns.iter().any(|p| matches(p)),
+    }
+}
+
+/// A client as far as it is known without looking anything up
+enum Client<'a> {
+    Name(&'a str),
+    Addr(IpAddr),
+}
+
+fn daemon_match(pattern: &str, daemon: &str) -> bool {
+    pattern == "ALL" || wildcard_match(pattern, daemon)
+}
+
+fn client_match(pattern: &str, client: &Client<'_>) -> bool {
+    if pattern == "ALL" {
+        return true;
+    }
+    match client {
+        Client::Name(name) => match pattern {
+            "LOCAL" => !name.contains('.'),
+            _ if pattern.starts_with('.') => {
+                let suffix = name.len().wrapping_sub(pattern.len());
+                name.is_char_boundary(suffix) && hostname_eq(&name[suffix..], pattern)
+            }
+            _ if pattern.contains(['*', '?']) => wildcard_match(pattern, name),
+            _ => hostname_eq(pattern, name),
+        },
+        Client::Addr(ip) => {
+            let text = ip.to_string();
+            if pattern.ends_with('.') {
+                ip.is_ipv4() && text.starts_with(pattern)
+            } else if let Some((net, mask)) = pattern.split_once('/') {
+                net_match(net, mask, *ip)
+            } else if pattern.contains(['*', '?']) {
+                wildcard_match(pattern, &text)
+            } else {
+                strip_brackets(pattern).parse() == Ok(*ip)
+            }
+        }
+    }
+}
+
+/// Whether `ip` is in the network `net/mask`, where the mask is a prefix length or, for
+/// IPv4, a netmask like `255.255.255.0`
+fn net_match(net: &str, mask: &str, ip: IpAddr) -> bool {
+    let net: IpAddr = match strip_brackets(net).parse() {
+        Ok(net) => net,
+        Err(_) => return false,
+    };
+    let bits = match (net, mask.parse::<u8>(), mask.parse::<Ipv4Addr>()) {
+        (_, Ok(len), _) => len,
+        (IpAddr::V4(_), _, Ok(mask)) => u32::from(mask).count_ones() as u8,
+        _ => return false,
+    };
+    match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) if bits <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(bits)).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) if bits <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(bits)).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// `addr` without the brackets IPv6 addresses are written in
+fn strip_brackets(addr: &str) -> &str {
+    addr.strip_prefix('[')
+        .and_then(|addr| addr.strip_suffix(']'))
+        .unwrap_or(addr)
+}
+
+/// Whether all of `text` matches `pattern`, where `*` matches any run of characters and `?`
+/// any one character, ignoring ASCII case
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    match pattern.chars().next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            text.char_indices()
+                .map(|(idx, _)| idx)
+                .chain(Some(text.len()))
+                .any(|idx| wildcard_match(rest, &text[idx..]))
+        }
+        Some(p) => match text.chars().next() {
+            Some(c) if p == '?' || p.eq_ignore_ascii_case(&c) => {
+                wildcard_match(&pattern[p.len_utf8()..], &text[c.len_utf8()..])
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Parse the contents of a `hosts.allow` or `hosts.deny` file. Lines ending with a
+/// backslash continue on the next line.
+pub fn parse_access_str(input: &str) -> Result<Vec<AccessRule>, HostfileError> {
+    let mut rules = Vec::new();
+    let mut rule = String::new();
+    let mut start = 0;
+    for (idx, line) in input.lines().enumerate() {
+        if rule.is_empty() {
+            start = idx + 1;
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+        }
+        if let Some(line) = line.strip_suffix('\\') {
+            rule.push_str(line);
+            rule.push(' ');
+            continue;
+        }
+        rule.push_str(line);
+        if !rule.trim().is_empty() {
+            let parsed = rule
+                .parse()
+                .map_err(|err| line_error(err, start, rule.as_bytes()))?;
+            rules.push(parsed);
+        }
+        rule.clear();
+    }
+    Ok(rules)
+}
+
+/// Parse a `hosts.allow` or `hosts.deny` file, see [`parse_access_str`]
+pub fn parse_access_file(path: &Path) -> Result<Vec<AccessRule>, HostfileError> {
+    parse_access_str(&read_file(path)?)
+}
+
+/// The access control of TCP wrappers, from the rules of `hosts.allow` and `hosts.deny`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TcpWrappers {
+    pub allow: Vec<AccessRule>,
+    pub deny: Vec<AccessRule>,
+}
+
+impl TcpWrappers {
+    pub fn new(allow: Vec<AccessRule>, deny: Vec<AccessRule>) -> Self {
+        TcpWrappers { allow, deny }
+    }
+
+    /// Read the system's `hosts.allow` and `hosts.deny`, which are next to the hosts file. A
+    /// file that doesn't exist has no rules.
+    pub fn read() -> Result<Self, HostfileError> {
+        let read = |path: PathBuf| {
+            if path.exists() {
+                parse_access_file(&path)
+            } else {
+                Ok(Vec::new())
+            }
+        };
+        let hosts = get_hostfile_path()?;
+        Ok(TcpWrappers {
+            allow: read(hosts.with_file_name("hosts.allow"))?,
+            deny: read(hosts.with_file_name("hosts.deny"))?,
+        })
+    }
+
+    /// Whether `client`, an address or a hostname, may connect to `daemon`, the name of the
+    /// server's executable like `sshd`.
+    ///
+    /// The first rule of `hosts.allow` that matches grants access and, failing that, the
+    /// first rule of `hosts.deny` that matches refuses it. Access is granted if neither does.
+    /// An `allow` or `deny` option overrides the file the rule is in.
+    ///
+    /// Nothing is looked up, so address patterns only match addresses and name patterns only
+    /// match names. Patterns that need lookups or other information (`KNOWN`, `UNKNOWN`,
+    /// `PARANOID`, `@netgroup`, `user@host`, `daemon@host` and `/file`) never match.
+    pub fn is_client_allowed(&self, daemon: &str, client: &str) -> bool {
+        if let Some(rule) = self.allow.iter().find(|rule| rule.matches(daemon, client)) {
+            return rule.access_option().unwrap_or(true);
+        }
+        if let Some(rule) = self.deny.iter().find(|rule| rule.matches(daemon, client)) {
+            return rule.access_option().unwrap_or(false);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_rules() {
+        let allow = parse_access_str(
+            "# hosts.allow\n\
+             sshd, in.ftpd: 192.168.1. [fd00::]/64 .example.com EXCEPT bad.example.com\n\
+             ALL : 10.0.0.0/255.0.0.0 : spawn /bin/echo %h\\: connected \\\n\
+             \t: deny\n\
+             ALL: LOCAL\n\
+             imap*: mail?.corp\n",
+        )
+        .unwrap();
+        assert_eq!(allow.len(), 4);
+        assert_eq!(allow[0].daemons, ["sshd", "in.ftpd"]);
+        assert_eq!(allow[1].options, ["spawn /bin/echo %h: connected", "deny"]);
+        assert_eq!(
+            allow[1].to_string(),
+            "ALL : 10.0.0.0/255.0.0.0 : spawn /bin/echo %h\\: connected : deny"
+        );
+
+        let deny = parse_access_str("ALL: ALL\n").unwrap();
+        let wrappers = TcpWrappers::new(allow, deny);
+        assert!(wrappers.is_client_allowed("sshd", "192.168.1.20"));
+        assert!(wrappers.is_client_allowed("sshd", "fd00::5"));
+        assert!(wrappers.is_client_allowed("in.ftpd", "www.Example.com"));
+        assert!(!wrappers.is_client_allowed("sshd", "bad.example.com"));
+        assert!(!wrappers.is_client_allowed("httpd", "192.168.1.20"));
+        assert!(!wrappers.is_client_allowed("sshd", "10.1.2.3"));
+        assert!(wrappers.is_client_allowed("httpd", "printer"));
+        assert!(wrappers.is_client_allowed("imapd", "mail1.corp"));
+        assert!(!wrappers.is_client_allowed("imapd", "mail10.corp"));
+        assert!(TcpWrappers::default().is_client_allowed("sshd", "1.2.3.4"));
+
+        let err = parse_access_str("\nsshd 10.0.0.1\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Expected a daemon list and a client list separated by ':' at line 2 with \
+             content: 'sshd 10.0.0.1'"
+        );
+    }
+}
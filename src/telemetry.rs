@@ -0,0 +1,119 @@
+//! Metrics recorded with the `metrics` crate, so daemons can export them with any exporter,
+//! e.g. `metrics-exporter-prometheus`. Every metric has a `path` label with the watched file.
+
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use metrics::{counter, describe_counter, describe_gauge, gauge, Unit};
+
+pub const ENTRIES: &str = "hostfile_entries";
+pub const PARSE_ERRORS: &str = "hostfile_parse_errors";
+pub const RELOADS: &str = "hostfile_reloads_total";
+pub const READ_ERRORS: &str = "hostfile_read_errors_total";
+pub const LAST_MODIFIED: &str = "hostfile_last_modified_seconds";
+pub const WRITES: &str = "hostfile_writes_total";
+
+/// Register descriptions of the metrics with the installed recorder
+pub fn describe_metrics() {
+    describe_gauge!(ENTRIES, "Number of valid entries in the hosts file");
+    describe_gauge!(
+        PARSE_ERRORS,
+        "Number of lines of the hosts file that can't be parsed"
+    );
+    describe_counter!(
+        RELOADS,
+        "Number of times a changed hosts file was read again"
+    );
+    describe_counter!(
+        READ_ERRORS,
+        "Number of times the hosts file couldn't be read"
+    );
+    describe_gauge!(
+        LAST_MODIFIED,
+        Unit::Seconds,
+        "Modification time of the hosts file as a Unix timestamp"
+    );
+    describe_counter!(
+        WRITES,
+        "Number of times the hosts file was written, e.g. to enforce its contents"
+    );
+}
+
+fn path_label(path: &Path) -> String {
+    path.display().to_string()
+}
+
+/// Record the state of the file at `path` after it was (re)read
+pub(crate) fn record_read(path: &Path, entries: usize, parse_errors: usize, reload: bool) {
+    let label = path_label(path);
+    gauge!(ENTRIES, "path" => label.clone()).set(entries as f64);
+    gauge!(PARSE_ERRORS, "path" => label.clone()).set(parse_errors as f64);
+    if reload {
+        counter!(RELOADS, "path" => label.clone()).increment(1);
+    }
+
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+    if let Some(modified) = modified {
+        gauge!(LAST_MODIFIED, "path" => label).set(modified.as_secs_f64());
+    }
+}
+
+pub(crate) fn record_read_error(path: &Path) {
+    counter!(READ_ERRORS, "path" => path_label(path)).increment(1);
+}
+
+pub(crate) fn record_write(path: &Path) {
+    counter!(WRITES, "path" => path_label(path)).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    use super::*;
+    use crate::{write_file, Watcher, WriteOptions};
+
+    #[test]
+    fn watcher_and_writes_record_metrics() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        std::fs::write(&path, "10.0.0.1 web\nbad line\n").unwrap();
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let mut watcher = Watcher::new(&path).unwrap();
+            let entries = vec!["10.0.0.1 web".parse().unwrap(), "::1 db".parse().unwrap()];
+            write_file(&path, &entries, &WriteOptions::new()).unwrap();
+            watcher.poll().unwrap();
+        });
+
+        let values: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+            .collect();
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+        };
+        let gauge = |name: &str| match value(name) {
+            Some(DebugValue::Gauge(value)) => value.0,
+            value => panic!("{} is {:?}", name, value),
+        };
+        assert_eq!(gauge(ENTRIES), 2.0);
+        assert_eq!(gauge(PARSE_ERRORS), 0.0);
+        assert_eq!(value(RELOADS), Some(&DebugValue::Counter(1)));
+        assert_eq!(value(WRITES), Some(&DebugValue::Counter(1)));
+        assert!(gauge(LAST_MODIFIED) > 0.0);
+    }
+}
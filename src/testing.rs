@@ -0,0 +1,144 @@
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use mktemp::Temp;
+
+use crate::{HostEntry, HostfileError, HostsFile};
+
+/// Builds hosts files for tests, a line at a time.
+///
+/// ```
+/// # use hostfile::HostfileFixture;
+/// let hosts = HostfileFixture::new()
+///     .comment("fixture")
+///     .entry("127.0.0.1", ["localhost"])
+///     .entry("10.0.0.1", ["web.test", "www.test"])
+///     .build_tempfile()
+///     .unwrap();
+/// let entries = hostfile::parse_file(&hosts).unwrap();
+/// assert_eq!(entries[1].names, ["web.test", "www.test"]);
+/// // The file and its directory are deleted when `hosts` is dropped
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostfileFixture {
+    contents: String,
+}
+
+impl HostfileFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry mapping `names` to `ip`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ip` isn't an IP address.
+    pub fn entry<I, S>(self, ip: &str, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let ip: IpAddr = ip
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid fixture address '{}': {}", ip, err));
+        let entry = HostEntry {
+            ip,
+            names: names.into_iter().map(Into::into).collect(),
+        };
+        self.line(&entry.to_string())
+    }
+
+    /// Add a `# ` comment line with `text`
+    pub fn comment(self, text: &str) -> Self {
+        self.line(&format!("# {text}"))
+    }
+
+    /// Add `line` as it is, such as a blank line or one that doesn't parse
+    pub fn line(mut self, line: &str) -> Self {
+        self.contents.push_str(line);
+        self.contents.push('\n');
+        self
+    }
+
+    /// The contents of the file
+    pub fn build(&self) -> String {
+        self.contents.clone()
+    }
+
+    /// The file as a document that can be edited
+    pub fn build_hosts_file(&self) -> HostsFile {
+        HostsFile::parse(&self.contents)
+    }
+
+    /// Write the file as `hosts` in a new temporary directory, which is deleted along with the
+    /// file when the returned guard is dropped
+    pub fn build_tempfile(&self) -> Result<TempHostsFile, HostfileError> {
+        let dir = Temp::new_dir().map_err(|err| format!("Could not create temp dir: {err}"))?;
+        let path = dir.as_path().join("hosts");
+        fs::write(&path, &self.contents)
+            .map_err(|err| format!("Could not write fixture ({:?}): {err}", path))?;
+        Ok(TempHostsFile { path, _dir: dir })
+    }
+}
+
+/// The path of a hosts file written by [`HostfileFixture::build_tempfile`], which is deleted
+/// when this is dropped
+pub struct TempHostsFile {
+    path: PathBuf,
+    _dir: Temp,
+}
+
+impl TempHostsFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Debug for TempHostsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TempHostsFile").field(&self.path).finish()
+    }
+}
+
+impl Deref for TempHostsFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempHostsFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_files() {
+        let fixture = HostfileFixture::new()
+            .comment("test")
+            .entry("::1", vec![String::from("localhost")])
+            .line("")
+            .entry("10.0.0.1", ["a.test"]);
+        assert_eq!(
+            fixture.build(),
+            "# test\n::1\tlocalhost\n\n10.0.0.1\ta.test\n"
+        );
+        assert_eq!(fixture.build_hosts_file().entries().count(), 2);
+
+        let hosts = fixture.build_tempfile().unwrap();
+        let path = hosts.path().to_path_buf();
+        assert_eq!(fs::read_to_string(&hosts).unwrap(), fixture.build());
+        drop(hosts);
+        assert!(!path.exists());
+    }
+}
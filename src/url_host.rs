@@ -0,0 +1,46 @@
+use url::{Host, Url};
+
+use crate::{hostname_eq, HostEntry};
+
+/// The entry of `entries` that the host of `url` resolves to instead of going to DNS, or
+/// `None` if it isn't overridden.
+///
+/// This is the first entry with the host's name, which is the one resolvers use. The port,
+/// credentials and path of `url` don't matter, and hosts that are IP addresses, such as
+/// `http://[::1]:8080/`, are never overridden. A trailing dot marking an absolute name is
+/// ignored, so `http://web.example./` is overridden like `http://web.example/`.
+pub fn overrides_url<'a>(entries: &'a [HostEntry], url: &Url) -> Option<&'a HostEntry> {
+    let host = match url.host()? {
+        Host::Domain(host) => host,
+        Host::Ipv4(_) | Host::Ipv6(_) => return None,
+    };
+    let host = host.strip_suffix('.').unwrap_or(host);
+    entries
+        .iter()
+        .find(|entry| entry.names.iter().any(|name| hostname_eq(name, host)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_hosts() {
+        let entries: Vec<HostEntry> = ["10.0.0.1 web.example api", "10.0.0.2 web.example"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let overridden = |url: &str| {
+            overrides_url(&entries, &Url::parse(url).unwrap()).map(|entry| entry.ip.to_string())
+        };
+        assert_eq!(
+            overridden("https://user:pw@WEB.example.:8443/path?q#f").as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(overridden("ws://api/socket").as_deref(), Some("10.0.0.1"));
+        assert_eq!(overridden("http://other.example/"), None);
+        assert_eq!(overridden("http://10.0.0.1/"), None);
+        assert_eq!(overridden("http://[::1]:8080/"), None);
+        assert_eq!(overridden("mailto:web.example"), None);
+    }
+}
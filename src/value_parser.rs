@@ -0,0 +1,190 @@
+use std::ffi::OsStr;
+use std::net::IpAddr;
+
+use clap::builder::{TypedValueParser, ValueParserFactory};
+use clap::error::ErrorKind;
+use clap::{Arg, Command};
+
+use crate::strict::is_name_char;
+use crate::{HostEntry, HostfileError};
+
+/// Parses command line arguments into [`HostEntry`]s, so that other tools can take entries
+/// as options. This is the value parser of `HostEntry` arguments declared with clap's derive
+/// API.
+///
+/// Entries are written as an address and names separated by a colon, with commas between the
+/// names, like `10.0.0.5:api.local` or `[fd00::5]:api.local,api`. The brackets around IPv6
+/// addresses are optional. A hosts file line like `"10.0.0.5 api.local api"` works too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostEntryParser;
+
+/// Parses command line arguments into hostnames, rejecting the characters the strict parser
+/// rejects, see [`crate::parse_str_strict`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostNameParser;
+
+impl HostEntryParser {
+    pub fn new() -> Self {
+        HostEntryParser
+    }
+}
+
+impl HostNameParser {
+    pub fn new() -> Self {
+        HostNameParser
+    }
+}
+
+impl TypedValueParser for HostEntryParser {
+    type Value = HostEntry;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<HostEntry, clap::Error> {
+        let value = utf8(cmd, arg, value)?;
+        parse_entry_arg(value).map_err(|err| invalid(cmd, arg, value, &err))
+    }
+}
+
+impl TypedValueParser for HostNameParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<String, clap::Error> {
+        let value = utf8(cmd, arg, value)?;
+        check_name(value).map_err(|err| invalid(cmd, arg, value, &err))?;
+        Ok(value.to_string())
+    }
+}
+
+impl ValueParserFactory for HostEntry {
+    type Parser = HostEntryParser;
+
+    fn value_parser() -> HostEntryParser {
+        HostEntryParser
+    }
+}
+
+/// Parse an entry in the forms [`HostEntryParser`] takes
+fn parse_entry_arg(value: &str) -> Result<HostEntry, String> {
+    if value.trim().contains([' ', '\t']) {
+        let entry: HostEntry = value
+            .parse()
+            .map_err(|err: HostfileError| err.to_string())?;
+        for name in &entry.names {
+            check_name(name)?;
+        }
+        return Ok(entry);
+    }
+
+    let (ip, names) = value
+        .rsplit_once(':')
+        .ok_or_else(|| String::from("expected IP:NAME[,NAME...]"))?;
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(ip);
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|err| format!("'{ip}' is not an IP address: {err}"))?;
+    let names: Vec<String> = names.split(',').map(String::from).collect();
+    for name in &names {
+        check_name(name)?;
+    }
+    Ok(HostEntry { ip, names })
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err(String::from("hostnames can't be empty"));
+    }
+    match name.chars().find(|&c| !is_name_char(c)) {
+        Some(c) => Err(format!("'{c}' is not allowed in hostname '{name}'")),
+        None => Ok(()),
+    }
+}
+
+fn utf8<'a>(cmd: &Command, arg: Option<&Arg>, value: &'a OsStr) -> Result<&'a str, clap::Error> {
+    value
+        .to_str()
+        .ok_or_else(|| invalid(cmd, arg, &value.to_string_lossy(), "not valid UTF-8"))
+}
+
+/// An error like clap's own for `value` of `arg`
+fn invalid(cmd: &Command, arg: Option<&Arg>, value: &str, reason: &str) -> clap::Error {
+    let arg = arg.map_or_else(|| String::from("..."), ToString::to_string);
+    clap::Error::raw(
+        ErrorKind::ValueValidation,
+        format!("invalid value '{value}' for '{arg}': {reason}\n"),
+    )
+    .with_cmd(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::value_parser;
+
+    #[test]
+    fn parse_arguments() {
+        let cmd = Command::new("tool")
+            .arg(
+                Arg::new("host")
+                    .long("host")
+                    .action(clap::ArgAction::Append)
+                    .value_parser(value_parser!(HostEntry)),
+            )
+            .arg(Arg::new("name").long("name").value_parser(HostNameParser));
+        let matches = cmd
+            .clone()
+            .try_get_matches_from([
+                "tool",
+                "--host",
+                "10.0.0.5:api.local",
+                "--host",
+                "[fd00::5]:api,api.local",
+                "--host",
+                "fd00::6:db",
+                "--host",
+                "10.0.0.7 web www # comment",
+                "--name",
+                "box.lan",
+            ])
+            .unwrap();
+        let hosts: Vec<String> = matches
+            .get_many::<HostEntry>("host")
+            .unwrap()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            hosts,
+            [
+                "10.0.0.5\tapi.local",
+                "fd00::5\tapi api.local",
+                "fd00::6\tdb",
+                "10.0.0.7\tweb www"
+            ]
+        );
+        assert_eq!(matches.get_one::<String>("name").unwrap(), "box.lan");
+
+        let err = |args: &[&str]| {
+            let mut argv = vec!["tool"];
+            argv.extend(args);
+            cmd.clone()
+                .try_get_matches_from(argv)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err(&["--host", "api.local"]).contains("expected IP:NAME"));
+        assert!(err(&["--host", "10.0.0:api"]).contains("not an IP address"));
+        assert!(err(&["--host", "10.0.0.5:a,"]).contains("can't be empty"));
+        assert!(err(&["--name", "a_b"]).contains("invalid value 'a_b' for '--name <name>'"));
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::{hostname_key, read_file, HostEntry, HostfileError, HostsFile};
+
+/// A change to the addresses of a hostname between two versions of a hosts file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WatchEvent {
+    /// `name` was added with `addrs`
+    EntryAdded { name: String, addrs: Vec<IpAddr> },
+    /// `name`, which had `addrs`, was removed
+    EntryRemoved { name: String, addrs: Vec<IpAddr> },
+    /// The addresses of `name` changed from `old` to `new`
+    IpChanged {
+        name: String,
+        old: Vec<IpAddr>,
+        new: Vec<IpAddr>,
+    },
+}
+
+impl WatchEvent {
+    /// The hostname this event is about
+    pub fn name(&self) -> &str {
+        match self {
+            WatchEvent::EntryAdded { name, .. }
+            | WatchEvent::EntryRemoved { name, .. }
+            | WatchEvent::IpChanged { name, .. } => name,
+        }
+    }
+}
+
+/// Names keyed by [`hostname_key`], with the name as first written and its addresses
+type NameAddrs<'a> = HashMap<String, (&'a str, Vec<IpAddr>)>;
+
+/// The addresses of each name in `entries`, along with the keys in order of first appearance
+fn addrs_by_name(entries: &[HostEntry]) -> (Vec<String>, NameAddrs<'_>) {
+    let mut order = Vec::new();
+    let mut addrs = NameAddrs::new();
+    for entry in entries {
+        for name in &entry.names {
+            let key = hostname_key(name).into_owned();
+            let (_, ips) = addrs.entry(key.clone()).or_insert_with(|| {
+                order.push(key);
+                (name, Vec::new())
+            });
+            if !ips.contains(&entry.ip) {
+                ips.push(entry.ip);
+            }
+        }
+    }
+    (order, addrs)
+}
+
+/// The name-level changes from `old` to `new`.
+///
+/// Removed names are reported first in the order of `old`, followed by added and changed
+/// names in the order of `new`. Names are compared with [`crate::hostname_eq`], and only the
+/// addresses of a name matter, not which lines they are on.
+pub fn diff_entries(old: &[HostEntry], new: &[HostEntry]) -> Vec<WatchEvent> {
+    let (old_order, old_addrs) = addrs_by_name(old);
+    let (new_order, new_addrs) = addrs_by_name(new);
+
+    let mut events = Vec::new();
+    for key in &old_order {
+        if !new_addrs.contains_key(key) {
+            let (name, addrs) = &old_addrs[key];
+            events.push(WatchEvent::EntryRemoved {
+                name: name.to_string(),
+                addrs: addrs.clone(),
+            });
+        }
+    }
+    for key in &new_order {
+        let (name, addrs) = &new_addrs[key];
+        match old_addrs.get(key) {
+            None => events.push(WatchEvent::EntryAdded {
+                name: name.to_string(),
+                addrs: addrs.clone(),
+            }),
+            Some((_, old)) if old != addrs => events.push(WatchEvent::IpChanged {
+                name: name.to_string(),
+                old: old.clone(),
+                new: addrs.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    events
+}
+
+/// Watches a hosts file for changes to its entries by polling it.
+///
+/// Polling reads the whole file, which is cheap for hosts files and doesn't miss changes that
+/// keep the modification time. Invalid lines are skipped like [`HostsFile::entries`] does.
+///
+/// With the `metrics` feature, each read records the metrics described in the `telemetry`
+/// module.
+#[derive(Debug)]
+pub struct Watcher {
+    path: PathBuf,
+    contents: String,
+    entries: Vec<HostEntry>,
+}
+
+impl Watcher {
+    /// Start watching the file at `path` from its current contents
+    pub fn new(path: &Path) -> Result<Self, HostfileError> {
+        let contents = read_contents(path)?;
+        let entries = parse_entries(path, &contents, false);
+        Ok(Watcher {
+            path: path.to_path_buf(),
+            contents,
+            entries,
+        })
+    }
+
+    /// The entries of the file as of the last poll
+    pub fn entries(&self) -> &[HostEntry] {
+        &self.entries
+    }
+
+    /// Read the file again and return what changed since the last poll
+    pub fn poll(&mut self) -> Result<Vec<WatchEvent>, HostfileError> {
+        let contents = read_contents(&self.path)?;
+        if contents == self.contents {
+            return Ok(Vec::new());
+        }
+
+        let entries = parse_entries(&self.path, &contents, true);
+        let events = diff_entries(&self.entries, &entries);
+        self.contents = contents;
+        self.entries = entries;
+        Ok(events)
+    }
+
+    /// Poll the file every `interval` on a background thread, calling `on_change` with the
+    /// events of each poll that found changes, or with the error if the file couldn't be read.
+    ///
+    /// WASI targets without thread support can't spawn the thread, so call [`Watcher::poll`]
+    /// from the application's own loop there.
+    pub fn spawn<F>(mut self, interval: Duration, mut on_change: F) -> WatchHandle
+    where
+        F: FnMut(Result<Vec<WatchEvent>, HostfileError>) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    thread::park_timeout(interval);
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    match self.poll() {
+                        Ok(events) if events.is_empty() => {}
+                        result => on_change(result),
+                    }
+                }
+            })
+        };
+        WatchHandle { stop, thread }
+    }
+}
+
+fn read_contents(path: &Path) -> Result<String, HostfileError> {
+    let result = read_file(path);
+    #[cfg(feature = "metrics")]
+    if result.is_err() {
+        crate::telemetry::record_read_error(path);
+    }
+    result
+}
+
+/// The valid entries of `contents`, recording metrics about the file at `path` that they
+/// were read from
+fn parse_entries(path: &Path, contents: &str, reload: bool) -> Vec<HostEntry> {
+    let entries: Vec<_> = HostsFile::parse(contents).entries().collect();
+    #[cfg(feature = "metrics")]
+    {
+        let tree = crate::parse_lossless(contents);
+        let parse_errors = tree.lines().iter().filter(|line| line.error().is_some());
+        crate::telemetry::record_read(path, entries.len(), parse_errors.count(), reload);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (path, reload);
+    entries
+}
+
+/// A watcher running on a background thread, see [`Watcher::spawn`]
+#[derive(Debug)]
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl WatchHandle {
+    /// Stop watching and wait for the background thread to finish
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use std::fs;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    fn entries(contents: &str) -> Vec<HostEntry> {
+        HostsFile::parse(contents).entries().collect()
+    }
+
+    #[test]
+    fn diff_names() {
+        let old = entries("10.0.0.1 web www\n10.0.0.2 db\n::1 localhost\n");
+        let new = entries("::1 LOCALHOST\n10.0.0.1 www\n10.0.0.3 web\n10.0.0.4 cache\n");
+        assert_eq!(
+            diff_entries(&old, &new),
+            vec![
+                WatchEvent::EntryRemoved {
+                    name: String::from("db"),
+                    addrs: ips(&["10.0.0.2"]),
+                },
+                WatchEvent::IpChanged {
+                    name: String::from("web"),
+                    old: ips(&["10.0.0.1"]),
+                    new: ips(&["10.0.0.3"]),
+                },
+                WatchEvent::EntryAdded {
+                    name: String::from("cache"),
+                    addrs: ips(&["10.0.0.4"]),
+                },
+            ]
+        );
+        assert!(diff_entries(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn watch_in_background() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "10.0.0.1 web\n").unwrap();
+
+        let mut watcher = Watcher::new(&path).unwrap();
+        assert_eq!(watcher.poll(), Ok(Vec::new()));
+        // Changes that don't affect entries produce no events
+        fs::write(&path, "# comment\n10.0.0.1   web\n").unwrap();
+        assert_eq!(watcher.poll(), Ok(Vec::new()));
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = watcher.spawn(Duration::from_millis(10), move |events| {
+            let _ = sender.send(events);
+        });
+        fs::write(&path, "10.0.0.2 web\n").unwrap();
+        let events = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.stop();
+        assert_eq!(
+            events.unwrap(),
+            vec![WatchEvent::IpChanged {
+                name: String::from("web"),
+                old: ips(&["10.0.0.1"]),
+                new: ips(&["10.0.0.2"]),
+            }]
+        );
+    }
+}
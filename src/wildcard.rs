@@ -0,0 +1,129 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::{hostname_key, HostEntries, HostEntry, HostfileError};
+
+/// A rule giving an address to every subdomain of a domain, written as
+/// `*.test.corp -> 10.0.0.5`.
+///
+/// Hosts files have no wildcards, so rules are expanded into entries for concrete names with
+/// [`expand_wildcards`]. Like DNS wildcards, `*.test.corp` matches subdomains at any depth,
+/// such as `a.test.corp` and `a.b.test.corp`, but not `test.corp` itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WildcardRule {
+    /// The domain whose subdomains match, without the `*.`
+    domain: String,
+    ip: IpAddr,
+}
+
+impl WildcardRule {
+    /// A rule for `pattern`, which must start with `*.`
+    pub fn new(pattern: &str, ip: IpAddr) -> Result<Self, HostfileError> {
+        match pattern.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => Ok(WildcardRule {
+                domain: domain.to_string(),
+                ip,
+            }),
+            _ => Err(format!(
+                "Invalid wildcard pattern '{pattern}', expected '*.' followed by a domain"
+            )
+            .into()),
+        }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// Whether `name` is a subdomain of the domain of this rule
+    pub fn matches(&self, name: &str) -> bool {
+        let name = hostname_key(name);
+        let domain = hostname_key(&self.domain);
+        name.len() > domain.len() + 1
+            && name.ends_with(domain.as_ref())
+            && name.as_bytes()[name.len() - domain.len() - 1] == b'.'
+    }
+}
+
+impl FromStr for WildcardRule {
+    type Err = HostfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, ip) = s
+            .split_once("->")
+            .ok_or_else(|| format!("Expected '<pattern> -> <ip>', found '{}'", s.trim()))?;
+        let ip = ip
+            .trim()
+            .parse()
+            .map_err(|err| format!("Couldn't parse a valid IP address: {err}"))?;
+        WildcardRule::new(pattern.trim(), ip)
+    }
+}
+
+impl fmt::Display for WildcardRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "*.{} -> {}", self.domain, self.ip)
+    }
+}
+
+/// Parse wildcard rules, one per line. Blank lines and `#` comments are skipped.
+pub fn parse_wildcard_rules(input: &str) -> Result<Vec<WildcardRule>, HostfileError> {
+    crate::parse_table(input)
+}
+
+/// Generate entries for each of `names` that matches one of `rules`, such as the names seen
+/// in DNS query logs or a zone export.
+///
+/// Each name gets the address of the first rule that matches it, and names that match no
+/// rule are skipped. Names are grouped into one entry per address, see
+/// [`HostEntries`].
+pub fn expand_wildcards<I, S>(rules: &[WildcardRule], names: I) -> Vec<HostEntry>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let name = name.as_ref();
+            let rule = rules.iter().find(|rule| rule.matches(name))?;
+            Some((rule.ip, name.to_string()))
+        })
+        .collect::<HostEntries>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_matching_names() {
+        let rules = parse_wildcard_rules(
+            "# lab\n*.db.test.corp -> 10.0.0.6\n*.test.corp -> 10.0.0.5\n*.v6.corp -> fd00::1\n",
+        )
+        .unwrap();
+        assert_eq!(rules[1].to_string(), "*.test.corp -> 10.0.0.5");
+
+        let names = [
+            "web.test.corp",
+            "test.corp",
+            "pg.db.test.corp",
+            "API.Test.Corp",
+            "web.test.corp",
+            "host.v6.corp",
+            "example.com",
+            "xtest.corp",
+        ];
+        let expected: Vec<HostEntry> = vec![
+            "10.0.0.5 web.test.corp API.Test.Corp".parse().unwrap(),
+            "10.0.0.6 pg.db.test.corp".parse().unwrap(),
+            "fd00::1 host.v6.corp".parse().unwrap(),
+        ];
+        assert_eq!(expand_wildcards(&rules, names), expected);
+
+        assert!("test.corp -> 10.0.0.5".parse::<WildcardRule>().is_err());
+        assert!("*.test.corp 10.0.0.5".parse::<WildcardRule>().is_err());
+    }
+}
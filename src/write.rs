@@ -0,0 +1,1018 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    cloud_init_manages_etc_hosts, get_hostfile_path, io_error, HostEntry, HostfileError,
+    ManageEtcHosts, SyntaxTree,
+};
+
+/// How many backups [`BackupPolicy::new`] keeps
+const DEFAULT_BACKUPS_KEPT: usize = 5;
+
+/// Options for [`write_file`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    backup: Option<BackupPolicy>,
+    in_place: bool,
+    clear_readonly: bool,
+    max_names_per_line: usize,
+    #[cfg(feature = "gzip")]
+    gzip: bool,
+    #[cfg(feature = "elevate")]
+    elevation: Option<crate::Elevation>,
+}
+
+impl WriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Back up the existing file according to `policy` before replacing it
+    pub fn backup(mut self, policy: BackupPolicy) -> Self {
+        self.backup = Some(policy);
+        self
+    }
+
+    /// Truncate and overwrite an existing file instead of renaming a new file over it.
+    ///
+    /// This keeps everything attached to the file itself, such as its owner, ACLs and hard
+    /// links, but readers may see a partially written file and a failed write can leave it
+    /// truncated. Files that don't exist yet are always created by renaming.
+    pub fn in_place(mut self, in_place: bool) -> Self {
+        self.in_place = in_place;
+        self
+    }
+
+    /// On Windows, clear the read-only attribute of the file for the write and set it again
+    /// afterwards. Without this, writing a read-only file fails with
+    /// [`HostfileError::ReadOnly`]. Other platforms have no such attribute, so this does
+    /// nothing there.
+    ///
+    /// Antivirus and admin tools often mark the hosts file read-only to stop it being changed.
+    pub fn clear_readonly(mut self, clear_readonly: bool) -> Self {
+        self.clear_readonly = clear_readonly;
+        self
+    }
+
+    /// Write at most `n` names on each line, continuing longer entries on more lines with the
+    /// same address. `0`, the default, puts every name of an entry on one line.
+    ///
+    /// Some resolvers, notably older Windows ones, ignore names past a per-line limit.
+    pub fn max_names_per_line(mut self, n: usize) -> Self {
+        self.max_names_per_line = n;
+        self
+    }
+
+    /// Compress the file with gzip, for blocklists that are served to or stored by other
+    /// tools rather than read by the resolver
+    #[cfg(feature = "gzip")]
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// If writing fails because we aren't allowed to, retry the write with elevated
+    /// privileges, e.g. by prompting for a password with `sudo`.
+    ///
+    /// The elevated write overwrites the file in place. Backups are still made by the current
+    /// process, so they need a [`BackupPolicy::dir`] it can write to.
+    #[cfg(feature = "elevate")]
+    pub fn elevate(mut self, elevation: crate::Elevation) -> Self {
+        self.elevation = Some(elevation);
+        self
+    }
+}
+
+/// Where backups are kept and how many of them.
+///
+/// Backups are named `<file name>.<UTC timestamp>.bak`, e.g.
+/// `hosts.20240131T235959.123456789Z.bak`, so that they sort by age.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupPolicy {
+    keep: usize,
+    dir: Option<PathBuf>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            keep: DEFAULT_BACKUPS_KEPT,
+            dir: None,
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// Keep the last 5 backups next to the file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the last `n` backups, deleting older ones. `0` keeps every backup.
+    pub fn keep(mut self, n: usize) -> Self {
+        self.keep = n;
+        self
+    }
+
+    /// Store backups in `dir` instead of next to the file
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Copy `path` to a new backup and prune old backups. Returns the path of the new backup.
+    fn backup(&self, path: &Path) -> Result<PathBuf, HostfileError> {
+        let file_name = file_name(path)?;
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => parent_dir(path),
+        };
+
+        // Claim the name by creating the file, so that concurrent backups can't pick the same
+        let mut now = SystemTime::now();
+        let backup = loop {
+            let candidate = dir.join(format!("{file_name}.{}.bak", utc_timestamp(now)));
+            match create_new(&candidate) {
+                Ok(_) => break candidate,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    now += Duration::from_nanos(1);
+                }
+                Err(err) => return Err(io_error(&candidate, err)),
+            }
+        };
+        if let Err(err) = fs::copy(path, &backup) {
+            let _ = fs::remove_file(&backup);
+            return Err(io_error(&backup, err));
+        }
+
+        if self.keep > 0 {
+            let mut backups = list_backups(&dir, &file_name)?;
+            backups.sort();
+            let excess = backups.len().saturating_sub(self.keep);
+            for old in &backups[..excess] {
+                fs::remove_file(old).map_err(|err| io_error(old, err))?;
+            }
+        }
+
+        Ok(backup)
+    }
+}
+
+fn file_name(path: &Path) -> Result<String, HostfileError> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| HostfileError::NotAFile(path.to_path_buf()))
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Backups of `file_name` in `dir`
+fn list_backups(dir: &Path, file_name: &str) -> Result<Vec<PathBuf>, HostfileError> {
+    let prefix = format!("{file_name}.");
+    let entries = fs::read_dir(dir).map_err(|err| io_error(dir, err))?;
+
+    let mut backups = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_backup = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".bak"))
+            .is_some_and(is_timestamp);
+        if is_backup {
+            backups.push(entry.path());
+        }
+    }
+    Ok(backups)
+}
+
+/// Format `time` as `YYYYMMDDTHHMMSS.nnnnnnnnnZ` in UTC
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}.{:09}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_nanos()
+    )
+}
+
+fn is_timestamp(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() == 26
+        && bytes.iter().enumerate().all(|(idx, &b)| match idx {
+            8 => b == b'T',
+            15 => b == b'.',
+            25 => b == b'Z',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+/// Render entries in hosts file format, one line per entry unless it has more than
+/// `max_names` names
+fn render(entries: &[HostEntry], max_names: usize) -> String {
+    entries
+        .iter()
+        .flat_map(|entry| entry_lines(entry, max_names))
+        .collect()
+}
+
+/// The lines of `entry` in hosts file format, including their newlines, with at most
+/// `max_names` names on each unless it is `0`
+fn entry_lines(entry: &HostEntry, max_names: usize) -> Vec<String> {
+    if max_names == 0 || entry.names.len() <= max_names {
+        return vec![format!("{entry}\n")];
+    }
+    entry
+        .names
+        .chunks(max_names)
+        .map(|names| format!("{}\t{}\n", entry.ip, names.join(" ")))
+        .collect()
+}
+
+impl HostEntry {
+    /// Write this entry as a hosts file line, including the newline
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{self}")
+    }
+
+    /// Async version of [`HostEntry::write_to`]
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(format!("{self}\n").as_bytes()).await
+    }
+}
+
+impl SyntaxTree<'_> {
+    /// Write the document exactly as it was parsed
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(self.source().as_bytes())
+    }
+
+    /// Async version of [`SyntaxTree::write_to`]
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(self.source().as_bytes()).await
+    }
+}
+
+/// Write `entries` in hosts file format, one line per entry
+pub fn write_entries<W: Write>(entries: &[HostEntry], w: W) -> io::Result<()> {
+    let mut w = io::BufWriter::new(w);
+    for entry in entries {
+        entry.write_to(&mut w)?;
+    }
+    w.flush()
+}
+
+/// Async version of [`write_entries`]
+#[cfg(feature = "tokio")]
+pub async fn write_entries_async<W: AsyncWrite + Unpin>(
+    entries: &[HostEntry],
+    w: W,
+) -> io::Result<()> {
+    let mut w = tokio::io::BufWriter::new(w);
+    for entry in entries {
+        entry.write_to_async(&mut w).await?;
+    }
+    w.flush().await
+}
+
+/// Replace the file at `path` with `entries`.
+///
+/// The new contents are written to a temporary file in the same directory which is then
+/// renamed over `path`, so readers never see a partially written file. If `path` already
+/// exists its permissions and extended attributes are kept (on Windows, its ACL), and it is
+/// backed up first if `options` ask for it. See [`WriteOptions::in_place`] to overwrite the
+/// file instead.
+///
+/// If `path` is a symlink, the file it points to is replaced and the symlink is kept.
+///
+/// Writes that succeed but are likely to be undone return [`WriteWarning`]s.
+pub fn write_file(
+    path: &Path,
+    entries: &[HostEntry],
+    options: &WriteOptions,
+) -> Result<Vec<WriteWarning>, HostfileError> {
+    let contents = render(entries, options.max_names_per_line);
+    replace_file(path, contents.as_bytes(), options)
+}
+
+/// Replace the file at `path` with the entries of `entries`, writing each one as it is
+/// produced so that memory use doesn't grow with the number of entries.
+///
+/// This is meant for pipelines that transform large blocklists without collecting them.
+/// The file is replaced like [`write_file`] does, but isn't retried with elevated privileges
+/// if that was asked for, since the entries can only be consumed once.
+pub fn write_file_streaming<I>(
+    path: &Path,
+    entries: I,
+    options: &WriteOptions,
+) -> Result<Vec<WriteWarning>, HostfileError>
+where
+    I: IntoIterator<Item = HostEntry>,
+{
+    replace_file_with(path, options, None, |w| {
+        let mut w = io::BufWriter::new(w);
+        for entry in entries {
+            for line in entry_lines(&entry, options.max_names_per_line) {
+                w.write_all(line.as_bytes())?;
+            }
+        }
+        w.flush()
+    })
+}
+
+/// Atomically replace the file at `path` with `contents`, see [`write_file`]
+pub(crate) fn replace_file(
+    path: &Path,
+    contents: &[u8],
+    options: &WriteOptions,
+) -> Result<Vec<WriteWarning>, HostfileError> {
+    replace_file_with(path, options, Some(contents), |w| w.write_all(contents))
+}
+
+/// Atomically replace the file at `path` with what `write` writes, see [`write_file`].
+///
+/// `contents` is what `write` writes if it is already in memory, which is needed to retry
+/// with elevated privileges.
+fn replace_file_with<F>(
+    path: &Path,
+    options: &WriteOptions,
+    #[cfg_attr(not(feature = "elevate"), allow(unused_variables))] contents: Option<&[u8]>,
+    write: F,
+) -> Result<Vec<WriteWarning>, HostfileError>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    if let (Some(policy), true) = (&options.backup, path.exists()) {
+        policy.backup(path)?;
+    }
+
+    // Write to the target of a symlink rather than replacing the symlink itself
+    let path = &resolve_symlinks(path).map_err(|err| io_error(path, err))?;
+    let file_name = file_name(path)?;
+    let existing = fs::metadata(path).ok();
+
+    // Windows refuses to replace or overwrite a read-only file, even for administrators
+    #[cfg(windows)]
+    let readonly = existing
+        .as_ref()
+        .is_some_and(|metadata| metadata.permissions().readonly());
+    #[cfg(windows)]
+    if readonly {
+        if !options.clear_readonly {
+            return Err(HostfileError::ReadOnly(path.to_path_buf()));
+        }
+        set_readonly(path, false).map_err(|err| io_error(path, err))?;
+    }
+
+    let result = if options.in_place && existing.is_some() {
+        write_in_place(path, options, write)
+    } else {
+        write_with_rename(path, &file_name, existing.as_ref(), options, write)
+    };
+
+    #[cfg(feature = "elevate")]
+    let result = match (result, options.elevation, contents) {
+        (Err(err), Some(elevation), Some(contents))
+            if err.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            let mut encoded = Vec::new();
+            encode(&mut encoded, options, |w| w.write_all(contents))
+                .map_err(|err| io_error(path, err))?;
+            crate::elevate::write_elevated(path, &encoded, elevation)?;
+            Ok(())
+        }
+        (result, _, _) => result,
+    };
+
+    // The renamed file already has the attribute from copying the permissions, but a file
+    // that was overwritten or failed to be written needs it back
+    #[cfg(windows)]
+    if readonly {
+        set_readonly(path, true).map_err(|err| io_error(path, err))?;
+    }
+
+    result.map_err(|err| io_error(path, err))?;
+    #[cfg(feature = "metrics")]
+    crate::telemetry::record_write(path);
+    Ok(warnings(path))
+}
+
+/// A problem with a file that was written successfully
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WriteWarning {
+    /// cloud-init regenerates the system hosts file on boot, which will undo the write
+    CloudInitManaged(ManageEtcHosts),
+}
+
+impl fmt::Display for WriteWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteWarning::CloudInitManaged(mode) => write!(
+                f,
+                "cloud-init regenerates the hosts file on boot (manage_etc_hosts: {mode}), \
+                 edit its template in /etc/cloud/templates or disable manage_etc_hosts to keep \
+                 changes"
+            ),
+        }
+    }
+}
+
+/// Warnings about writing to `path`
+fn warnings(path: &Path) -> Vec<WriteWarning> {
+    let is_system_hosts = get_hostfile_path()
+        .ok()
+        .and_then(|hosts| fs::canonicalize(hosts).ok())
+        .is_some_and(|hosts| fs::canonicalize(path).is_ok_and(|path| path == hosts));
+
+    let mut warnings = Vec::new();
+    if is_system_hosts {
+        if let Some(mode) = cloud_init_manages_etc_hosts() {
+            warnings.push(WriteWarning::CloudInitManaged(mode));
+        }
+    }
+    warnings
+}
+
+/// Follow `path` through any symlinks to the file it points to, which may not exist yet
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    // The same limit as Linux's ELOOP
+    const MAX_LINKS: usize = 40;
+
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = parent_dir(&path).join(target);
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+/// Write to `w` with `write`, compressing what it writes if `options` ask for it
+fn encode<W, F>(
+    w: W,
+    #[cfg_attr(not(feature = "gzip"), allow(unused_variables))] options: &WriteOptions,
+    write: F,
+) -> io::Result<()>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let mut w = w;
+    #[cfg(feature = "gzip")]
+    if options.gzip {
+        let mut encoder = flate2::write::GzEncoder::new(w, flate2::Compression::default());
+        write(&mut encoder)?;
+        encoder.finish()?;
+        return Ok(());
+    }
+    write(&mut w)
+}
+
+/// Write the contents to a temporary file next to `path` with `write` and rename it over
+/// `path`
+fn write_with_rename<F>(
+    path: &Path,
+    file_name: &str,
+    #[cfg_attr(target_os = "wasi", allow(unused_variables))] existing: Option<&fs::Metadata>,
+    options: &WriteOptions,
+    write: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let (temp_path, temp) = create_temp_file(path, file_name)?;
+    let result = (|| {
+        let mut temp = temp;
+        encode(&mut temp, options, write)?;
+        temp.sync_all()?;
+        // WASI has no permissions or other metadata to copy
+        #[cfg(not(target_os = "wasi"))]
+        if let Some(metadata) = existing {
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+            #[cfg(unix)]
+            copy_xattrs(path, &temp_path)?;
+            #[cfg(windows)]
+            copy_dacl(path, &temp_path)?;
+        }
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Set or clear the read-only attribute of `path`
+#[cfg(windows)]
+fn set_readonly(path: &Path, readonly: bool) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    if permissions.readonly() != readonly {
+        // Only the attribute changes on Windows, unlike on Unix where this would make the
+        // file writable by everyone
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(readonly);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// Create a file at `path`, failing if it already exists
+fn create_new(path: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+/// Create a new temporary file next to `path`, named `.<file name>.<process>.<count>.tmp` so
+/// that processes and threads writing at once each get their own
+fn create_temp_file(path: &Path, file_name: &str) -> io::Result<(PathBuf, fs::File)> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = parent_dir(path);
+    loop {
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let temp_path = dir.join(format!(".{file_name}.{}.{count}.tmp", unique_suffix()));
+        match create_new(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            // Left behind by an earlier process with the same ID
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A suffix for temporary file names that differs between processes writing at once
+fn unique_suffix() -> u128 {
+    #[cfg(not(target_os = "wasi"))]
+    {
+        std::process::id().into()
+    }
+
+    // WASI has no process IDs
+    #[cfg(target_os = "wasi")]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos())
+    }
+}
+
+fn write_in_place<F>(path: &Path, options: &WriteOptions, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    encode(&mut file, options, write)?;
+    file.sync_all()
+}
+
+/// Copy the extended attributes of `from` to `to`, most importantly its SELinux label.
+///
+/// A new file gets the default label of its directory, and on enforcing systems confined
+/// daemons may not be allowed to read `/etc/hosts` without its usual `net_conf_t` label.
+/// Nothing is copied on file systems or platforms without extended attributes. Attributes
+/// that we aren't allowed to set, such as `trusted.*` ones for unprivileged writers, are
+/// skipped, except for the SELinux label while SELinux is enforcing.
+#[cfg(unix)]
+fn copy_xattrs(from: &Path, to: &Path) -> io::Result<()> {
+    let names = match xattr::list_deref(from) {
+        Ok(names) => names,
+        Err(err) if is_unsupported(&err) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let enforcing = selinux_enforcing();
+    for name in names {
+        let copied = xattr::get_deref(from, &name).and_then(|value| match value {
+            Some(value) => xattr::set(to, &name, &value),
+            None => Ok(()),
+        });
+        match copied {
+            Err(err) if !skip_xattr_error(&name, &err, enforcing) => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_unsupported(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::Unsupported
+        || matches!(err.raw_os_error(), Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP)
+}
+
+/// Whether failing to copy the attribute `name` with `err` should be ignored
+#[cfg(unix)]
+fn skip_xattr_error(name: &std::ffi::OsStr, err: &io::Error, enforcing: bool) -> bool {
+    let refused = err.kind() == io::ErrorKind::PermissionDenied || is_unsupported(err);
+    refused && !(enforcing && name == "security.selinux")
+}
+
+/// Whether SELinux is enabled and enforcing its policy
+#[cfg(unix)]
+fn selinux_enforcing() -> bool {
+    fs::read_to_string("/sys/fs/selinux/enforce").is_ok_and(|mode| mode.trim() == "1")
+}
+
+/// Copy the DACL of `from` to `to`, including whether it inherits from its parent directory.
+///
+/// A renamed file keeps its own security descriptor rather than the one of the file it
+/// replaces, which would otherwise reset the ACL of the hosts file to that of its directory.
+/// The owner isn't copied as setting it needs the restore privilege.
+#[cfg(windows)]
+fn copy_dacl(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::{
+        Foundation::{LocalFree, ERROR_SUCCESS},
+        Security::{
+            Authorization::{GetNamedSecurityInfoW, SetNamedSecurityInfoW, SE_FILE_OBJECT},
+            GetSecurityDescriptorControl, ACL, DACL_SECURITY_INFORMATION,
+            PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SE_DACL_PROTECTED,
+            UNPROTECTED_DACL_SECURITY_INFORMATION,
+        },
+    };
+
+    let wide =
+        |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(from), wide(to));
+
+    let mut dacl = null_mut::<ACL>();
+    let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+    let ret = unsafe {
+        GetNamedSecurityInfoW(
+            from.as_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION,
+            null_mut(),
+            null_mut(),
+            &mut dacl,
+            null_mut(),
+            &mut descriptor,
+        )
+    };
+    if ret != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(ret as i32));
+    }
+
+    let mut control = 0;
+    let mut revision = 0;
+    let protected =
+        unsafe { GetSecurityDescriptorControl(descriptor, &mut control, &mut revision) } != 0
+            && control & SE_DACL_PROTECTED != 0;
+    let info = DACL_SECURITY_INFORMATION
+        | if protected {
+            PROTECTED_DACL_SECURITY_INFORMATION
+        } else {
+            UNPROTECTED_DACL_SECURITY_INFORMATION
+        };
+
+    let ret = unsafe {
+        SetNamedSecurityInfoW(
+            to.as_ptr(),
+            SE_FILE_OBJECT,
+            info,
+            null_mut(),
+            null_mut(),
+            dacl,
+            null(),
+        )
+    };
+    // `dacl` points into `descriptor`, so it must only be freed once we're done with both
+    unsafe { LocalFree(descriptor) };
+    if ret != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(ret as i32));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mktemp;
+    use mktemp::Temp;
+
+    use super::*;
+    use crate::parse_file;
+
+    fn entries() -> Vec<HostEntry> {
+        vec![
+            "127.0.0.1 localhost".parse().unwrap(),
+            "::1 localhost ip6-localhost".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn write_and_parse_back() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+
+        write_file(&path, &entries(), &WriteOptions::new()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "127.0.0.1\tlocalhost\n::1\tlocalhost ip6-localhost\n"
+        );
+        assert_eq!(parse_file(&path), Ok(entries()));
+        // No backup of a file that didn't exist, no leftover temp file
+        assert_eq!(fs::read_dir(temp_dir.as_path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn split_long_entries() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        let mut entries = entries();
+        entries.push("10.0.0.1 a b c d e".parse().unwrap());
+
+        let options = WriteOptions::new().max_names_per_line(2);
+        write_file(&path, &entries, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "127.0.0.1\tlocalhost\n::1\tlocalhost ip6-localhost\n\
+             10.0.0.1\ta b\n10.0.0.1\tc d\n10.0.0.1\te\n"
+        );
+    }
+
+    #[test]
+    fn write_streaming() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        let numbered = (1..=3).map(|n| format!("10.0.0.{n} host{n}").parse().unwrap());
+
+        write_file_streaming(&path, numbered, &WriteOptions::new()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "10.0.0.1\thost1\n10.0.0.2\thost2\n10.0.0.3\thost3\n"
+        );
+
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Read;
+
+            let options = WriteOptions::new().gzip(true);
+            write_file_streaming(&path, entries(), &options).unwrap();
+            let mut decoded = String::new();
+            flate2::read::GzDecoder::new(fs::File::open(&path).unwrap())
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, render(&entries(), 0));
+        }
+    }
+
+    #[test]
+    fn backups_are_rotated() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "# original\n").unwrap();
+
+        let options = WriteOptions::new().backup(BackupPolicy::new().keep(2));
+        for _ in 0..4 {
+            write_file(&path, &entries(), &options).unwrap();
+        }
+
+        let mut backups = list_backups(temp_dir.as_path(), "hosts").unwrap();
+        backups.sort();
+        assert_eq!(backups.len(), 2);
+        for backup in &backups {
+            assert_eq!(fs::read_to_string(backup).unwrap(), render(&entries(), 0));
+        }
+    }
+
+    #[test]
+    fn backups_in_custom_dir() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let backup_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "# original\n").unwrap();
+        // Files that merely look like backups are left alone
+        fs::write(backup_dir.as_path().join("hosts.old.bak"), "").unwrap();
+
+        let policy = BackupPolicy::new().keep(0).dir(backup_dir.as_path());
+        let options = WriteOptions::new().backup(policy);
+        write_file(&path, &entries(), &options).unwrap();
+        write_file(&path, &entries(), &options).unwrap();
+
+        let mut backups = list_backups(backup_dir.as_path(), "hosts").unwrap();
+        backups.sort();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "# original\n");
+        assert_eq!(fs::read_dir(backup_dir.as_path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn write_in_place_keeps_file() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        let link = temp_dir.as_path().join("hosts.link");
+        fs::write(&path, "# a much longer original file\n").unwrap();
+        fs::hard_link(&path, &link).unwrap();
+
+        let options = WriteOptions::new().in_place(true);
+        write_file(&path, &entries(), &options).unwrap();
+        // Still the same file, so the hard link sees the new contents
+        assert_eq!(fs::read_to_string(&link).unwrap(), render(&entries(), 0));
+
+        // Files that don't exist are still created
+        let new_path = temp_dir.as_path().join("new");
+        write_file(&new_path, &entries(), &options).unwrap();
+        assert_eq!(parse_file(&new_path), Ok(entries()));
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "# original\n").unwrap();
+
+        let options = WriteOptions::new().backup(BackupPolicy::new().keep(0));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| write_file(&path, &entries(), &options).unwrap());
+            }
+        });
+        assert_eq!(parse_file(&path), Ok(entries()));
+        assert_eq!(list_backups(temp_dir.as_path(), "hosts").unwrap().len(), 8);
+        // Only the file and its backups are left, no temporary files
+        assert_eq!(fs::read_dir(temp_dir.as_path()).unwrap().count(), 9);
+    }
+
+    #[test]
+    fn write_errors_keep_io_error() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("missing/hosts");
+        let err = write_file(&path, &entries(), &WriteOptions::new()).unwrap_err();
+        match &err {
+            HostfileError::Io {
+                path: Some(err_path),
+                error,
+                ..
+            } => {
+                assert_eq!(err_path, &path);
+                assert_eq!(error.kind(), io::ErrorKind::NotFound);
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.is::<io::Error>());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xattrs_are_kept() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "# original\n").unwrap();
+        if xattr::set(&path, "user.hostfile", b"kept").is_err() {
+            // The file system doesn't support user attributes
+            return;
+        }
+
+        write_file(&path, &entries(), &WriteOptions::new()).unwrap();
+        assert_eq!(
+            xattr::get(&path, "user.hostfile").unwrap(),
+            Some(b"kept".to_vec())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refused_xattrs_are_skipped() {
+        use std::ffi::OsStr;
+
+        let denied = io::Error::from_raw_os_error(libc::EPERM);
+        let unsupported = io::Error::from_raw_os_error(libc::ENOTSUP);
+        let other = io::Error::from_raw_os_error(libc::EIO);
+        let trusted = OsStr::new("trusted.overlay.opaque");
+        let selinux = OsStr::new("security.selinux");
+        assert!(skip_xattr_error(trusted, &denied, true));
+        assert!(skip_xattr_error(trusted, &unsupported, false));
+        assert!(!skip_xattr_error(trusted, &other, false));
+        assert!(skip_xattr_error(selinux, &denied, false));
+        assert!(!skip_xattr_error(selinux, &denied, true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_through_symlink() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let dir = temp_dir.as_path();
+        fs::create_dir(dir.join("static")).unwrap();
+        fs::write(dir.join("static/hosts"), "# original\n").unwrap();
+        std::os::unix::fs::symlink("static/hosts", dir.join("hosts")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+
+        let options = WriteOptions::new().backup(BackupPolicy::new());
+        write_file(&dir.join("hosts"), &entries(), &options).unwrap();
+        assert!(fs::symlink_metadata(dir.join("hosts"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(parse_file(&dir.join("hosts")), Ok(entries()));
+        assert_eq!(parse_file(&dir.join("static/hosts")), Ok(entries()));
+        // The backup is next to the path that was written to and has the original contents
+        let backups = list_backups(dir, "hosts").unwrap();
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "# original\n");
+
+        write_file(&dir.join("dangling"), &entries(), &options).unwrap();
+        assert_eq!(parse_file(&dir.join("missing")), Ok(entries()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn readonly_attribute() {
+        let temp_dir = Temp::new_dir().unwrap();
+        let path = temp_dir.as_path().join("hosts");
+        fs::write(&path, "# original\n").unwrap();
+        set_readonly(&path, true).unwrap();
+
+        assert_eq!(
+            write_file(&path, &entries(), &WriteOptions::new()),
+            Err(HostfileError::ReadOnly(path.clone()))
+        );
+        for in_place in [false, true] {
+            let options = WriteOptions::new().clear_readonly(true).in_place(in_place);
+            write_file(&path, &entries(), &options).unwrap();
+            assert_eq!(parse_file(&path), Ok(entries()));
+            assert!(fs::metadata(&path).unwrap().permissions().readonly());
+        }
+        set_readonly(&path, false).unwrap();
+    }
+
+    #[test]
+    fn write_to_writers() {
+        let mut out = Vec::new();
+        write_entries(&entries(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), render(&entries(), 0));
+
+        let mut out = Vec::new();
+        entries()[0].write_to(&mut out).unwrap();
+        assert_eq!(out, b"127.0.0.1\tlocalhost\n");
+
+        let source = "# comment\r\n127.0.0.1   localhost # c\nbad line";
+        let mut out = Vec::new();
+        crate::parse_lossless(source).write_to(&mut out).unwrap();
+        assert_eq!(out, source.as_bytes());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn write_to_async_writers() {
+        let mut out = Vec::new();
+        write_entries_async(&entries(), &mut out).await.unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), render(&entries(), 0));
+
+        let source = "# comment\n127.0.0.1 localhost\n";
+        let mut out = Vec::new();
+        crate::parse_lossless(source)
+            .write_to_async(&mut out)
+            .await
+            .unwrap();
+        assert_eq!(out, source.as_bytes());
+    }
+
+    #[test]
+    fn timestamps() {
+        let time = UNIX_EPOCH + Duration::new(951_782_400 + 3661, 5);
+        assert_eq!(utc_timestamp(time), "20000229T010101.000000005Z");
+        assert!(is_timestamp(&utc_timestamp(SystemTime::now())));
+        assert!(!is_timestamp("old"));
+    }
+}